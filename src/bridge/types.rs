@@ -7,6 +7,9 @@ pub struct Bridge {
     pub port: u16,
     pub fingerprint: Option<String>,
     pub transport: Option<String>,
+    /// Pluggable transport arguments, e.g. `cert=...` and `iat-mode=0`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
 }
 
 impl Bridge {
@@ -17,10 +20,14 @@ impl Bridge {
             port,
             fingerprint: None,
             transport: None,
+            args: Vec::new(),
         }
     }
 
-    /// Parse bridge from string (format: "IP:PORT" or "IP:PORT FINGERPRINT" or "Bridge IP:PORT FINGERPRINT TRANSPORT")
+    /// Parse bridge from string
+    ///
+    /// Takes Tor's format, "[Bridge] [TRANSPORT] IP:PORT [FINGERPRINT] [ARGS]",
+    /// and the older "IP:PORT FINGERPRINT TRANSPORT".
     pub fn from_str(s: &str) -> Result<Self, String> {
         let line = s.trim();
         if line.is_empty() {
@@ -34,12 +41,19 @@ impl Bridge {
             line
         };
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.is_empty() {
-            return Err("Invalid bridge format. Expected IP:PORT".to_string());
-        }
+        let mut parts = line.split_whitespace().peekable();
+
+        // A transport name comes before the address, e.g. "obfs4 1.2.3.4:443"
+        let mut transport = match parts.peek() {
+            Some(part) if !part.contains(':') && !part.contains('=') => parts.next().map(str::to_string),
+            Some(_) => None,
+            None => return Err("Invalid bridge format. Expected IP:PORT".to_string()),
+        };
 
-        let addr_part = parts[0];
+        let addr_part = match parts.next() {
+            Some(part) => part,
+            None => return Err("Invalid bridge format. Expected IP:PORT".to_string()),
+        };
         let addr_parts: Vec<&str> = addr_part.split(':').collect();
         if addr_parts.len() != 2 {
             return Err("Invalid bridge format. Expected IP:PORT (e.g., 1.2.3.4:443)".to_string());
@@ -59,33 +73,31 @@ impl Bridge {
             return Err("Port number cannot be 0".to_string());
         }
 
-        // Parse fingerprint (optional, typically 40 hex characters)
-        let fingerprint = if parts.len() > 1 {
-            let fp = parts[1].to_string();
-            if fp.len() == 40 && fp.chars().all(|c| c.is_ascii_hexdigit()) {
-                Some(fp)
-            } else if !fp.is_empty() {
-                // Allow non-standard fingerprints but warn
-                Some(fp)
-            } else {
-                None
-            }
-        } else {
-            None
+        // Fingerprint (optional, typically 40 hex characters; non-standard
+        // ones are allowed but warned about by `validate`)
+        let fingerprint = match parts.peek() {
+            Some(part) if !part.contains('=') => parts.next().map(str::to_string),
+            _ => None,
         };
 
-        // Parse transport (optional, e.g., obfs4, meek)
-        let transport = if parts.len() > 2 {
-            Some(parts[2].to_string())
-        } else {
-            None
-        };
+        // Transport arguments (key=value), or a transport in the older format
+        let mut args = Vec::new();
+        for part in parts {
+            if part.contains('=') {
+                args.push(part.to_string());
+            } else if transport.is_none() {
+                transport = Some(part.to_string());
+            } else {
+                return Err(format!("Unexpected bridge argument: {}", part));
+            }
+        }
 
         Ok(Self {
             address,
             port,
             fingerprint,
             transport,
+            args,
         })
     }
 
@@ -113,13 +125,20 @@ impl Bridge {
     }
 
     /// Convert bridge to Tor configuration format
+    ///
+    /// "Bridge [TRANSPORT] IP:PORT [FINGERPRINT] [ARGS]"; transports also need
+    /// a `ClientTransportPlugin` line.
     pub fn to_tor_config(&self) -> String {
-        let mut config = format!("Bridge {}:{}", self.address, self.port);
+        let mut config = String::from("Bridge");
+        if let Some(ref transport) = self.transport {
+            config.push_str(&format!(" {}", transport));
+        }
+        config.push_str(&format!(" {}:{}", self.address, self.port));
         if let Some(ref fp) = self.fingerprint {
             config.push_str(&format!(" {}", fp));
         }
-        if let Some(ref transport) = self.transport {
-            config.push_str(&format!(" {}", transport));
+        for arg in &self.args {
+            config.push_str(&format!(" {}", arg));
         }
         config
     }
@@ -0,0 +1,89 @@
+use std::io::Write;
+use std::time::Duration;
+
+use crate::core::TorrerEngine;
+use crate::error::TorrerResult;
+use crate::tor::{BootstrapPhase, BootstrapStatus, TorClient};
+use crate::utils::format_progress_bar;
+
+const BAR_WIDTH: usize = 30;
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long `torrer start` waits for Tor to finish bootstrapping
+pub const BOOTSTRAP_WAIT: Duration = Duration::from_secs(120);
+
+/// One-line progress bar for a bootstrap phase
+pub fn render_phase(phase: &BootstrapPhase) -> String {
+    format!(
+        "{} {:>3}% {}",
+        format_progress_bar(phase.progress, BAR_WIDTH),
+        phase.progress,
+        phase.summary
+    )
+}
+
+/// Print where bootstrap is stuck, if Tor reported a problem
+pub fn print_stall(status: &BootstrapStatus) {
+    if let Some(warning) = status.stalled_at() {
+        println!(
+            "  ⚠ Stalled at {}% ({}): {}",
+            warning.progress,
+            warning.tag,
+            warning.warning.as_deref().unwrap_or(&warning.summary)
+        );
+        if let Some(ref reason) = warning.reason {
+            println!("    Reason: {}", reason);
+        }
+        if let Some(ref host) = warning.host {
+            println!("    Host: {}", host);
+        }
+    }
+}
+
+/// Print the bootstrap bar and any stall warning
+pub fn print_bootstrap(status: &BootstrapStatus) {
+    println!("  Bootstrap: {}", render_phase(&status.phase));
+    print_stall(status);
+}
+
+/// Redraw a progress bar until Tor has bootstrapped or `timeout` passes
+///
+/// Returns whether bootstrap finished.
+pub async fn wait_for_bootstrap(engine: &mut TorrerEngine, timeout: Duration) -> TorrerResult<bool> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut last = None;
+
+    loop {
+        let status = match engine.status().await?.bootstrap {
+            Some(status) => status,
+            None => return Ok(false),
+        };
+
+        let line = render_phase(&status.phase);
+        if last.as_ref() != Some(&line) {
+            print!("\r\x1b[2K{}", line);
+            let _ = std::io::stdout().flush();
+            last = Some(line);
+        }
+
+        if status.phase.is_done() {
+            println!();
+            return Ok(true);
+        }
+
+        if tokio::time::Instant::now() >= deadline {
+            println!();
+            print_stall(&status);
+            return Ok(false);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Query Tor's bootstrap phase directly (when this process is not routing)
+pub async fn fetch_bootstrap() -> TorrerResult<BootstrapPhase> {
+    let mut client = TorClient::from_system_config();
+    client.connect().await?;
+    client.authenticate().await?;
+    BootstrapPhase::fetch(&mut client).await
+}
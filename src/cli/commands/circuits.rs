@@ -1,12 +1,17 @@
-use crate::error::TorrerResult;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use crate::core::{IdentityRotator, Scheduler};
+use crate::error::{TorrerError, TorrerResult};
 use crate::tor::{TorClient, CircuitManager};
+use super::connect_tor;
 
 /// List active Tor circuits
 pub async fn list_circuits() -> TorrerResult<()> {
     println!("Active Tor Circuits:");
     println!();
 
-    let mut client = TorClient::new();
+    let mut client = TorClient::from_system_config();
     
     // Connect and authenticate
     client.connect().await?;
@@ -25,8 +30,31 @@ pub async fn list_circuits() -> TorrerResult<()> {
                     if let Some(ref purpose) = circuit.purpose {
                         println!("  Purpose: {}", purpose);
                     }
-                    if let Some(ref flags) = circuit.flags {
-                        println!("  Flags: {}", flags);
+                    if let Some(ref hs_state) = circuit.hs_state {
+                        println!("  HS state: {}", hs_state);
+                    }
+                    if let Some(ref rend_query) = circuit.rend_query {
+                        println!("  Onion service: {}", rend_query);
+                    }
+                    if !circuit.build_flags.is_empty() {
+                        println!("  Build flags: {}", circuit.build_flags.join(", "));
+                    }
+                    if let Some(ref created) = circuit.time_created {
+                        println!("  Created: {}", created);
+                    }
+                    if let Some(ref reason) = circuit.reason {
+                        println!("  Reason: {}", reason);
+                    }
+                    if let Some(ref username) = circuit.socks_username {
+                        println!("  Isolated to SOCKS user: {}", username);
+                    }
+                    if circuit.path.is_empty() {
+                        println!("  Path: (none yet)");
+                    } else {
+                        println!("  Path:");
+                        for (role, hop) in circuit.hops() {
+                            println!("    {:<7} {}", role.as_str(), hop);
+                        }
                     }
                     println!();
                 }
@@ -41,20 +69,103 @@ pub async fn list_circuits() -> TorrerResult<()> {
     Ok(())
 }
 
-/// Request new circuit
-pub async fn new_circuit() -> TorrerResult<()> {
-    println!("Requesting new Tor circuit...");
+/// Request a new identity, optionally repeating every `every_minutes`
+pub async fn new_circuit(every_minutes: Option<u64>) -> TorrerResult<()> {
+    let mut rotator = IdentityRotator::connect().await?;
 
-    let mut client = TorClient::new();
-    
-    // Connect and authenticate
-    client.connect().await?;
-    client.authenticate().await?;
+    let minutes = match every_minutes {
+        None => {
+            println!("Requesting new Tor identity...");
+            let rotation = rotator.rotate().await?;
+            if rotation.delayed_by.is_zero() {
+                println!("✓ New identity in effect");
+            } else {
+                println!(
+                    "✓ New identity in effect (Tor delayed it by {}s)",
+                    rotation.delayed_by.as_secs()
+                );
+            }
+            return Ok(());
+        }
+        Some(0) => {
+            return Err(TorrerError::Config(
+                "Rotation interval must be at least one minute".to_string(),
+            ))
+        }
+        Some(minutes) => minutes,
+    };
+    let every = match minutes.checked_mul(60) {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => {
+            return Err(TorrerError::Config(format!(
+                "Rotation interval of {} minutes is too long",
+                minutes
+            )))
+        }
+    };
+
+    println!("Rotating Tor identity every {} minute(s); press Ctrl+C to stop", minutes);
+
+    let rotator = Arc::new(Mutex::new(rotator));
+    let mut scheduler = Scheduler::new();
+    scheduler.add_task(IdentityRotator::schedule(rotator, every));
+    scheduler.start().await?;
+
+    std::future::pending::<()>().await;
+    Ok(())
+}
 
-    // Request new circuit
-    CircuitManager::new_circuit(&mut client).await?;
+/// Close a single circuit
+pub async fn close_circuit(id: &str) -> TorrerResult<()> {
+    let mut client = connect_tor().await?;
+    CircuitManager::close_circuit(&mut client, id).await?;
 
-    println!("✓ New circuit requested");
+    println!("✓ Circuit {} closed", id);
     Ok(())
 }
 
+/// Build a new circuit
+pub async fn extend_circuit(relays: &[String]) -> TorrerResult<()> {
+    let mut client = connect_tor().await?;
+    let id = CircuitManager::extend_circuit(&mut client, relays).await?;
+
+    println!("✓ Building circuit {}", id);
+    Ok(())
+}
+
+/// List application streams
+pub async fn list_streams() -> TorrerResult<()> {
+    let mut client = connect_tor().await?;
+    let streams = CircuitManager::list_streams(&mut client).await?;
+
+    if streams.is_empty() {
+        println!("No active streams");
+        return Ok(());
+    }
+
+    println!("{:<8} {:<12} {:<8} TARGET", "ID", "STATUS", "CIRCUIT");
+    for stream in streams {
+        let circuit = if stream.is_attached() { stream.circuit_id.as_str() } else { "-" };
+        println!("{:<8} {:<12} {:<8} {}", stream.id, stream.status, circuit, stream.target);
+    }
+
+    Ok(())
+}
+
+/// Close a single stream
+pub async fn close_stream(id: &str) -> TorrerResult<()> {
+    let mut client = connect_tor().await?;
+    CircuitManager::close_stream(&mut client, id).await?;
+
+    println!("✓ Stream {} closed", id);
+    Ok(())
+}
+
+/// Attach a stream to a circuit
+pub async fn attach_stream(stream: &str, circuit: &str) -> TorrerResult<()> {
+    let mut client = connect_tor().await?;
+    CircuitManager::attach_stream(&mut client, stream, circuit).await?;
+
+    println!("✓ Stream {} attached to circuit {}", stream, circuit);
+    Ok(())
+}
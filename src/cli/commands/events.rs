@@ -1,8 +1,9 @@
 use crate::error::TorrerResult;
-use crate::core::EventManager;
+use crate::core::{Event, EventManager};
+use crate::tor::{EventKind, TorClient};
 
 /// Monitor events
-pub fn monitor_events() -> TorrerResult<()> {
+pub async fn monitor_events() -> TorrerResult<()> {
     println!("Monitoring Torrer events...");
     println!("Press Ctrl+C to stop");
     println!();
@@ -16,10 +17,22 @@ pub fn monitor_events() -> TorrerResult<()> {
         println!("[{}] {}: {:?}", timestamp_str, event.name(), event);
     });
 
-    // Keep running
-    loop {
-        std::thread::sleep(std::time::Duration::from_secs(1));
+    // Follow events as Tor reports them
+    let mut client = TorClient::from_system_config();
+    client.connect().await?;
+    client.authenticate().await?;
+    let mut stream = client.subscribe(&EventKind::DEFAULT).await?;
+
+    while let Some(tor_event) = stream.next().await {
+        // Per-second bandwidth samples would flood the terminal
+        match Event::from_tor_event(&tor_event) {
+            Some(Event::Bandwidth { .. }) | None => {}
+            Some(event) => event_manager.emit(event)?,
+        }
     }
+
+    println!("Tor closed the control connection");
+    Ok(())
 }
 
 /// Show recent events
@@ -0,0 +1,82 @@
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::config::ConfigManager;
+use crate::core::NamespaceSandbox;
+use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::create_backend;
+use crate::utils::{is_root, lookup_user};
+
+/// User to drop to inside the namespace: `--user`, else whoever ran sudo
+fn target_user(user: Option<&str>) -> TorrerResult<Option<(u32, u32)>> {
+    if let Some(user) = user {
+        return lookup_user(user)
+            .map(Some)
+            .ok_or_else(|| TorrerError::Config(format!("Unknown user: {}", user)));
+    }
+
+    let sudo_id = |name: &str| std::env::var(name).ok().and_then(|id| id.parse::<u32>().ok());
+    Ok(match (sudo_id("SUDO_UID"), sudo_id("SUDO_GID")) {
+        (Some(uid), Some(gid)) if uid != 0 => Some((uid, gid)),
+        _ => None,
+    })
+}
+
+/// Run one program in a network namespace whose traffic all goes through Tor
+///
+/// Returns the program's exit code.
+pub async fn exec(command: &[String], user: Option<&str>) -> TorrerResult<i32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| TorrerError::Config("No command given".to_string()))?;
+
+    if !is_root() {
+        return Err(TorrerError::Config(
+            "torrer exec needs root to create a network namespace (run with sudo)".to_string(),
+        ));
+    }
+
+    let config = ConfigManager::new()?.load()?;
+    let ports = config.routing_ports();
+
+    // Without Tor listening, the namespace would have no connectivity at all
+    let trans_port = SocketAddr::from(([127, 0, 0, 1], ports.trans_port));
+    if TcpStream::connect_timeout(&trans_port, Duration::from_secs(2)).is_err() {
+        return Err(TorrerError::Tor(format!(
+            "Tor's TransPort is not reachable on {}; is tor running?",
+            trans_port
+        )));
+    }
+
+    let user = target_user(user)?;
+    let firewall = create_backend(config.firewall_backend, ports)?;
+    let sandbox = NamespaceSandbox::create(std::process::id(), firewall)?;
+    log::info!(
+        "Running {} in namespace {} ({})",
+        program,
+        sandbox.layout().name,
+        sandbox.layout().ns_addr
+    );
+
+    let mut child = tokio::process::Command::from(sandbox.command(program, args, user))
+        .spawn()
+        .map_err(|e| TorrerError::Config(format!("Failed to run {}: {}", program, e)))?;
+
+    // Ctrl+C reaches the program too; wait for it so the sandbox is removed
+    let mut terminate = signal(SignalKind::terminate())?;
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => break status?,
+            _ = tokio::signal::ctrl_c() => log::debug!("Interrupted, waiting for {} to exit", program),
+            _ = terminate.recv() => {
+                log::info!("Terminated, stopping {}", program);
+                let _ = child.start_kill();
+            }
+        }
+    };
+
+    drop(sandbox);
+    Ok(status.code().unwrap_or(1))
+}
@@ -1,4 +1,6 @@
+use crate::config::ConfigManager;
 use crate::error::TorrerResult;
+use crate::iptables::{FirewallBackendKind, FirewallPlan};
 use crate::security::FirewallManager;
 
 /// Check firewall status
@@ -25,6 +27,18 @@ pub fn check_firewall() -> TorrerResult<()> {
         println!("Firewall: Not active or not detected");
     }
 
+    let configured = ConfigManager::new()
+        .and_then(|manager| manager.load())
+        .map(|config| config.firewall_backend)
+        .unwrap_or_default();
+    match configured {
+        FirewallBackendKind::Auto => match FirewallBackendKind::detect() {
+            Some(detected) => println!("Routing backend: {} (auto-detected)", detected),
+            None => println!("Routing backend: none found (install nftables or iptables)"),
+        },
+        kind => println!("Routing backend: {}", kind),
+    }
+
     Ok(())
 }
 
@@ -39,3 +53,38 @@ pub fn configure_firewall() -> TorrerResult<()> {
     Ok(())
 }
 
+
+/// Print the ruleset start would apply and diff it against the live one
+pub fn plan_firewall() -> TorrerResult<()> {
+    let config = ConfigManager::new()?.load()?;
+    let plan = FirewallPlan::from_config(&config);
+
+    println!("=== Firewall Plan ({}) ===", plan.kind);
+    println!();
+    print!("{}", plan.script);
+    println!();
+
+    let live = match plan.live() {
+        Ok(live) => live,
+        Err(e) => {
+            println!("Could not read the live ruleset: {}", e);
+            println!("Run with sudo to compare against it.");
+            return Ok(());
+        }
+    };
+
+    let diff = plan.diff(&live);
+    let changes = diff.iter().filter(|line| line.is_change()).count();
+    println!("=== Diff against live ruleset ===");
+    if changes == 0 {
+        println!("No changes: the live ruleset already matches the plan");
+        return Ok(());
+    }
+    for line in &diff {
+        println!("{}", line);
+    }
+    println!();
+    println!("{} line(s) would change", changes);
+
+    Ok(())
+}
@@ -31,15 +31,19 @@ fn show_general_help() -> TorrerResult<()> {
     println!("    set-country        Set exit node country");
     println!("    randomize-mac       Randomize MAC addresses");
     println!("    validate           Validate installation");
-    println!("    health             Health check");
+    println!("    health             Health check, including the exit of a live stream");
+    println!("    exit-relay         Show the exit relay and verify it against the consensus");
     println!("    test               Run diagnostic tests");
     println!("    diagnostics        Collect diagnostics");
     println!("    completion         Generate shell completions");
     println!("    clean              Clean temporary files");
     println!("    info               Show system information");
-    println!("    leak-test          Test for DNS/IPv6 leaks");
-    println!("    circuits           List Tor circuits");
-    println!("    new-circuit        Request new Tor circuit");
+    println!("    leak-test          Probe TCP/UDP/ICMP/IPv6/DNS/STUN for leaks (--harness, --format json)");
+    println!("    onion-test <addr>  Check an onion service is reachable through routing");
+    println!("    circuits           List and close Tor circuits and streams");
+    println!("    firewall plan      Preview the routing ruleset and diff it with the live one");
+    println!("    new-circuit        Request new Tor identity (--every N to rotate)");
+    println!("    exec -- <cmd>      Run one program with all its traffic through Tor");
     println!("    state              Show application state");
     println!("    save-state         Save state to file");
     println!("    load-state         Load state from file");
@@ -65,9 +69,18 @@ fn show_command_help(command: &str) -> TorrerResult<()> {
             println!();
             println!("This command will:");
             println!("  - Connect to Tor daemon");
-            println!("  - Configure iptables for transparent routing");
-            println!("  - Set up DNS leak prevention");
-            println!("  - Disable IPv6 (prevent leaks)");
+            println!("  - Add TORRER_NAT/TORRER_FILTER chains (or the inet torrer nftables table)");
+            println!("  - Set up DNS leak prevention, refusing DNS over TCP and TLS (853)");
+            println!("  - Point systemd-resolved, NetworkManager or /etc/resolv.conf at Tor");
+            println!("  - Answer DNS with Torrer's caching resolver, if dns_resolver is set");
+            println!("  - Map .onion names to virtual addresses and route those to Tor");
+            println!("  - Block IPv6, or route it through Tor if ipv6_enabled is set");
+            println!("  - Enable the kill switch first, if kill_switch is set");
+            println!("  - Let traffic listed under [bypass] go direct (split tunneling)");
+            println!("  - Drop or reject other UDP, if a [udp] policy is set");
+            println!("  - Route LAN clients through Tor, if [gateway] is set");
+            println!("  - Load all rules in one transaction, verify them, and roll back on failure");
+            println!("  - Show Tor bootstrap progress until it completes");
             println!();
             println!("Requires: sudo");
         }
@@ -75,9 +88,9 @@ fn show_command_help(command: &str) -> TorrerResult<()> {
             println!("Stop Tor routing");
             println!();
             println!("This command will:");
-            println!("  - Remove Tor routing rules");
-            println!("  - Restore original iptables configuration");
-            println!("  - Restore DNS settings");
+            println!("  - Remove Torrer's own chains, leaving other rules untouched");
+            println!("  - Restore DNS settings: remove Torrer's resolver drop-ins and");
+            println!("    put back /etc/resolv.conf from /var/lib/torrer/dns");
             println!();
             println!("Requires: sudo");
         }
@@ -88,6 +101,41 @@ fn show_command_help(command: &str) -> TorrerResult<()> {
             println!("  - Routing status (active/inactive)");
             println!("  - Tor connection status");
             println!("  - Circuit establishment status");
+            println!("  - Tor bootstrap progress and where it stalls");
+            println!("  - Whether the kill switch is blocking non-Tor traffic");
+        }
+        "circuits" => {
+            println!("List and control Tor circuits and streams");
+            println!();
+            println!("Subcommands:");
+            println!("  list                      List circuits with their relays (default)");
+            println!("  close <id>                Close one circuit without changing identity");
+            println!("  extend [relay...]         Build a new circuit, optionally through given relays");
+            println!("  streams                   List application streams");
+            println!("  close-stream <id>         Close one stream");
+            println!("  attach <stream> <circuit> Attach an unattached stream to a circuit");
+        }
+        "exec" => {
+            println!("Run one program with all its traffic routed through Tor");
+            println!();
+            println!("Usage: torrer exec [--user <name>] -- <command> [args...]");
+            println!();
+            println!("The program runs in its own network namespace, connected to the");
+            println!("host by a veth pair. Its TCP and DNS (including UDP) are redirected");
+            println!("to Tor's TransPort and DNSPort; everything else is dropped. Works for");
+            println!("static binaries too, and needs no 'torrer start'.");
+            println!();
+            println!("The program runs as the user who invoked sudo unless --user is given.");
+            println!();
+            println!("Requires: sudo, a running tor");
+        }
+        "firewall" => {
+            println!("Inspect the rules Torrer routes traffic with");
+            println!();
+            println!("Subcommands:");
+            println!("  plan                      Print the iptables-restore/nft script start would load");
+            println!("                            for the current configuration, and diff it against");
+            println!("                            the live ruleset (reading it requires sudo)");
         }
         _ => {
             println!("Help for '{}' command", command);
@@ -1,50 +1,159 @@
-use crate::error::TorrerResult;
-use crate::security::LeakDetector;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Child;
+
+use crate::config::{ConfigManager, UdpPolicy};
+use crate::core::LeakHarness;
+use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::{FirewallPlan, RoutingRules};
+use crate::security::leak_probe::run_probes;
+use crate::security::{LeakReport, LeakTarget, ProbeKind, ProbeVerdict, RulesetInspection, StandInLog};
+
+/// How long the stand-in internet may take to bind its ports
+const STAND_IN_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Run leak detection tests
-pub async fn run_leak_tests() -> TorrerResult<()> {
-    println!("Running leak detection tests...");
-    println!();
+///
+/// Probes go to `target`, or with `harness` to a stand-in internet in a local
+/// network namespace, which needs root but no network access. Fails when any
+/// probe leaked, so the command can gate CI jobs.
+pub async fn run_leak_tests(format: &str, target: LeakTarget, harness: bool) -> TorrerResult<()> {
+    let config = ConfigManager::new()
+        .and_then(|manager| manager.load())
+        .unwrap_or_default();
+    let plan = FirewallPlan::from_config(&config);
+    let ruleset = RulesetInspection::inspect(&plan);
 
-    let detector = LeakDetector::new();
-
-    // DNS leak test
-    println!("Testing DNS leaks...");
-    match detector.test_dns_leak().await {
-        Ok(result) => {
-            println!("  Tor DNS: {}", if result.tor_dns_working { "✓ Working" } else { "✗ Not working" });
-            println!("  Direct DNS: {}", if result.direct_dns_blocked { "✓ Blocked" } else { "✗ Not blocked" });
-            if result.leak_detected {
-                println!("  ⚠ DNS LEAK DETECTED!");
-            } else {
-                println!("  ✓ No DNS leaks detected");
-            }
-        }
-        Err(e) => {
-            println!("  ✗ DNS leak test failed: {}", e);
+    // Nothing is redirected unless Torrer's rules are loaded
+    let rules = if ruleset.active() { plan.rules.clone() } else { RoutingRules::default() };
+
+    let (target, probes) = if harness {
+        let harness = LeakHarness::create(std::process::id())?;
+        let log = StandInLog::new();
+        let mut stand_in = spawn_stand_in(&harness, &log).await?;
+        let probes = run_probes(&harness.target(), &rules, Some(&log)).await;
+        let _ = stand_in.kill().await;
+        (harness.target(), probes)
+    } else {
+        let probes = run_probes(&target, &rules, None).await;
+        (target, probes)
+    };
+
+    let report = LeakReport {
+        harness,
+        ruleset,
+        probes,
+    };
+
+    match format {
+        "json" => println!("{}", serde_json::to_string_pretty(&report)?),
+        _ => print_report(&report, &target, config.udp.policy),
+    }
+
+    match report.count(ProbeVerdict::Leaked) {
+        0 => Ok(()),
+        leaks => Err(TorrerError::Iptables(format!("{} leak test probe(s) left the host outside Tor", leaks))),
+    }
+}
+
+/// Start the stand-in internet inside the harness namespace
+///
+/// It is this same binary, run as `torrer leak-test --stand-in`; every line it
+/// prints after `ready` names a probe that reached it.
+async fn spawn_stand_in(harness: &LeakHarness, log: &StandInLog) -> TorrerResult<Child> {
+    let program = std::env::current_exe()?;
+    let mut child = tokio::process::Command::from(harness.command(program.as_os_str(), &["leak-test", "--stand-in"]))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let stdout = match child.stdout.take() {
+        Some(stdout) => stdout,
+        None => return Err(TorrerError::Iptables("Stand-in internet has no output".to_string())),
+    };
+    let mut lines = BufReader::new(stdout).lines();
+    match tokio::time::timeout(STAND_IN_TIMEOUT, lines.next_line()).await {
+        Ok(Ok(Some(line))) if line == "ready" => {}
+        _ => {
+            let _ = child.kill().await;
+            return Err(TorrerError::Iptables("Stand-in internet did not start".to_string()));
         }
     }
 
+    let log = log.clone();
+    tokio::spawn(async move {
+        while let Ok(Some(line)) = lines.next_line().await {
+            log.record(&line);
+        }
+    });
+
+    Ok(child)
+}
+
+fn print_report(report: &LeakReport, target: &LeakTarget, policy: UdpPolicy) {
+    println!("Running leak detection tests...");
     println!();
 
-    // IPv6 leak test
-    println!("Testing IPv6 leaks...");
-    match detector.test_ipv6_leak().await {
-        Ok(leak_detected) => {
-            if leak_detected {
-                println!("  ⚠ IPv6 LEAK DETECTED!");
-            } else {
-                println!("  ✓ No IPv6 leaks detected");
-            }
-        }
-        Err(e) => {
-            println!("  ✗ IPv6 leak test failed: {}", e);
+    let ruleset = &report.ruleset;
+    println!("Firewall ruleset ({}):", ruleset.backend);
+    match &ruleset.error {
+        Some(e) => println!("  ✗ Could not read the ruleset: {}", e),
+        None if !ruleset.active() => println!("  ✗ No Torrer rules loaded; routing is not active"),
+        None if ruleset.matches_plan() => println!("  ✓ {} rules loaded, as configured", ruleset.loaded),
+        None => {
+            println!(
+                "  ⚠ {} rules loaded; {} planned rule(s) missing, {} unexpected",
+                ruleset.loaded,
+                ruleset.missing.len(),
+                ruleset.unexpected.len()
+            );
+            println!("  Run `sudo torrer firewall plan` for the differences");
         }
     }
+    println!();
 
+    if report.harness {
+        println!("Probes toward the stand-in internet at {} (local harness):", target.ipv4);
+    } else {
+        println!("Probes toward {}:", target.ipv4);
+    }
+    for probe in &report.probes {
+        let mark = match probe.verdict {
+            ProbeVerdict::Leaked => "⚠",
+            _ => "✓",
+        };
+        println!(
+            "  {} {:<13} {:<22} {} ({})",
+            mark,
+            probe.kind.name(),
+            probe.target,
+            probe.verdict.label(),
+            probe.detail
+        );
+    }
     println!();
-    println!("Leak detection tests complete");
 
-    Ok(())
-}
+    println!(
+        "{} routed via Tor, {} blocked, {} leaked",
+        report.count(ProbeVerdict::ViaTor),
+        report.count(ProbeVerdict::Blocked),
+        report.count(ProbeVerdict::Leaked)
+    );
 
+    let leaked = |kind: ProbeKind| {
+        report
+            .probes
+            .iter()
+            .any(|probe| probe.kind == kind && probe.verdict == ProbeVerdict::Leaked)
+    };
+    if (leaked(ProbeKind::Udp) || leaked(ProbeKind::Stun)) && policy == UdpPolicy::Allow {
+        println!("  Set policy = \"reject\" (or \"drop\") under [udp] in the config file");
+    }
+    if leaked(ProbeKind::Icmp) {
+        println!("  Enable the kill switch to block ICMP and anything else Tor cannot carry");
+    }
+}
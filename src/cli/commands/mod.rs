@@ -6,12 +6,15 @@ pub mod test;
 pub mod clean;
 pub mod info;
 pub mod leak_test;
+pub mod onion_test;
 pub mod circuits;
+pub mod bootstrap;
 pub mod state;
 pub mod update;
 pub mod backup;
 pub mod help;
 pub mod events;
+pub mod exec;
 pub mod firewall;
 pub mod schedule;
 pub mod checksum;
@@ -22,3 +25,14 @@ pub mod daemon;
 pub mod stats;
 
 
+
+use crate::error::TorrerResult;
+use crate::tor::TorClient;
+
+/// Connect and authenticate to the Tor control port
+pub(crate) async fn connect_tor() -> TorrerResult<TorClient> {
+    let mut client = TorClient::from_system_config();
+    client.connect().await?;
+    client.authenticate().await?;
+    Ok(client)
+}
@@ -0,0 +1,151 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+use crate::config::ConfigManager;
+use crate::error::{TorrerError, TorrerResult};
+use crate::security::dns_server::{lookup, RCODE_NOERROR, TYPE_A};
+use crate::tor::{EventKind, EventStream, TorClient, TorEvent};
+use crate::utils::{in_range, network_range, parse_onion_address};
+
+/// How long to wait for Tor to reach the onion service
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Check that an onion service is reachable through transparent routing
+///
+/// Resolves the name through Tor's DNSPort, checks it was mapped into the
+/// virtual network, then connects to the mapped address as any program would
+/// and follows the stream on the control port until Tor reports it succeeded.
+pub async fn run_onion_test(address: &str) -> TorrerResult<()> {
+    let (host, port) = parse_onion_address(address)?;
+    let config = ConfigManager::new()
+        .and_then(|manager| manager.load())
+        .unwrap_or_default();
+
+    println!("Testing {}:{}...", host, port);
+    println!();
+
+    if !config.onion.automap {
+        println!("  ✗ .onion mapping is off; set automap = true under [onion]");
+        return Err(TorrerError::Config("Onion address mapping is disabled".to_string()));
+    }
+
+    // Tor answers with a virtual address it maps back to the onion name
+    println!("Resolving through Tor's DNSPort...");
+    let upstream = (Ipv4Addr::LOCALHOST, config.tor_dns_port).into();
+    let mapped: IpAddr = match lookup(upstream, &host, TYPE_A).await {
+        Ok((RCODE_NOERROR, addresses)) if !addresses.is_empty() => addresses[0],
+        Ok((rcode, _)) => {
+            println!("  ✗ Tor did not map the name (DNS response code {})", rcode);
+            println!("  Is AutomapHostsOnResolve set? `sudo torrer start` enables it");
+            return Err(TorrerError::Tor(format!("{} was not mapped", host)));
+        }
+        Err(e) => {
+            println!("  ✗ Could not ask Tor's DNSPort on port {}: {}", config.tor_dns_port, e);
+            return Err(e);
+        }
+    };
+
+    let network = &config.onion.virtual_network_ipv4;
+    let virtual_address = network_range(network)
+        .map(|(range, prefix)| in_range(mapped, range, prefix))
+        .unwrap_or(false);
+    if virtual_address {
+        println!("  ✓ Mapped to {} in {}", mapped, network);
+    } else {
+        println!("  ⚠ Mapped to {}, outside {}; Tor may be using another VirtualAddrNetworkIPv4", mapped, network);
+    }
+
+    // Tor accepts redirected connections at once; its STREAM events tell
+    // whether the stream then reached the service
+    let mut client = TorClient::from_config(&config);
+    let subscribed = async {
+        client.connect().await?;
+        client.authenticate().await?;
+        client.subscribe(&[EventKind::Stream]).await
+    }
+    .await;
+    let mut events = match subscribed {
+        Ok(events) => events,
+        Err(e) => {
+            println!("  ✗ Cannot follow the stream on Tor's control port: {}", e);
+            return Err(e);
+        }
+    };
+
+    println!();
+    println!("Connecting to {}:{}...", mapped, port);
+    let deadline = tokio::time::Instant::now() + CONNECT_TIMEOUT;
+    let stream = match tokio::time::timeout_at(deadline, TcpStream::connect((mapped, port))).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            println!("  ✗ Connection failed: {}", e);
+            println!("  Is Tor routing active? Run `sudo torrer start` first");
+            return Err(TorrerError::Tor(format!("{} is not reachable: {}", host, e)));
+        }
+        Err(_) => {
+            println!("  ✗ No connection after {} seconds", CONNECT_TIMEOUT.as_secs());
+            return Err(TorrerError::Tor(format!("{} did not answer", host)));
+        }
+    };
+    let source = stream.local_addr()?.to_string();
+    let target = format!("{}:{}", host, port);
+
+    match stream_outcome(&mut events, &source, &target, deadline).await {
+        Some(Ok(())) => {
+            println!("  ✓ Connected to {} through Tor", host);
+            Ok(())
+        }
+        Some(Err(reason)) => {
+            println!("  ✗ Tor could not reach the onion service ({})", reason);
+            Err(TorrerError::Tor(format!("{} is not reachable: {}", host, reason)))
+        }
+        None => {
+            println!("  ✗ Tor reported no stream to {} within {} seconds", host, CONNECT_TIMEOUT.as_secs());
+            println!("  Is Tor routing active? Run `sudo torrer start` first");
+            Err(TorrerError::Tor(format!("{} did not answer", host)))
+        }
+    }
+}
+
+/// Follow the stream Tor opened for the connection from `source`
+///
+/// Returns the failure reason when it failed or closed, or `None` when it
+/// had no outcome by `deadline`.
+async fn stream_outcome(
+    events: &mut EventStream,
+    source: &str,
+    target: &str,
+    deadline: Instant,
+) -> Option<Result<(), String>> {
+    let mut stream_id: Option<String> = None;
+
+    loop {
+        let event = match tokio::time::timeout_at(deadline, events.next()).await {
+            Ok(Some(TorEvent::Stream(event))) => event,
+            Ok(Some(_)) => continue,
+            Ok(None) | Err(_) => return None,
+        };
+
+        let ours = match stream_id {
+            Some(ref id) => *id == event.id,
+            None => {
+                event.keywords.get("SOURCE_ADDR").map(String::as_str) == Some(source)
+                    || event.target == target
+            }
+        };
+        if !ours {
+            continue;
+        }
+
+        match event.status.as_str() {
+            "SUCCEEDED" => return Some(Ok(())),
+            "FAILED" | "CLOSED" => {
+                return Some(Err(event.reason().unwrap_or("unknown reason").to_string()))
+            }
+            _ => stream_id = Some(event.id),
+        }
+    }
+}
@@ -1,11 +1,16 @@
+use std::time::Duration;
+
 use crate::error::TorrerResult;
-use crate::tor::{TorClient, RelayManager};
+use crate::tor::{ExitVerifier, TorClient, RelayManager};
+
+/// How long `exit-relay` waits for a stream when none is open
+const EXIT_STREAM_WAIT: Duration = Duration::from_secs(10);
 
 /// Get relay information
 pub async fn get_relay_info(fingerprint: &str) -> TorrerResult<()> {
     println!("Fetching relay information for: {}", fingerprint);
 
-    let mut client = TorClient::new();
+    let mut client = TorClient::from_system_config();
     client.connect().await?;
     client.authenticate().await?;
 
@@ -37,7 +42,7 @@ pub async fn get_relay_info(fingerprint: &str) -> TorrerResult<()> {
 pub async fn get_exit_relay() -> TorrerResult<()> {
     println!("Fetching current exit relay information...");
 
-    let mut client = TorClient::new();
+    let mut client = TorClient::from_system_config();
     client.connect().await?;
     client.authenticate().await?;
 
@@ -51,16 +56,43 @@ pub async fn get_exit_relay() -> TorrerResult<()> {
             if let Some(ref country) = relay.country {
                 println!("  Country: {}", country);
             }
-            Ok(())
         }
         Ok(None) => {
             println!("No exit relay information available");
-            Ok(())
         }
         Err(e) => {
             println!("Failed to get exit relay: {}", e);
-            Err(e)
+            return Err(e);
+        }
+    }
+
+    verify_exit(&mut client).await
+}
+
+/// Check which exit a live stream used against the consensus
+async fn verify_exit(client: &mut TorClient) -> TorrerResult<()> {
+    let expected = ExitVerifier::expected_countries();
+
+    println!();
+    println!("Exit Verification:");
+    match ExitVerifier::verify(client, &expected, EXIT_STREAM_WAIT).await? {
+        Some(verification) => {
+            println!("  Stream: {} to {} (circuit {})", verification.stream_id, verification.target, verification.circuit_id);
+            println!("  Exit: {}", verification);
+            let problems = verification.problems();
+            if problems.is_empty() {
+                println!("  ✓ Exit matches the consensus");
+            } else {
+                for problem in problems {
+                    println!("  ✗ {}", problem);
+                }
+            }
+        }
+        None => {
+            println!("  No stream went out through an exit in {} seconds", EXIT_STREAM_WAIT.as_secs());
+            println!("  Open any connection through Tor and run this again");
         }
     }
+    Ok(())
 }
 
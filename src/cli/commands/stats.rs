@@ -1,27 +1,69 @@
 // Statistics command - Story 3.6 implementation
 use crate::error::TorrerResult;
 use crate::core::Monitoring;
-use crate::tor::TorClient;
+use crate::security::{DnsPaths, DnsStats};
+use crate::tor::EventKind;
+use super::connect_tor;
 use serde_json;
 use std::fs::File;
 use std::io::Write;
 
+/// Collect traffic counters and recent history from Tor
+async fn collect_stats() -> Monitoring {
+    let mut monitoring = Monitoring::new();
+
+    let synced = match connect_tor().await {
+        Ok(mut client) => monitoring.sync_with_tor(&mut client).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = synced {
+        log::warn!("Could not read traffic statistics from Tor: {}", e);
+    }
+
+    monitoring
+}
+
+/// Counters written by Torrer's DNS resolver, if it has run
+fn dns_stats() -> Option<DnsStats> {
+    match DnsStats::load(&DnsPaths::default().resolver_stats()) {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::warn!("Could not read DNS resolver statistics: {}", e);
+            None
+        }
+    }
+}
+
+/// Statistics and bandwidth history as JSON
+fn stats_json(monitoring: &Monitoring) -> serde_json::Value {
+    let stats = monitoring.get_stats();
+    let history = monitoring.history();
+
+    serde_json::json!({
+        "uptime_seconds": stats.uptime.map(|d| d.as_secs()),
+        "bytes_sent": stats.bytes_sent,
+        "bytes_received": stats.bytes_received,
+        "read_rate": stats.read_rate,
+        "write_rate": stats.write_rate,
+        "connection_attempts": stats.connection_attempts,
+        "successful_connections": stats.successful_connections,
+        "success_rate": stats.success_rate,
+        "history": {
+            "per_second": history.per_second().collect::<Vec<_>>(),
+            "per_minute": history.per_minute().collect::<Vec<_>>(),
+        },
+        "dns_resolver": dns_stats(),
+    })
+}
+
 /// Show detailed statistics
 pub async fn show_stats(format: Option<&str>) -> TorrerResult<()> {
-    let mut monitoring = Monitoring::new();
+    let monitoring = collect_stats().await;
     let stats = monitoring.get_stats();
     
     match format {
         Some("json") => {
-            let json = serde_json::json!({
-                "uptime_seconds": stats.uptime.map(|d| d.as_secs()),
-                "bytes_sent": stats.bytes_sent,
-                "bytes_received": stats.bytes_received,
-                "connection_attempts": stats.connection_attempts,
-                "successful_connections": stats.successful_connections,
-                "success_rate": stats.success_rate,
-            });
-            println!("{}", serde_json::to_string_pretty(&json)?);
+            println!("{}", serde_json::to_string_pretty(&stats_json(&monitoring))?);
         }
         Some("csv") => {
             println!("uptime_seconds,bytes_sent,bytes_received,connection_attempts,successful_connections,success_rate");
@@ -48,9 +90,22 @@ pub async fn show_stats(format: Option<&str>) -> TorrerResult<()> {
             }
             println!("Bytes sent: {}", format_bytes(stats.bytes_sent));
             println!("Bytes received: {}", format_bytes(stats.bytes_received));
+            println!("Current rate: {}/s down, {}/s up",
+                format_bytes(stats.read_rate),
+                format_bytes(stats.write_rate)
+            );
             println!("Connection attempts: {}", stats.connection_attempts);
             println!("Successful connections: {}", stats.successful_connections);
             println!("Success rate: {:.2}%", stats.success_rate);
+            if let Some(dns) = dns_stats() {
+                println!();
+                println!("DNS queries: {}", dns.queries);
+                println!("  Answered from cache: {} ({:.2}%)", dns.cache_hits, dns.cache_hit_rate());
+                println!("  Forwarded to Tor: {}", dns.forwarded);
+                println!("  Failed: {}", dns.failures);
+                println!("  Refused (unsupported type): {}", dns.unsupported);
+                println!("  Reverse lookups of virtual addresses: {}", dns.mapped_ptr);
+            }
         }
     }
     
@@ -59,21 +114,11 @@ pub async fn show_stats(format: Option<&str>) -> TorrerResult<()> {
 
 /// Export statistics to file
 pub async fn export_stats(path: &str, format: &str) -> TorrerResult<()> {
-    let mut monitoring = Monitoring::new();
+    let monitoring = collect_stats().await;
     let stats = monitoring.get_stats();
     
     let content = match format {
-        "json" => {
-            let json = serde_json::json!({
-                "uptime_seconds": stats.uptime.map(|d| d.as_secs()),
-                "bytes_sent": stats.bytes_sent,
-                "bytes_received": stats.bytes_received,
-                "connection_attempts": stats.connection_attempts,
-                "successful_connections": stats.successful_connections,
-                "success_rate": stats.success_rate,
-            });
-            serde_json::to_string_pretty(&json)?
-        }
+        "json" => serde_json::to_string_pretty(&stats_json(&monitoring))?,
         "csv" => {
             let mut csv = String::from("uptime_seconds,bytes_sent,bytes_received,connection_attempts,successful_connections,success_rate\n");
             let uptime_secs = stats.uptime.map(|d| d.as_secs()).unwrap_or(0);
@@ -103,17 +148,37 @@ pub async fn export_stats(path: &str, format: &str) -> TorrerResult<()> {
 
 /// Monitor statistics in real-time
 pub async fn monitor_stats(interval: u64) -> TorrerResult<()> {
-    use tokio::time::{sleep, Duration};
+    use tokio::time::{interval as tick_interval, Duration};
     use std::io::{self, Write};
     
     println!("Monitoring Torrer statistics (updating every {} seconds)...", interval);
     println!("Press Ctrl+C to stop");
     println!();
     
+    let mut client = connect_tor().await?;
     let mut monitoring = Monitoring::new();
-    monitoring.start();
+    monitoring.sync_with_tor(&mut client).await?;
+    let mut events = client
+        .subscribe(&[EventKind::Bw, EventKind::StreamBw, EventKind::Stream])
+        .await?;
+
+    let mut ticker = tick_interval(Duration::from_secs(interval.max(1)));
     
     loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    monitoring.handle_tor_event(&event);
+                    continue;
+                }
+                None => {
+                    println!("Tor closed the control connection");
+                    return Ok(());
+                }
+            },
+            _ = ticker.tick() => {}
+        }
+
         // Clear screen (ANSI escape code)
         print!("\x1B[2J\x1B[1;1H");
         io::stdout().flush()?;
@@ -130,16 +195,35 @@ pub async fn monitor_stats(interval: u64) -> TorrerResult<()> {
         }
         println!("Bytes sent: {}", format_bytes(stats.bytes_sent));
         println!("Bytes received: {}", format_bytes(stats.bytes_received));
-        println!("Connection attempts: {}", stats.connection_attempts);
-        println!("Successful connections: {}", stats.successful_connections);
-        println!("Success rate: {:.2}%", stats.success_rate);
+        println!("Current rate: {}/s down, {}/s up",
+            format_bytes(stats.read_rate),
+            format_bytes(stats.write_rate)
+        );
+        println!("Streams with traffic: {}", monitoring.stream_bandwidth().len());
+        println!("Last minute: {}", sparkline(&monitoring, 60));
         println!();
         println!("Press Ctrl+C to stop");
-        
-        sleep(Duration::from_secs(interval)).await;
     }
 }
 
+/// Render the last `seconds` of total traffic as a text sparkline
+fn sparkline(monitoring: &Monitoring, seconds: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let samples: Vec<u64> = monitoring
+        .history()
+        .per_second()
+        .map(|s| s.read + s.written)
+        .collect();
+    let recent = &samples[samples.len().saturating_sub(seconds)..];
+    let peak = recent.iter().copied().max().unwrap_or(0).max(1);
+
+    recent
+        .iter()
+        .map(|&v| BARS[(v * (BARS.len() as u64 - 1) / peak) as usize])
+        .collect()
+}
+
 fn format_bytes(bytes: u64) -> String {
     if bytes >= 1_000_000_000 {
         format!("{:.2} GB", bytes as f64 / 1_000_000_000.0)
@@ -84,7 +84,7 @@ async fn test_tor_daemon() -> bool {
 
 async fn test_tor_control() -> bool {
     use crate::tor::TorClient;
-    let mut client = TorClient::new();
+    let mut client = TorClient::from_system_config();
     client.connect().await.is_ok()
 }
 
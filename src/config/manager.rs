@@ -126,8 +126,42 @@ impl ConfigManager {
         existing_config.tor_control_port = imported_config.tor_control_port;
         existing_config.tor_transport_port = imported_config.tor_transport_port;
         existing_config.tor_dns_port = imported_config.tor_dns_port;
+        existing_config.dns_resolver = imported_config.dns_resolver;
+        existing_config.firewall_backend = imported_config.firewall_backend;
+        existing_config.kill_switch = imported_config.kill_switch;
+        if !imported_config.lan_ranges.is_empty() {
+            existing_config.lan_ranges = imported_config.lan_ranges;
+        }
+        if !imported_config.bypass.is_empty() {
+            existing_config.bypass = imported_config.bypass;
+        }
+        if imported_config.gateway.is_some() {
+            existing_config.gateway = imported_config.gateway;
+        }
+        if !imported_config.onion.is_default() {
+            existing_config.onion = imported_config.onion;
+        }
+        if !imported_config.udp.is_empty() {
+            existing_config.udp = imported_config.udp;
+        }
         existing_config.ipv6_enabled = imported_config.ipv6_enabled;
         existing_config.auto_fallback = imported_config.auto_fallback;
+        existing_config.tor_managed = imported_config.tor_managed;
+        if imported_config.tor_control_endpoint.is_some() {
+            existing_config.tor_control_endpoint = imported_config.tor_control_endpoint;
+        }
+        if imported_config.tor_control_password.is_some() {
+            existing_config.tor_control_password = imported_config.tor_control_password;
+        }
+        if imported_config.tor_binary.is_some() {
+            existing_config.tor_binary = imported_config.tor_binary;
+        }
+        if imported_config.tor_data_directory.is_some() {
+            existing_config.tor_data_directory = imported_config.tor_data_directory;
+        }
+        if !imported_config.transport_plugins.is_empty() {
+            existing_config.transport_plugins = imported_config.transport_plugins;
+        }
         if imported_config.country_code.is_some() {
             existing_config.country_code = imported_config.country_code;
         }
@@ -221,9 +255,28 @@ impl ConfigManager {
         // Show current configuration
         println!("Current configuration:");
         println!("  Tor Control Port: {}", config.tor_control_port);
+        if let Some(ref endpoint) = config.tor_control_endpoint {
+            println!("  Tor Control Endpoint: {}", endpoint);
+        }
+        if config.tor_managed {
+            println!("  Managed Tor: {}", config.tor_data_directory().display());
+        }
         println!("  Tor Transport Port: {}", config.tor_transport_port);
         println!("  Tor DNS Port: {}", config.tor_dns_port);
-        println!("  IPv6 Enabled: {}", config.ipv6_enabled);
+        println!("  DNS Resolver: {}", config.dns_resolver);
+        println!("  Firewall Backend: {}", config.firewall_backend);
+        println!("  Kill Switch: {}", config.kill_switch);
+        if !config.bypass.is_empty() {
+            println!("  Split Tunneling: enabled (see [bypass] in the config file)");
+        }
+        if let Some(ref gateway) = config.gateway {
+            println!("  Gateway Interface: {}", gateway.interface);
+        }
+        println!("  UDP Policy: {}", config.udp.policy.as_str());
+        if config.onion.automap {
+            println!("  Onion Virtual Networks: {}", config.onion.virtual_networks().join(", "));
+        }
+        println!("  IPv6 Routed: {}", config.ipv6_enabled);
         println!("  Auto Fallback: {}", config.auto_fallback);
         if let Some(ref country) = config.country_code {
             println!("  Exit Country: {}", country);
@@ -284,7 +337,7 @@ impl ConfigManager {
         }
 
         // IPv6 configuration
-        print!("Enable IPv6? [{}] (y/N): ", if config.ipv6_enabled { "Y" } else { "N" });
+        print!("Route IPv6 through Tor? [{}] (y/N): ", if config.ipv6_enabled { "Y" } else { "N" });
         io::stdout().flush()?;
         input.clear();
         io::stdin().read_line(&mut input)?;
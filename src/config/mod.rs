@@ -6,7 +6,7 @@ pub mod migration;
 pub mod schema;
 
 pub use manager::ConfigManager;
-pub use types::Configuration;
+pub use types::{BypassConfig, Configuration, GatewayConfig, OnionConfig, UdpConfig, UdpPolicy};
 pub use validator::validate_config;
 pub use defaults::Defaults;
 pub use migration::ConfigMigration;
@@ -54,7 +54,7 @@ impl ConfigSchema {
                     r#type: "boolean".to_string(),
                     required: false,
                     default: Some(serde_json::Value::Bool(false)),
-                    description: "Enable IPv6".to_string(),
+                    description: "Route IPv6 through Tor instead of blocking it".to_string(),
                 },
                 auto_fallback: FieldSchema {
                     r#type: "boolean".to_string(),
@@ -1,11 +1,52 @@
 use serde::{Deserialize, Serialize};
 
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::iptables::{FirewallBackendKind, RoutingPorts};
+use crate::tor::ControlEndpoint;
+use crate::tor::process::{
+    CONTROL_SOCKET_NAME, DEFAULT_DATA_DIRECTORY, DEFAULT_TOR_BINARY, DEFAULT_TRANSPORT_PLUGINS,
+};
+
 /// Torrer configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Configuration {
     pub tor_control_port: u16,
+    /// Control endpoint overriding `tor_control_port` (e.g. "unix:/run/tor/control")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tor_control_endpoint: Option<ControlEndpoint>,
+    /// Password for Tor's HashedControlPassword authentication
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tor_control_password: Option<String>,
+    /// Launch and own a private tor process instead of using the system daemon
+    #[serde(default)]
+    pub tor_managed: bool,
+    /// tor executable used in managed mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tor_binary: Option<PathBuf>,
+    /// DataDirectory of the managed tor process
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tor_data_directory: Option<PathBuf>,
+    /// Pluggable transport clients by transport name, used for bridges in
+    /// managed mode, e.g. obfs4 = "/usr/bin/lyrebird"
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub transport_plugins: BTreeMap<String, PathBuf>,
     pub tor_transport_port: u16,
     pub tor_dns_port: u16,
+    /// Answer DNS on 127.0.0.1:53 with Torrer's caching resolver, which
+    /// forwards to tor_dns_port and refuses record types Tor cannot resolve
+    #[serde(default)]
+    pub dns_resolver: bool,
+    /// Packet filter used for routing: auto, iptables-legacy, iptables-nft or nftables
+    #[serde(default)]
+    pub firewall_backend: FirewallBackendKind,
+    /// Drop all traffic that does not go through Tor, even if Tor or `torrer stop` fails
+    #[serde(default)]
+    pub kill_switch: bool,
+    /// Ranges (CIDR) the kill switch lets through, e.g. "192.168.0.0/16"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lan_ranges: Vec<String>,
     pub ipv6_enabled: bool,
     pub auto_fallback: bool,
     pub country_code: Option<String>,
@@ -13,6 +54,163 @@ pub struct Configuration {
     pub auto_collect_bridges: bool,
     #[serde(default = "default_bridge_collection_interval")]
     pub bridge_collection_interval_days: u32,
+    /// Traffic that goes direct instead of through Tor
+    #[serde(default, skip_serializing_if = "BypassConfig::is_empty")]
+    pub bypass: BypassConfig,
+    /// Also route clients on a LAN interface through Tor (gateway mode)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway: Option<GatewayConfig>,
+    /// What happens to UDP other than DNS while routing through Tor
+    #[serde(default, skip_serializing_if = "UdpConfig::is_empty")]
+    pub udp: UdpConfig,
+    /// How `.onion` names are reached through transparent routing
+    #[serde(default, skip_serializing_if = "OnionConfig::is_default")]
+    pub onion: OnionConfig,
+}
+
+/// Handling of UDP other than DNS, which Tor cannot carry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UdpPolicy {
+    /// Let it go out directly, outside Tor
+    #[default]
+    Allow,
+    /// Drop it silently
+    Drop,
+    /// Reject it with ICMP port unreachable, so programs (such as browsers
+    /// trying QUIC) fall back to TCP at once
+    Reject,
+}
+
+impl UdpPolicy {
+    /// Name used in the configuration file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Drop => "drop",
+            Self::Reject => "reject",
+        }
+    }
+}
+
+/// UDP policy and its exceptions
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UdpConfig {
+    #[serde(default)]
+    pub policy: UdpPolicy,
+    /// Destinations still reached over UDP with `drop` or `reject`, as
+    /// "port" or "host:port", e.g. "123" or "pool.ntp.org:123"; hosts may be
+    /// addresses, ranges (CIDR) or names resolved when routing starts, and
+    /// IPv6 ones are written in brackets
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+}
+
+impl UdpConfig {
+    /// Whether the default policy applies, without exceptions
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Reaching onion services through transparent routing
+///
+/// Tor answers lookups of `.onion` (and `.exit`) names with addresses from
+/// the virtual networks and remembers the mapping; TCP to those addresses is
+/// always redirected to Tor, which connects to the mapped name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OnionConfig {
+    /// Map names to virtual addresses (AutomapHostsOnResolve)
+    #[serde(default = "default_onion_automap")]
+    pub automap: bool,
+    /// Range (CIDR) IPv4 addresses are handed out from (VirtualAddrNetworkIPv4)
+    #[serde(default = "default_virtual_network_ipv4")]
+    pub virtual_network_ipv4: String,
+    /// Range (CIDR) IPv6 addresses are handed out from (VirtualAddrNetworkIPv6)
+    #[serde(default = "default_virtual_network_ipv6")]
+    pub virtual_network_ipv6: String,
+}
+
+impl Default for OnionConfig {
+    fn default() -> Self {
+        Self {
+            automap: default_onion_automap(),
+            virtual_network_ipv4: default_virtual_network_ipv4(),
+            virtual_network_ipv6: default_virtual_network_ipv6(),
+        }
+    }
+}
+
+impl OnionConfig {
+    /// Whether the defaults apply
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The virtual networks, IPv4 first
+    pub fn virtual_networks(&self) -> [&str; 2] {
+        [&self.virtual_network_ipv4, &self.virtual_network_ipv6]
+    }
+}
+
+/// Gateway mode: Torrer as a Tor middlebox for a LAN
+///
+/// TCP and DNS from clients on `interface` are redirected to Tor, which also
+/// listens on the interface's address; all other forwarding to or from the
+/// interface is dropped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayConfig {
+    /// LAN interface the clients are on, e.g. "eth1"
+    pub interface: String,
+    /// Client ranges (CIDR) served; empty serves everyone on the interface
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<String>,
+}
+
+/// Split tunneling: traffic that bypasses Tor
+///
+/// Matching traffic is neither redirected nor blocked, including by the kill
+/// switch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BypassConfig {
+    /// Reach private networks (RFC 1918, IPv6 ULA and link-local) directly
+    #[serde(default)]
+    pub local_networks: bool,
+    /// Destination ranges (CIDR) reached directly
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub networks: Vec<String>,
+    /// Destination host names, resolved when routing starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    /// Users (names or UIDs) whose traffic goes direct
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    /// Groups (names or GIDs) whose traffic goes direct
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    /// cgroup v2 paths (systemd slices and units) whose traffic goes direct,
+    /// e.g. "system.slice/backup.service"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cgroups: Vec<String>,
+}
+
+impl BypassConfig {
+    /// Whether nothing bypasses Tor
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn default_onion_automap() -> bool {
+    true
+}
+
+fn default_virtual_network_ipv4() -> String {
+    "10.192.0.0/10".to_string()
+}
+
+fn default_virtual_network_ipv6() -> String {
+    "fc00::/7".to_string()
 }
 
 fn default_auto_collect_bridges() -> bool {
@@ -27,14 +225,78 @@ impl Default for Configuration {
     fn default() -> Self {
         Self {
             tor_control_port: 9051,
+            tor_control_endpoint: None,
+            tor_control_password: None,
+            tor_managed: false,
+            tor_binary: None,
+            tor_data_directory: None,
+            transport_plugins: BTreeMap::new(),
             tor_transport_port: 9040,
             tor_dns_port: 5353,
+            dns_resolver: false,
+            firewall_backend: FirewallBackendKind::Auto,
+            kill_switch: false,
+            lan_ranges: Vec::new(),
             ipv6_enabled: false,
             auto_fallback: true,
             country_code: None,
             auto_collect_bridges: true,
             bridge_collection_interval_days: 7,
+            bypass: BypassConfig::default(),
+            gateway: None,
+            udp: UdpConfig::default(),
+            onion: OnionConfig::default(),
         }
     }
 }
 
+
+impl Configuration {
+    /// Endpoint used to reach Tor's control port
+    ///
+    /// In managed mode this is the ControlSocket inside the data directory.
+    pub fn control_endpoint(&self) -> ControlEndpoint {
+        if let Some(ref endpoint) = self.tor_control_endpoint {
+            return endpoint.clone();
+        }
+
+        if self.tor_managed {
+            ControlEndpoint::Unix(self.tor_data_directory().join(CONTROL_SOCKET_NAME))
+        } else {
+            ControlEndpoint::localhost(self.tor_control_port)
+        }
+    }
+
+    /// Ports Tor listens on for redirected traffic
+    pub fn routing_ports(&self) -> RoutingPorts {
+        RoutingPorts {
+            trans_port: self.tor_transport_port,
+            dns_port: self.tor_dns_port,
+        }
+    }
+
+    /// tor executable used in managed mode
+    pub fn tor_binary(&self) -> PathBuf {
+        self.tor_binary
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_TOR_BINARY))
+    }
+
+    /// Client executable for a pluggable transport, if one is known
+    pub fn transport_plugin(&self, transport: &str) -> Option<PathBuf> {
+        match self.transport_plugins.get(transport) {
+            Some(path) => Some(path.clone()),
+            None => DEFAULT_TRANSPORT_PLUGINS
+                .iter()
+                .find(|(name, _)| *name == transport)
+                .map(|(_, path)| PathBuf::from(path)),
+        }
+    }
+
+    /// DataDirectory of the managed tor process
+    pub fn tor_data_directory(&self) -> PathBuf {
+        self.tor_data_directory
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_DATA_DIRECTORY))
+    }
+}
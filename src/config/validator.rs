@@ -1,5 +1,6 @@
 use crate::error::{TorrerError, TorrerResult};
 use crate::config::Configuration;
+use crate::utils::{parse_cidr, split_host_port};
 
 /// Validate configuration values
 pub fn validate_config(config: &Configuration) -> TorrerResult<()> {
@@ -37,6 +38,67 @@ pub fn validate_config(config: &Configuration) -> TorrerResult<()> {
         }
     }
 
+    // Validate kill switch exceptions
+    for range in &config.lan_ranges {
+        parse_cidr(range)?;
+    }
+
+    // Validate split tunneling entries
+    for range in &config.bypass.networks {
+        parse_cidr(range)?;
+    }
+    for cgroup in &config.bypass.cgroups {
+        if cgroup.is_empty() || cgroup.contains(char::is_whitespace) || cgroup.contains('"') {
+            return Err(TorrerError::Config(format!("Invalid cgroup path: {:?}", cgroup)));
+        }
+    }
+    for name in config.bypass.users.iter().chain(&config.bypass.groups).chain(&config.bypass.domains) {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(TorrerError::Config(format!("Invalid bypass entry: {:?}", name)));
+        }
+    }
+
+    // Validate UDP exceptions
+    for entry in &config.udp.allow {
+        match split_host_port(entry)? {
+            // IPv6, ranges and dotted digits are addresses, anything else a name
+            (Some(host), _) if host.contains([':', '/']) || host.chars().all(|c| c.is_ascii_digit() || c == '.') => {
+                parse_cidr(host)?;
+            }
+            (Some(host), _) if host.contains(|c: char| c.is_whitespace() || c == '"') => {
+                return Err(TorrerError::Config(format!("Invalid UDP exception: {:?}", entry)));
+            }
+            _ => {}
+        }
+    }
+
+    // Validate the onion virtual networks
+    let onion = &config.onion;
+    let (ipv4, _) = parse_cidr(&onion.virtual_network_ipv4)?;
+    let (ipv6, _) = parse_cidr(&onion.virtual_network_ipv6)?;
+    if !ipv4.is_ipv4() || !ipv6.is_ipv6() {
+        return Err(TorrerError::Config(format!(
+            "Invalid onion virtual networks: {} and {}",
+            onion.virtual_network_ipv4, onion.virtual_network_ipv6
+        )));
+    }
+
+    // Validate gateway mode
+    if let Some(ref gateway) = config.gateway {
+        let interface = &gateway.interface;
+        if interface.is_empty() || interface.len() > 15 || interface.contains(|c: char| c.is_whitespace() || c == '/' || c == '"') {
+            return Err(TorrerError::Config(format!("Invalid gateway interface: {:?}", interface)));
+        }
+        for range in &gateway.clients {
+            if !parse_cidr(range)?.0.is_ipv4() {
+                return Err(TorrerError::Config(format!(
+                    "Gateway clients must be IPv4 ranges: {}",
+                    range
+                )));
+            }
+        }
+    }
+
     Ok(())
 }
 
@@ -1,26 +1,61 @@
 use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{mpsc, Arc};
+use tokio::task::JoinHandle;
+use crate::config::{ConfigManager, Configuration};
+use crate::core::events::{Event, EventManager};
 use crate::error::{TorrerError, TorrerResult};
-use crate::iptables::IptablesManager;
-use crate::security::{DnsManager, Ipv6Manager};
-use crate::tor::TorClient;
+use crate::iptables::{create_backend, FirewallBackend, RoutingPorts, RoutingRules};
+use crate::security::dns_server::DEFAULT_VIRTUAL_NETWORKS;
+use crate::security::{DnsManager, DnsPaths, DnsResolverHandle, Ipv6Manager};
+use crate::tor::{
+    BootstrapStatus, BootstrapTracker, EventKind, EventStream, ManagedTorConfig, TorClient,
+    TorProcess,
+};
+use crate::utils::interface_ipv4;
 
 /// Core Torrer engine
 pub struct TorrerEngine {
-    iptables: IptablesManager,
+    firewall: Arc<dyn FirewallBackend>,
     dns: DnsManager,
     ipv6: Ipv6Manager,
     tor_client: Option<TorClient>,
+    tor_process: Option<TorProcess>,
+    events: EventManager,
+    event_task: Option<JoinHandle<()>>,
+    bootstrap: BootstrapTracker,
+    /// Ports Tor was given listeners besides 127.0.0.1 on (IPv6, gateway mode)
+    listener_ports: Option<RoutingPorts>,
+    /// Torrer's caching DNS resolver, if enabled
+    dns_resolver: Option<DnsResolverHandle>,
+    /// Whether the system tor was told to map onion names to virtual addresses
+    automapped: bool,
     is_running: bool,
 }
 
 impl TorrerEngine {
     /// Create a new TorrerEngine
     pub fn new() -> TorrerResult<Self> {
+        let config = ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .unwrap_or_else(|e| {
+                log::debug!("Using default configuration: {}", e);
+                Configuration::default()
+            });
+        let firewall = create_backend(config.firewall_backend, config.routing_ports())?;
+
         Ok(Self {
-            iptables: IptablesManager::new()?,
-            dns: DnsManager::new(),
-            ipv6: Ipv6Manager::new(false), // IPv6 disabled by default
+            dns: DnsManager::new(firewall.clone(), config.tor_dns_port),
+            ipv6: Ipv6Manager::new(false, firewall.clone()), // IPv6 disabled by default
+            firewall,
             tor_client: None,
+            tor_process: None,
+            events: EventManager::new(),
+            event_task: None,
+            bootstrap: BootstrapTracker::new(),
+            listener_ports: None,
+            dns_resolver: None,
+            automapped: false,
             is_running: false,
         })
     }
@@ -33,31 +68,160 @@ impl TorrerEngine {
 
         log::info!("Starting Tor routing...");
 
+        let config = ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .unwrap_or_else(|e| {
+                log::debug!("Using default configuration: {}", e);
+                Configuration::default()
+            });
+
+        // In managed mode Torrer runs its own tor instead of the system daemon
+        if config.tor_managed {
+            let managed = ManagedTorConfig::from_config(&config)?;
+            self.tor_process = Some(TorProcess::launch(managed).await?);
+        }
+
         // Check if Tor daemon is running
-        let mut tor_client = TorClient::new();
-        tor_client.connect().await?;
-        tor_client.authenticate().await?;
+        let mut tor_client = TorClient::from_config(&config);
+        let connected = async {
+            tor_client.connect().await?;
+            tor_client.authenticate().await
+        }
+        .await;
+        if let Err(e) = connected {
+            self.stop_tor_process().await;
+            return Err(e);
+        }
 
-        // Backup iptables rules
-        self.iptables.backup()?;
+        // Tor listens on loopback; routing IPv6 adds ::1, and gateway mode the
+        // LAN address. Redirected LAN packets are delivered locally, so IP
+        // forwarding stays off and nothing from the LAN can be forwarded
+        // around Tor.
+        let mut listeners = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+        if config.ipv6_enabled {
+            listeners.push(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        }
+        if let Some(ref gateway) = config.gateway {
+            match interface_ipv4(&gateway.interface) {
+                Some(address) => {
+                    log::info!("Serving LAN clients on {} ({})", gateway.interface, address);
+                    listeners.push(IpAddr::V4(address));
+                }
+                None => {
+                    self.stop_tor_process().await;
+                    return Err(TorrerError::Config(format!(
+                        "Gateway interface {} has no IPv4 address",
+                        gateway.interface
+                    )));
+                }
+            }
+        }
+        if listeners.len() > 1 {
+            let ports = config.routing_ports();
+            if let Err(e) = tor_client.set_listeners(&listeners, ports, config.ipv6_enabled).await {
+                self.stop_tor_process().await;
+                return Err(e);
+            }
+            self.listener_ports = Some(ports);
+        }
 
-        // Apply Tor routing rules
-        self.iptables.apply_tor_routing()?;
+        // Onion names resolve to virtual addresses, which are redirected to
+        // Tor; a managed tor already has this in its torrc
+        if config.onion.automap && !config.tor_managed {
+            let [ipv4, ipv6] = config.onion.virtual_networks();
+            match tor_client.set_automap(ipv4, ipv6).await {
+                Ok(()) => self.automapped = true,
+                Err(e) => log::warn!("Failed to enable .onion address mapping; onion services will be unreachable: {}", e),
+            }
+        }
 
-        // Configure DNS leak prevention
-        self.dns.configure_dns()?;
+        // The resolver must be listening before DNS is redirected to it
+        let mut rules = RoutingRules::planned(&config);
+        if config.dns_resolver {
+            let mut addresses = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+            if config.ipv6_enabled {
+                addresses.push(IpAddr::V6(Ipv6Addr::LOCALHOST));
+            }
+            let virtual_networks = if config.onion.automap {
+                config.onion.virtual_networks()
+            } else {
+                DEFAULT_VIRTUAL_NETWORKS
+            };
+            let stats = DnsPaths::default().resolver_stats();
+            match DnsResolverHandle::spawn(&addresses, config.tor_dns_port, &virtual_networks, stats).await {
+                Ok(resolver) => self.dns_resolver = Some(resolver),
+                Err(e) => {
+                    log::warn!("{}; sending DNS straight to Tor's DNSPort", e);
+                    rules.dns_resolver = false;
+                }
+            }
+        }
+        self.dns.set_dns_port(rules.local_dns_port());
 
-        // Disable IPv6 (prevent leaks)
-        self.ipv6.set_enabled(false)?;
+        // Backup firewall rules, which a failed apply rolls back to
+        if let Err(e) = self.firewall.backup() {
+            self.abort_start(&mut tor_client).await;
+            return Err(e);
+        }
+
+        // Kill switch, Tor routing, DNS leak prevention and IPv6 routing (or
+        // the IPv6 block) go in as one verified transaction
+        if let Err(e) = self.firewall.apply(&rules) {
+            self.abort_start(&mut tor_client).await;
+            return Err(e);
+        }
+
+        // The redirect already catches queries; pointing the resolver at Tor
+        // as well keeps systemd-resolved and NetworkManager from working around it
+        if let Err(e) = self.dns.take_over() {
+            log::warn!("DNS stays on the firewall redirect alone: {}", e);
+        }
 
         // Verify connection
-        let status = tor_client.get_status().await?;
-        if !status.circuit_established {
-            log::warn!("Tor circuit not yet established, but routing is active");
+        let status = match tor_client.get_status().await {
+            Ok(status) => status,
+            Err(e) => {
+                if let Err(e) = self.dns.release() {
+                    log::error!("Failed to restore the system resolver: {}", e);
+                }
+                if let Err(e) = self.firewall.restore() {
+                    log::error!("Failed to restore firewall rules: {}", e);
+                }
+                self.abort_start(&mut tor_client).await;
+                return Err(e);
+            }
+        };
+        self.bootstrap.reset();
+        match self.bootstrap.refresh(&mut tor_client).await {
+            Ok(phase) if !phase.is_done() => log::warn!(
+                "Tor is still bootstrapping ({}%: {}), but routing is active",
+                phase.progress,
+                phase.summary
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                log::debug!("Failed to read Tor bootstrap phase: {}", e);
+                if !status.circuit_established {
+                    log::warn!("Tor circuit not yet established, but routing is active");
+                }
+            }
+        }
+
+        // Follow circuit builds/failures and bootstrap progress as Tor reports them
+        match tor_client.subscribe(&EventKind::DEFAULT).await {
+            Ok(stream) => {
+                self.event_task = Some(tokio::spawn(Self::forward_events(
+                    stream,
+                    self.events.sender(),
+                    self.bootstrap.clone(),
+                )));
+            }
+            Err(e) => log::warn!("Failed to subscribe to Tor events: {}", e),
         }
 
         self.tor_client = Some(tor_client);
         self.is_running = true;
+        let _ = self.events.emit(Event::RoutingStarted);
 
         log::info!("Tor routing started successfully");
         Ok(())
@@ -66,6 +230,27 @@ impl TorrerEngine {
     /// Stop Tor routing
     pub async fn stop(&mut self) -> TorrerResult<()> {
         if !self.is_running {
+            // A kill switch left by a failed stop (or another process) blocks all traffic
+            if self.firewall.kill_switch_active() {
+                log::warn!("Removing kill switch and firewall rules left by an earlier run");
+                self.firewall.restore()?;
+                self.dns.release()?;
+                self.reset_tor_settings().await;
+                return self.firewall.disable_kill_switch();
+            }
+            // Routing started by another process, usually an earlier `torrer start`
+            if self.firewall.routing_active() {
+                log::info!("Removing Tor routing rules left by an earlier run");
+                self.firewall.restore()?;
+                self.dns.release()?;
+                self.reset_tor_settings().await;
+                return Ok(());
+            }
+            // As is a resolver takeover, which would otherwise leave DNS pointing at Tor
+            if self.dns.is_taken_over() {
+                log::warn!("Restoring the system resolver left by an earlier run");
+                return self.dns.release();
+            }
             log::warn!("Tor routing is not running, nothing to stop");
             return Ok(()); // Not an error if already stopped
         }
@@ -77,7 +262,7 @@ impl TorrerEngine {
 
         // Step 1: Remove Tor routing rules
         log::debug!("Removing Tor routing rules...");
-        if let Err(e) = self.iptables.remove_tor_routing() {
+        if let Err(e) = self.firewall.remove_tor_routing() {
             log::error!("Failed to remove Tor routing rules: {}", e);
             errors.push(format!("Failed to remove routing rules: {}", e));
         } else {
@@ -93,23 +278,49 @@ impl TorrerEngine {
             log::debug!("DNS configuration removed");
         }
 
-        // Step 3: Restore iptables rules
-        log::debug!("Restoring iptables rules...");
-        if let Err(e) = self.iptables.restore() {
-            log::error!("Failed to restore iptables rules: {}", e);
-            errors.push(format!("Failed to restore iptables: {}", e));
+        // Step 3: Remove the IPv6 block or redirect
+        if let Err(e) = self.ipv6.set_enabled(true).and_then(|_| self.ipv6.set_routed(false)) {
+            log::error!("Failed to remove IPv6 rules: {}", e);
+            errors.push(format!("Failed to remove IPv6 rules: {}", e));
+        }
+
+        // Step 4: Restore firewall rules
+        log::debug!("Restoring firewall rules...");
+        if let Err(e) = self.firewall.restore() {
+            log::error!("Failed to restore firewall rules: {}", e);
+            errors.push(format!("Failed to restore firewall: {}", e));
             // This is critical - try to restore anyway
-            log::warn!("Attempting emergency iptables restoration...");
-            if let Err(e2) = self.iptables.restore() {
+            log::warn!("Attempting emergency firewall restoration...");
+            if let Err(e2) = self.firewall.restore() {
                 log::error!("Emergency restoration also failed: {}", e2);
             }
         } else {
-            log::debug!("Iptables rules restored");
+            log::debug!("Firewall rules restored");
         }
 
-        // Step 4: Update state
-        self.tor_client = None;
+        // Step 5: Update state
+        if let Some(task) = self.event_task.take() {
+            task.abort();
+        }
+        if let Some(mut tor_client) = self.tor_client.take() {
+            self.reset_automap(&mut tor_client).await;
+            Self::close_extra_listeners(&mut tor_client, self.listener_ports.take()).await;
+        }
+        self.stop_dns_resolver();
+        self.stop_tor_process().await;
+        self.bootstrap.reset();
         self.is_running = false;
+        let _ = self.events.emit(Event::RoutingStopped);
+
+        // Step 6: Lift the kill switch only once everything else is cleaned up
+        if errors.is_empty() {
+            if let Err(e) = self.firewall.disable_kill_switch() {
+                log::error!("Failed to disable kill switch: {}", e);
+                errors.push(format!("Failed to disable kill switch: {}", e));
+            }
+        } else if self.firewall.kill_switch_active() {
+            log::warn!("Kill switch left enabled; run 'torrer stop' again to remove it");
+        }
 
         // Update state manager
         let state_manager = crate::core::state::StateManager::new();
@@ -129,6 +340,122 @@ impl TorrerEngine {
         }
     }
 
+    /// Whether Torrer launched and owns the tor process
+    pub fn is_managed(&self) -> bool {
+        self.tor_process.is_some()
+    }
+
+    /// Whether Torrer's DNS resolver is answering queries
+    ///
+    /// Like a managed tor, it stops with the process that started routing.
+    pub fn is_resolving(&self) -> bool {
+        self.dns_resolver.is_some()
+    }
+
+    /// Stop Torrer's DNS resolver, if running
+    fn stop_dns_resolver(&mut self) {
+        if let Some(resolver) = self.dns_resolver.take() {
+            resolver.stop();
+        }
+    }
+
+    /// Undo what a failed start did to Tor and the resolver
+    async fn abort_start(&mut self, tor_client: &mut TorClient) {
+        self.stop_dns_resolver();
+        self.reset_automap(tor_client).await;
+        Self::close_extra_listeners(tor_client, self.listener_ports.take()).await;
+        self.stop_tor_process().await;
+    }
+
+    /// Undo `set_automap` on the system tor
+    async fn reset_automap(&mut self, tor_client: &mut TorClient) {
+        if std::mem::take(&mut self.automapped) {
+            if let Err(e) = tor_client.reset_automap().await {
+                log::warn!("Failed to reset Tor's .onion address mapping: {}", e);
+            }
+        }
+    }
+
+    /// Undo the automap and listener settings an earlier run gave the system tor
+    ///
+    /// A fresh process does not know what that run changed, so this goes by
+    /// the configuration it started from. A managed tor went with its process.
+    async fn reset_tor_settings(&mut self) {
+        let config = ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .unwrap_or_else(|e| {
+                log::debug!("Using default configuration: {}", e);
+                Configuration::default()
+            });
+        let automapped = config.onion.automap && !config.tor_managed;
+        let ports = if !config.tor_managed && (config.ipv6_enabled || config.gateway.is_some()) {
+            Some(config.routing_ports())
+        } else {
+            None
+        };
+        if !automapped && ports.is_none() {
+            return;
+        }
+
+        let mut tor_client = TorClient::from_config(&config);
+        let connected = async {
+            tor_client.connect().await?;
+            tor_client.authenticate().await
+        }
+        .await;
+        if let Err(e) = connected {
+            log::warn!("Failed to reach Tor to reset its listeners and .onion mapping: {}", e);
+            return;
+        }
+        self.automapped = automapped;
+        self.reset_automap(&mut tor_client).await;
+        Self::close_extra_listeners(&mut tor_client, ports).await;
+    }
+
+    /// Put Tor's listeners back on 127.0.0.1 only
+    async fn close_extra_listeners(tor_client: &mut TorClient, ports: Option<RoutingPorts>) {
+        if let Some(ports) = ports {
+            let closed = tor_client
+                .set_listeners(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], ports, false)
+                .await;
+            if let Err(e) = closed {
+                log::warn!("Failed to close Tor's extra listeners: {}", e);
+            }
+        }
+    }
+
+    /// Shut down the managed tor process, if any
+    async fn stop_tor_process(&mut self) {
+        if let Some(mut process) = self.tor_process.take() {
+            if let Err(e) = process.stop().await {
+                log::error!("Failed to stop managed tor: {}", e);
+            }
+        }
+    }
+
+    /// Events emitted by the engine, including those reported by Tor
+    pub fn events(&self) -> &EventManager {
+        &self.events
+    }
+
+    /// Forward Tor control-port events into the engine's event channel
+    async fn forward_events(
+        mut stream: EventStream,
+        sender: mpsc::SyncSender<Event>,
+        bootstrap: BootstrapTracker,
+    ) {
+        while let Some(tor_event) = stream.next().await {
+            bootstrap.handle_event(&tor_event);
+            if let Some(event) = Event::from_tor_event(&tor_event) {
+                // Nobody is reading when the queue is full; drop the event
+                if let Err(mpsc::TrySendError::Disconnected(_)) = sender.try_send(event) {
+                    break;
+                }
+            }
+        }
+        log::debug!("Tor event stream ended");
+    }
+
     /// Stop Tor routing with cleanup verification
     pub async fn stop_with_verification(&mut self) -> TorrerResult<()> {
         self.stop().await?;
@@ -150,16 +477,27 @@ impl TorrerEngine {
                 is_running: false,
                 tor_connected: false,
                 circuit_established: false,
+                bootstrap: None,
+                kill_switch: self.firewall.kill_switch_active(),
             });
         }
 
         if let Some(ref mut tor_client) = self.tor_client {
+            // Events keep the tracker current; poll until it has seen a phase
+            if self.bootstrap.status().is_none_or(|status| !status.phase.is_done()) {
+                if let Err(e) = self.bootstrap.refresh(tor_client).await {
+                    log::debug!("Failed to read Tor bootstrap phase: {}", e);
+                }
+            }
+
             match tor_client.get_status().await {
                 Ok(tor_status) => {
                     Ok(EngineStatus {
                         is_running: true,
                         tor_connected: tor_status.is_connected,
                         circuit_established: tor_status.circuit_established,
+                        bootstrap: self.bootstrap.status(),
+                        kill_switch: self.firewall.kill_switch_active(),
                     })
                 }
                 Err(e) => {
@@ -168,6 +506,8 @@ impl TorrerEngine {
                         is_running: true,
                         tor_connected: false,
                         circuit_established: false,
+                        bootstrap: self.bootstrap.status(),
+                        kill_switch: self.firewall.kill_switch_active(),
                     })
                 }
             }
@@ -176,6 +516,8 @@ impl TorrerEngine {
                 is_running: true,
                 tor_connected: false,
                 circuit_established: false,
+                bootstrap: None,
+                kill_switch: self.firewall.kill_switch_active(),
             })
         }
     }
@@ -261,6 +603,10 @@ pub struct EngineStatus {
     pub is_running: bool,
     pub tor_connected: bool,
     pub circuit_established: bool,
+    /// Tor's bootstrap progress, when connected
+    pub bootstrap: Option<BootstrapStatus>,
+    /// Whether the kill switch is blocking non-Tor traffic
+    pub kill_switch: bool,
 }
 
 impl fmt::Display for EngineStatus {
@@ -269,7 +615,14 @@ impl fmt::Display for EngineStatus {
             f,
             "Running: {}, Tor Connected: {}, Circuit Established: {}",
             self.is_running, self.tor_connected, self.circuit_established
-        )
+        )?;
+        if let Some(ref bootstrap) = self.bootstrap {
+            write!(f, ", Bootstrap: {}%", bootstrap.phase.progress)?;
+        }
+        if self.kill_switch {
+            write!(f, ", Kill Switch: on")?;
+        }
+        Ok(())
     }
 }
 
@@ -1,49 +1,89 @@
-use std::sync::mpsc;
+use std::sync::{mpsc, Mutex};
 use std::thread;
 use serde::{Serialize, Deserialize};
 use crate::error::TorrerResult;
+use crate::tor::TorEvent;
+
+/// Events held for a consumer before new ones are dropped
+///
+/// Tor reports bandwidth every second, so a queue nobody reads would
+/// otherwise grow for as long as routing runs.
+pub const EVENT_QUEUE_CAPACITY: usize = 256;
 
 /// Event system for Torrer
 pub struct EventManager {
-    sender: mpsc::Sender<Event>,
-    receiver: mpsc::Receiver<Event>,
+    sender: mpsc::SyncSender<Event>,
+    /// Taken by `start_listener`
+    receiver: Mutex<Option<mpsc::Receiver<Event>>>,
 }
 
 impl EventManager {
     /// Create a new event manager
     pub fn new() -> Self {
-        let (sender, receiver) = mpsc::channel();
-        Self { sender, receiver }
+        let (sender, receiver) = mpsc::sync_channel(EVENT_QUEUE_CAPACITY);
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// Get a sender for emitting events from other threads or tasks
+    ///
+    /// Send with `try_send`: a full queue means nobody is reading.
+    pub fn sender(&self) -> mpsc::SyncSender<Event> {
+        self.sender.clone()
     }
 
     /// Emit an event
+    ///
+    /// The event is dropped when the queue is full.
     pub fn emit(&self, event: Event) -> TorrerResult<()> {
-        self.sender.send(event).map_err(|e| {
-            crate::error::TorrerError::Config(format!("Failed to emit event: {}", e))
-        })?;
-        Ok(())
+        match self.sender.try_send(event) {
+            Ok(()) => Ok(()),
+            Err(mpsc::TrySendError::Full(event)) => {
+                log::debug!("Event queue full, dropping {}", event.name());
+                Ok(())
+            }
+            Err(e) => Err(crate::error::TorrerError::Config(format!("Failed to emit event: {}", e))),
+        }
     }
 
     /// Receive events (non-blocking)
+    ///
+    /// Returns `None` while `receive` is waiting on another thread.
     pub fn try_receive(&self) -> Option<Event> {
-        self.receiver.try_recv().ok()
+        self.receiver.try_lock().ok()?.as_ref()?.try_recv().ok()
     }
 
     /// Receive events (blocking)
     pub fn receive(&self) -> Result<Event, mpsc::RecvError> {
-        self.receiver.recv()
+        match *self.receiver.lock().map_err(|_| mpsc::RecvError)? {
+            Some(ref receiver) => receiver.recv(),
+            None => Err(mpsc::RecvError),
+        }
     }
 
     /// Start event listener
+    ///
+    /// The handler runs on its own thread and takes the receiver, so
+    /// `try_receive`/`receive` will not see events any more. Only the first
+    /// listener is started.
     pub fn start_listener<F>(&self, handler: F)
     where
         F: Fn(Event) + Send + 'static,
     {
-        // Note: mpsc::Receiver doesn't implement Clone
-        // For a proper implementation, we'd need to use Arc<Mutex<Receiver>> or a different channel
-        // For now, this is a simplified implementation
-        log::warn!("Event listener requires shared receiver - using simplified implementation");
-        // In a full implementation, we'd use a shared event queue or different channel type
+        let receiver = match self.receiver.lock().ok().and_then(|mut receiver| receiver.take()) {
+            Some(receiver) => receiver,
+            None => {
+                log::warn!("Event listener already started");
+                return;
+            }
+        };
+        thread::spawn(move || {
+            for event in receiver {
+                handler(event);
+            }
+        });
     }
 }
 
@@ -62,8 +102,20 @@ pub enum Event {
     RoutingStopped,
     /// Tor circuit established
     CircuitEstablished,
-    /// Tor circuit failed
-    CircuitFailed,
+    /// Tor circuit built (circuit ID)
+    CircuitBuilt(String),
+    /// Tor circuit failed (circuit ID and reason)
+    CircuitFailed(String),
+    /// Tor circuit closed (circuit ID)
+    CircuitClosed(String),
+    /// Stream failed (stream ID and target)
+    StreamFailed(String),
+    /// Bandwidth used in the last second
+    Bandwidth { read: u64, written: u64 },
+    /// Entry guard status changed
+    GuardChanged(String),
+    /// New Tor identity (NEWNYM) in effect
+    IdentityRotated,
     /// Fallback triggered
     FallbackTriggered(String),
     /// Bridge added
@@ -85,7 +137,13 @@ impl Event {
             Event::RoutingStarted => "routing_started",
             Event::RoutingStopped => "routing_stopped",
             Event::CircuitEstablished => "circuit_established",
-            Event::CircuitFailed => "circuit_failed",
+            Event::CircuitBuilt(_) => "circuit_built",
+            Event::CircuitFailed(_) => "circuit_failed",
+            Event::CircuitClosed(_) => "circuit_closed",
+            Event::StreamFailed(_) => "stream_failed",
+            Event::Bandwidth { .. } => "bandwidth",
+            Event::GuardChanged(_) => "guard_changed",
+            Event::IdentityRotated => "identity_rotated",
             Event::FallbackTriggered(_) => "fallback_triggered",
             Event::BridgeAdded(_) => "bridge_added",
             Event::ConfigChanged => "config_changed",
@@ -94,5 +152,48 @@ impl Event {
             Event::Info(_) => "info",
         }
     }
-}
 
+    /// Translate a Tor control-port event into a Torrer event
+    ///
+    /// Returns `None` for transitions Torrer does not surface (e.g.
+    /// circuits being extended or streams succeeding).
+    pub fn from_tor_event(event: &TorEvent) -> Option<Event> {
+        match event {
+            TorEvent::Circ(circ) => match circ.status.as_str() {
+                "BUILT" => Some(Event::CircuitBuilt(circ.id.clone())),
+                "FAILED" => Some(Event::CircuitFailed(format!(
+                    "{} ({})",
+                    circ.id,
+                    circ.reason().unwrap_or("unknown reason")
+                ))),
+                "CLOSED" => Some(Event::CircuitClosed(circ.id.clone())),
+                _ => None,
+            },
+            TorEvent::Stream(stream) if stream.status == "FAILED" => Some(Event::StreamFailed(
+                format!("{} to {}", stream.id, stream.target),
+            )),
+            TorEvent::Stream(_) => None,
+            TorEvent::Bw(bw) => Some(Event::Bandwidth {
+                read: bw.read,
+                written: bw.written,
+            }),
+            TorEvent::StreamBw(_) => None,
+            TorEvent::StatusClient(status) => match status.action.as_str() {
+                "CIRCUIT_ESTABLISHED" => Some(Event::CircuitEstablished),
+                "CIRCUIT_NOT_ESTABLISHED" => Some(Event::Warning(format!(
+                    "Tor lost its circuits: {}",
+                    status.arguments.get("REASON").map(String::as_str).unwrap_or("unknown reason")
+                ))),
+                _ => None,
+            },
+            TorEvent::Guard(guard) => Some(Event::GuardChanged(format!(
+                "{} {}",
+                guard.name, guard.status
+            ))),
+            TorEvent::Log { severity, message } if severity == "WARN" || severity == "ERR" => {
+                Some(Event::Warning(message.clone()))
+            }
+            TorEvent::Log { message, .. } => Some(Event::Info(message.clone())),
+        }
+    }
+}
@@ -32,7 +32,7 @@ impl FallbackManager {
         log::debug!("Checking Tor connection (timeout: {}s)...", TOR_CHECK_TIMEOUT);
         
         let check_future = async {
-            let mut client = TorClient::new();
+            let mut client = TorClient::from_system_config();
             
             client.connect().await?;
             client.authenticate().await?;
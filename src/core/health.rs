@@ -1,5 +1,10 @@
+use std::time::Duration;
+
 use crate::error::TorrerResult;
-use crate::tor::TorClient;
+use crate::tor::{ExitVerification, ExitVerifier, TorClient};
+
+/// How long the exit check waits for a stream when none is open
+const EXIT_STREAM_WAIT: Duration = Duration::from_secs(3);
 
 /// Health check for Torrer system
 pub struct HealthChecker;
@@ -13,19 +18,23 @@ impl HealthChecker {
             tor_circuit: false,
             iptables: false,
             dns: false,
+            exit: None,
         };
 
         // Check Tor daemon
         status.tor_daemon = Self::check_tor_daemon().await;
 
-        // Check Tor control port
-        if status.tor_daemon {
-            status.tor_control = Self::check_tor_control().await;
-            
-            // Check circuit if control is working
-            if status.tor_control {
-                status.tor_circuit = Self::check_tor_circuit().await;
-            }
+        // Check Tor control port; a Tor Torrer manages is no system service
+        status.tor_control = Self::check_tor_control().await;
+
+        // Check circuit if control is working
+        if status.tor_control {
+            status.tor_circuit = Self::check_tor_circuit().await;
+        }
+
+        // Verify the exit of a live stream
+        if status.tor_circuit {
+            status.exit = Self::check_exit().await;
         }
 
         // Check iptables
@@ -47,12 +56,12 @@ impl HealthChecker {
     }
 
     async fn check_tor_control() -> bool {
-        let mut client = TorClient::new();
+        let mut client = TorClient::from_system_config();
         client.connect().await.is_ok()
     }
 
     async fn check_tor_circuit() -> bool {
-        let mut client = TorClient::new();
+        let mut client = TorClient::from_system_config();
         if let Ok(_) = client.connect().await {
             if let Ok(_) = client.authenticate().await {
                 if let Ok(status) = client.get_status().await {
@@ -63,6 +72,21 @@ impl HealthChecker {
         false
     }
 
+    async fn check_exit() -> Option<ExitVerification> {
+        let expected = ExitVerifier::expected_countries();
+
+        let mut client = TorClient::from_system_config();
+        client.connect().await.ok()?;
+        client.authenticate().await.ok()?;
+        match ExitVerifier::verify(&mut client, &expected, EXIT_STREAM_WAIT).await {
+            Ok(verification) => verification,
+            Err(e) => {
+                log::debug!("Exit verification failed: {}", e);
+                None
+            }
+        }
+    }
+
     fn check_iptables() -> bool {
         use std::process::Command;
         Command::new("iptables")
@@ -100,12 +124,27 @@ pub struct HealthStatus {
     pub tor_circuit: bool,
     pub iptables: bool,
     pub dns: bool,
+    /// Exit of a live stream, checked against the consensus; `None` when no
+    /// stream went through an exit
+    pub exit: Option<ExitVerification>,
 }
 
 impl HealthStatus {
     /// Check if all systems are healthy
+    ///
+    /// An unverified exit is unhealthy; having no stream to check is not.
     pub fn is_healthy(&self) -> bool {
-        self.tor_daemon && self.tor_control && self.tor_circuit && self.iptables && self.dns
+        self.tor_daemon
+            && self.tor_control
+            && self.tor_circuit
+            && self.iptables
+            && self.dns
+            && self.exit_verified() != Some(false)
+    }
+
+    /// Whether the exit checked out, if a stream was found
+    pub fn exit_verified(&self) -> Option<bool> {
+        self.exit.as_ref().map(ExitVerification::is_verified)
     }
 
     /// Get health score (0-100)
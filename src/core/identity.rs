@@ -0,0 +1,188 @@
+// Identity rotation (SIGNAL NEWNYM) that respects Tor's rate limit
+
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::Mutex;
+
+use crate::core::events::Event;
+use crate::core::rate_limiter::RateLimiter;
+use crate::core::scheduler::{ScheduledTask, TaskBuilder};
+use crate::error::TorrerResult;
+use crate::tor::{CircuitManager, EventKind, EventStream, TorClient, TorEvent};
+use crate::utils::current_timestamp;
+
+/// Minimum time Tor enforces between two NEWNYM signals
+pub const NEWNYM_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long to listen for Tor's rate-limit notice after NEWNYM is accepted
+const NOTICE_TIMEOUT: Duration = Duration::from_millis(500);
+
+const NEWNYM_KEY: &str = "newnym";
+const RATE_LIMIT_NOTICE: &str = "Rate limiting NEWNYM request: delaying by ";
+
+/// Outcome of one identity rotation
+#[derive(Debug, Clone, Serialize)]
+pub struct IdentityRotation {
+    /// When NEWNYM was sent (Unix timestamp)
+    pub requested_at: u64,
+    /// Extra delay Tor applied because of its own rate limit
+    pub delayed_by: Duration,
+    /// When new streams started using fresh circuits (Unix timestamp)
+    pub effective_at: u64,
+}
+
+/// Requests new Tor identities without tripping Tor's NEWNYM rate limit
+pub struct IdentityRotator {
+    client: TorClient,
+    notices: Option<EventStream>,
+    limiter: RateLimiter,
+    events: Option<mpsc::SyncSender<Event>>,
+}
+
+impl IdentityRotator {
+    /// Create a rotator on an authenticated control connection
+    ///
+    /// Subscribes to NOTICE events to learn about delays Tor imposes, e.g.
+    /// when another controller sent NEWNYM moments earlier.
+    pub async fn new(mut client: TorClient) -> Self {
+        let notices = Self::subscribe_notices(&mut client).await;
+
+        Self {
+            client,
+            notices,
+            limiter: RateLimiter::new(1, NEWNYM_INTERVAL),
+            events: None,
+        }
+    }
+
+    /// Connect using the system configuration
+    pub async fn connect() -> TorrerResult<Self> {
+        let mut client = TorClient::from_system_config();
+        client.connect().await?;
+        client.authenticate().await?;
+        Ok(Self::new(client).await)
+    }
+
+    /// Report completed rotations as `Event::IdentityRotated`
+    pub fn with_events(mut self, sender: mpsc::SyncSender<Event>) -> Self {
+        self.events = Some(sender);
+        self
+    }
+
+    /// Time until Torrer will send the next NEWNYM
+    pub fn time_until_allowed(&self) -> Duration {
+        self.limiter.time_until_allowed(NEWNYM_KEY)
+    }
+
+    /// Request a new identity and wait until it is in effect
+    pub async fn rotate(&mut self) -> TorrerResult<IdentityRotation> {
+        let wait = self.time_until_allowed();
+        if !wait.is_zero() {
+            log::info!("Waiting {}s for Tor's NEWNYM rate limit", wait.as_secs_f64().ceil());
+        }
+        self.limiter.wait(NEWNYM_KEY).await;
+        self.ensure_connected().await?;
+
+        // Drop stale notices so only the reply to this signal is considered
+        if let Some(ref mut notices) = self.notices {
+            while notices.try_next().is_some() {}
+        }
+
+        if let Err(e) = CircuitManager::new_circuit(&mut self.client).await {
+            // A connection Tor closed (e.g. on restart) only shows up when used
+            if self.client.is_connected() {
+                return Err(e);
+            }
+            log::debug!("NEWNYM failed on a closed connection: {}", e);
+            self.ensure_connected().await?;
+            CircuitManager::new_circuit(&mut self.client).await?;
+        }
+        let requested_at = current_timestamp();
+
+        // Tor answers 250 even when it postpones the signal; the delay is
+        // only reported in a NOTICE sent right after the reply.
+        let delayed_by = self.wait_for_delay_notice().await.unwrap_or_default();
+        if !delayed_by.is_zero() {
+            log::info!("Tor delayed NEWNYM by {}s", delayed_by.as_secs());
+            tokio::time::sleep(delayed_by).await;
+        }
+
+        let rotation = IdentityRotation {
+            requested_at,
+            delayed_by,
+            effective_at: current_timestamp(),
+        };
+
+        log::info!("New Tor identity in effect");
+        if let Some(ref sender) = self.events {
+            let _ = sender.try_send(Event::IdentityRotated);
+        }
+
+        Ok(rotation)
+    }
+
+    /// Build a task that rotates the identity every `every`
+    pub fn schedule(rotator: Arc<Mutex<Self>>, every: Duration) -> ScheduledTask {
+        TaskBuilder::new("identity-rotation")
+            .interval(every)
+            .build(move || {
+                let rotator = rotator.clone();
+                async move {
+                    let mut rotator = rotator.lock().await;
+                    if let Err(e) = rotator.rotate().await {
+                        log::warn!("Scheduled identity rotation failed: {}", e);
+                    }
+                }
+            })
+    }
+
+    /// Reconnect, re-authenticate and re-subscribe if the connection was lost
+    async fn ensure_connected(&mut self) -> TorrerResult<()> {
+        if self.client.is_connected() {
+            return Ok(());
+        }
+
+        log::info!("Reconnecting to the Tor control port");
+        self.client.connect().await?;
+        self.client.authenticate().await?;
+        self.notices = Self::subscribe_notices(&mut self.client).await;
+        Ok(())
+    }
+
+    async fn subscribe_notices(client: &mut TorClient) -> Option<EventStream> {
+        match client.subscribe(&[EventKind::Notice]).await {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                log::warn!("Failed to subscribe to Tor notices: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn wait_for_delay_notice(&mut self) -> Option<Duration> {
+        let notices = self.notices.as_mut()?;
+        let deadline = tokio::time::Instant::now() + NOTICE_TIMEOUT;
+
+        loop {
+            let event = tokio::time::timeout_at(deadline, notices.next()).await.ok()??;
+            if let TorEvent::Log { ref message, .. } = event {
+                if let Some(delay) = parse_newnym_delay(message) {
+                    return Some(delay);
+                }
+            }
+        }
+    }
+}
+
+/// Parse Tor's "Rate limiting NEWNYM request: delaying by N second(s)" notice
+pub fn parse_newnym_delay(message: &str) -> Option<Duration> {
+    let start = message.find(RATE_LIMIT_NOTICE)? + RATE_LIMIT_NOTICE.len();
+    let seconds: String = message[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+
+    seconds.parse().ok().map(Duration::from_secs)
+}
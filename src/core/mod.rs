@@ -12,8 +12,10 @@ pub mod scheduler;
 pub mod persistence;
 pub mod rate_limiter;
 pub mod daemon;
+pub mod identity;
+pub mod netns;
 
-pub use engine::TorrerEngine;
+pub use engine::{EngineStatus, TorrerEngine};
 pub use fallback::FallbackManager;
 pub use monitoring::Monitoring;
 pub use health::{HealthChecker, HealthStatus};
@@ -27,3 +29,5 @@ pub use scheduler::{Scheduler, ScheduledTask, TaskBuilder};
 pub use persistence::PersistenceManager;
 pub use rate_limiter::RateLimiter;
 pub use daemon::DaemonManager;
+pub use identity::{IdentityRotator, IdentityRotation};
+pub use netns::{LeakHarness, NamespaceLayout, NamespaceSandbox};
@@ -1,6 +1,16 @@
+use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
 use crate::error::TorrerResult;
-use crate::tor::TorClient;
+use crate::tor::commands::build_getinfo;
+use crate::tor::{TorClient, TorEvent};
+use crate::utils::current_timestamp;
+
+/// Per-second samples kept (one hour)
+const SECOND_SAMPLES: usize = 3600;
+
+/// Per-minute samples kept (one day)
+const MINUTE_SAMPLES: usize = 1440;
 
 /// Advanced monitoring and statistics
 pub struct Monitoring {
@@ -9,6 +19,8 @@ pub struct Monitoring {
     bytes_received: u64,
     connection_attempts: u32,
     successful_connections: u32,
+    history: BandwidthHistory,
+    stream_bytes: HashMap<String, StreamBandwidth>,
 }
 
 impl Monitoring {
@@ -20,6 +32,8 @@ impl Monitoring {
             bytes_received: 0,
             connection_attempts: 0,
             successful_connections: 0,
+            history: BandwidthHistory::default(),
+            stream_bytes: HashMap::new(),
         }
     }
 
@@ -60,12 +74,87 @@ impl Monitoring {
         self.bytes_received += bytes;
     }
 
+    /// Record one second of Tor traffic (from a `BW` event)
+    pub fn record_bandwidth(&mut self, read: u64, written: u64) {
+        self.record_bytes_received(read);
+        self.record_bytes_sent(written);
+        // A BW event covers the second that just ended
+        self.history.record(current_timestamp().saturating_sub(1), read, written);
+    }
+
+    /// Update counters from a Tor event
+    pub fn handle_tor_event(&mut self, event: &TorEvent) {
+        match event {
+            TorEvent::Bw(bw) => self.record_bandwidth(bw.read, bw.written),
+            TorEvent::StreamBw(bw) => {
+                let entry = self.stream_bytes.entry(bw.stream_id.clone()).or_default();
+                entry.read += bw.read;
+                entry.written += bw.written;
+            }
+            TorEvent::Stream(stream) if stream.status == "CLOSED" || stream.status == "FAILED" => {
+                self.stream_bytes.remove(&stream.id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Bytes transferred by currently open streams, keyed by stream ID
+    pub fn stream_bandwidth(&self) -> &HashMap<String, StreamBandwidth> {
+        &self.stream_bytes
+    }
+
+    /// Rolling bandwidth history
+    pub fn history(&self) -> &BandwidthHistory {
+        &self.history
+    }
+
+    /// Load totals, uptime and recent history from Tor
+    ///
+    /// Tor counts traffic since it started, so this replaces the local
+    /// counters rather than adding to them.
+    pub async fn sync_with_tor(&mut self, client: &mut TorClient) -> TorrerResult<()> {
+        let response = client
+            .send_command(&build_getinfo("traffic/read traffic/written uptime"))
+            .await?;
+
+        let value = |key: &str| response.get(key).and_then(|v| v.parse::<u64>().ok());
+        if let Some(read) = value("traffic/read") {
+            self.bytes_received = read;
+        }
+        if let Some(written) = value("traffic/written") {
+            self.bytes_sent = written;
+        }
+        if let Some(uptime) = value("uptime") {
+            self.start_time = Instant::now().checked_sub(Duration::from_secs(uptime));
+        }
+
+        // Seed the per-second history from Tor's recent BW events
+        if self.history.is_empty() {
+            match client.send_command(&build_getinfo("bw-event-cache")).await {
+                Ok(cache) => {
+                    let samples = parse_bw_event_cache(&cache.get("bw-event-cache").unwrap_or_default());
+                    let now = current_timestamp();
+                    let start = now.saturating_sub(samples.len() as u64);
+                    for (offset, (read, written)) in samples.into_iter().enumerate() {
+                        self.history.record(start + offset as u64, read, written);
+                    }
+                }
+                Err(e) => log::debug!("Tor bandwidth cache unavailable: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get statistics
     pub fn get_stats(&self) -> Statistics {
+        let latest = self.history.latest();
         Statistics {
             uptime: self.uptime(),
             bytes_sent: self.bytes_sent,
             bytes_received: self.bytes_received,
+            read_rate: latest.map(|s| s.read).unwrap_or(0),
+            write_rate: latest.map(|s| s.written).unwrap_or(0),
             connection_attempts: self.connection_attempts,
             successful_connections: self.successful_connections,
             success_rate: if self.connection_attempts > 0 {
@@ -100,11 +189,97 @@ pub struct Statistics {
     pub uptime: Option<Duration>,
     pub bytes_sent: u64,
     pub bytes_received: u64,
+    /// Bytes read in the most recent second
+    pub read_rate: u64,
+    /// Bytes written in the most recent second
+    pub write_rate: u64,
     pub connection_attempts: u32,
     pub successful_connections: u32,
     pub success_rate: f64,
 }
 
+/// Bytes transferred by a single stream
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamBandwidth {
+    pub read: u64,
+    pub written: u64,
+}
+
+/// Bandwidth over one sampling interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BandwidthSample {
+    /// Unix timestamp at the start of the interval
+    pub timestamp: u64,
+    pub read: u64,
+    pub written: u64,
+}
+
+/// Rolling bandwidth history: the last hour per second, the last day per minute
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthHistory {
+    per_second: VecDeque<BandwidthSample>,
+    per_minute: VecDeque<BandwidthSample>,
+    current_minute: Option<BandwidthSample>,
+}
+
+impl BandwidthHistory {
+    /// Record the traffic of the second starting at `timestamp`
+    pub fn record(&mut self, timestamp: u64, read: u64, written: u64) {
+        self.per_second.push_back(BandwidthSample { timestamp, read, written });
+        while self.per_second.len() > SECOND_SAMPLES {
+            self.per_second.pop_front();
+        }
+
+        let minute = timestamp - timestamp % 60;
+        match self.current_minute.as_mut() {
+            Some(current) if current.timestamp == minute => {
+                current.read += read;
+                current.written += written;
+            }
+            _ => {
+                if let Some(finished) = self.current_minute.take() {
+                    self.per_minute.push_back(finished);
+                    while self.per_minute.len() > MINUTE_SAMPLES {
+                        self.per_minute.pop_front();
+                    }
+                }
+                self.current_minute = Some(BandwidthSample { timestamp: minute, read, written });
+            }
+        }
+    }
+
+    /// Whether nothing has been recorded yet
+    pub fn is_empty(&self) -> bool {
+        self.per_second.is_empty()
+    }
+
+    /// Most recent per-second sample
+    pub fn latest(&self) -> Option<&BandwidthSample> {
+        self.per_second.back()
+    }
+
+    /// Per-second samples, oldest first
+    pub fn per_second(&self) -> impl Iterator<Item = &BandwidthSample> {
+        self.per_second.iter()
+    }
+
+    /// Per-minute totals, oldest first, including the current partial minute
+    pub fn per_minute(&self) -> impl Iterator<Item = &BandwidthSample> {
+        self.per_minute.iter().chain(self.current_minute.iter())
+    }
+}
+
+/// Parse `GETINFO bw-event-cache` ("read,written read,written ...", oldest first)
+fn parse_bw_event_cache(cache: &str) -> Vec<(u64, u64)> {
+    cache
+        .split_whitespace()
+        .filter_map(|pair| {
+            let (read, written) = pair.split_once(',')?;
+            Some((read.parse().ok()?, written.parse().ok()?))
+        })
+        .collect()
+}
+
 /// Connection status
 #[derive(Debug, Clone)]
 pub struct ConnectionStatus {
@@ -0,0 +1,285 @@
+// Network namespaces that route a single program through Tor (`torrer exec`)
+
+use std::ffi::OsStr;
+use std::fs;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::FirewallBackend;
+use crate::security::LeakTarget;
+
+/// Range the /30 networks of namespace veth pairs are carved from
+pub const NAMESPACE_SUBNET: Ipv4Addr = Ipv4Addr::new(10, 200, 0, 0);
+
+/// Number of /30 networks in `NAMESPACE_SUBNET` (a /16)
+const NAMESPACE_BLOCKS: u32 = 1 << 14;
+
+/// Names and addresses of one namespace and its veth pair
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamespaceLayout {
+    /// Namespace name, as used by `ip netns`
+    pub name: String,
+    /// veth end left in the host namespace
+    pub host_veth: String,
+    /// veth end moved into the namespace
+    pub ns_veth: String,
+    /// Address of the host end, the namespace's gateway and DNS server
+    pub host_addr: Ipv4Addr,
+    /// Address of the namespace end
+    pub ns_addr: Ipv4Addr,
+}
+
+impl NamespaceLayout {
+    /// Layout for namespace `id` (usually the torrer process ID)
+    pub fn new(id: u32) -> Self {
+        let base = u32::from(NAMESPACE_SUBNET) + (id % NAMESPACE_BLOCKS) * 4;
+
+        Self {
+            name: format!("torrer-{}", id),
+            // Interface names are limited to 15 characters
+            host_veth: format!("tx{}h", id),
+            ns_veth: format!("tx{}n", id),
+            host_addr: Ipv4Addr::from(base + 1),
+            ns_addr: Ipv4Addr::from(base + 2),
+        }
+    }
+
+    /// Directory whose files `ip netns exec` mounts over /etc
+    pub fn etc_dir(&self) -> PathBuf {
+        PathBuf::from("/etc/netns").join(&self.name)
+    }
+
+    /// resolv.conf seen inside the namespace
+    pub fn resolv_conf(&self) -> String {
+        format!("nameserver {}\n", self.host_addr)
+    }
+}
+
+/// A network namespace whose only way out is Tor
+///
+/// The namespace reaches the host through a veth pair, and the firewall
+/// backend DNATs its TCP and DNS to Tor's TransPort and DNSPort. Nothing else
+/// leaves it, and no system-wide rule is needed. Dropping the sandbox removes
+/// the namespace, the veth pair and the rules.
+pub struct NamespaceSandbox {
+    layout: NamespaceLayout,
+    firewall: Arc<dyn FirewallBackend>,
+    created: bool,
+    attached: bool,
+}
+
+impl NamespaceSandbox {
+    /// Create the namespace and route it through Tor
+    pub fn create(id: u32, firewall: Arc<dyn FirewallBackend>) -> TorrerResult<Self> {
+        let mut sandbox = Self {
+            layout: NamespaceLayout::new(id),
+            firewall,
+            created: false,
+            attached: false,
+        };
+
+        // On failure, dropping the sandbox undoes what was set up
+        sandbox.setup()?;
+        Ok(sandbox)
+    }
+
+    /// Names and addresses in use
+    pub fn layout(&self) -> &NamespaceLayout {
+        &self.layout
+    }
+
+    fn setup(&mut self) -> TorrerResult<()> {
+        let layout = self.layout.clone();
+        let host_cidr = format!("{}/30", layout.host_addr);
+        let ns_cidr = format!("{}/30", layout.ns_addr);
+        let gateway = layout.host_addr.to_string();
+
+        log::info!("Creating network namespace {}", layout.name);
+        run_ip(&["netns", "add", &layout.name])?;
+        self.created = true;
+
+        run_ip(&["link", "add", &layout.host_veth, "type", "veth", "peer", "name", &layout.ns_veth])?;
+        run_ip(&["link", "set", &layout.ns_veth, "netns", &layout.name])?;
+        run_ip(&["addr", "add", &host_cidr, "dev", &layout.host_veth])?;
+        run_ip(&["link", "set", &layout.host_veth, "up"])?;
+
+        // Tor listens on loopback; DNAT there from the veth needs route_localnet
+        fs::write(
+            format!("/proc/sys/net/ipv4/conf/{}/route_localnet", layout.host_veth),
+            "1",
+        )?;
+
+        run_ip(&["-n", &layout.name, "link", "set", "lo", "up"])?;
+        run_ip(&["-n", &layout.name, "addr", "add", &ns_cidr, "dev", &layout.ns_veth])?;
+        run_ip(&["-n", &layout.name, "link", "set", &layout.ns_veth, "up"])?;
+        run_ip(&["-n", &layout.name, "route", "add", "default", "via", &gateway])?;
+
+        // DNS goes to the gateway, where it is redirected to Tor's DNSPort
+        fs::create_dir_all(layout.etc_dir())?;
+        fs::write(layout.etc_dir().join("resolv.conf"), layout.resolv_conf())?;
+
+        self.firewall.attach_namespace(&layout.host_veth)?;
+        self.attached = true;
+
+        Ok(())
+    }
+
+    /// Command running `program` inside the namespace
+    ///
+    /// With `user` (UID, GID) the program runs as that user instead of root.
+    pub fn command<S: AsRef<OsStr>>(&self, program: &str, args: &[S], user: Option<(u32, u32)>) -> Command {
+        let mut command = Command::new("ip");
+        command.args(["netns", "exec", &self.layout.name]);
+        if let Some((uid, gid)) = user {
+            command
+                .arg("setpriv")
+                .arg(format!("--reuid={}", uid))
+                .arg(format!("--regid={}", gid))
+                .args(["--init-groups", "--"]);
+        }
+        command.arg(program).args(args);
+        command
+    }
+
+    /// Remove the rules, the veth pair and the namespace
+    fn teardown(&mut self) {
+        if self.attached {
+            if let Err(e) = self.firewall.detach_namespace(&self.layout.host_veth) {
+                log::error!("Failed to remove rules for {}: {}", self.layout.host_veth, e);
+            }
+            self.attached = false;
+        }
+
+        if self.created {
+            log::info!("Removing network namespace {}", self.layout.name);
+            let _ = fs::remove_dir_all(self.layout.etc_dir());
+            // Deleting either end removes the pair
+            let _ = run_ip(&["link", "del", &self.layout.host_veth]);
+            if let Err(e) = run_ip(&["netns", "del", &self.layout.name]) {
+                log::error!("{}", e);
+            }
+            self.created = false;
+        }
+    }
+}
+
+impl Drop for NamespaceSandbox {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+/// Address of the host end of the leak-test harness (RFC 2544 benchmarking range)
+pub const HARNESS_HOST_ADDR: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 1);
+/// Address of the stand-in internet, off every local and bypass range
+pub const HARNESS_INTERNET_ADDR: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 2);
+/// IPv6 address of the host end (documentation range)
+pub const HARNESS_HOST_ADDR_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0x7e57, 0, 0, 0, 0, 1);
+/// IPv6 address of the stand-in internet
+pub const HARNESS_INTERNET_ADDR_V6: Ipv6Addr = Ipv6Addr::new(0x2001, 0xdb8, 0x7e57, 0, 0, 0, 0, 2);
+
+/// A namespace standing in for the internet in `torrer leak-test --harness`
+///
+/// Unlike `NamespaceSandbox`, nothing is routed: the host reaches the
+/// namespace over a veth pair, so probes sent from the host go through the
+/// host's own ruleset and a listener inside sees whatever gets past it.
+pub struct LeakHarness {
+    name: String,
+    host_veth: String,
+    ipv6: bool,
+    created: bool,
+}
+
+impl LeakHarness {
+    /// Create the namespace and its veth pair
+    pub fn create(id: u32) -> TorrerResult<Self> {
+        let mut harness = Self {
+            name: format!("torrer-leak-{}", id),
+            host_veth: format!("tl{}h", id),
+            ipv6: false,
+            created: false,
+        };
+
+        harness.setup(&format!("tl{}n", id))?;
+        Ok(harness)
+    }
+
+    fn setup(&mut self, ns_veth: &str) -> TorrerResult<()> {
+        log::info!("Creating leak-test namespace {}", self.name);
+        run_ip(&["netns", "add", &self.name])?;
+        self.created = true;
+
+        run_ip(&["link", "add", &self.host_veth, "type", "veth", "peer", "name", ns_veth])?;
+        run_ip(&["link", "set", ns_veth, "netns", &self.name])?;
+        run_ip(&["addr", "add", &format!("{}/30", HARNESS_HOST_ADDR), "dev", &self.host_veth])?;
+        run_ip(&["link", "set", &self.host_veth, "up"])?;
+        run_ip(&["-n", &self.name, "link", "set", "lo", "up"])?;
+        run_ip(&["-n", &self.name, "addr", "add", &format!("{}/30", HARNESS_INTERNET_ADDR), "dev", ns_veth])?;
+        run_ip(&["-n", &self.name, "link", "set", ns_veth, "up"])?;
+
+        // IPv6 may be disabled on the host; the IPv6 probe is then skipped
+        let host_v6 = format!("{}/126", HARNESS_HOST_ADDR_V6);
+        let ns_v6 = format!("{}/126", HARNESS_INTERNET_ADDR_V6);
+        self.ipv6 = run_ip(&["addr", "add", &host_v6, "dev", &self.host_veth, "nodad"])
+            .and_then(|_| run_ip(&["-n", &self.name, "addr", "add", &ns_v6, "dev", ns_veth, "nodad"]))
+            .map_err(|e| log::warn!("No IPv6 in the leak-test harness: {}", e))
+            .is_ok();
+
+        Ok(())
+    }
+
+    /// Addresses of the stand-in internet
+    pub fn target(&self) -> LeakTarget {
+        LeakTarget {
+            ipv4: HARNESS_INTERNET_ADDR,
+            ipv6: if self.ipv6 { Some(HARNESS_INTERNET_ADDR_V6) } else { None },
+        }
+    }
+
+    /// Command running `program` inside the namespace
+    pub fn command<S: AsRef<OsStr>>(&self, program: &OsStr, args: &[S]) -> Command {
+        let mut command = Command::new("ip");
+        command.args(["netns", "exec", &self.name]).arg(program).args(args);
+        command
+    }
+
+    fn teardown(&mut self) {
+        if self.created {
+            log::info!("Removing leak-test namespace {}", self.name);
+            let _ = run_ip(&["link", "del", &self.host_veth]);
+            if let Err(e) = run_ip(&["netns", "del", &self.name]) {
+                log::error!("{}", e);
+            }
+            self.created = false;
+        }
+    }
+}
+
+impl Drop for LeakHarness {
+    fn drop(&mut self) {
+        self.teardown();
+    }
+}
+
+/// Run `ip` with `args`, failing on a non-zero exit code
+fn run_ip(args: &[&str]) -> TorrerResult<()> {
+    let output = Command::new("ip")
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| TorrerError::Iptables(format!("Failed to run ip: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(TorrerError::Iptables(format!(
+            "ip {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
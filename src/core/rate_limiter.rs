@@ -39,6 +39,31 @@ impl RateLimiter {
         }
     }
 
+    /// Time until the next request for `key` would be allowed
+    pub fn time_until_allowed(&self, key: &str) -> Duration {
+        let limits = self.limits.lock().unwrap();
+        let now = Instant::now();
+
+        let requests = match limits.get(key) {
+            Some(requests) => requests,
+            None => return Duration::ZERO,
+        };
+
+        let mut recent: Vec<Instant> = requests
+            .iter()
+            .copied()
+            .filter(|&time| now.duration_since(time) < self.window)
+            .collect();
+        if recent.is_empty() || recent.len() < self.max_requests as usize {
+            return Duration::ZERO;
+        }
+
+        // The oldest request that still counts has to leave the window first
+        recent.sort();
+        let index = recent.len().saturating_sub(self.max_requests.max(1) as usize);
+        self.window.saturating_sub(now.duration_since(recent[index]))
+    }
+
     /// Wait until request is allowed
     pub async fn wait(&self, key: &str) {
         while !self.check(key) {
@@ -1,15 +1,24 @@
 // Circuit visualization component
 use gtk4::prelude::*;
-use gtk4::{Box, Label, DrawingArea, Orientation, ScrolledWindow};
-use crate::tor::circuit::CircuitInfo;
+use gtk4::{Box, Button, Label, ListBox, DrawingArea, Orientation, ScrolledWindow, glib};
+use crate::error::TorrerResult;
+use crate::tor::circuit::{CircuitInfo, HopRole, StreamInfo};
+use crate::tor::{CircuitManager, TorClient};
 use std::sync::Arc;
 use std::sync::Mutex;
 
 /// Circuit visualization widget
 pub struct CircuitVisualization {
     container: Box,
+    view: CircuitView,
+}
+
+/// Widgets shared with button handlers so they can redraw after an action
+#[derive(Clone)]
+struct CircuitView {
     drawing_area: DrawingArea,
     info_label: Label,
+    circuit_list: ListBox,
     circuits: Arc<Mutex<Vec<CircuitInfo>>>,
 }
 
@@ -27,17 +36,25 @@ impl CircuitVisualization {
         info_label.set_markup("<b>Active Circuits:</b>");
         container.append(&info_label);
 
+        // Toolbar
+        let toolbar = Box::new(Orientation::Horizontal, 5);
+        let refresh_button = Button::with_label("Refresh");
+        let build_button = Button::with_label("Build New Circuit");
+        toolbar.append(&refresh_button);
+        toolbar.append(&build_button);
+        container.append(&toolbar);
+
         // Drawing area for circuit visualization
         let drawing_area = DrawingArea::new();
         drawing_area.set_content_width(500);
         drawing_area.set_content_height(300);
-        
+
         let circuits = Arc::new(Mutex::new(Vec::new()));
         let circuits_clone = circuits.clone();
-        
+
         drawing_area.set_draw_func(move |_, cr, width, height| {
             let circuits = circuits_clone.lock().unwrap();
-            
+
             // Clear background
             cr.set_source_rgb(0.1, 0.1, 0.1);
             cr.paint().unwrap();
@@ -50,40 +67,63 @@ impl CircuitVisualization {
                 cr.move_to(width as f64 / 2.0 - 80.0, height as f64 / 2.0);
                 cr.show_text("No active circuits").unwrap();
             } else {
-                // Draw circuits
-                let center_x = width as f64 / 2.0;
-                let center_y = height as f64 / 2.0;
-                let radius = (width.min(height) as f64 / 2.0) - 50.0;
-
-                for (i, circuit) in circuits.iter().enumerate() {
-                    let angle = (i as f64 * 2.0 * std::f64::consts::PI) / circuits.len() as f64;
-                    let x = center_x + radius * angle.cos();
-                    let y = center_y + radius * angle.sin();
-
-                    // Draw node
-                    cr.set_source_rgb(0.2, 0.6, 0.9);
-                    cr.arc(x, y, 15.0, 0.0, 2.0 * std::f64::consts::PI);
-                    cr.fill().unwrap();
-
-                    // Draw connection to center
+                // One row per circuit: guard -> middle -> exit
+                let row_height = 60.0;
+                let left = 60.0;
+                let right = width as f64 - 60.0;
+
+                for (row, circuit) in circuits.iter().enumerate() {
+                    let y = 30.0 + row as f64 * row_height;
+
+                    cr.set_source_rgb(0.9, 0.9, 0.9);
+                    cr.select_font_face("Sans", gtk4::cairo::FontSlant::Normal, gtk4::cairo::FontWeight::Bold);
+                    cr.set_font_size(10.0);
+                    cr.move_to(5.0, y + 4.0);
+                    cr.show_text(&format!("#{}", circuit.id)).unwrap();
+
+                    let hops: Vec<_> = circuit.hops().collect();
+                    if hops.is_empty() {
+                        cr.set_source_rgb(0.6, 0.6, 0.6);
+                        cr.move_to(left, y + 4.0);
+                        cr.show_text(&circuit.status).unwrap();
+                        continue;
+                    }
+
+                    let step = if hops.len() > 1 {
+                        (right - left) / (hops.len() - 1) as f64
+                    } else {
+                        0.0
+                    };
+
+                    // Links between hops
                     cr.set_source_rgb(0.4, 0.4, 0.4);
                     cr.set_line_width(2.0);
-                    cr.move_to(center_x, center_y);
-                    cr.line_to(x, y);
+                    cr.move_to(left, y);
+                    cr.line_to(left + step * (hops.len() - 1) as f64, y);
                     cr.stroke().unwrap();
 
-                    // Draw circuit ID
-                    cr.set_source_rgb(0.9, 0.9, 0.9);
-                    cr.select_font_face("Sans", gtk4::cairo::FontSlant::Normal, gtk4::cairo::FontWeight::Normal);
-                    cr.set_font_size(10.0);
-                    cr.move_to(x + 20.0, y);
-                    cr.show_text(&format!("Circuit {}", circuit.id)).unwrap();
-                }
+                    for (i, (role, hop)) in hops.iter().enumerate() {
+                        let x = left + step * i as f64;
+                        let (r, g, b) = match role {
+                            HopRole::Guard => (0.3, 0.8, 0.4),
+                            HopRole::Middle => (0.2, 0.6, 0.9),
+                            HopRole::Exit => (0.9, 0.3, 0.3),
+                        };
+                        cr.set_source_rgb(r, g, b);
+                        cr.arc(x, y, 10.0, 0.0, 2.0 * std::f64::consts::PI);
+                        cr.fill().unwrap();
 
-                // Draw center node (exit)
-                cr.set_source_rgb(0.9, 0.3, 0.3);
-                cr.arc(center_x, center_y, 20.0, 0.0, 2.0 * std::f64::consts::PI);
-                cr.fill().unwrap();
+                        let name = hop
+                            .nickname
+                            .clone()
+                            .unwrap_or_else(|| hop.fingerprint.chars().take(8).collect());
+                        cr.set_source_rgb(0.9, 0.9, 0.9);
+                        cr.select_font_face("Sans", gtk4::cairo::FontSlant::Normal, gtk4::cairo::FontWeight::Normal);
+                        cr.set_font_size(9.0);
+                        cr.move_to(x - 25.0, y + 22.0);
+                        cr.show_text(&format!("{}: {}", role.as_str(), name)).unwrap();
+                    }
+                }
             }
         });
 
@@ -91,27 +131,36 @@ impl CircuitVisualization {
         scroll.set_child(Some(&drawing_area));
         container.append(&scroll);
 
-        Self {
-            container,
+        // Per-circuit and per-stream controls
+        let circuit_list = ListBox::new();
+        container.append(&circuit_list);
+
+        let view = CircuitView {
             drawing_area,
             info_label,
+            circuit_list,
             circuits,
-        }
+        };
+
+        let view_for_refresh = view.clone();
+        refresh_button.connect_clicked(move |_| {
+            view_for_refresh.run(|client| async { Ok(client) });
+        });
+
+        let view_for_build = view.clone();
+        build_button.connect_clicked(move |_| {
+            view_for_build.run(|mut client| async move {
+                CircuitManager::extend_circuit(&mut client, &[]).await?;
+                Ok(client)
+            });
+        });
+
+        Self { container, view }
     }
 
     /// Update circuits
     pub fn update_circuits(&self, circuits: Vec<CircuitInfo>) {
-        {
-            let mut stored = self.circuits.lock().unwrap();
-            *stored = circuits;
-        }
-        
-        self.info_label.set_markup(&format!(
-            "<b>Active Circuits:</b> {}",
-            self.circuits.lock().unwrap().len()
-        ));
-        
-        self.drawing_area.queue_draw();
+        self.view.show(circuits, Vec::new());
     }
 
     /// Get the container widget
@@ -121,7 +170,118 @@ impl CircuitVisualization {
 
     /// Get current circuits (for testing)
     pub fn get_circuits(&self) -> Vec<CircuitInfo> {
-        self.circuits.lock().unwrap().clone()
+        self.view.circuits.lock().unwrap().clone()
     }
 }
 
+impl CircuitView {
+    /// Run a control-port action, then reload circuits and streams
+    fn run<F, Fut>(&self, action: F)
+    where
+        F: FnOnce(TorClient) -> Fut + 'static,
+        Fut: std::future::Future<Output = TorrerResult<TorClient>> + 'static,
+    {
+        let view = self.clone();
+        glib::spawn_future_local(async move {
+            let result = async {
+                let mut client = TorClient::from_system_config();
+                client.connect().await?;
+                client.authenticate().await?;
+                let mut client = action(client).await?;
+
+                let circuits = CircuitManager::get_circuits(&mut client).await?;
+                let streams = CircuitManager::list_streams(&mut client).await?;
+                Ok::<_, crate::error::TorrerError>((circuits, streams))
+            }
+            .await;
+
+            match result {
+                Ok((circuits, streams)) => view.show(circuits, streams),
+                Err(e) => view.info_label.set_text(&format!("Circuit action failed: {}", e)),
+            }
+        });
+    }
+
+    /// Redraw circuits and rebuild the control list
+    fn show(&self, circuits: Vec<CircuitInfo>, streams: Vec<StreamInfo>) {
+        // Each circuit gets its own row
+        self.drawing_area
+            .set_content_height((circuits.len() as i32 * 60 + 20).max(300));
+
+        while let Some(child) = self.circuit_list.first_child() {
+            self.circuit_list.remove(&child);
+        }
+
+        for circuit in &circuits {
+            let row = Box::new(Orientation::Vertical, 2);
+
+            let header = Box::new(Orientation::Horizontal, 5);
+            let path = circuit
+                .path
+                .iter()
+                .map(|hop| hop.nickname.clone().unwrap_or_else(|| hop.fingerprint.clone()))
+                .collect::<Vec<_>>()
+                .join(" → ");
+            let label = Label::new(Some(&format!(
+                "#{} {} {} {}",
+                circuit.id,
+                circuit.status,
+                circuit.purpose.as_deref().unwrap_or(""),
+                path
+            )));
+            label.set_xalign(0.0);
+            label.set_hexpand(true);
+            header.append(&label);
+
+            let close_button = Button::with_label("Close");
+            let view = self.clone();
+            let id = circuit.id.clone();
+            close_button.connect_clicked(move |_| {
+                let id = id.clone();
+                view.run(|mut client| async move {
+                    CircuitManager::close_circuit(&mut client, &id).await?;
+                    Ok(client)
+                });
+            });
+            header.append(&close_button);
+            row.append(&header);
+
+            for stream in streams.iter().filter(|s| s.circuit_id == circuit.id) {
+                let stream_row = Box::new(Orientation::Horizontal, 5);
+                stream_row.set_margin_start(20);
+
+                let label = Label::new(Some(&format!("{} {}", stream.target, stream.status)));
+                label.set_xalign(0.0);
+                label.set_hexpand(true);
+                stream_row.append(&label);
+
+                let close_button = Button::with_label("Close Stream");
+                let view = self.clone();
+                let id = stream.id.clone();
+                close_button.connect_clicked(move |_| {
+                    let id = id.clone();
+                    view.run(|mut client| async move {
+                        CircuitManager::close_stream(&mut client, &id).await?;
+                        Ok(client)
+                    });
+                });
+                stream_row.append(&close_button);
+                row.append(&stream_row);
+            }
+
+            self.circuit_list.append(&row);
+        }
+
+        self.info_label.set_markup(&format!(
+            "<b>Active Circuits:</b> {}",
+            circuits.len()
+        ));
+
+        {
+            let mut stored = self.circuits.lock().unwrap();
+            *stored = circuits;
+        }
+
+        self.drawing_area.queue_draw();
+    }
+}
@@ -33,13 +33,15 @@ mod integration_tests {
                 id: "12345".to_string(),
                 status: "BUILT".to_string(),
                 purpose: Some("GENERAL".to_string()),
-                flags: Some("FAST STABLE".to_string()),
+                build_flags: vec!["FAST".to_string(), "STABLE".to_string()],
+                ..Default::default()
             },
             CircuitInfo {
                 id: "67890".to_string(),
                 status: "BUILT".to_string(),
                 purpose: Some("HS_CLIENT_HSDIR".to_string()),
-                flags: Some("FAST".to_string()),
+                build_flags: vec!["FAST".to_string()],
+                ..Default::default()
             },
         ];
 
@@ -128,6 +130,8 @@ mod integration_tests {
             uptime: Some(Duration::from_secs(100)),
             bytes_sent: 5000,
             bytes_received: 10000,
+            read_rate: 0,
+            write_rate: 0,
             connection_attempts: 5,
             successful_connections: 5,
             success_rate: 100.0,
@@ -138,7 +142,8 @@ mod integration_tests {
             id: "1".to_string(),
             status: "BUILT".to_string(),
             purpose: Some("GENERAL".to_string()),
-            flags: Some("FAST".to_string()),
+            build_flags: vec!["FAST".to_string()],
+            ..Default::default()
         }];
         viz.update_circuits(circuits);
 
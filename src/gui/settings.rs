@@ -108,7 +108,7 @@ impl SettingsPanel {
 
         // IPv6 toggle
         let ipv6_box = Box::new(Orientation::Horizontal, 10);
-        let ipv6_label = Label::new(Some("Enable IPv6:"));
+        let ipv6_label = Label::new(Some("Route IPv6 through Tor:"));
         ipv6_label.set_halign(gtk4::Align::Start);
         ipv6_label.set_hexpand(false);
         
@@ -1,11 +1,16 @@
 // Statistics dashboard with charts and graphs
 use gtk4::prelude::*;
 use gtk4::{Box, Label, DrawingArea, Orientation};
-use crate::core::monitoring::Statistics;
+use crate::core::monitoring::{BandwidthHistory, Statistics};
+use crate::core::Event;
+use std::cell::Cell;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::collections::VecDeque;
 
+/// Seconds of bandwidth shown in the chart
+const HISTORY_POINTS: usize = 120;
+
 /// Statistics dashboard widget
 pub struct StatisticsDashboard {
     container: Box,
@@ -13,6 +18,9 @@ pub struct StatisticsDashboard {
     bytes_sent_label: Label,
     bytes_received_label: Label,
     success_rate_label: Label,
+    circuits_label: Label,
+    circuits_built: Cell<u32>,
+    circuits_failed: Cell<u32>,
     chart_area: DrawingArea,
     data_history: Arc<Mutex<VecDeque<(f64, f64, f64)>>>, // (time, sent, received)
 }
@@ -45,50 +53,85 @@ impl StatisticsDashboard {
         success_rate_label.set_markup("<b>Success Rate:</b> 0%");
         stats_box.append(&success_rate_label);
 
+        let circuits_label = Label::new(None);
+        circuits_label.set_markup("<b>Circuits:</b> 0 built, 0 failed");
+        stats_box.append(&circuits_label);
+
         container.append(&stats_box);
 
-        // Chart area
+        let data_history: Arc<Mutex<VecDeque<(f64, f64, f64)>>> =
+            Arc::new(Mutex::new(VecDeque::with_capacity(HISTORY_POINTS)));
+
+        // Chart area: bytes per second, written (sent) and read (received)
         let chart_area = DrawingArea::new();
         chart_area.set_content_width(400);
         chart_area.set_content_height(200);
-        chart_area.set_draw_func(|_, cr, width, height| {
-            // Simple line chart drawing
+        let history_for_chart = data_history.clone();
+        chart_area.set_draw_func(move |_, cr, width, height| {
+            let (width, height) = (width as f64, height as f64);
             cr.set_source_rgb(0.2, 0.2, 0.2);
             cr.paint().unwrap();
 
             // Draw axes
             cr.set_source_rgb(0.8, 0.8, 0.8);
             cr.set_line_width(1.0);
-            cr.move_to(20.0, height as f64 - 20.0);
-            cr.line_to(width as f64 - 20.0, height as f64 - 20.0);
+            cr.move_to(20.0, height - 20.0);
+            cr.line_to(width - 20.0, height - 20.0);
             cr.move_to(20.0, 20.0);
-            cr.line_to(20.0, height as f64 - 20.0);
+            cr.line_to(20.0, height - 20.0);
             cr.stroke().unwrap();
 
-            // Draw placeholder text
-            cr.set_source_rgb(0.6, 0.6, 0.6);
-            cr.select_font_face("Sans", gtk4::cairo::FontSlant::Normal, gtk4::cairo::FontWeight::Normal);
-            cr.set_font_size(12.0);
-            cr.move_to(width as f64 / 2.0 - 50.0, height as f64 / 2.0);
-            cr.show_text("Chart will display here").unwrap();
+            let history = history_for_chart.lock().unwrap();
+            if history.len() < 2 {
+                cr.set_source_rgb(0.6, 0.6, 0.6);
+                cr.select_font_face("Sans", gtk4::cairo::FontSlant::Normal, gtk4::cairo::FontWeight::Normal);
+                cr.set_font_size(12.0);
+                cr.move_to(width / 2.0 - 60.0, height / 2.0);
+                cr.show_text("Waiting for traffic data").unwrap();
+                return;
+            }
+
+            let peak = history
+                .iter()
+                .map(|&(_, sent, received)| sent.max(received))
+                .fold(1.0, f64::max);
+            let step = (width - 40.0) / (HISTORY_POINTS - 1) as f64;
+            let offset = HISTORY_POINTS - history.len();
+            let y = |value: f64| height - 20.0 - value / peak * (height - 40.0);
+
+            for (series, (r, g, b)) in [(1usize, (0.9, 0.5, 0.2)), (2, (0.3, 0.7, 0.9))] {
+                cr.set_source_rgb(r, g, b);
+                cr.set_line_width(1.5);
+                for (i, point) in history.iter().enumerate() {
+                    let value = if series == 1 { point.1 } else { point.2 };
+                    let x = 20.0 + (offset + i) as f64 * step;
+                    if i == 0 {
+                        cr.move_to(x, y(value));
+                    } else {
+                        cr.line_to(x, y(value));
+                    }
+                }
+                cr.stroke().unwrap();
+            }
         });
 
         container.append(&chart_area);
 
-        let data_history = Arc::new(Mutex::new(VecDeque::with_capacity(100)));
-
         Self {
             container,
             uptime_label,
             bytes_sent_label,
             bytes_received_label,
             success_rate_label,
+            circuits_label,
+            circuits_built: Cell::new(0),
+            circuits_failed: Cell::new(0),
             chart_area,
             data_history,
         }
     }
 
-    /// Update statistics display
+    /// Update the totals, uptime and success rate
     pub fn update_stats(&self, stats: &Statistics) {
         // Update uptime
         if let Some(uptime) = stats.uptime {
@@ -118,20 +161,42 @@ impl StatisticsDashboard {
             "<b>Success Rate:</b> {:.1}%",
             stats.success_rate
         ));
+    }
 
-        // Update chart data
-        let time = stats.uptime.map(|d| d.as_secs() as f64).unwrap_or(0.0);
-        let mut history = self.data_history.lock().unwrap();
-        history.push_back((time, stats.bytes_sent as f64, stats.bytes_received as f64));
-        if history.len() > 100 {
-            history.pop_front();
-        }
-        drop(history);
+    /// Replace the chart with samples from a bandwidth history
+    pub fn update_history(&self, history: &BandwidthHistory) {
+        let samples: Vec<_> = history.per_second().collect();
+        let recent = &samples[samples.len().saturating_sub(HISTORY_POINTS)..];
+
+        let mut data = self.data_history.lock().unwrap();
+        data.clear();
+        data.extend(
+            recent
+                .iter()
+                .map(|s| (s.timestamp as f64, s.written as f64, s.read as f64)),
+        );
+        drop(data);
 
-        // Redraw chart
         self.chart_area.queue_draw();
     }
 
+    /// React to an engine event (circuit builds and failures)
+    ///
+    /// Bandwidth reaches the chart through `update_history`.
+    pub fn handle_event(&self, event: &Event) {
+        match event {
+            Event::CircuitBuilt(_) => self.circuits_built.set(self.circuits_built.get() + 1),
+            Event::CircuitFailed(_) => self.circuits_failed.set(self.circuits_failed.get() + 1),
+            _ => return,
+        }
+
+        self.circuits_label.set_markup(&format!(
+            "<b>Circuits:</b> {} built, {} failed",
+            self.circuits_built.get(),
+            self.circuits_failed.get()
+        ));
+    }
+
     /// Format bytes to human-readable format
     fn format_bytes(bytes: u64) -> String {
         if bytes < 1024 {
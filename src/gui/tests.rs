@@ -21,6 +21,8 @@ mod tests {
             uptime: Some(Duration::from_secs(3600)),
             bytes_sent: 1024 * 1024,
             bytes_received: 2048 * 1024,
+            read_rate: 0,
+            write_rate: 0,
             connection_attempts: 10,
             successful_connections: 9,
             success_rate: 90.0,
@@ -44,13 +46,15 @@ mod tests {
                 id: "1".to_string(),
                 status: "BUILT".to_string(),
                 purpose: Some("GENERAL".to_string()),
-                flags: Some("FAST".to_string()),
+                build_flags: vec!["FAST".to_string()],
+                ..Default::default()
             },
             CircuitInfo {
                 id: "2".to_string(),
                 status: "BUILT".to_string(),
                 purpose: Some("GENERAL".to_string()),
-                flags: Some("STABLE".to_string()),
+                build_flags: vec!["STABLE".to_string()],
+                ..Default::default()
             },
         ];
         viz.update_circuits(circuits);
@@ -3,25 +3,29 @@ use gtk4::prelude::*;
 use gtk4::{
     ApplicationWindow, Box, Button, Label, Notebook, HeaderBar, MenuButton, 
     ScrolledWindow, TextView, Orientation, Align, ResponseType, 
-    FileChooserDialog, FileFilter, FileChooserAction, glib
+    FileChooserDialog, FileFilter, FileChooserAction, ProgressBar, glib
 };
 use gio::SimpleAction;
 use std::sync::Arc;
 use std::sync::Mutex;
 use crate::error::{TorrerError, TorrerResult};
 use crate::core::engine::{TorrerEngine, EngineStatus};
+use crate::core::monitoring::Monitoring;
+use crate::core::Event;
 use crate::gui::settings::SettingsPanel;
 use crate::gui::statistics::StatisticsDashboard;
 use crate::gui::circuit_viz::CircuitVisualization;
 use crate::gui::preferences::PreferencesPanel;
 use crate::gui::notifications::GuiNotificationManager;
 use crate::gui::dialogs;
+use crate::tor::BootstrapStatus;
 
 /// Main application window
 pub struct MainWindow {
     window: ApplicationWindow,
     engine: Arc<Mutex<Option<TorrerEngine>>>,
     status_label: Label,
+    bootstrap_bar: ProgressBar,
     start_button: Button,
     stop_button: Button,
     restart_button: Button,
@@ -87,6 +91,18 @@ impl MainWindow {
         status_box.append(&status_label);
         main_box.append(&status_box);
 
+        // Bootstrap progress, with the reason when Tor stalls
+        let bootstrap_bar = ProgressBar::new();
+        bootstrap_bar.set_show_text(true);
+        bootstrap_bar.set_text(Some("Bootstrap: unknown"));
+        main_box.append(&bootstrap_bar);
+
+        let bootstrap_warning = Label::new(None);
+        bootstrap_warning.set_halign(Align::Start);
+        bootstrap_warning.set_wrap(true);
+        bootstrap_warning.set_visible(false);
+        main_box.append(&bootstrap_warning);
+
         // Control buttons
         let button_box = Box::new(Orientation::Horizontal, 10);
         let start_button = Button::with_label("Start");
@@ -384,33 +400,71 @@ impl MainWindow {
         // Setup real-time updates
         let engine_for_updates = engine.clone();
         let status_label_for_updates = status_label.clone();
+        let bootstrap_bar_for_updates = bootstrap_bar.clone();
+        let bootstrap_warning_for_updates = bootstrap_warning.clone();
         let statistics_for_updates = statistics.clone();
         let circuit_viz_for_updates = circuit_viz.clone();
+        // The one source of traffic figures the dashboard shows
+        let monitoring = Arc::new(Mutex::new(Monitoring::new()));
         
         let update_source_id = glib::timeout_add_local(
             std::time::Duration::from_secs(2),
             move || {
                 let engine = engine_for_updates.clone();
                 let status_label = status_label_for_updates.clone();
+                let bootstrap_bar = bootstrap_bar_for_updates.clone();
+                let bootstrap_warning = bootstrap_warning_for_updates.clone();
                 let statistics = statistics_for_updates.clone();
                 let circuit_viz = circuit_viz_for_updates.clone();
+                let monitoring = monitoring.clone();
                 
                 glib::spawn_future_local(async move {
                     let mut engine_guard = engine.lock().unwrap();
                     if let Some(ref mut eng) = *engine_guard {
+                        // Bandwidth goes into the history, circuit builds/failures
+                        // reported by Tor to the dashboard
+                        {
+                            let mut monitoring = monitoring.lock().unwrap();
+                            let statistics = statistics.lock().unwrap();
+                            while let Some(event) = eng.events().try_receive() {
+                                match event {
+                                    Event::Bandwidth { read, written } => {
+                                        monitoring.record_bandwidth(read, written)
+                                    }
+                                    event => statistics.handle_event(&event),
+                                }
+                            }
+                        }
+
                         match eng.status().await {
                             Ok(status) => {
+                                {
+                                    let mut monitoring = monitoring.lock().unwrap();
+                                    match (status.is_running, monitoring.uptime().is_some()) {
+                                        (true, false) => monitoring.start(),
+                                        (false, true) => monitoring.stop(),
+                                        _ => {}
+                                    }
+                                }
                                 let status_text = format!(
-                                    "Status: {} | Tor: {} | Circuit: {}",
+                                    "Status: {} | Tor: {} | Circuit: {} | Kill Switch: {}",
                                     if status.is_running { "Running" } else { "Stopped" },
                                     if status.tor_connected { "Connected" } else { "Disconnected" },
-                                    if status.circuit_established { "Established" } else { "Not Established" }
+                                    if status.circuit_established { "Established" } else { "Not Established" },
+                                    if status.kill_switch { "On" } else { "Off" }
                                 );
                                 
                                 glib::idle_add_local(move || {
                                     status_label.set_text(&status_text);
-                                    // Update statistics and circuit visualization
-                                    // (These would need methods to update from EngineStatus)
+                                    update_bootstrap(
+                                        &bootstrap_bar,
+                                        &bootstrap_warning,
+                                        status.bootstrap.as_ref(),
+                                    );
+                                    let monitoring = monitoring.lock().unwrap();
+                                    let statistics = statistics.lock().unwrap();
+                                    statistics.update_stats(&monitoring.get_stats());
+                                    statistics.update_history(monitoring.history());
                                     false
                                 });
                             }
@@ -427,6 +481,7 @@ impl MainWindow {
             window,
             engine,
             status_label,
+            bootstrap_bar,
             start_button,
             stop_button,
             restart_button,
@@ -451,6 +506,40 @@ impl MainWindow {
     }
 }
 
+/// Show bootstrap progress and where it stalls
+fn update_bootstrap(bar: &ProgressBar, warning: &Label, bootstrap: Option<&BootstrapStatus>) {
+    let bootstrap = match bootstrap {
+        Some(bootstrap) => bootstrap,
+        None => {
+            bar.set_fraction(0.0);
+            bar.set_text(Some("Bootstrap: unknown"));
+            warning.set_visible(false);
+            return;
+        }
+    };
+
+    let phase = &bootstrap.phase;
+    bar.set_fraction(f64::from(phase.progress) / 100.0);
+    bar.set_text(Some(&format!("Bootstrap {}%: {}", phase.progress, phase.summary)));
+
+    match bootstrap.stalled_at() {
+        Some(stall) => {
+            let mut text = format!(
+                "⚠ Stalled at {}% ({}): {}",
+                stall.progress,
+                stall.tag,
+                stall.warning.as_deref().unwrap_or(&stall.summary)
+            );
+            if let Some(ref host) = stall.host {
+                text.push_str(&format!(" [{}]", host));
+            }
+            warning.set_text(&text);
+            warning.set_visible(true);
+        }
+        None => warning.set_visible(false),
+    }
+}
+
 impl Drop for MainWindow {
     fn drop(&mut self) {
         if let Some(source_id) = self.update_source_id {
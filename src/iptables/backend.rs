@@ -0,0 +1,517 @@
+// Firewall backends used for transparent routing
+
+use std::fmt;
+use std::net::ToSocketAddrs;
+use std::process::Command;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{BypassConfig, Configuration, GatewayConfig, UdpConfig, UdpPolicy};
+use crate::error::TorrerResult;
+use crate::iptables::{IptablesManager, NftablesManager};
+use crate::security::DNS_RESOLVER_PORT;
+use crate::utils::{command_exists, lookup_gid, lookup_uid, network_range, split_host_port};
+
+/// User and group tor runs as; their traffic is never redirected or blocked
+///
+/// The system daemon runs as this user, and managed tor is started with this
+/// group.
+pub const TOR_USER: &str = "debian-tor";
+
+/// Comment marking the kill switch's final DROP rule
+pub const KILL_SWITCH_COMMENT: &str = "torrer-kill-switch";
+
+/// Packet filter implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FirewallBackendKind {
+    /// Pick one at startup (see `FirewallBackendKind::detect`)
+    #[default]
+    Auto,
+    /// iptables with the legacy x_tables kernel API
+    IptablesLegacy,
+    /// iptables translated to nftables (iptables-nft)
+    IptablesNft,
+    /// Native nftables with a dedicated `inet torrer` table
+    Nftables,
+}
+
+impl FirewallBackendKind {
+    /// Name used in the configuration file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::IptablesLegacy => "iptables-legacy",
+            Self::IptablesNft => "iptables-nft",
+            Self::Nftables => "nftables",
+        }
+    }
+
+    /// Detect the backend this system should use
+    pub fn detect() -> Option<Self> {
+        let nft_usable = command_exists("nft")
+            && Command::new("nft")
+                .args(["list", "tables"])
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false);
+
+        let iptables_version = Command::new("iptables")
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|o| o.status.success())
+            .map(|o| String::from_utf8_lossy(&o.stdout).into_owned());
+
+        Self::detect_from(nft_usable, iptables_version.as_deref())
+    }
+
+    /// Choose a backend from what is installed
+    ///
+    /// Native nftables wins when `nft` works; otherwise iptables is used in
+    /// whichever mode `iptables --version` reports ("(nf_tables)" or "(legacy)").
+    pub fn detect_from(nft_usable: bool, iptables_version: Option<&str>) -> Option<Self> {
+        if nft_usable {
+            return Some(Self::Nftables);
+        }
+
+        match iptables_version {
+            Some(version) if version.contains("nf_tables") => Some(Self::IptablesNft),
+            Some(_) => Some(Self::IptablesLegacy),
+            None => None,
+        }
+    }
+
+    /// Resolve `Auto` to a concrete backend
+    pub fn resolve(self) -> Self {
+        if self != Self::Auto {
+            return self;
+        }
+
+        match Self::detect() {
+            Some(kind) => kind,
+            None => {
+                log::warn!("No usable nft or iptables found, assuming iptables-legacy");
+                Self::IptablesLegacy
+            }
+        }
+    }
+}
+
+impl fmt::Display for FirewallBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Local ports Tor listens on for redirected traffic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutingPorts {
+    /// Tor TransPort
+    pub trans_port: u16,
+    /// Tor DNSPort
+    pub dns_port: u16,
+}
+
+impl Default for RoutingPorts {
+    fn default() -> Self {
+        Self {
+            trans_port: 9040,
+            dns_port: 5353,
+        }
+    }
+}
+
+/// Which of Torrer's rules are enabled
+///
+/// Backends render the whole set on every change, so exclusions always come
+/// before the rules they exempt from, whatever order features are enabled in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoutingRules {
+    pub ports: RoutingPorts,
+    pub tor_routing: bool,
+    pub dns_redirect: bool,
+    /// Redirect the host's DNS to Torrer's resolver rather than the DNSPort
+    pub dns_resolver: bool,
+    pub block_ipv6: bool,
+    /// Redirect IPv6 TCP (and DNS) to Tor and reject other IPv6 egress;
+    /// `block_ipv6` wins when both are set
+    pub route_ipv6: bool,
+    /// Drop all outgoing traffic that is not loopback, tor or redirected to Tor
+    pub kill_switch: bool,
+    /// Ranges (CIDR) the kill switch still lets through, e.g. the LAN
+    pub lan_ranges: Vec<String>,
+    /// UID whose traffic bypasses the redirect (the tor daemon)
+    pub tor_uid: Option<u32>,
+    /// GID whose traffic bypasses the redirect (managed tor)
+    pub tor_gid: Option<u32>,
+    /// Split tunneling: traffic that goes direct
+    pub bypass: BypassRules,
+    /// Gateway mode: LAN clients routed through Tor
+    pub gateway: Option<GatewayRules>,
+    /// Handling of UDP other than DNS while routing
+    pub udp: UdpRules,
+    /// Ranges (as `network/prefix`) Tor maps onion names into; TCP to them
+    /// goes to Tor ahead of any exemption
+    pub virtual_networks: Vec<String>,
+}
+
+/// LAN interface and clients routed through Tor in gateway mode
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GatewayRules {
+    pub interface: String,
+    /// Client ranges, as `network/prefix`; empty serves the whole interface
+    pub clients: Vec<String>,
+}
+
+impl GatewayRules {
+    /// Normalize the gateway configuration
+    pub fn from_config(config: &GatewayConfig) -> Self {
+        Self {
+            interface: config.interface.clone(),
+            clients: config
+                .clients
+                .iter()
+                .filter_map(|range| network_range(range).ok())
+                .filter(|(address, _)| address.is_ipv4())
+                .map(|(address, prefix)| format!("{}/{}", address, prefix))
+                .collect(),
+        }
+    }
+}
+
+/// DHCP client and server ports, for IPv4 and IPv6, never blocked by the UDP policy
+pub const DHCP_PORTS: [(u16, u16); 2] = [(68, 67), (546, 547)];
+
+/// Private ranges reached directly with `local_networks`
+pub const LOCAL_NETWORKS: [&str; 5] = [
+    "10.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+/// Split tunneling entries resolved to what the packet filter matches on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BypassRules {
+    /// Destination ranges, as `network/prefix`
+    pub destinations: Vec<String>,
+    pub uids: Vec<u32>,
+    pub gids: Vec<u32>,
+    /// cgroup v2 paths, relative to the cgroup root
+    pub cgroups: Vec<String>,
+}
+
+impl BypassRules {
+    /// Resolve users, groups and domains in `config`
+    ///
+    /// Entries that cannot be resolved are skipped with a warning; their
+    /// traffic then keeps going through Tor.
+    pub fn resolve(config: &BypassConfig) -> Self {
+        let mut bypass = Self::default();
+
+        let mut ranges: Vec<String> = config.networks.clone();
+        if config.local_networks {
+            ranges.extend(LOCAL_NETWORKS.iter().map(|range| range.to_string()));
+        }
+        for domain in &config.domains {
+            match (domain.as_str(), 0).to_socket_addrs() {
+                Ok(addrs) => ranges.extend(addrs.map(|addr| addr.ip().to_string())),
+                Err(e) => log::warn!("Cannot resolve bypass domain {}: {}", domain, e),
+            }
+        }
+        for range in ranges {
+            match network_range(&range) {
+                Ok((address, prefix)) => {
+                    let range = format!("{}/{}", address, prefix);
+                    if !bypass.destinations.contains(&range) {
+                        bypass.destinations.push(range);
+                    }
+                }
+                Err(e) => log::warn!("Ignoring bypass range {}: {}", range, e),
+            }
+        }
+
+        for user in &config.users {
+            match user.parse().ok().or_else(|| lookup_uid(user)) {
+                Some(uid) => bypass.uids.push(uid),
+                None => log::warn!("Ignoring unknown bypass user {}", user),
+            }
+        }
+        for group in &config.groups {
+            match group.parse().ok().or_else(|| lookup_gid(group)) {
+                Some(gid) => bypass.gids.push(gid),
+                None => log::warn!("Ignoring unknown bypass group {}", group),
+            }
+        }
+        bypass.cgroups = config
+            .cgroups
+            .iter()
+            .map(|path| path.trim_matches('/').to_string())
+            .collect();
+
+        bypass
+    }
+
+    /// Destination ranges of one address family
+    pub fn destinations(&self, ipv6: bool) -> impl Iterator<Item = &str> {
+        self.destinations
+            .iter()
+            .map(String::as_str)
+            .filter(move |range| range.contains(':') == ipv6)
+    }
+}
+
+/// UDP policy with its exceptions resolved
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UdpRules {
+    pub policy: UdpPolicy,
+    /// Destination range (any when `None`) and port still reached over UDP
+    pub allow: Vec<(Option<String>, u16)>,
+}
+
+impl UdpRules {
+    /// Resolve the host names among the exceptions in `config`
+    ///
+    /// Entries that cannot be parsed or resolved are skipped with a warning,
+    /// so their traffic falls under the policy.
+    pub fn resolve(config: &UdpConfig) -> Self {
+        let mut udp = Self {
+            policy: config.policy,
+            allow: Vec::new(),
+        };
+
+        for entry in &config.allow {
+            let (host, port) = match split_host_port(entry) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    log::warn!("Ignoring UDP exception: {}", e);
+                    continue;
+                }
+            };
+            let host = match host {
+                Some(host) => host,
+                None => {
+                    udp.allow.push((None, port));
+                    continue;
+                }
+            };
+
+            let ranges: Vec<String> = match network_range(host) {
+                Ok((address, prefix)) => vec![format!("{}/{}", address, prefix)],
+                Err(_) => match (host, 0).to_socket_addrs() {
+                    Ok(addrs) => addrs
+                        .filter_map(|addr| network_range(&addr.ip().to_string()).ok())
+                        .map(|(address, prefix)| format!("{}/{}", address, prefix))
+                        .collect(),
+                    Err(e) => {
+                        log::warn!("Cannot resolve UDP exception {}: {}", host, e);
+                        Vec::new()
+                    }
+                },
+            };
+            for range in ranges {
+                let allowed = (Some(range), port);
+                if !udp.allow.contains(&allowed) {
+                    udp.allow.push(allowed);
+                }
+            }
+        }
+
+        udp
+    }
+
+    /// Exceptions of one address family; port-only ones apply to both
+    pub fn allowed(&self, ipv6: bool) -> impl Iterator<Item = (Option<&str>, u16)> {
+        self.allow
+            .iter()
+            .map(|(range, port)| (range.as_deref(), *port))
+            .filter(move |(range, _)| match range {
+                Some(range) => range.contains(':') == ipv6,
+                None => true,
+            })
+    }
+}
+
+impl RoutingRules {
+    /// No rules enabled, exempting the system tor user if it exists
+    pub fn new(ports: RoutingPorts) -> Self {
+        Self {
+            ports,
+            tor_uid: lookup_uid(TOR_USER),
+            tor_gid: lookup_gid(TOR_USER),
+            ..Self::default()
+        }
+    }
+
+    /// The rules `torrer start` ends up with for `config`
+    ///
+    /// Start always routes TCP and DNS through Tor. IPv6 is routed the same
+    /// way with `ipv6_enabled`, and blocked otherwise; the kill switch and
+    /// split tunneling depend on the configuration.
+    pub fn planned(config: &Configuration) -> Self {
+        Self {
+            tor_routing: true,
+            dns_redirect: true,
+            dns_resolver: config.dns_resolver,
+            block_ipv6: !config.ipv6_enabled,
+            route_ipv6: config.ipv6_enabled,
+            kill_switch: config.kill_switch,
+            lan_ranges: if config.kill_switch { config.lan_ranges.clone() } else { Vec::new() },
+            bypass: BypassRules::resolve(&config.bypass),
+            gateway: config.gateway.as_ref().map(GatewayRules::from_config),
+            udp: UdpRules::resolve(&config.udp),
+            virtual_networks: if config.onion.automap {
+                config
+                    .onion
+                    .virtual_networks()
+                    .iter()
+                    .filter_map(|range| network_range(range).ok())
+                    .map(|(network, prefix)| format!("{}/{}", network, prefix))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            ..Self::new(config.routing_ports())
+        }
+    }
+
+    /// Whether any rule is enabled
+    pub fn is_empty(&self) -> bool {
+        !self.tor_routing
+            && !self.dns_redirect
+            && !self.block_ipv6
+            && !self.route_ipv6
+            && !self.kill_switch
+    }
+
+    /// Whether IPv6 traffic is filtered (blocked, routed or by the kill switch)
+    pub fn blocks_ipv6(&self) -> bool {
+        self.block_ipv6 || self.route_ipv6 || self.kill_switch
+    }
+
+    /// Whether IPv6 TCP and DNS are redirected to Tor
+    pub fn routes_ipv6(&self) -> bool {
+        self.route_ipv6 && !self.block_ipv6
+    }
+
+    /// Whether UDP other than DNS is dropped or rejected
+    ///
+    /// The policy applies while routing; the kill switch drops UDP anyway.
+    pub fn filters_udp(&self) -> bool {
+        self.tor_routing && self.udp.policy != UdpPolicy::Allow
+    }
+
+    /// Port the host's DNS is redirected to
+    ///
+    /// Gateway clients always go to the DNSPort, since the resolver only
+    /// listens on loopback.
+    pub fn local_dns_port(&self) -> u16 {
+        if self.dns_resolver {
+            DNS_RESOLVER_PORT
+        } else {
+            self.ports.dns_port
+        }
+    }
+
+    /// Whether the gateway chains are loaded
+    ///
+    /// With the kill switch they stay after routing stops, still dropping
+    /// LAN forwarding.
+    pub fn serves_gateway(&self) -> bool {
+        self.gateway.is_some() && (self.tor_routing || self.kill_switch)
+    }
+
+    /// Disable every rule except the kill switch
+    pub fn clear(&mut self) {
+        self.tor_routing = false;
+        self.dns_redirect = false;
+        self.block_ipv6 = false;
+        self.route_ipv6 = false;
+    }
+}
+
+/// Packet filter rules needed for transparent Tor routing
+///
+/// `TorrerEngine`, `DnsManager` and `Ipv6Manager` share one backend, so every
+/// rule Torrer installs goes through the same tool.
+pub trait FirewallBackend: Send + Sync {
+    /// Which implementation this is
+    fn kind(&self) -> FirewallBackendKind;
+
+    /// Save the current ruleset before Torrer changes it
+    fn backup(&self) -> TorrerResult<()>;
+
+    /// Load `rules` as one transaction and check they are in place
+    ///
+    /// On any failure the ruleset saved by `backup` is put back before the
+    /// error is returned.
+    fn apply(&self, rules: &RoutingRules) -> TorrerResult<()>;
+
+    /// Undo Torrer's changes, except the kill switch
+    fn restore(&self) -> TorrerResult<()>;
+
+    /// Redirect outgoing TCP to Tor's TransPort
+    fn apply_tor_routing(&self) -> TorrerResult<()>;
+
+    /// Remove the TCP redirect
+    fn remove_tor_routing(&self) -> TorrerResult<()>;
+
+    /// Redirect DNS to Tor's DNSPort and drop DNS that bypasses it
+    fn apply_dns_redirect(&self) -> TorrerResult<()>;
+
+    /// Remove the DNS redirect and block
+    fn remove_dns_redirect(&self) -> TorrerResult<()>;
+
+    /// Drop outgoing IPv6, which Tor's TransPort does not carry
+    fn block_ipv6(&self) -> TorrerResult<()>;
+
+    /// Remove the IPv6 block
+    fn unblock_ipv6(&self) -> TorrerResult<()>;
+
+    /// Redirect outgoing IPv6 TCP and DNS to Tor, rejecting all other IPv6
+    fn apply_ipv6_routing(&self) -> TorrerResult<()>;
+
+    /// Remove the IPv6 redirect and reject
+    fn remove_ipv6_routing(&self) -> TorrerResult<()>;
+
+    /// Drop all outgoing traffic except loopback, tor, connections redirected
+    /// to Tor and `lan_ranges`
+    ///
+    /// `restore` leaves the kill switch in place, so traffic stays blocked
+    /// until it is lifted explicitly.
+    fn enable_kill_switch(&self, lan_ranges: &[String]) -> TorrerResult<()>;
+
+    /// Lift the kill switch
+    fn disable_kill_switch(&self) -> TorrerResult<()>;
+
+    /// Whether the kill switch is installed in the kernel (by any process)
+    fn kill_switch_active(&self) -> bool;
+
+    /// Whether Torrer's redirects are installed in the kernel (by any process)
+    fn routing_active(&self) -> bool;
+
+    /// Send all traffic from a network namespace's veth into Tor
+    ///
+    /// Used by `torrer exec`; works without the system-wide rules.
+    fn attach_namespace(&self, veth: &str) -> TorrerResult<()>;
+
+    /// Remove the rules added by `attach_namespace`
+    fn detach_namespace(&self, veth: &str) -> TorrerResult<()>;
+}
+
+/// Create the backend for `kind`, detecting one if it is `Auto`
+pub fn create_backend(
+    kind: FirewallBackendKind,
+    ports: RoutingPorts,
+) -> TorrerResult<Arc<dyn FirewallBackend>> {
+    let kind = kind.resolve();
+    log::info!("Using {} firewall backend", kind);
+
+    Ok(match kind {
+        FirewallBackendKind::Nftables => Arc::new(NftablesManager::new(ports)),
+        other => Arc::new(IptablesManager::with_backend(other, ports)?),
+    })
+}
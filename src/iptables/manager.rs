@@ -1,22 +1,60 @@
 use std::process::{Command, Stdio};
 use std::path::PathBuf;
 use std::fs;
+use std::io::Write;
+use std::sync::Mutex;
 
 use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::plan::{iptables_lines, loaded_families, saved_lines};
+use crate::iptables::rules::{
+    chain_rules, insert_script, merge_scripts, namespace_rules, remove_script, restore_script, ChainFamily,
+    RuleType, FILTER_CHAIN, KILL_SWITCH_DROP,
+};
+use crate::iptables::{FirewallBackend, FirewallBackendKind, RoutingPorts, RoutingRules};
+use crate::utils::command_exists;
 
 const IPTABLES_BACKUP_DIR: &str = "/var/lib/torrer";
 const IPTABLES_BACKUP_FILE: &str = "iptables-backup.rules";
-const TOR_TRANSPORT_PORT: u16 = 9040;
-const TOR_DNS_PORT: u16 = 5353;
+
+/// Chain families loaded together, in one `-restore` run per tool
+const TRANSACTIONS: [(&str, [ChainFamily; 2]); 2] = [
+    ("iptables", [ChainFamily::Ipv4, ChainFamily::Gateway]),
+    ("ip6tables", [ChainFamily::Ipv6, ChainFamily::Ipv6Routing]),
+];
 
 /// iptables manager for Tor routing
+///
+/// All rules live in Torrer's own `TORRER_NAT`/`TORRER_FILTER` chains, jumped
+/// to from the top of OUTPUT. Rules installed by Docker, libvirt or VPN
+/// clients are never flushed or reordered.
 pub struct IptablesManager {
     backup_path: PathBuf,
+    kind: FirewallBackendKind,
+    /// "-legacy"/"-nft" when the mode-specific binaries are installed
+    suffix: &'static str,
+    rules: Mutex<RoutingRules>,
+    /// `iptables-save`/`ip6tables-save` output taken by `backup`, for rollback
+    snapshot: Mutex<Vec<(ChainFamily, String)>>,
 }
 
 impl IptablesManager {
-    /// Create a new IptablesManager
+    /// Create a new IptablesManager using the default `iptables` binaries
     pub fn new() -> TorrerResult<Self> {
+        Self::with_backend(FirewallBackendKind::IptablesLegacy, RoutingPorts::default())
+    }
+
+    /// Create an IptablesManager for iptables-legacy or iptables-nft
+    ///
+    /// Falls back to plain `iptables` when the mode-specific binary is missing.
+    pub fn with_backend(kind: FirewallBackendKind, ports: RoutingPorts) -> TorrerResult<Self> {
+        if !matches!(kind, FirewallBackendKind::IptablesNft | FirewallBackendKind::IptablesLegacy) {
+            return Err(TorrerError::Iptables(format!(
+                "{} is not an iptables backend",
+                kind
+            )));
+        }
+        let suffix = tool_suffix(kind);
+
         let backup_dir = PathBuf::from(IPTABLES_BACKUP_DIR);
         
         // Create backup directory if it doesn't exist
@@ -28,39 +66,28 @@ impl IptablesManager {
 
         Ok(Self {
             backup_path: backup_dir.join(IPTABLES_BACKUP_FILE),
+            kind,
+            suffix,
+            rules: Mutex::new(RoutingRules::new(ports)),
+            snapshot: Mutex::new(Vec::new()),
         })
     }
 
-    /// Backup current iptables rules
-    pub fn backup(&self) -> TorrerResult<()> {
-        log::info!("Backing up current iptables rules to {:?}", self.backup_path);
-
-        let output = Command::new("iptables-save")
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| {
-                TorrerError::Iptables(format!("Failed to run iptables-save: {}", e))
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            return Err(TorrerError::Iptables(format!(
-                "iptables-save failed: {}",
-                stderr
-            )));
-        }
-
-        fs::write(&self.backup_path, &output.stdout).map_err(|e| {
-            TorrerError::Iptables(format!("Failed to write backup file: {}", e))
-        })?;
+    /// Name of an iptables tool in this manager's mode, e.g. `iptables-nft-save`
+    pub fn tool(&self, name: &str) -> String {
+        tool_name(self.suffix, name)
+    }
 
-        log::info!("iptables rules backed up successfully");
-        Ok(())
+    /// The rules currently applied by this manager
+    pub fn rules(&self) -> RoutingRules {
+        self.rules.lock().unwrap().clone()
     }
 
-    /// Restore iptables rules from backup
-    pub fn restore(&self) -> TorrerResult<()> {
+    /// Restore the full ruleset saved by `backup`
+    ///
+    /// This also discards rules other software added since the backup, so it
+    /// is only meant for manual recovery; stopping routing uses `restore`.
+    pub fn restore_backup(&self) -> TorrerResult<()> {
         if !self.backup_path.exists() {
             log::warn!("No backup file found at {:?}, skipping restore", self.backup_path);
             return Ok(());
@@ -72,106 +99,375 @@ impl IptablesManager {
             TorrerError::Iptables(format!("Failed to read backup file: {}", e))
         })?;
 
-        let mut output = Command::new("iptables-restore")
+        self.run_restore(&self.tool("iptables-restore"), &[], &backup_content)?;
+
+        log::info!("iptables rules restored successfully");
+        Ok(())
+    }
+
+    /// Change the rules and load them, keeping the old ones on failure
+    ///
+    /// A failed load can leave one tool's transaction committed, so the
+    /// previous rules are loaded again to keep the kernel matching `rules`.
+    fn update(&self, change: impl FnOnce(&mut RoutingRules)) -> TorrerResult<()> {
+        let mut rules = self.rules.lock().unwrap();
+        let mut updated = rules.clone();
+        change(&mut updated);
+
+        if let Err(e) = self.load(&updated) {
+            log::error!("Loading iptables rules failed, putting the previous ones back: {}", e);
+            if let Err(e) = self.load(&rules) {
+                log::error!("Putting the previous iptables rules back failed: {}", e);
+            }
+            return Err(e);
+        }
+
+        *rules = updated;
+        Ok(())
+    }
+
+    /// Load `rules` into Torrer's chains and remove the chains they do not use
+    ///
+    /// Each tool gets one `--noflush` transaction: iptables for the IPv4 and
+    /// gateway chains, ip6tables for IPv6 blocking and routing.
+    fn load(&self, rules: &RoutingRules) -> TorrerResult<()> {
+        let loaded = loaded_families(rules);
+
+        for (tool, families) in TRANSACTIONS {
+            let iptables = self.tool(tool);
+            let mut scripts = Vec::new();
+
+            for family in families {
+                if !loaded.contains(&family) {
+                    scripts.push(remove_script(family, &self.live_jumps(family)));
+                    continue;
+                }
+
+                // Hooks go into the same commit as their chains
+                let mut unhooked = Vec::new();
+                for (table, chain, hook) in family.chains() {
+                    if !self.run(&iptables, &["-t", table.as_str(), "-C", hook, "-j", chain])? {
+                        unhooked.push(*chain);
+                    }
+                }
+                scripts.push(restore_script(&chain_rules(rules, family), family, &unhooked));
+            }
+
+            // Nothing to load or remove, e.g. no ip6tables on this host
+            let script = merge_scripts(&scripts);
+            if !script.is_empty() {
+                self.run_restore(&self.tool(&format!("{}-restore", tool)), &["--noflush"], &script)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Torrer's chains of `family` that exist, with the jumps to each from its hook
+    fn live_jumps(&self, family: ChainFamily) -> Vec<(&'static str, usize)> {
+        let iptables = self.tool(family.tool());
+
+        family
+            .chains()
+            .iter()
+            .filter(|(table, chain, _)| matches!(self.run(&iptables, &["-t", table.as_str(), "-S", chain]), Ok(true)))
+            .map(|(table, chain, hook)| {
+                let jump = format!("-A {} -j {}", hook, chain);
+                let jumps = Command::new(&iptables)
+                    .args(["-t", table.as_str(), "-S", hook])
+                    .output()
+                    .map(|output| {
+                        String::from_utf8_lossy(&output.stdout)
+                            .lines()
+                            .filter(|line| line.trim() == jump)
+                            .count()
+                    })
+                    .unwrap_or(0);
+                (*chain, jumps)
+            })
+            .collect()
+    }
+
+    /// Check that the kernel holds exactly `rules` in Torrer's chains
+    fn verify(&self, rules: &RoutingRules) -> TorrerResult<()> {
+        let mut expected = iptables_lines(rules);
+        let mut live = saved_lines(self.suffix, &loaded_families(rules))?;
+        expected.sort();
+        live.sort();
+
+        if live != expected {
+            let missing = expected.iter().filter(|line| !live.contains(line)).count();
+            let unexpected = live.iter().filter(|line| !expected.contains(line)).count();
+            return Err(TorrerError::Iptables(format!(
+                "Ruleset check failed: {} rule(s) missing, {} unexpected",
+                missing, unexpected
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Put back the ruleset saved by `backup`, or drop Torrer's chains without one
+    fn rollback(&self) {
+        let snapshot = self.snapshot.lock().unwrap().clone();
+        if snapshot.is_empty() {
+            log::warn!("No iptables backup taken, removing Torrer's chains instead");
+            if let Err(e) = self.load(&RoutingRules::default()) {
+                log::error!("Rollback failed: {}", e);
+            }
+        }
+
+        for (family, dump) in &snapshot {
+            let restore = self.tool(&format!("{}-restore", family.tool()));
+            if let Err(e) = self.run_restore(&restore, &[], dump) {
+                log::error!("Rollback with {} failed: {}", restore, e);
+            }
+        }
+
+        // The snapshot may hold a kill switch enabled before this run
+        let kill_switch = self.kill_switch_active();
+        let mut rules = self.rules.lock().unwrap();
+        rules.clear();
+        rules.kill_switch = kill_switch;
+    }
+
+    /// Feed a script to iptables-restore / ip6tables-restore
+    fn run_restore(&self, program: &str, args: &[&str], script: &str) -> TorrerResult<()> {
+        let mut child = Command::new(program)
+            .args(args)
             .stdin(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
             .map_err(|e| {
-                TorrerError::Iptables(format!("Failed to run iptables-restore: {}", e))
+                TorrerError::Iptables(format!("Failed to run {}: {}", program, e))
             })?;
 
-        // Write backup content to stdin
-        use std::io::Write;
-        if let Some(mut stdin) = output.stdin.take() {
-            stdin.write_all(backup_content.as_bytes()).map_err(|e| {
-                TorrerError::Iptables(format!("Failed to write to iptables-restore: {}", e))
-            })?;
-            stdin.flush().map_err(|e| {
-                TorrerError::Iptables(format!("Failed to flush iptables-restore: {}", e))
+        // Write the script to stdin; dropping it signals EOF
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(script.as_bytes()).map_err(|e| {
+                TorrerError::Iptables(format!("Failed to write to {}: {}", program, e))
             })?;
-            // Drop stdin to close it, signaling EOF to the process
-            drop(stdin);
         }
 
-        let status = output.wait().map_err(|e| {
-            TorrerError::Iptables(format!("Failed to wait for iptables-restore: {}", e))
+        let output = child.wait_with_output().map_err(|e| {
+            TorrerError::Iptables(format!("Failed to wait for {}: {}", program, e))
         })?;
 
-        if !status.success() {
+        if !output.status.success() {
             return Err(TorrerError::Iptables(format!(
-                "iptables-restore failed with exit code: {:?}",
-                status.code()
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
             )));
         }
 
-        log::info!("iptables rules restored successfully");
         Ok(())
     }
 
-    /// Apply Tor routing rules
-    pub fn apply_tor_routing(&self) -> TorrerResult<()> {
-        log::info!("Applying iptables rules for Tor routing");
+    /// Run an iptables command, returning whether it succeeded
+    fn run(&self, program: &str, args: &[&str]) -> TorrerResult<bool> {
+        let output = Command::new(program)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| {
+                TorrerError::Iptables(format!("Failed to run {}: {}", program, e))
+            })?;
 
-        // Flush existing NAT rules (be careful!)
-        self.run_iptables(&["-t", "nat", "-F", "OUTPUT"])?;
-
-        // Redirect TCP traffic to Tor TransPort
-        self.run_iptables(&[
-            "-t", "nat",
-            "-A", "OUTPUT",
-            "!", "-o", "lo",
-            "-p", "tcp",
-            "-m", "tcp",
-            "--syn",
-            "-j", "REDIRECT",
-            "--to-ports", &TOR_TRANSPORT_PORT.to_string(),
-        ])?;
-
-        // Don't redirect Tor's own traffic (if debian-tor user exists)
-        // This may fail on some systems, which is OK
-        let _ = self.run_iptables(&[
-            "-t", "nat",
-            "-A", "OUTPUT",
-            "-m", "owner",
-            "--uid-owner", "debian-tor",
-            "-j", "RETURN",
-        ]);
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            // Some errors are expected (e.g., chain doesn't exist when removing)
+            log::debug!("{} command failed: {} (args: {:?})", program, stderr, args);
+        }
 
-        log::info!("Tor routing rules applied successfully");
-        Ok(())
+        Ok(output.status.success())
     }
+}
 
-    /// Remove Tor routing rules
-    pub fn remove_tor_routing(&self) -> TorrerResult<()> {
-        log::info!("Removing Tor routing rules");
-
-        // Try to remove the rules (may fail if they don't exist, which is OK)
-        let _ = self.run_iptables(&["-t", "nat", "-D", "OUTPUT", "-p", "tcp", "-m", "tcp", "--syn", "-j", "REDIRECT", "--to-ports", &TOR_TRANSPORT_PORT.to_string()]);
-        let _ = self.run_iptables(&["-t", "nat", "-D", "OUTPUT", "-m", "owner", "--uid-owner", "debian-tor", "-j", "RETURN"]);
-
-        log::info!("Tor routing rules removed");
-        Ok(())
+impl FirewallBackend for IptablesManager {
+    fn kind(&self) -> FirewallBackendKind {
+        self.kind
     }
 
-    /// Run an iptables command
-    fn run_iptables(&self, args: &[&str]) -> TorrerResult<()> {
-        let output = Command::new("iptables")
-            .args(args)
+    /// Backup current iptables rules
+    fn backup(&self) -> TorrerResult<()> {
+        log::info!("Backing up current iptables rules to {:?}", self.backup_path);
+
+        let output = Command::new(self.tool("iptables-save"))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
             .map_err(|e| {
-                TorrerError::Iptables(format!("Failed to run iptables: {}", e))
+                TorrerError::Iptables(format!("Failed to run iptables-save: {}", e))
             })?;
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            // Some errors are expected (e.g., rule doesn't exist when removing)
-            log::debug!("iptables command failed: {} (args: {:?})", stderr, args);
+            return Err(TorrerError::Iptables(format!(
+                "iptables-save failed: {}",
+                stderr
+            )));
+        }
+
+        fs::write(&self.backup_path, &output.stdout).map_err(|e| {
+            TorrerError::Iptables(format!("Failed to write backup file: {}", e))
+        })?;
+
+        let mut snapshot = vec![(ChainFamily::Ipv4, String::from_utf8_lossy(&output.stdout).into_owned())];
+        // Hosts without IPv6 have nothing to save
+        match Command::new(self.tool("ip6tables-save")).output() {
+            Ok(ipv6) if ipv6.status.success() => {
+                snapshot.push((ChainFamily::Ipv6, String::from_utf8_lossy(&ipv6.stdout).into_owned()));
+            }
+            _ => log::debug!("ip6tables-save unavailable, IPv6 rules not backed up"),
+        }
+        *self.snapshot.lock().unwrap() = snapshot;
+
+        log::info!("iptables rules backed up successfully");
+        Ok(())
+    }
+
+    /// Load all chains and hooks, checking them with iptables-save afterwards
+    fn apply(&self, rules: &RoutingRules) -> TorrerResult<()> {
+        log::info!("Applying Torrer's iptables rules");
+
+        let result = self
+            .update(|current| *current = rules.clone())
+            .and_then(|_| self.verify(rules));
+        if let Err(e) = result {
+            log::error!("Applying iptables rules failed, rolling back: {}", e);
+            self.rollback();
+            return Err(e);
         }
 
+        log::info!("iptables rules applied and verified");
+        Ok(())
+    }
+
+    /// Remove Torrer's chains
+    fn restore(&self) -> TorrerResult<()> {
+        log::info!("Removing Torrer iptables chains");
+        self.update(RoutingRules::clear)
+    }
+
+    /// Apply Tor routing rules
+    fn apply_tor_routing(&self) -> TorrerResult<()> {
+        log::info!("Applying iptables rules for Tor routing");
+        self.update(|rules| rules.tor_routing = true)?;
+        log::info!("Tor routing rules applied successfully");
+        Ok(())
+    }
+
+    /// Remove Tor routing rules
+    fn remove_tor_routing(&self) -> TorrerResult<()> {
+        log::info!("Removing Tor routing rules");
+        self.update(|rules| rules.tor_routing = false)?;
+        log::info!("Tor routing rules removed");
         Ok(())
     }
+
+    /// Redirect DNS queries to Tor DNSPort and block direct DNS queries
+    fn apply_dns_redirect(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.dns_redirect = true)
+    }
+
+    /// Remove DNS redirect and block rules
+    fn remove_dns_redirect(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.dns_redirect = false)
+    }
+
+    /// Drop outgoing IPv6 except on loopback
+    fn block_ipv6(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.block_ipv6 = true)
+    }
+
+    /// Remove the IPv6 block rule
+    fn unblock_ipv6(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.block_ipv6 = false)
+    }
+
+    /// Redirect IPv6 TCP and DNS to Tor via ip6tables' nat table
+    fn apply_ipv6_routing(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.route_ipv6 = true)
+    }
+
+    /// Remove the IPv6 redirect and reject rules
+    fn remove_ipv6_routing(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.route_ipv6 = false)
+    }
+
+    fn enable_kill_switch(&self, lan_ranges: &[String]) -> TorrerResult<()> {
+        log::info!("Enabling kill switch");
+        self.update(|rules| {
+            rules.kill_switch = true;
+            rules.lan_ranges = lan_ranges.to_vec();
+        })
+    }
+
+    fn disable_kill_switch(&self) -> TorrerResult<()> {
+        log::info!("Disabling kill switch");
+        self.update(|rules| rules.kill_switch = false)
+    }
+
+    fn attach_namespace(&self, veth: &str) -> TorrerResult<()> {
+        let ports = self.rules.lock().unwrap().ports;
+        let script = insert_script(&namespace_rules(veth, &ports), false);
+        self.run_restore(&self.tool("iptables-restore"), &["--noflush"], &script)
+    }
+
+    fn detach_namespace(&self, veth: &str) -> TorrerResult<()> {
+        let ports = self.rules.lock().unwrap().ports;
+        let script = insert_script(&namespace_rules(veth, &ports), true);
+        self.run_restore(&self.tool("iptables-restore"), &["--noflush"], &script)
+    }
+
+    fn kill_switch_active(&self) -> bool {
+        let mut check = vec!["-C", FILTER_CHAIN];
+        check.extend_from_slice(&KILL_SWITCH_DROP);
+        self.run(&self.tool("iptables"), &check).unwrap_or(false)
+    }
+
+    fn routing_active(&self) -> bool {
+        [ChainFamily::Ipv4, ChainFamily::Ipv6Routing, ChainFamily::Gateway]
+            .iter()
+            .any(|family| {
+                let iptables = self.tool(family.tool());
+                family
+                    .chains()
+                    .iter()
+                    .filter(|(table, _, _)| *table == RuleType::Nat)
+                    .any(|(table, chain, _)| {
+                        matches!(self.run(&iptables, &["-t", table.as_str(), "-S", chain]), Ok(true))
+                    })
+            })
+    }
+}
+
+/// Suffix of the binaries for `kind`, or "" when they are not installed
+pub(crate) fn tool_suffix(kind: FirewallBackendKind) -> &'static str {
+    let suffix = match kind {
+        FirewallBackendKind::IptablesNft => "-nft",
+        FirewallBackendKind::IptablesLegacy => "-legacy",
+        _ => return "",
+    };
+    if command_exists(&format!("iptables{}", suffix)) {
+        suffix
+    } else {
+        ""
+    }
+}
+
+/// Insert `suffix` after the iptables/ip6tables prefix of `name`
+pub(crate) fn tool_name(suffix: &str, name: &str) -> String {
+    match name.strip_prefix("ip6tables") {
+        Some(rest) => format!("ip6tables{}{}", suffix, rest),
+        None => format!(
+            "iptables{}{}",
+            suffix,
+            name.strip_prefix("iptables").unwrap_or(name)
+        ),
+    }
 }
 
 impl Default for IptablesManager {
@@ -179,4 +475,3 @@ impl Default for IptablesManager {
         Self::new().expect("Failed to create IptablesManager")
     }
 }
-
@@ -1,4 +1,16 @@
+pub mod backend;
 pub mod manager;
+pub mod nftables;
+pub mod plan;
 pub mod rules;
 
+pub use backend::{
+    create_backend, BypassRules, FirewallBackend, FirewallBackendKind, GatewayRules, RoutingPorts,
+    RoutingRules, UdpRules,
+};
 pub use manager::IptablesManager;
+pub use nftables::{render_namespace_table, render_ruleset, NftablesManager};
+pub use plan::{diff_lines, DiffLine, FirewallPlan};
+pub use rules::{
+    chain_rules, insert_script, merge_scripts, namespace_rules, remove_script, restore_script, ChainFamily,
+};
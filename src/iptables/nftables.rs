@@ -0,0 +1,513 @@
+// Native nftables backend: all rules live in a dedicated `inet torrer` table
+
+use std::fmt::Write as _;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::config::UdpPolicy;
+use crate::iptables::backend::{DHCP_PORTS, KILL_SWITCH_COMMENT};
+use crate::iptables::plan::{listed_lines, nft_lines};
+use crate::iptables::{FirewallBackend, FirewallBackendKind, RoutingPorts, RoutingRules};
+use crate::utils::network_range;
+
+/// Family and name of Torrer's nftables table
+pub const NFT_TABLE: &str = "inet torrer";
+
+/// Script for `nft -f` that replaces Torrer's table in one transaction
+///
+/// The leading `table`/`delete table` pair makes the delete succeed even
+/// when the table does not exist yet.
+pub fn render_ruleset(rules: &RoutingRules) -> String {
+    let mut script = String::new();
+    let _ = writeln!(script, "table {}", NFT_TABLE);
+    let _ = writeln!(script, "delete table {}", NFT_TABLE);
+
+    if rules.is_empty() {
+        return script;
+    }
+
+    let _ = writeln!(script, "table {} {{", NFT_TABLE);
+
+    // tor's own traffic must never loop back into Tor
+    let mut exemptions = String::new();
+    if let Some(uid) = rules.tor_uid {
+        let _ = writeln!(exemptions, "\t\tmeta skuid {} return", uid);
+    }
+    if let Some(gid) = rules.tor_gid {
+        let _ = writeln!(exemptions, "\t\tmeta skgid {} return", gid);
+    }
+
+    // Split tunneling
+    let bypass = &rules.bypass;
+    for uid in &bypass.uids {
+        let _ = writeln!(exemptions, "\t\tmeta skuid {} return", uid);
+    }
+    for gid in &bypass.gids {
+        let _ = writeln!(exemptions, "\t\tmeta skgid {} return", gid);
+    }
+    for path in &bypass.cgroups {
+        let level = path.split('/').filter(|part| !part.is_empty()).count();
+        let _ = writeln!(exemptions, "\t\tsocket cgroupv2 level {} \"{}\" return", level, path);
+    }
+    for range in bypass.destinations(false) {
+        let _ = writeln!(exemptions, "\t\tip daddr {} return", range);
+    }
+    for range in bypass.destinations(true) {
+        let _ = writeln!(exemptions, "\t\tip6 daddr {} return", range);
+    }
+
+    let _ = writeln!(script, "\tchain output_nat {{");
+    let _ = writeln!(script, "\t\ttype nat hook output priority dstnat; policy accept;");
+    // Tor's virtual addresses (mapped onion names), ahead of any exemption
+    for network in &rules.virtual_networks {
+        let (family, routed) = if network.contains(':') {
+            ("ip6", rules.routes_ipv6())
+        } else {
+            ("ip", rules.tor_routing)
+        };
+        if routed {
+            let _ = writeln!(
+                script,
+                "\t\t{} daddr {} tcp flags & (fin|syn|rst|ack) == syn redirect to :{}",
+                family, network, rules.ports.trans_port
+            );
+        }
+    }
+    script.push_str(&exemptions);
+    // IPv4 is routed with tor_routing, IPv6 with route_ipv6
+    let mut families = Vec::new();
+    if rules.tor_routing {
+        families.push("ipv4");
+    }
+    if rules.routes_ipv6() {
+        families.push("ipv6");
+    }
+    if rules.dns_redirect {
+        let _ = writeln!(
+            script,
+            "\t\tmeta nfproto ipv4 udp dport 53 redirect to :{}",
+            rules.local_dns_port()
+        );
+        if rules.routes_ipv6() {
+            let _ = writeln!(
+                script,
+                "\t\tmeta nfproto ipv6 udp dport 53 redirect to :{}",
+                rules.local_dns_port()
+            );
+        }
+        // DNS over TCP and over TLS would reach the resolver through Tor, skipping the DNSPort
+        let _ = writeln!(script, "\t\ttcp dport {{ 53, 853 }} return");
+    }
+    if !families.is_empty() {
+        let _ = writeln!(script, "\t\toifname \"lo\" return");
+    }
+    for family in families {
+        let _ = writeln!(
+            script,
+            "\t\tmeta nfproto {} tcp flags & (fin|syn|rst|ack) == syn redirect to :{}",
+            family, rules.ports.trans_port
+        );
+    }
+    let _ = writeln!(script, "\t}}");
+
+    let _ = writeln!(script, "\tchain output_filter {{");
+    let _ = writeln!(script, "\t\ttype filter hook output priority filter; policy accept;");
+    script.push_str(&exemptions);
+    if rules.dns_redirect {
+        let _ = writeln!(script, "\t\toifname != \"lo\" udp dport 53 drop");
+        let _ = writeln!(script, "\t\toifname != \"lo\" tcp dport {{ 53, 853 }} reject with tcp reset");
+    }
+    if rules.filters_udp() {
+        render_udp_policy(&mut script, rules);
+    }
+    if rules.kill_switch {
+        let _ = writeln!(script, "\t\toifname \"lo\" return");
+        // Connections Torrer redirected to Tor
+        let _ = writeln!(script, "\t\tct status dnat return");
+        for (address, prefix) in rules.lan_ranges.iter().filter_map(|range| network_range(range).ok()) {
+            let family = if address.is_ipv6() { "ip6" } else { "ip" };
+            let _ = writeln!(script, "\t\t{} daddr {}/{} return", family, address, prefix);
+        }
+        let _ = writeln!(script, "\t\tdrop comment \"{}\"", KILL_SWITCH_COMMENT);
+    } else if rules.block_ipv6 {
+        let _ = writeln!(script, "\t\tmeta nfproto ipv6 oifname != \"lo\" drop");
+    } else if rules.route_ipv6 {
+        let _ = writeln!(script, "\t\tmeta nfproto ipv6 ct status dnat return");
+        // Rejected rather than dropped, so dual-stack programs fall back to IPv4 at once
+        let _ = writeln!(
+            script,
+            "\t\tmeta nfproto ipv6 oifname != \"lo\" reject with icmpv6 admin-prohibited"
+        );
+    }
+    let _ = writeln!(script, "\t}}");
+
+    if rules.serves_gateway() {
+        render_gateway_chains(&mut script, rules);
+    }
+
+    let _ = writeln!(script, "}}");
+    script
+}
+
+/// UDP exceptions, then the policy's drop or reject for the rest
+///
+/// IPv6 exceptions only apply while IPv6 is routed; otherwise it stays blocked.
+fn render_udp_policy(script: &mut String, rules: &RoutingRules) {
+    let ipv6 = rules.routes_ipv6();
+    let any_family = if ipv6 { "" } else { "meta nfproto ipv4 " };
+    for (range, port) in &rules.udp.allow {
+        match range {
+            Some(range) if range.contains(':') => {
+                if ipv6 {
+                    let _ = writeln!(script, "\t\tip6 daddr {} udp dport {} return", range, port);
+                }
+            }
+            Some(range) => {
+                let _ = writeln!(script, "\t\tip daddr {} udp dport {} return", range, port);
+            }
+            None => {
+                let _ = writeln!(script, "\t\t{}udp dport {} return", any_family, port);
+            }
+        }
+    }
+    let dhcp = if ipv6 { &DHCP_PORTS[..] } else { &DHCP_PORTS[..1] };
+    for (client, server) in dhcp {
+        let _ = writeln!(script, "\t\t{}udp sport {} udp dport {} return", any_family, client, server);
+    }
+
+    match rules.udp.policy {
+        UdpPolicy::Drop => {
+            let _ = writeln!(script, "\t\toifname != \"lo\" meta l4proto udp drop");
+        }
+        // ICMP or ICMPv6 port unreachable, matching the packet's family
+        UdpPolicy::Reject => {
+            let _ = writeln!(script, "\t\toifname != \"lo\" meta l4proto udp reject");
+        }
+        UdpPolicy::Allow => {}
+    }
+}
+
+/// Chains serving LAN clients in gateway mode, like `ChainFamily::Gateway`
+fn render_gateway_chains(script: &mut String, rules: &RoutingRules) {
+    let gateway = match rules.gateway {
+        Some(ref gateway) => gateway,
+        None => return,
+    };
+    let interface = &gateway.interface;
+    let sources: Vec<String> = if gateway.clients.is_empty() {
+        vec![String::new()]
+    } else {
+        gateway.clients.iter().map(|range| format!(" ip saddr {}", range)).collect()
+    };
+
+    if rules.tor_routing {
+        let _ = writeln!(script, "\tchain prerouting_nat {{");
+        let _ = writeln!(script, "\t\ttype nat hook prerouting priority dstnat; policy accept;");
+        for source in &sources {
+            let _ = writeln!(
+                script,
+                "\t\tiifname \"{}\"{} udp dport 53 redirect to :{}",
+                interface, source, rules.ports.dns_port
+            );
+            let _ = writeln!(
+                script,
+                "\t\tiifname \"{}\"{} tcp flags & (fin|syn|rst|ack) == syn redirect to :{}",
+                interface, source, rules.ports.trans_port
+            );
+        }
+        let _ = writeln!(script, "\t}}");
+    }
+
+    let _ = writeln!(script, "\tchain forward_filter {{");
+    let _ = writeln!(script, "\t\ttype filter hook forward priority filter; policy accept;");
+    let _ = writeln!(script, "\t\tiifname \"{}\" drop", interface);
+    let _ = writeln!(script, "\t\toifname \"{}\" drop", interface);
+    let _ = writeln!(script, "\t}}");
+
+    if !gateway.clients.is_empty() {
+        let _ = writeln!(script, "\tchain input_filter {{");
+        let _ = writeln!(script, "\t\ttype filter hook input priority filter; policy accept;");
+        for source in &sources {
+            let _ = writeln!(script, "\t\tiifname \"{}\"{} return", interface, source);
+        }
+        let _ = writeln!(script, "\t\tiifname \"{}\" tcp dport {} drop", interface, rules.ports.trans_port);
+        let _ = writeln!(script, "\t\tiifname \"{}\" udp dport {} drop", interface, rules.ports.dns_port);
+        let _ = writeln!(script, "\t}}");
+    }
+}
+
+/// Name of the table holding the rules for one `torrer exec` namespace
+pub fn namespace_table(veth: &str) -> String {
+    format!("ip torrer_{}", veth)
+}
+
+/// Script for `nft -f` with the rules of `namespace_rules` for `veth`
+///
+/// `delete` renders a script that removes the table again.
+pub fn render_namespace_table(veth: &str, ports: &RoutingPorts, delete: bool) -> String {
+    let table = namespace_table(veth);
+    let mut script = String::new();
+    let _ = writeln!(script, "table {}", table);
+    let _ = writeln!(script, "delete table {}", table);
+    if delete {
+        return script;
+    }
+
+    let _ = writeln!(script, "table {} {{", table);
+    let _ = writeln!(script, "\tchain prerouting {{");
+    let _ = writeln!(script, "\t\ttype nat hook prerouting priority dstnat; policy accept;");
+    let _ = writeln!(script, "\t\tiifname \"{}\" udp dport 53 dnat to 127.0.0.1:{}", veth, ports.dns_port);
+    let _ = writeln!(
+        script,
+        "\t\tiifname \"{}\" tcp flags & (fin|syn|rst|ack) == syn dnat to 127.0.0.1:{}",
+        veth, ports.trans_port
+    );
+    let _ = writeln!(script, "\t}}");
+    let _ = writeln!(script, "\tchain input {{");
+    let _ = writeln!(script, "\t\ttype filter hook input priority filter; policy accept;");
+    let _ = writeln!(script, "\t\tiifname \"{}\" ct status dnat accept", veth);
+    let _ = writeln!(script, "\t\tiifname \"{}\" drop", veth);
+    let _ = writeln!(script, "\t}}");
+    let _ = writeln!(script, "\tchain forward {{");
+    let _ = writeln!(script, "\t\ttype filter hook forward priority filter; policy accept;");
+    let _ = writeln!(script, "\t\tiifname \"{}\" drop", veth);
+    let _ = writeln!(script, "\t\toifname \"{}\" drop", veth);
+    let _ = writeln!(script, "\t}}");
+    let _ = writeln!(script, "}}");
+    script
+}
+
+/// nftables backend
+///
+/// Every change re-renders the `inet torrer` table and loads it with a single
+/// `nft -f`, so the ruleset is never half-applied and other tables are left
+/// alone.
+pub struct NftablesManager {
+    rules: Mutex<RoutingRules>,
+    /// `nft list table` output taken by `backup` ("" if the table was absent)
+    snapshot: Mutex<Option<String>>,
+}
+
+impl NftablesManager {
+    /// Create a new NftablesManager
+    pub fn new(ports: RoutingPorts) -> Self {
+        Self {
+            rules: Mutex::new(RoutingRules::new(ports)),
+            snapshot: Mutex::new(None),
+        }
+    }
+
+    /// The rules currently applied by this manager
+    pub fn rules(&self) -> RoutingRules {
+        self.rules.lock().unwrap().clone()
+    }
+
+    /// Change the rules and load them, keeping the old ones if `nft` fails
+    fn update(&self, change: impl FnOnce(&mut RoutingRules)) -> TorrerResult<()> {
+        let mut rules = self.rules.lock().unwrap();
+        let mut updated = rules.clone();
+        change(&mut updated);
+
+        run_nft(&render_ruleset(&updated))?;
+        *rules = updated;
+        Ok(())
+    }
+
+    /// Check that the loaded table holds exactly the rules of `rules`
+    ///
+    /// Both sides go through `nft_lines`, which evens out how `nft list`
+    /// spells some expressions, and are compared line by line.
+    fn verify(&self, rules: &RoutingRules) -> TorrerResult<()> {
+        let mut expected = if rules.is_empty() {
+            Vec::new()
+        } else {
+            nft_lines(&render_ruleset(rules))
+        };
+        let mut live = listed_lines()?;
+        expected.sort();
+        live.sort();
+
+        if live != expected {
+            let missing = expected.iter().filter(|line| !live.contains(line)).count();
+            let unexpected = live.iter().filter(|line| !expected.contains(line)).count();
+            return Err(TorrerError::Iptables(format!(
+                "Ruleset check failed: table {} has {} rule(s) missing, {} unexpected",
+                NFT_TABLE, missing, unexpected
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Put back the table saved by `backup`, or drop it without one
+    fn rollback(&self) {
+        let mut script = format!("table {}\ndelete table {}\n", NFT_TABLE, NFT_TABLE);
+        match self.snapshot.lock().unwrap().as_deref() {
+            Some(listing) => script.push_str(listing),
+            None => log::warn!("No nftables backup taken, removing table {} instead", NFT_TABLE),
+        }
+        if let Err(e) = run_nft(&script) {
+            log::error!("Rollback of table {} failed: {}", NFT_TABLE, e);
+        }
+
+        // The snapshot may hold a kill switch enabled before this run
+        let kill_switch = self.kill_switch_active();
+        let mut rules = self.rules.lock().unwrap();
+        rules.clear();
+        rules.kill_switch = kill_switch;
+    }
+}
+
+impl FirewallBackend for NftablesManager {
+    fn kind(&self) -> FirewallBackendKind {
+        FirewallBackendKind::Nftables
+    }
+
+    fn backup(&self) -> TorrerResult<()> {
+        // Nothing outside the torrer table is touched, so only it is saved
+        log::info!("Backing up nftables table {}", NFT_TABLE);
+
+        let output = Command::new("nft")
+            .args(["list", "table", "inet", "torrer"])
+            .output()
+            .map_err(|e| TorrerError::Iptables(format!("Failed to run nft: {}", e)))?;
+        let listing = if output.status.success() {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        } else {
+            String::new()
+        };
+
+        *self.snapshot.lock().unwrap() = Some(listing);
+        Ok(())
+    }
+
+    fn apply(&self, rules: &RoutingRules) -> TorrerResult<()> {
+        log::info!("Applying nftables table {}", NFT_TABLE);
+
+        let result = self
+            .update(|current| *current = rules.clone())
+            .and_then(|_| self.verify(rules));
+        if let Err(e) = result {
+            log::error!("Applying nftables rules failed, rolling back: {}", e);
+            self.rollback();
+            return Err(e);
+        }
+
+        log::info!("nftables rules applied and verified");
+        Ok(())
+    }
+
+    fn restore(&self) -> TorrerResult<()> {
+        log::info!("Removing nftables table {}", NFT_TABLE);
+        self.update(RoutingRules::clear)
+    }
+
+    fn apply_tor_routing(&self) -> TorrerResult<()> {
+        log::info!("Applying nftables rules for Tor routing");
+        self.update(|rules| rules.tor_routing = true)
+    }
+
+    fn remove_tor_routing(&self) -> TorrerResult<()> {
+        log::info!("Removing Tor routing rules");
+        self.update(|rules| rules.tor_routing = false)
+    }
+
+    fn apply_dns_redirect(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.dns_redirect = true)
+    }
+
+    fn remove_dns_redirect(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.dns_redirect = false)
+    }
+
+    fn block_ipv6(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.block_ipv6 = true)
+    }
+
+    fn unblock_ipv6(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.block_ipv6 = false)
+    }
+
+    fn apply_ipv6_routing(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.route_ipv6 = true)
+    }
+
+    fn remove_ipv6_routing(&self) -> TorrerResult<()> {
+        self.update(|rules| rules.route_ipv6 = false)
+    }
+
+    fn enable_kill_switch(&self, lan_ranges: &[String]) -> TorrerResult<()> {
+        log::info!("Enabling kill switch");
+        self.update(|rules| {
+            rules.kill_switch = true;
+            rules.lan_ranges = lan_ranges.to_vec();
+        })
+    }
+
+    fn disable_kill_switch(&self) -> TorrerResult<()> {
+        log::info!("Disabling kill switch");
+        self.update(|rules| rules.kill_switch = false)
+    }
+
+    fn attach_namespace(&self, veth: &str) -> TorrerResult<()> {
+        let ports = self.rules.lock().unwrap().ports;
+        run_nft(&render_namespace_table(veth, &ports, false))
+    }
+
+    fn detach_namespace(&self, veth: &str) -> TorrerResult<()> {
+        let ports = self.rules.lock().unwrap().ports;
+        run_nft(&render_namespace_table(veth, &ports, true))
+    }
+
+    fn kill_switch_active(&self) -> bool {
+        Command::new("nft")
+            .args(["list", "chain", "inet", "torrer", "output_filter"])
+            .output()
+            .map(|o| {
+                o.status.success()
+                    && String::from_utf8_lossy(&o.stdout)
+                        .contains(&format!("comment \"{}\"", KILL_SWITCH_COMMENT))
+            })
+            .unwrap_or(false)
+    }
+
+    fn routing_active(&self) -> bool {
+        Command::new("nft")
+            .args(["list", "table", "inet", "torrer"])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+    }
+}
+
+/// Load a script with `nft -f -`
+fn run_nft(script: &str) -> TorrerResult<()> {
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| TorrerError::Iptables(format!("Failed to run nft: {}", e)))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes()).map_err(|e| {
+            TorrerError::Iptables(format!("Failed to write to nft: {}", e))
+        })?;
+    }
+
+    let output = child.wait_with_output().map_err(|e| {
+        TorrerError::Iptables(format!("Failed to wait for nft: {}", e))
+    })?;
+
+    if !output.status.success() {
+        return Err(TorrerError::Iptables(format!(
+            "nft failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,324 @@
+// Preview of the ruleset Torrer would apply, compared with the live one
+
+use std::fmt;
+use std::fmt::Write as _;
+use std::process::Command;
+
+use crate::config::Configuration;
+use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::manager::{tool_name, tool_suffix};
+use crate::iptables::nftables::NFT_TABLE;
+use crate::iptables::rules::{chain_rules, restore_script, ChainFamily};
+use crate::iptables::{render_ruleset, FirewallBackendKind, RoutingRules};
+
+/// One line of a diff between the live and the planned rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// Present in both
+    Same(String),
+    /// Would be added
+    Added(String),
+    /// Would be removed
+    Removed(String),
+}
+
+impl DiffLine {
+    /// Whether this line is a change
+    pub fn is_change(&self) -> bool {
+        !matches!(self, DiffLine::Same(_))
+    }
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffLine::Same(line) => write!(f, "  {}", line),
+            DiffLine::Added(line) => write!(f, "+ {}", line),
+            DiffLine::Removed(line) => write!(f, "- {}", line),
+        }
+    }
+}
+
+/// The ruleset a backend would load, without touching the kernel
+///
+/// `script` is the exact input for `iptables-restore`/`nft -f`. `planned` is
+/// the same rules as `iptables-save`/`nft list` would print them once loaded,
+/// which is what `diff` compares with the live ruleset.
+#[derive(Debug, Clone)]
+pub struct FirewallPlan {
+    pub kind: FirewallBackendKind,
+    pub rules: RoutingRules,
+    pub script: String,
+    pub planned: Vec<String>,
+}
+
+impl FirewallPlan {
+    /// Plan `rules` for a resolved backend
+    pub fn new(kind: FirewallBackendKind, rules: RoutingRules) -> Self {
+        let (script, planned) = match kind {
+            FirewallBackendKind::Nftables => {
+                let script = render_ruleset(&rules);
+                let planned = if rules.is_empty() { Vec::new() } else { nft_lines(&script) };
+                (script, planned)
+            }
+            _ => (iptables_script(&rules), iptables_lines(&rules)),
+        };
+
+        Self {
+            kind,
+            rules,
+            script,
+            planned,
+        }
+    }
+
+    /// Plan what `torrer start` would apply for `config`
+    pub fn from_config(config: &Configuration) -> Self {
+        Self::new(config.firewall_backend.resolve(), RoutingRules::planned(config))
+    }
+
+    /// Torrer's rules currently loaded in the kernel, in the form of `planned`
+    ///
+    /// Reading the ruleset usually requires root.
+    pub fn live(&self) -> TorrerResult<Vec<String>> {
+        match self.kind {
+            FirewallBackendKind::Nftables => listed_lines(),
+            kind => saved_lines(
+                tool_suffix(kind),
+                &[
+                    ChainFamily::Ipv4,
+                    ChainFamily::Ipv6,
+                    ChainFamily::Ipv6Routing,
+                    ChainFamily::Gateway,
+                ],
+            ),
+        }
+    }
+
+    /// Changes needed to get from `live` to the planned rules
+    pub fn diff(&self, live: &[String]) -> Vec<DiffLine> {
+        diff_lines(live, &self.planned)
+    }
+}
+
+/// Label of a table in planned/live lines, e.g. `nat` or `ip6 filter`
+fn table_label(family: ChainFamily, table: &str) -> String {
+    match family {
+        ChainFamily::Ipv6 | ChainFamily::Ipv6Routing => format!("ip6 {}", table),
+        _ => table.to_string(),
+    }
+}
+
+/// Families `IptablesManager` loads chains for with `rules`
+pub(crate) fn loaded_families(rules: &RoutingRules) -> Vec<ChainFamily> {
+    let mut families = Vec::new();
+    if !rules.is_empty() {
+        families.push(ChainFamily::Ipv4);
+    }
+    if rules.blocks_ipv6() {
+        families.push(ChainFamily::Ipv6);
+    }
+    if rules.routes_ipv6() {
+        families.push(ChainFamily::Ipv6Routing);
+    }
+    if rules.serves_gateway() {
+        families.push(ChainFamily::Gateway);
+    }
+    families
+}
+
+/// The restore scripts `IptablesManager` would load
+///
+/// Hooks are included for every chain; when applying, they are only
+/// inserted where missing.
+fn iptables_script(rules: &RoutingRules) -> String {
+    let mut script = String::new();
+
+    for family in loaded_families(rules) {
+        let chains: Vec<&str> = family.chains().iter().map(|(_, chain, _)| *chain).collect();
+        let _ = writeln!(script, "# {}-restore --noflush", family.tool());
+        script.push_str(&restore_script(&chain_rules(rules, family), family, &chains));
+    }
+
+    script
+}
+
+/// Torrer's chains and their hooks as loaded, read with `iptables-save`
+///
+/// `suffix` selects the mode-specific tools, e.g. "-nft".
+pub(crate) fn saved_lines(suffix: &str, families: &[ChainFamily]) -> TorrerResult<Vec<String>> {
+    let mut lines = Vec::new();
+
+    for family in families {
+        let program = tool_name(suffix, &format!("{}-save", family.tool()));
+        let output = Command::new(&program).output().map_err(|e| {
+            TorrerError::Iptables(format!("Failed to run {}: {}", program, e))
+        })?;
+        if !output.status.success() {
+            return Err(TorrerError::Iptables(format!(
+                "{} failed: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        lines.extend(parse_iptables_save(&String::from_utf8_lossy(&output.stdout), *family));
+    }
+
+    Ok(lines)
+}
+
+/// Torrer's nftables table as loaded, empty when it does not exist
+pub(crate) fn listed_lines() -> TorrerResult<Vec<String>> {
+    let output = Command::new("nft")
+        .args(["list", "table", "inet", "torrer"])
+        .output()
+        .map_err(|e| TorrerError::Iptables(format!("Failed to run nft: {}", e)))?;
+
+    // A missing table just means nothing is applied
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+    Ok(nft_lines(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Torrer's chains and their hooks as `iptables-save` prints them
+pub fn iptables_lines(rules: &RoutingRules) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for family in loaded_families(rules) {
+        let planned = chain_rules(rules, family);
+        for (table, chain, hook) in family.chains() {
+            let label = table_label(family, table.as_str());
+            lines.push(format!("{}: -A {} -j {}", label, hook, chain));
+            for rule in planned.iter().filter(|rule| rule.table == *table && rule.chain == *chain) {
+                lines.push(format!("{}: -A {} {}", label, chain, rule.rule.join(" ")));
+            }
+        }
+    }
+
+    lines
+}
+
+/// Pick Torrer's chains and their hooks out of `iptables-save` output
+///
+/// Lines are grouped per chain in the order `iptables_lines` uses, with the
+/// hook first, whatever order the tables were dumped in.
+pub fn parse_iptables_save(dump: &str, family: ChainFamily) -> Vec<String> {
+    let mut table = String::new();
+    // (table, chain, line) of every hook and owned rule
+    let mut found: Vec<(String, String, String)> = Vec::new();
+
+    for line in dump.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix('*') {
+            table = name.to_string();
+            continue;
+        }
+        let rule = match line.strip_prefix("-A ") {
+            Some(rule) => rule,
+            None => continue,
+        };
+        let chain = rule.split_whitespace().next().unwrap_or("");
+        for (t, c, hook) in family.chains() {
+            if t.as_str() == table && (chain == *c || rule == format!("{} -j {}", hook, c)) {
+                found.push((table.clone(), c.to_string(), line.to_string()));
+            }
+        }
+    }
+
+    let mut lines = Vec::new();
+    for (table, chain, hook) in family.chains() {
+        let label = table_label(family, table.as_str());
+        let hook_line = format!("-A {} -j {}", hook, chain);
+        let owned: Vec<&String> = found
+            .iter()
+            .filter(|(t, c, _)| t == table.as_str() && c == chain)
+            .map(|(_, _, line)| line)
+            .collect();
+        // The hook leads its chain's rules
+        for line in owned.iter().filter(|line| **line == &hook_line) {
+            lines.push(format!("{}: {}", label, line));
+        }
+        for line in owned.iter().filter(|line| **line != &hook_line) {
+            lines.push(format!("{}: {}", label, line));
+        }
+    }
+    lines
+}
+
+/// SYN match as Torrer writes it
+const NFT_SYN: &str = "tcp flags & (fin|syn|rst|ack) == syn";
+
+/// The same match as `nft list` prints it since nftables 0.9.9
+const NFT_LISTED_SYN: &str = "tcp flags syn / fin,syn,rst,ack";
+
+/// Torrer's table body in a form that compares across `nft` versions
+///
+/// Takes either a script from `render_ruleset` or `nft list table` output.
+/// Whitespace is collapsed, spaces around `|` are dropped, and the spellings
+/// newer `nft` versions list for SYN matches and ICMP rejects are turned back
+/// into the ones Torrer writes.
+pub fn nft_lines(ruleset: &str) -> Vec<String> {
+    let header = format!("table {} {{", NFT_TABLE);
+    let mut in_table = false;
+    let mut lines = Vec::new();
+
+    for line in ruleset.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line == header {
+            in_table = true;
+            continue;
+        }
+        if !in_table || line.is_empty() {
+            continue;
+        }
+        lines.push(
+            line.replace(" | ", "|")
+                .replace("( ", "(")
+                .replace(" )", ")")
+                .replace(NFT_LISTED_SYN, NFT_SYN)
+                .replace("reject with icmpv6 type ", "reject with icmpv6 ")
+                .replace("reject with icmpx type ", "reject with icmpx ")
+                .replace("reject with icmp type ", "reject with icmp "),
+        );
+    }
+
+    // Closing brace of the table itself
+    if lines.last().map(String::as_str) == Some("}") {
+        lines.pop();
+    }
+    lines
+}
+
+/// Line diff of `old` against `new`, based on their longest common subsequence
+pub fn diff_lines(old: &[String], new: &[String]) -> Vec<DiffLine> {
+    // lcs[i][j]: length of the LCS of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Same(old[i].clone()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i].clone()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j].clone()));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().cloned().map(DiffLine::Removed));
+    diff.extend(new[j..].iter().cloned().map(DiffLine::Added));
+    diff
+}
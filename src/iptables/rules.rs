@@ -1,13 +1,49 @@
 // iptables rule definitions and utilities
 
+use std::fmt::Write as _;
+
+use crate::config::UdpPolicy;
+use crate::iptables::backend::{DHCP_PORTS, KILL_SWITCH_COMMENT};
+use crate::iptables::{RoutingPorts, RoutingRules};
+use crate::utils::network_range;
+
+/// Chain in the nat table holding Torrer's redirects
+pub const NAT_CHAIN: &str = "TORRER_NAT";
+
+/// Chain in the filter table holding Torrer's drops
+pub const FILTER_CHAIN: &str = "TORRER_FILTER";
+
+/// Chain in the nat table redirecting LAN clients in gateway mode
+pub const GATEWAY_NAT_CHAIN: &str = "TORRER_GW_NAT";
+
+/// Chain in the filter table dropping LAN forwarding in gateway mode
+pub const GATEWAY_FORWARD_CHAIN: &str = "TORRER_GW_FORWARD";
+
+/// Chain in the filter table guarding Tor's LAN listeners in gateway mode
+pub const GATEWAY_INPUT_CHAIN: &str = "TORRER_GW_INPUT";
+
+/// Final rule of the kill switch, also used to check whether it is installed
+pub const KILL_SWITCH_DROP: [&str; 6] = ["-m", "comment", "--comment", KILL_SWITCH_COMMENT, "-j", "DROP"];
+
 /// iptables rule types
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RuleType {
     Nat,
     Filter,
     Mangle,
 }
 
+impl RuleType {
+    /// Table name as passed to `iptables -t`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleType::Nat => "nat",
+            RuleType::Filter => "filter",
+            RuleType::Mangle => "mangle",
+        }
+    }
+}
+
 /// iptables rule
 #[derive(Debug, Clone)]
 pub struct Rule {
@@ -16,3 +52,438 @@ pub struct Rule {
     pub rule: Vec<String>,
 }
 
+impl Rule {
+    /// Create a rule from its match/target arguments
+    pub fn new<S: AsRef<str>>(table: RuleType, chain: &str, rule: &[S]) -> Self {
+        Self {
+            table,
+            chain: chain.to_string(),
+            rule: rule.iter().map(|arg| arg.as_ref().to_string()).collect(),
+        }
+    }
+}
+
+/// A set of Torrer's chains that is loaded and removed together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainFamily {
+    /// The host's own IPv4 traffic, hooked from OUTPUT
+    Ipv4,
+    /// The host's own IPv6 traffic, hooked from OUTPUT
+    Ipv6,
+    /// IPv6 redirects to Tor, hooked from OUTPUT in ip6tables' nat table
+    ///
+    /// Kept apart from `Ipv6` so blocking IPv6 works without IPv6 NAT support.
+    Ipv6Routing,
+    /// LAN clients in gateway mode (IPv4), hooked from PREROUTING, FORWARD
+    /// and INPUT
+    Gateway,
+}
+
+impl ChainFamily {
+    /// (table, chain, hooked from) triples Torrer owns in this family
+    pub fn chains(&self) -> &'static [(RuleType, &'static str, &'static str)] {
+        match self {
+            ChainFamily::Ipv4 => &[
+                (RuleType::Nat, NAT_CHAIN, "OUTPUT"),
+                (RuleType::Filter, FILTER_CHAIN, "OUTPUT"),
+            ],
+            ChainFamily::Ipv6 => &[(RuleType::Filter, FILTER_CHAIN, "OUTPUT")],
+            ChainFamily::Ipv6Routing => &[(RuleType::Nat, NAT_CHAIN, "OUTPUT")],
+            ChainFamily::Gateway => &[
+                (RuleType::Nat, GATEWAY_NAT_CHAIN, "PREROUTING"),
+                (RuleType::Filter, GATEWAY_FORWARD_CHAIN, "FORWARD"),
+                (RuleType::Filter, GATEWAY_INPUT_CHAIN, "INPUT"),
+            ],
+        }
+    }
+
+    /// Base name of the tools for this family, `iptables` or `ip6tables`
+    pub fn tool(&self) -> &'static str {
+        if self.is_ipv6() {
+            "ip6tables"
+        } else {
+            "iptables"
+        }
+    }
+
+    /// Whether the chains live in ip6tables' tables
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, ChainFamily::Ipv6 | ChainFamily::Ipv6Routing)
+    }
+}
+
+/// DNS over TCP (53) and over TLS (853) is kept out of the TCP redirect
+///
+/// Tor would carry it to whatever resolver was asked, skipping the DNSPort,
+/// so it is refused instead and clients fall back to plain DNS over UDP.
+const DNS_TCP_RETURN: [&str; 8] = ["-p", "tcp", "-m", "multiport", "--dports", "53,853", "-j", "RETURN"];
+
+/// Reset rather than drop, so resolvers give up on TCP at once
+const DNS_TCP_REJECT: [&str; 13] = ["!", "-o", "lo", "-p", "tcp", "-m", "multiport", "--dports", "53,853", "-j", "REJECT", "--reject-with", "tcp-reset"];
+
+/// Rules for Torrer's chains, exclusions first
+///
+/// Arguments are written the way `iptables-save` prints them, so planned
+/// rules can be compared with the live ruleset line by line.
+pub fn chain_rules(rules: &RoutingRules, family: ChainFamily) -> Vec<Rule> {
+    let mut out = Vec::new();
+    let exemptions = exemptions(rules, family);
+
+    match family {
+        ChainFamily::Ipv4 => {
+            let trans_port = rules.ports.trans_port.to_string();
+            let dns_port = rules.local_dns_port().to_string();
+
+            if rules.tor_routing {
+                virtual_network_rules(&mut out, rules, false);
+            }
+            // tor's own traffic must never loop back into Tor
+            for exemption in &exemptions {
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, exemption));
+            }
+            if rules.dns_redirect {
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-p", "udp", "-m", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", &dns_port]));
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &DNS_TCP_RETURN));
+            }
+            if rules.tor_routing {
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-o", "lo", "-j", "RETURN"]));
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-p", "tcp", "-m", "tcp", "--tcp-flags", "FIN,SYN,RST,ACK", "SYN", "-j", "REDIRECT", "--to-ports", &trans_port]));
+            }
+
+            for exemption in &exemptions {
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, exemption));
+            }
+            if rules.dns_redirect {
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["!", "-o", "lo", "-p", "udp", "-m", "udp", "--dport", "53", "-j", "DROP"]));
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &DNS_TCP_REJECT));
+            }
+            if rules.filters_udp() {
+                udp_rules(&mut out, rules, false);
+            }
+            if rules.kill_switch {
+                // Connections Torrer redirected to Tor
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["-m", "conntrack", "--ctstate", "DNAT", "-j", "RETURN"]));
+                kill_switch_rules(&mut out, rules, false);
+            }
+        }
+        ChainFamily::Gateway => gateway_rules(&mut out, rules),
+        ChainFamily::Ipv6Routing => {
+            if rules.routes_ipv6() {
+                let trans_port = rules.ports.trans_port.to_string();
+                let dns_port = rules.local_dns_port().to_string();
+
+                virtual_network_rules(&mut out, rules, true);
+                for exemption in &exemptions {
+                    out.push(Rule::new(RuleType::Nat, NAT_CHAIN, exemption));
+                }
+                if rules.dns_redirect {
+                    out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-p", "udp", "-m", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", &dns_port]));
+                    out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &DNS_TCP_RETURN));
+                }
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-o", "lo", "-j", "RETURN"]));
+                out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-p", "tcp", "-m", "tcp", "--tcp-flags", "FIN,SYN,RST,ACK", "SYN", "-j", "REDIRECT", "--to-ports", &trans_port]));
+            }
+        }
+        ChainFamily::Ipv6 => {
+            if rules.blocks_ipv6() {
+                for exemption in &exemptions {
+                    out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, exemption));
+                }
+            }
+            if rules.routes_ipv6() {
+                // Connections Torrer redirected to Tor
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["-m", "conntrack", "--ctstate", "DNAT", "-j", "RETURN"]));
+                if rules.filters_udp() {
+                    udp_rules(&mut out, rules, true);
+                }
+            }
+            if rules.kill_switch {
+                kill_switch_rules(&mut out, rules, true);
+            } else if rules.block_ipv6 {
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["!", "-o", "lo", "-j", "DROP"]));
+            } else if rules.route_ipv6 {
+                // Rejected rather than dropped, so dual-stack programs fall back to IPv4 at once
+                out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["!", "-o", "lo", "-j", "REJECT", "--reject-with", "icmp6-adm-prohibited"]));
+            }
+        }
+    }
+
+    out
+}
+
+/// Redirect TCP to Tor's virtual addresses (mapped onion names)
+///
+/// These come first: the ranges are private, so split tunneling of local
+/// networks or the loopback return would otherwise let them go direct.
+fn virtual_network_rules(out: &mut Vec<Rule>, rules: &RoutingRules, ipv6: bool) {
+    let trans_port = rules.ports.trans_port.to_string();
+    for network in rules.virtual_networks.iter().filter(|network| network.contains(':') == ipv6) {
+        out.push(Rule::new(RuleType::Nat, NAT_CHAIN, &["-d", network.as_str(), "-p", "tcp", "-m", "tcp", "--tcp-flags", "FIN,SYN,RST,ACK", "SYN", "-j", "REDIRECT", "--to-ports", &trans_port]));
+    }
+}
+
+/// `-j RETURN` matches for tor's traffic, then for split tunneling
+fn exemptions(rules: &RoutingRules, family: ChainFamily) -> Vec<Vec<String>> {
+    let bypass = &rules.bypass;
+    let mut matches: Vec<Vec<String>> = Vec::new();
+
+    let owner = |option: &str, id: u32| vec!["-m".into(), "owner".into(), option.into(), id.to_string()];
+    matches.extend(rules.tor_uid.map(|uid| owner("--uid-owner", uid)));
+    matches.extend(rules.tor_gid.map(|gid| owner("--gid-owner", gid)));
+    matches.extend(bypass.uids.iter().map(|uid| owner("--uid-owner", *uid)));
+    matches.extend(bypass.gids.iter().map(|gid| owner("--gid-owner", *gid)));
+    matches.extend(
+        bypass
+            .cgroups
+            .iter()
+            .map(|path| vec!["-m".into(), "cgroup".into(), "--path".into(), path.clone()]),
+    );
+    matches.extend(
+        bypass
+            .destinations(family.is_ipv6())
+            .map(|range| vec!["-d".into(), range.to_string()]),
+    );
+
+    matches
+        .into_iter()
+        .map(|mut args| {
+            args.extend(["-j".to_string(), "RETURN".to_string()]);
+            args
+        })
+        .collect()
+}
+
+/// UDP exceptions, then the policy's DROP or REJECT for the rest
+fn udp_rules(out: &mut Vec<Rule>, rules: &RoutingRules, ipv6: bool) {
+    for (range, port) in rules.udp.allowed(ipv6) {
+        let mut args = Vec::new();
+        if let Some(range) = range {
+            args.extend(["-d".to_string(), range.to_string()]);
+        }
+        args.extend(["-p", "udp", "-m", "udp", "--dport"].map(String::from));
+        args.extend([port.to_string(), "-j".to_string(), "RETURN".to_string()]);
+        out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &args));
+    }
+
+    // Losing the DHCP lease would take the network down with it
+    let (client, server) = DHCP_PORTS[usize::from(ipv6)];
+    let (client, server) = (client.to_string(), server.to_string());
+    out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["-p", "udp", "-m", "udp", "--sport", &client, "--dport", &server, "-j", "RETURN"]));
+
+    match rules.udp.policy {
+        UdpPolicy::Drop => out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["!", "-o", "lo", "-p", "udp", "-j", "DROP"])),
+        UdpPolicy::Reject => {
+            let reject_with = if ipv6 { "icmp6-port-unreachable" } else { "icmp-port-unreachable" };
+            out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["!", "-o", "lo", "-p", "udp", "-j", "REJECT", "--reject-with", reject_with]));
+        }
+        UdpPolicy::Allow => {}
+    }
+}
+
+/// Redirects for LAN clients, and drops for everything else they send
+fn gateway_rules(out: &mut Vec<Rule>, rules: &RoutingRules) {
+    let gateway = match rules.gateway {
+        Some(ref gateway) => gateway,
+        None => return,
+    };
+    let interface = gateway.interface.as_str();
+    let trans_port = rules.ports.trans_port.to_string();
+    let trans_port = trans_port.as_str();
+    let dns_port = rules.ports.dns_port.to_string();
+    let dns_port = dns_port.as_str();
+
+    // No client ranges serves the whole interface
+    let sources: Vec<Option<&str>> = if gateway.clients.is_empty() {
+        vec![None]
+    } else {
+        gateway.clients.iter().map(|range| Some(range.as_str())).collect()
+    };
+    let with_source = |source: Option<&str>, rest: &[&str]| -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(source) = source {
+            args.extend(["-s".to_string(), source.to_string()]);
+        }
+        args.extend(["-i".to_string(), interface.to_string()]);
+        args.extend(rest.iter().map(|arg| arg.to_string()));
+        args
+    };
+
+    if rules.tor_routing {
+        for source in &sources {
+            let dns = ["-p", "udp", "-m", "udp", "--dport", "53", "-j", "REDIRECT", "--to-ports", dns_port];
+            out.push(Rule::new(RuleType::Nat, GATEWAY_NAT_CHAIN, &with_source(*source, &dns)));
+            let syn = [
+                "-p", "tcp", "-m", "tcp", "--tcp-flags", "FIN,SYN,RST,ACK", "SYN",
+                "-j", "REDIRECT", "--to-ports", trans_port,
+            ];
+            out.push(Rule::new(RuleType::Nat, GATEWAY_NAT_CHAIN, &with_source(*source, &syn)));
+        }
+    }
+
+    // Nothing from the LAN is forwarded in the clear
+    out.push(Rule::new(RuleType::Filter, GATEWAY_FORWARD_CHAIN, &["-i", interface, "-j", "DROP"]));
+    out.push(Rule::new(RuleType::Filter, GATEWAY_FORWARD_CHAIN, &["-o", interface, "-j", "DROP"]));
+
+    // Only served clients may use Tor's listeners on the LAN address
+    if !gateway.clients.is_empty() {
+        for source in &sources {
+            out.push(Rule::new(RuleType::Filter, GATEWAY_INPUT_CHAIN, &with_source(*source, &["-j", "RETURN"])));
+        }
+        for (protocol, port) in [("tcp", trans_port), ("udp", dns_port)] {
+            let drop = ["-i", interface, "-p", protocol, "-m", protocol, "--dport", port, "-j", "DROP"];
+            out.push(Rule::new(RuleType::Filter, GATEWAY_INPUT_CHAIN, &drop));
+        }
+    }
+}
+
+/// Loopback and LAN exceptions followed by the final DROP
+fn kill_switch_rules(out: &mut Vec<Rule>, rules: &RoutingRules, ipv6: bool) {
+    out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["-o", "lo", "-j", "RETURN"]));
+    for (address, prefix) in rules.lan_ranges.iter().filter_map(|range| network_range(range).ok()) {
+        if address.is_ipv6() == ipv6 {
+            let range = format!("{}/{}", address, prefix);
+            out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &["-d", &range, "-j", "RETURN"]));
+        }
+    }
+    out.push(Rule::new(RuleType::Filter, FILTER_CHAIN, &KILL_SWITCH_DROP));
+}
+
+/// Input for `iptables-restore --noflush` that replaces Torrer's chains
+///
+/// Declaring a chain flushes it, and each table is committed as a unit, so
+/// the chains are never seen half-filled. Chains listed in `unhooked` also
+/// get their jump from the top of their built-in chain in the same commit.
+/// Other chains are not touched.
+pub fn restore_script(rules: &[Rule], family: ChainFamily, unhooked: &[&str]) -> String {
+    let mut script = String::new();
+
+    for (table, chain, hook) in family.chains() {
+        let _ = writeln!(script, "*{}", table.as_str());
+        let _ = writeln!(script, ":{} - [0:0]", chain);
+        for rule in rules.iter().filter(|rule| rule.table == *table && rule.chain == *chain) {
+            let _ = writeln!(script, "-A {} {}", chain, rule.rule.join(" "));
+        }
+        if unhooked.contains(chain) {
+            let _ = writeln!(script, "-I {} 1 -j {}", hook, chain);
+        }
+        let _ = writeln!(script, "COMMIT");
+    }
+
+    script
+}
+
+/// Input for `iptables-restore --noflush` that removes Torrer's chains
+///
+/// `jumps` lists the chains of `family` that exist, with the number of jumps
+/// to each from its built-in chain; the others are skipped. Each chain is
+/// emptied, unhooked and deleted in one commit.
+pub fn remove_script(family: ChainFamily, jumps: &[(&str, usize)]) -> String {
+    let mut script = String::new();
+
+    for (table, chain, hook) in family.chains() {
+        let count = match jumps.iter().find(|(name, _)| name == chain) {
+            Some((_, count)) => *count,
+            None => continue,
+        };
+        let _ = writeln!(script, "*{}", table.as_str());
+        let _ = writeln!(script, ":{} - [0:0]", chain);
+        for _ in 0..count {
+            let _ = writeln!(script, "-D {} -j {}", hook, chain);
+        }
+        let _ = writeln!(script, "-X {}", chain);
+        let _ = writeln!(script, "COMMIT");
+    }
+
+    script
+}
+
+/// Join restore scripts into one that commits each table once
+///
+/// Chain declarations go first in each table, the other lines keep their order.
+pub fn merge_scripts(scripts: &[String]) -> String {
+    let mut tables: Vec<(&str, Vec<&str>)> = Vec::new();
+    let mut current = None;
+
+    for line in scripts.iter().flat_map(|script| script.lines()) {
+        if let Some(table) = line.strip_prefix('*') {
+            current = match tables.iter().position(|(name, _)| *name == table) {
+                Some(index) => Some(index),
+                None => {
+                    tables.push((table, Vec::new()));
+                    Some(tables.len() - 1)
+                }
+            };
+        } else if line == "COMMIT" {
+            current = None;
+        } else if let Some(index) = current {
+            tables[index].1.push(line);
+        }
+    }
+
+    let mut script = String::new();
+    for (table, mut lines) in tables {
+        lines.sort_by_key(|line| !line.starts_with(':'));
+        let _ = writeln!(script, "*{}", table);
+        for line in lines {
+            let _ = writeln!(script, "{}", line);
+        }
+        let _ = writeln!(script, "COMMIT");
+    }
+
+    script
+}
+
+/// Rules that send a network namespace's traffic into Tor (`torrer exec`)
+///
+/// Traffic arriving on `veth`, the host end of the namespace's veth pair, is
+/// DNATed to Tor's ports on loopback; anything else from or to it is dropped,
+/// so nothing is forwarded in the clear.
+pub fn namespace_rules(veth: &str, ports: &RoutingPorts) -> Vec<Rule> {
+    let dns = format!("127.0.0.1:{}", ports.dns_port);
+    let trans = format!("127.0.0.1:{}", ports.trans_port);
+
+    vec![
+        Rule::new(RuleType::Nat, "PREROUTING", &["-i", veth, "-p", "udp", "-m", "udp", "--dport", "53", "-j", "DNAT", "--to-destination", &dns]),
+        Rule::new(RuleType::Nat, "PREROUTING", &["-i", veth, "-p", "tcp", "-m", "tcp", "--tcp-flags", "FIN,SYN,RST,ACK", "SYN", "-j", "DNAT", "--to-destination", &trans]),
+        Rule::new(RuleType::Filter, "INPUT", &["-i", veth, "-m", "conntrack", "--ctstate", "DNAT", "-j", "ACCEPT"]),
+        Rule::new(RuleType::Filter, "INPUT", &["-i", veth, "-j", "DROP"]),
+        Rule::new(RuleType::Filter, "FORWARD", &["-i", veth, "-j", "DROP"]),
+        Rule::new(RuleType::Filter, "FORWARD", &["-o", veth, "-j", "DROP"]),
+    ]
+}
+
+/// Input for `iptables-restore --noflush` that inserts `rules` at the top of
+/// their chains, in order, or deletes them again
+pub fn insert_script(rules: &[Rule], delete: bool) -> String {
+    let mut script = String::new();
+
+    for table in [RuleType::Nat, RuleType::Filter, RuleType::Mangle] {
+        let in_table: Vec<&Rule> = rules.iter().filter(|rule| rule.table == table).collect();
+        if in_table.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(script, "*{}", table.as_str());
+        let mut positions: Vec<(&str, usize)> = Vec::new();
+        for rule in in_table {
+            if delete {
+                let _ = writeln!(script, "-D {} {}", rule.chain, rule.rule.join(" "));
+                continue;
+            }
+            let position = match positions.iter_mut().find(|(chain, _)| *chain == rule.chain) {
+                Some((_, position)) => {
+                    *position += 1;
+                    *position
+                }
+                None => {
+                    positions.push((&rule.chain, 1));
+                    1
+                }
+            };
+            let _ = writeln!(script, "-I {} {} {}", rule.chain, position, rule.rule.join(" "));
+        }
+        let _ = writeln!(script, "COMMIT");
+    }
+
+    script
+}
@@ -46,7 +46,8 @@ enum Commands {
     Config,
     /// Add a bridge
     AddBridge {
-        /// Bridge address (IP:PORT)
+        /// Bridge address (IP:PORT), or a quoted bridge line such as
+        /// "obfs4 IP:PORT FINGERPRINT cert=... iat-mode=0"
         bridge: String,
     },
     /// List bridges
@@ -143,12 +144,49 @@ enum Commands {
     },
     /// Show system information
     Info,
-    /// Test for DNS and IPv6 leaks
-    LeakTest,
-    /// List active Tor circuits
-    Circuits,
-    /// Request new Tor circuit
-    NewCircuit,
+    /// Probe TCP, UDP, ICMP, IPv6, DNS and STUN for leaks outside Tor
+    LeakTest {
+        /// Output format (text, json)
+        #[arg(short, long, default_value = "text")]
+        format: String,
+        /// IPv4 address to probe (default: 1.1.1.1)
+        #[arg(long)]
+        target: Option<std::net::Ipv4Addr>,
+        /// IPv6 address to probe (default: 2606:4700:4700::1111)
+        #[arg(long)]
+        target6: Option<std::net::Ipv6Addr>,
+        /// Probe a stand-in internet in a local network namespace instead
+        #[arg(long, conflicts_with_all = ["target", "target6"])]
+        harness: bool,
+        /// Serve as the harness's stand-in internet (internal)
+        #[arg(long, hide = true)]
+        stand_in: bool,
+    },
+    /// Check that an onion service is reachable through transparent routing
+    OnionTest {
+        /// Onion address, e.g. "<56 characters>.onion", with an optional port or as a URL
+        address: String,
+    },
+    /// List and control Tor circuits and streams
+    Circuits {
+        #[command(subcommand)]
+        action: Option<CircuitCommands>,
+    },
+    /// Run one program with all its traffic routed through Tor
+    Exec {
+        /// Run as this user (defaults to the user who ran sudo)
+        #[arg(short, long)]
+        user: Option<String>,
+        /// Program and arguments, after `--`
+        #[arg(trailing_var_arg = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Request a new Tor identity (NEWNYM)
+    NewCircuit {
+        /// Keep rotating every N minutes
+        #[arg(short, long)]
+        every: Option<u64>,
+    },
     /// Show application state
     State,
     /// Save state to file
@@ -193,6 +231,11 @@ enum Commands {
     CheckFirewall,
     /// Configure firewall for Tor
     ConfigureFirewall,
+    /// Inspect the rules Torrer routes traffic with
+    Firewall {
+        #[command(subcommand)]
+        action: FirewallCommands,
+    },
     /// List scheduled tasks
     ListTasks,
     /// Add scheduled task
@@ -233,7 +276,7 @@ enum Commands {
         /// Relay fingerprint
         fingerprint: String,
     },
-    /// Get current exit relay
+    /// Show the current exit relay and verify it against the consensus
     ExitRelay,
     /// Install systemd service
     InstallService,
@@ -241,6 +284,60 @@ enum Commands {
     ServiceStatus,
 }
 
+#[derive(Subcommand)]
+enum CircuitCommands {
+    /// List active Tor circuits (default)
+    List,
+    /// Close one circuit without changing identity
+    Close {
+        /// Circuit ID
+        id: String,
+    },
+    /// Build a new circuit, optionally through the given relays
+    Extend {
+        /// Relay fingerprints or nicknames, guard first
+        relays: Vec<String>,
+    },
+    /// List application streams
+    Streams,
+    /// Close one stream
+    CloseStream {
+        /// Stream ID
+        id: String,
+    },
+    /// Attach an unattached stream to a circuit
+    Attach {
+        /// Stream ID
+        stream: String,
+        /// Circuit ID
+        circuit: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum FirewallCommands {
+    /// Show the ruleset start would apply and how it differs from the live one
+    Plan,
+}
+
+/// Wait for Ctrl+C or SIGTERM
+async fn wait_for_shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+        }
+        Err(e) => {
+            log::warn!("Failed to listen for SIGTERM: {}", e);
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> TorrerResult<()> {
     // Initialize logging
@@ -252,8 +349,28 @@ async fn main() -> TorrerResult<()> {
     
     match cli.command {
         Commands::Start => {
+            use cli::commands::bootstrap;
+
             engine.start().await?;
             println!("✓ Tor routing started successfully");
+
+            println!("Waiting for Tor to bootstrap...");
+            if !bootstrap::wait_for_bootstrap(&mut engine, bootstrap::BOOTSTRAP_WAIT).await? {
+                println!("⚠ Tor has not finished bootstrapping; routed traffic may fail until it does");
+            }
+
+            // A managed tor and the DNS resolver exit with their owning
+            // process, so stay in the foreground
+            if engine.is_managed() || engine.is_resolving() {
+                if engine.is_managed() {
+                    println!("Managed tor is running; press Ctrl+C to stop routing");
+                } else {
+                    println!("DNS resolver is running; press Ctrl+C to stop routing");
+                }
+                wait_for_shutdown_signal().await;
+                engine.stop().await?;
+                println!("✓ Tor routing stopped");
+            }
             Ok(())
         }
         Commands::Stop => {
@@ -283,12 +400,20 @@ async fn main() -> TorrerResult<()> {
             
             // Routing status
             println!("Routing Status: {}", if status.is_running { "ACTIVE" } else { "INACTIVE" });
+            println!("Kill Switch: {}", if status.kill_switch { "Active ✓ (non-Tor traffic blocked)" } else { "Off" });
             
             if status.is_running {
                 println!();
                 println!("Connection Details:");
                 println!("  Tor Connected: {}", if status.tor_connected { "Yes ✓" } else { "No ✗" });
                 println!("  Circuit Established: {}", if status.circuit_established { "Yes ✓" } else { "No ✗" });
+                if let Some(ref bootstrap) = status.bootstrap {
+                    cli::commands::bootstrap::print_bootstrap(bootstrap);
+                } else if status.tor_connected {
+                    if let Ok(phase) = cli::commands::bootstrap::fetch_bootstrap().await {
+                        println!("  Bootstrap: {}", cli::commands::bootstrap::render_phase(&phase));
+                    }
+                }
                 
                 // Determine routing method
                 let routing_method = if status.tor_connected {
@@ -316,29 +441,56 @@ async fn main() -> TorrerResult<()> {
                         println!("  Exit Country: {}", country);
                     }
                 }
-                
-                // Health check summary
+            } else {
+                // Routing is off here, but a running Tor can still be bootstrapping
+                if let Ok(phase) = cli::commands::bootstrap::fetch_bootstrap().await {
+                    println!("Tor Bootstrap: {}", cli::commands::bootstrap::render_phase(&phase));
+                    if phase.is_warning() {
+                        println!(
+                            "  ⚠ {}",
+                            phase.warning.as_deref().unwrap_or(&phase.summary)
+                        );
+                    }
+                }
+                if status.kill_switch {
+                    println!();
+                    println!("⚠ The kill switch is still blocking traffic from an earlier run.");
+                    println!("  Run 'sudo torrer stop' to remove it.");
+                }
+            }
+            
+            // Health check summary, with the exit check, whenever Tor's control port answers
+            let health = crate::core::health::HealthChecker::check_all()
+                .await
+                .ok()
+                .filter(|health| status.is_running || health.tor_control);
+            if let Some(health) = health {
                 println!();
                 println!("Health Check:");
-                if let Ok(health) = crate::core::health::HealthChecker::check_all().await {
-                    println!("  Tor Daemon: {}", if health.tor_daemon { "Running ✓" } else { "Stopped ✗" });
-                    println!("  Tor Control: {}", if health.tor_control { "Connected ✓" } else { "Disconnected ✗" });
-                    println!("  Tor Circuit: {}", if health.tor_circuit { "Established ✓" } else { "Not Established ✗" });
-                    println!("  iptables: {}", if health.iptables { "Available ✓" } else { "Unavailable ✗" });
-                    println!("  DNS: {}", if health.dns { "Configured ✓" } else { "Not Configured ✗" });
-                    
-                    let health_score = health.score();
-                    println!();
-                    println!("Overall Health: {}%", health_score);
-                    if health_score == 100 {
-                        println!("  Status: All systems operational ✓");
-                    } else if health_score >= 80 {
-                        println!("  Status: Mostly operational ⚠");
-                    } else {
-                        println!("  Status: Issues detected ✗");
-                    }
+                println!("  Tor Daemon: {}", if health.tor_daemon { "Running ✓" } else { "Stopped ✗" });
+                println!("  Tor Control: {}", if health.tor_control { "Connected ✓" } else { "Disconnected ✗" });
+                println!("  Tor Circuit: {}", if health.tor_circuit { "Established ✓" } else { "Not Established ✗" });
+                println!("  iptables: {}", if health.iptables { "Available ✓" } else { "Unavailable ✗" });
+                println!("  DNS: {}", if health.dns { "Configured ✓" } else { "Not Configured ✗" });
+                match health.exit {
+                    Some(ref exit) if exit.is_verified() => println!("  Tor Exit: Verified ✓ ({})", exit),
+                    Some(ref exit) => println!("  Tor Exit: Not Verified ✗ ({})", exit.problems().join("; ")),
+                    None => println!("  Tor Exit: Unknown (no stream through an exit yet)"),
                 }
-            } else {
+                
+                let health_score = health.score();
+                println!();
+                println!("Overall Health: {}%", health_score);
+                if health_score == 100 {
+                    println!("  Status: All systems operational ✓");
+                } else if health_score >= 80 {
+                    println!("  Status: Mostly operational ⚠");
+                } else {
+                    println!("  Status: Issues detected ✗");
+                }
+            }
+            
+            if !status.is_running {
                 println!();
                 println!("To start Tor routing, use:");
                 println!("  sudo torrer start");
@@ -539,7 +691,7 @@ async fn main() -> TorrerResult<()> {
         }
         Commands::SetCountry { country } => {
             use crate::tor::{TorClient, CountrySelector};
-            let mut client = TorClient::new();
+            let mut client = TorClient::from_system_config();
             client.connect().await?;
             client.authenticate().await?;
             let selector = CountrySelector::new(Some(country.clone()));
@@ -594,6 +746,11 @@ async fn main() -> TorrerResult<()> {
             println!("  Tor circuit: {}", if status.tor_circuit { "✓" } else { "✗" });
             println!("  iptables: {}", if status.iptables { "✓" } else { "✗" });
             println!("  DNS: {}", if status.dns { "✓" } else { "✗" });
+            match status.exit {
+                Some(ref exit) if exit.is_verified() => println!("  Tor exit: ✓ {}", exit),
+                Some(ref exit) => println!("  Tor exit: ✗ {}", exit.problems().join("; ")),
+                None => println!("  Tor exit: - (no stream to check)"),
+            }
             println!("  Health score: {}/100", status.score());
             if status.is_healthy() {
                 println!("  Status: ✓ All systems healthy");
@@ -635,19 +792,48 @@ async fn main() -> TorrerResult<()> {
             info::show_info().await?;
             Ok(())
         }
-        Commands::LeakTest => {
+        Commands::LeakTest { format, target, target6, harness, stand_in } => {
             use cli::commands::leak_test;
-            leak_test::run_leak_tests().await?;
-            Ok(())
+            use security::LeakTarget;
+            if stand_in {
+                return security::leak_probe::serve_stand_in().await;
+            }
+            let defaults = LeakTarget::default();
+            let target = LeakTarget {
+                ipv4: target.unwrap_or(defaults.ipv4),
+                ipv6: target6.or(defaults.ipv6),
+            };
+            leak_test::run_leak_tests(&format, target, harness).await
         }
-        Commands::Circuits => {
+        Commands::OnionTest { address } => {
+            use cli::commands::onion_test;
+            onion_test::run_onion_test(&address).await
+        }
+        Commands::Circuits { action } => {
             use cli::commands::circuits;
-            circuits::list_circuits().await?;
+            match action.unwrap_or(CircuitCommands::List) {
+                CircuitCommands::List => circuits::list_circuits().await?,
+                CircuitCommands::Close { id } => circuits::close_circuit(&id).await?,
+                CircuitCommands::Extend { relays } => circuits::extend_circuit(&relays).await?,
+                CircuitCommands::Streams => circuits::list_streams().await?,
+                CircuitCommands::CloseStream { id } => circuits::close_stream(&id).await?,
+                CircuitCommands::Attach { stream, circuit } => {
+                    circuits::attach_stream(&stream, &circuit).await?
+                }
+            }
+            Ok(())
+        }
+        Commands::Exec { user, command } => {
+            use cli::commands::exec;
+            let code = exec::exec(&command, user.as_deref()).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
             Ok(())
         }
-        Commands::NewCircuit => {
+        Commands::NewCircuit { every } => {
             use cli::commands::circuits;
-            circuits::new_circuit().await?;
+            circuits::new_circuit(every).await?;
             Ok(())
         }
         Commands::State => {
@@ -702,7 +888,7 @@ async fn main() -> TorrerResult<()> {
         }
         Commands::Monitor => {
             use cli::commands::events;
-            events::monitor_events()?;
+            events::monitor_events().await?;
             Ok(())
         }
         Commands::Events { count } => {
@@ -720,6 +906,13 @@ async fn main() -> TorrerResult<()> {
             firewall::configure_firewall()?;
             Ok(())
         }
+        Commands::Firewall { action } => {
+            use cli::commands::firewall;
+            match action {
+                FirewallCommands::Plan => firewall::plan_firewall()?,
+            }
+            Ok(())
+        }
         Commands::ListTasks => {
             use cli::commands::schedule;
             schedule::list_tasks()?;
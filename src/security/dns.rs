@@ -1,30 +1,41 @@
-use std::process::{Command, Stdio};
+use std::sync::Arc;
 
-use crate::error::{TorrerError, TorrerResult};
-
-const TOR_DNS_PORT: u16 = 5353;
+use crate::error::TorrerResult;
+use crate::iptables::FirewallBackend;
+use crate::security::dns_takeover::{DnsPaths, DnsTakeover, ResolvConfMode};
 
 /// DNS leak prevention manager
-pub struct DnsManager;
+pub struct DnsManager {
+    firewall: Arc<dyn FirewallBackend>,
+    dns_port: u16,
+    paths: DnsPaths,
+}
 
 impl DnsManager {
-    /// Create a new DnsManager
-    pub fn new() -> Self {
-        Self
+    /// Create a new DnsManager that installs its rules through `firewall`
+    /// and points the system resolver at Tor's `dns_port`
+    pub fn new(firewall: Arc<dyn FirewallBackend>, dns_port: u16) -> Self {
+        Self {
+            firewall,
+            dns_port,
+            paths: DnsPaths::default(),
+        }
+    }
+
+    /// Point the system resolver at `dns_port` from the next takeover on
+    pub fn set_dns_port(&mut self, dns_port: u16) {
+        self.dns_port = dns_port;
     }
 
     /// Configure DNS to route through Tor
     pub fn configure_dns(&self) -> TorrerResult<()> {
         log::info!("Configuring DNS leak prevention");
 
-        // Redirect DNS queries to Tor DNSPort via iptables
-        self.redirect_dns_queries()?;
+        // Redirect DNS queries to Tor DNSPort and block direct DNS queries
+        self.firewall.apply_dns_redirect()?;
 
-        // Block direct DNS queries
-        self.block_direct_dns()?;
-
-        // Configure systemd-resolved (if available)
-        self.configure_systemd_resolved()?;
+        // Point the system resolver at Tor
+        self.take_over()?;
 
         log::info!("DNS leak prevention configured");
         Ok(())
@@ -34,90 +45,63 @@ impl DnsManager {
     pub fn remove_dns_config(&self) -> TorrerResult<()> {
         log::info!("Removing DNS leak prevention configuration");
 
-        // Remove DNS redirect rules
-        let _ = self.run_iptables(&[
-            "-t", "nat",
-            "-D", "OUTPUT",
-            "-p", "udp",
-            "--dport", "53",
-            "-j", "REDIRECT",
-            "--to-ports", &TOR_DNS_PORT.to_string(),
-        ]);
-
-        // Remove DNS block rules
-        let _ = self.run_iptables(&[
-            "-D", "OUTPUT",
-            "-p", "udp",
-            "--dport", "53",
-            "!", "-o", "lo",
-            "-j", "DROP",
-        ]);
+        // Hand the resolver back before the redirect goes
+        self.release()?;
+
+        // Remove DNS redirect and block rules
+        self.firewall.remove_dns_redirect()?;
 
         log::info!("DNS leak prevention removed");
         Ok(())
     }
 
-    /// Redirect DNS queries to Tor DNSPort
-    fn redirect_dns_queries(&self) -> TorrerResult<()> {
-        self.run_iptables(&[
-            "-t", "nat",
-            "-A", "OUTPUT",
-            "-p", "udp",
-            "--dport", "53",
-            "-j", "REDIRECT",
-            "--to-ports", &TOR_DNS_PORT.to_string(),
-        ])
-    }
-
-    /// Block direct DNS queries (fallback)
-    fn block_direct_dns(&self) -> TorrerResult<()> {
-        self.run_iptables(&[
-            "-A", "OUTPUT",
-            "-p", "udp",
-            "--dport", "53",
-            "!", "-o", "lo",
-            "-j", "DROP",
-        ])
-    }
-
-    /// Configure systemd-resolved to use Tor
-    fn configure_systemd_resolved(&self) -> TorrerResult<()> {
-        // Try to configure systemd-resolved
-        // This is a best-effort operation
-        let _ = Command::new("systemctl")
-            .args(&["is-active", "systemd-resolved"])
-            .output();
+    /// Point systemd-resolved, NetworkManager or a static resolv.conf at Tor
+    pub fn take_over(&self) -> TorrerResult<()> {
+        // A takeover left by an earlier run may point at another port
+        if self.is_taken_over() {
+            self.release()?;
+        }
 
-        // Note: Full systemd-resolved configuration would require
-        // modifying /etc/systemd/resolved.conf, which is out of scope
-        // for this MVP. The iptables rules should be sufficient.
+        let mode = ResolvConfMode::of(&self.paths.resolv_conf)?;
+        if !mode.reaches(self.dns_port) {
+            log::info!(
+                "Leaving the system resolver ({:?}) alone; resolv.conf cannot point at port {}",
+                mode,
+                self.dns_port
+            );
+            return Ok(());
+        }
+        log::info!("Taking over the system resolver ({:?})", mode);
+
+        let takeover = DnsTakeover::plan(mode, self.paths.clone());
+        let applied = takeover
+            .write_files(self.dns_port)
+            .and_then(|_| takeover.reload_services());
+        if let Err(e) = applied {
+            log::error!("Failed to take over the system resolver: {}", e);
+            if let Err(e) = takeover.restore_files().and_then(|_| takeover.reload_services()) {
+                log::error!("Failed to undo the resolver takeover: {}", e);
+            }
+            return Err(e);
+        }
 
         Ok(())
     }
 
-    /// Run an iptables command
-    fn run_iptables(&self, args: &[&str]) -> TorrerResult<()> {
-        let output = Command::new("iptables")
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| {
-                TorrerError::Iptables(format!("Failed to run iptables: {}", e))
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            log::debug!("iptables command failed: {} (args: {:?})", stderr, args);
-        }
+    /// Undo the takeover recorded by `take_over`, if any
+    pub fn release(&self) -> TorrerResult<()> {
+        let takeover = match DnsTakeover::load(&self.paths)? {
+            Some(takeover) => takeover,
+            None => return Ok(()),
+        };
 
-        Ok(())
+        log::info!("Restoring the system resolver ({:?})", takeover.mode);
+        takeover.restore_files()?;
+        takeover.reload_services()
     }
-}
 
-impl Default for DnsManager {
-    fn default() -> Self {
-        Self::new()
+    /// Whether the system resolver is pointed at Tor
+    pub fn is_taken_over(&self) -> bool {
+        self.paths.record().exists()
     }
 }
-
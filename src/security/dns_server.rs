@@ -0,0 +1,684 @@
+// DNS front-end: answers what Tor's DNSPort cannot, caches the rest
+
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::utils::{in_range, network_range};
+
+/// Port the resolver listens on
+pub const DNS_RESOLVER_PORT: u16 = 53;
+
+/// Tor's default VirtualAddrNetworkIPv4 and VirtualAddrNetworkIPv6
+pub const DEFAULT_VIRTUAL_NETWORKS: [&str; 2] = ["127.192.0.0/10", "fe80::/10"];
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_AAAA: u16 = 28;
+const TYPE_OPT: u16 = 41;
+const CLASS_IN: u16 = 1;
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_FORMERR: u8 = 1;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_NOTIMP: u8 = 4;
+
+/// How long to wait for Tor's DNSPort
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long NXDOMAIN, empty answers and local PTR answers are kept
+const NEGATIVE_TTL: u32 = 60;
+/// Longest a cached answer is kept, whatever its TTL
+const MAX_TTL: u32 = 3600;
+const CACHE_CAPACITY: usize = 4096;
+const MAX_MESSAGE: usize = 4096;
+/// How often the counters are written for `torrer stats`
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+fn parse_error(message: &str) -> TorrerError {
+    TorrerError::Parse(format!("DNS message: {}", message))
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> TorrerResult<u16> {
+    packet
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| parse_error("truncated"))
+}
+
+fn read_u32(packet: &[u8], offset: usize) -> TorrerResult<u32> {
+    packet
+        .get(offset..offset + 4)
+        .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| parse_error("truncated"))
+}
+
+/// Read the name at `offset`, following compression pointers
+///
+/// Returns the name without its trailing dot and the offset just past it.
+fn read_name(packet: &[u8], offset: usize) -> TorrerResult<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let length = *packet.get(position).ok_or_else(|| parse_error("truncated name"))? as usize;
+        match length & 0xC0 {
+            0x00 if length == 0 => {
+                position += 1;
+                break;
+            }
+            0x00 => {
+                let label = packet
+                    .get(position + 1..position + 1 + length)
+                    .ok_or_else(|| parse_error("truncated label"))?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                position += 1 + length;
+            }
+            0xC0 => {
+                jumps += 1;
+                if jumps > 32 {
+                    return Err(parse_error("compression loop"));
+                }
+                let pointer = (read_u16(packet, position)? & 0x3FFF) as usize;
+                end.get_or_insert(position + 2);
+                position = pointer;
+            }
+            _ => return Err(parse_error("unknown label type")),
+        }
+    }
+
+    let name = labels.join(".");
+    if name.len() > 253 {
+        return Err(parse_error("name too long"));
+    }
+    Ok((name, end.unwrap_or(position)))
+}
+
+/// Encode `name` as DNS labels
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(name.len() + 2);
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend_from_slice(label);
+    }
+    out.push(0);
+    out
+}
+
+/// The question of a DNS query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    /// Lower-cased, without the trailing dot
+    pub name: String,
+    pub qtype: u16,
+    pub qclass: u16,
+}
+
+/// A DNS query with exactly one question
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub id: u16,
+    pub opcode: u8,
+    pub question: Question,
+    /// Header and question, echoed in responses
+    echo: Vec<u8>,
+}
+
+impl Query {
+    /// Parse a query received from a client
+    pub fn parse(packet: &[u8]) -> TorrerResult<Self> {
+        let id = read_u16(packet, 0)?;
+        let flags = read_u16(packet, 2)?;
+        if flags & 0x8000 != 0 {
+            return Err(parse_error("not a query"));
+        }
+        if read_u16(packet, 4)? != 1 {
+            return Err(parse_error("expected exactly one question"));
+        }
+
+        let (name, offset) = read_name(packet, 12)?;
+        let qtype = read_u16(packet, offset)?;
+        let qclass = read_u16(packet, offset + 2)?;
+
+        Ok(Self {
+            id,
+            opcode: ((flags >> 11) & 0x0F) as u8,
+            question: Question {
+                name: name.to_ascii_lowercase(),
+                qtype,
+                qclass,
+            },
+            echo: packet[..offset + 4].to_vec(),
+        })
+    }
+
+    /// A response to this query with `rcode` and answer records
+    pub fn respond(&self, rcode: u8, answers: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = self.echo.clone();
+        // QR, the query's opcode and RD; RA
+        out[2] = 0x80 | (self.opcode << 3) | (out[2] & 0x01);
+        out[3] = 0x80 | (rcode & 0x0F);
+        out[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        out[8..12].fill(0);
+        for answer in answers {
+            out.extend_from_slice(answer);
+        }
+        out
+    }
+}
+
+/// A recursive query for `name` with one question of `qtype`
+pub fn build_query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    packet.extend_from_slice(&encode_name(name));
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// A header-only response for a query too broken to echo
+///
+/// Responses (QR set) and packets shorter than a header get none, so two
+/// resolvers, or a spoofed source, cannot keep answering each other.
+fn header_response(packet: &[u8], rcode: u8) -> Option<Vec<u8>> {
+    let header = packet.get(..12)?;
+    if header[2] & 0x80 != 0 {
+        return None;
+    }
+    let mut out = header.to_vec();
+    out[2] = 0x80 | (out[2] & 0x79);
+    out[3] = 0x80 | rcode;
+    out[4..12].fill(0);
+    Some(out)
+}
+
+/// PTR answer for the query's name (pointed at by offset 12)
+fn ptr_record(target: &str, ttl: u32) -> Vec<u8> {
+    let target = encode_name(target);
+    let mut record = vec![0xC0, 0x0C];
+    record.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    record.extend_from_slice(&CLASS_IN.to_be_bytes());
+    record.extend_from_slice(&ttl.to_be_bytes());
+    record.extend_from_slice(&(target.len() as u16).to_be_bytes());
+    record.extend_from_slice(&target);
+    record
+}
+
+/// The address a reverse name (`in-addr.arpa` or `ip6.arpa`) stands for
+pub fn reverse_address(name: &str) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+
+    if let Some(octets) = name.strip_suffix(".in-addr.arpa") {
+        let octets: Vec<u8> = octets.split('.').map(|octet| octet.parse().ok()).collect::<Option<_>>()?;
+        if octets.len() != 4 {
+            return None;
+        }
+        return Some(IpAddr::V4(Ipv4Addr::new(octets[3], octets[2], octets[1], octets[0])));
+    }
+
+    let nibbles = name.strip_suffix(".ip6.arpa")?;
+    let nibbles: Vec<&str> = nibbles.split('.').collect();
+    if nibbles.len() != 32 {
+        return None;
+    }
+    let mut address: u128 = 0;
+    for nibble in nibbles.iter().rev() {
+        address = address << 4 | u8::from_str_radix(nibble, 16).ok().filter(|_| nibble.len() == 1)? as u128;
+    }
+    Some(IpAddr::V6(Ipv6Addr::from(address)))
+}
+
+/// A resource record in an upstream response
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    rtype: u16,
+    /// Offset of the TTL field, rewritten when served from the cache
+    ttl_offset: usize,
+    ttl: u32,
+    data: Vec<u8>,
+    /// Whether it is in the answer section
+    answer: bool,
+}
+
+/// The rcode and records of an upstream response, EDNS aside
+fn parse_response(packet: &[u8]) -> TorrerResult<(u8, Vec<Record>)> {
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 == 0 {
+        return Err(parse_error("not a response"));
+    }
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)? as usize;
+    let total = answers + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(packet, offset)?.1 + 4;
+    }
+
+    let mut records = Vec::new();
+    for index in 0..total {
+        offset = read_name(packet, offset)?.1;
+        let rtype = read_u16(packet, offset)?;
+        let ttl = read_u32(packet, offset + 4)?;
+        let length = read_u16(packet, offset + 8)? as usize;
+        let data = packet
+            .get(offset + 10..offset + 10 + length)
+            .ok_or_else(|| parse_error("truncated record"))?;
+        // An OPT record's TTL field holds EDNS flags
+        if rtype != TYPE_OPT {
+            records.push(Record {
+                rtype,
+                ttl_offset: offset + 4,
+                ttl,
+                data: data.to_vec(),
+                answer: index < answers,
+            });
+        }
+        offset += 10 + length;
+    }
+
+    Ok(((flags & 0x0F) as u8, records))
+}
+
+/// The address an A or AAAA record carries
+fn record_address(record: &Record) -> Option<IpAddr> {
+    match record.rtype {
+        TYPE_A => <[u8; 4]>::try_from(&record.data[..]).ok().map(IpAddr::from),
+        TYPE_AAAA => <[u8; 16]>::try_from(&record.data[..]).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
+
+struct CacheEntry {
+    response: Vec<u8>,
+    ttls: Vec<(usize, u32)>,
+    stored: Instant,
+    expires: Instant,
+}
+
+/// Upstream responses kept for their TTL
+#[derive(Default)]
+pub struct DnsCache {
+    entries: HashMap<(String, u16), CacheEntry>,
+}
+
+impl DnsCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached response to `question` for query `id`, with TTLs counted down
+    pub fn get(&mut self, question: &Question, id: u16, now: Instant) -> Option<Vec<u8>> {
+        let key = (question.name.clone(), question.qtype);
+        let entry = self.entries.get(&key)?;
+        if now >= entry.expires {
+            self.entries.remove(&key);
+            return None;
+        }
+
+        let elapsed = now.duration_since(entry.stored).as_secs() as u32;
+        let mut response = entry.response.clone();
+        response[0..2].copy_from_slice(&id.to_be_bytes());
+        for &(offset, ttl) in &entry.ttls {
+            response[offset..offset + 4].copy_from_slice(&ttl.saturating_sub(elapsed).to_be_bytes());
+        }
+        Some(response)
+    }
+
+    /// Keep `response` for its lowest TTL; failures are not cached
+    pub fn insert(&mut self, question: &Question, response: &[u8], now: Instant) -> bool {
+        let (rcode, records) = match parse_response(response) {
+            Ok(parsed) => parsed,
+            Err(_) => return false,
+        };
+        let ttl = match rcode {
+            RCODE_NOERROR => records.iter().map(|record| record.ttl).min().unwrap_or(NEGATIVE_TTL),
+            RCODE_NXDOMAIN => NEGATIVE_TTL,
+            _ => return false,
+        }
+        .min(MAX_TTL);
+        if ttl == 0 {
+            return false;
+        }
+
+        if self.entries.len() >= CACHE_CAPACITY {
+            self.entries.retain(|_, entry| entry.expires > now);
+            if self.entries.len() >= CACHE_CAPACITY {
+                return false;
+            }
+        }
+
+        self.entries.insert(
+            (question.name.clone(), question.qtype),
+            CacheEntry {
+                response: response.to_vec(),
+                ttls: records.iter().map(|record| (record.ttl_offset, record.ttl)).collect(),
+                stored: now,
+                expires: now + Duration::from_secs(ttl as u64),
+            },
+        );
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Resolution counters, written to a file for `torrer stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsStats {
+    pub queries: u64,
+    pub cache_hits: u64,
+    /// Queries passed on to Tor's DNSPort
+    pub forwarded: u64,
+    /// Queries Tor did not answer in time
+    pub failures: u64,
+    /// Record types and opcodes Tor cannot resolve, refused at once
+    pub unsupported: u64,
+    /// PTR queries answered from virtual address mappings
+    pub mapped_ptr: u64,
+}
+
+impl DnsStats {
+    /// Counters written by a running resolver, if any
+    pub fn load(path: &Path) -> TorrerResult<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(stats) => Ok(Some(serde_json::from_str(&stats)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> TorrerResult<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Share of queries answered from the cache, in percent
+    pub fn cache_hit_rate(&self) -> f64 {
+        if self.queries == 0 {
+            0.0
+        } else {
+            self.cache_hits as f64 * 100.0 / self.queries as f64
+        }
+    }
+}
+
+/// DNS front-end forwarding A/AAAA/PTR queries to Tor's DNSPort
+///
+/// Tor only resolves those types, and does not answer the rest promptly, so
+/// everything else is refused with NOTIMP. Addresses Tor hands out from its
+/// virtual networks (for `.onion` names) are remembered, so PTR queries for
+/// them are answered locally instead of leaving through an exit.
+pub struct DnsResolver {
+    upstream: SocketAddr,
+    virtual_networks: Vec<(IpAddr, u8)>,
+    cache: Mutex<DnsCache>,
+    mappings: Mutex<HashMap<IpAddr, String>>,
+    stats: Mutex<DnsStats>,
+}
+
+impl DnsResolver {
+    /// Create a resolver forwarding to Tor's DNSPort at `upstream`
+    pub fn new(upstream: SocketAddr) -> Self {
+        Self {
+            upstream,
+            virtual_networks: DEFAULT_VIRTUAL_NETWORKS
+                .iter()
+                .filter_map(|range| network_range(range).ok())
+                .collect(),
+            cache: Mutex::new(DnsCache::new()),
+            mappings: Mutex::new(HashMap::new()),
+            stats: Mutex::new(DnsStats::default()),
+        }
+    }
+
+    /// Treat `networks` (CIDR) as Tor's virtual networks instead of its defaults
+    pub fn with_virtual_networks(mut self, networks: &[&str]) -> Self {
+        self.virtual_networks = networks.iter().filter_map(|range| network_range(range).ok()).collect();
+        self
+    }
+
+    /// Counters so far
+    pub fn stats(&self) -> DnsStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    /// The name Tor mapped `address` to, if the resolver has seen it
+    pub fn mapped_name(&self, address: IpAddr) -> Option<String> {
+        self.mappings.lock().unwrap().get(&address).cloned()
+    }
+
+    fn count(&self, update: impl FnOnce(&mut DnsStats)) {
+        update(&mut self.stats.lock().unwrap());
+    }
+
+    fn is_virtual(&self, address: IpAddr) -> bool {
+        self.virtual_networks
+            .iter()
+            .any(|&(network, prefix)| in_range(address, network, prefix))
+    }
+
+    /// Answer one query; `None` when the packet is not worth a response
+    pub async fn resolve(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let query = match Query::parse(packet) {
+            Ok(query) => query,
+            Err(e) => {
+                log::debug!("Ignoring malformed DNS query: {}", e);
+                return header_response(packet, RCODE_FORMERR);
+            }
+        };
+        self.count(|stats| stats.queries += 1);
+
+        let question = &query.question;
+        let supported = matches!(question.qtype, TYPE_A | TYPE_AAAA | TYPE_PTR);
+        if query.opcode != 0 || question.qclass != CLASS_IN || !supported {
+            log::debug!("Refusing DNS query for {} (type {})", question.name, question.qtype);
+            self.count(|stats| stats.unsupported += 1);
+            return Some(query.respond(RCODE_NOTIMP, &[]));
+        }
+
+        if question.qtype == TYPE_PTR {
+            if let Some(address) = reverse_address(&question.name) {
+                if let Some(name) = self.mapped_name(address) {
+                    self.count(|stats| stats.mapped_ptr += 1);
+                    return Some(query.respond(RCODE_NOERROR, &[ptr_record(&name, NEGATIVE_TTL)]));
+                }
+                // Tor's own address space; no exit knows about it
+                if self.is_virtual(address) {
+                    return Some(query.respond(RCODE_NXDOMAIN, &[]));
+                }
+            }
+        }
+
+        if let Some(response) = self.cache.lock().unwrap().get(question, query.id, Instant::now()) {
+            self.count(|stats| stats.cache_hits += 1);
+            return Some(response);
+        }
+
+        match exchange(self.upstream, packet, query.id).await {
+            Ok(response) => {
+                self.count(|stats| stats.forwarded += 1);
+                self.remember_mappings(question, &response);
+                self.cache.lock().unwrap().insert(question, &response, Instant::now());
+                Some(response)
+            }
+            Err(e) => {
+                log::debug!("Failed to resolve {} through Tor: {}", question.name, e);
+                self.count(|stats| stats.failures += 1);
+                Some(query.respond(RCODE_SERVFAIL, &[]))
+            }
+        }
+    }
+
+    /// Note the virtual addresses Tor answered `question` with
+    fn remember_mappings(&self, question: &Question, response: &[u8]) {
+        let records = match parse_response(response) {
+            Ok((_, records)) => records,
+            Err(_) => return,
+        };
+        let mut mappings = self.mappings.lock().unwrap();
+        for address in records.iter().filter(|record| record.answer).filter_map(record_address) {
+            if self.is_virtual(address) {
+                mappings.insert(address, question.name.clone());
+            }
+        }
+    }
+
+    /// Answer queries arriving on `socket` until the task is aborted
+    pub async fn serve(self: Arc<Self>, socket: UdpSocket) {
+        let socket = Arc::new(socket);
+        let mut buffer = vec![0u8; MAX_MESSAGE];
+        loop {
+            let (length, peer) = match socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    log::debug!("DNS resolver receive failed: {}", e);
+                    continue;
+                }
+            };
+            let packet = buffer[..length].to_vec();
+            let (resolver, socket) = (self.clone(), socket.clone());
+            tokio::spawn(async move {
+                if let Some(response) = resolver.resolve(&packet).await {
+                    if let Err(e) = socket.send_to(&response, peer).await {
+                        log::debug!("Failed to answer DNS query from {}: {}", peer, e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Send `packet` to `upstream` and wait for the response to query `id`
+async fn exchange(upstream: SocketAddr, packet: &[u8], id: u16) -> TorrerResult<Vec<u8>> {
+    let local: SocketAddr = match upstream {
+        SocketAddr::V4(_) => (Ipv4Addr::LOCALHOST, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::LOCALHOST, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(upstream).await?;
+    socket.send(packet).await?;
+
+    let mut buffer = vec![0u8; MAX_MESSAGE];
+    let exchange = async {
+        loop {
+            let length = socket.recv(&mut buffer).await?;
+            if length >= 12 && read_u16(&buffer, 0)? == id {
+                return Ok::<_, TorrerError>(buffer[..length].to_vec());
+            }
+        }
+    };
+    tokio::time::timeout(UPSTREAM_TIMEOUT, exchange)
+        .await
+        .map_err(|_| TorrerError::Tor("Tor's DNSPort did not answer".to_string()))?
+}
+
+/// Ask `upstream` for the `qtype` addresses of `name`
+///
+/// Returns the response code and the A/AAAA records answered.
+pub async fn lookup(upstream: SocketAddr, name: &str, qtype: u16) -> TorrerResult<(u8, Vec<IpAddr>)> {
+    let id = rand::random::<u16>();
+    let response = exchange(upstream, &build_query(id, name, qtype), id).await?;
+    let (rcode, records) = parse_response(&response)?;
+    let addresses = records
+        .iter()
+        .filter(|record| record.answer)
+        .filter_map(record_address)
+        .collect();
+    Ok((rcode, addresses))
+}
+
+/// A running resolver and its tasks
+pub struct DnsResolverHandle {
+    resolver: Arc<DnsResolver>,
+    tasks: Vec<JoinHandle<()>>,
+    stats_path: PathBuf,
+}
+
+impl DnsResolverHandle {
+    /// Listen on port 53 of each of `addresses`, forwarding to Tor's DNSPort
+    ///
+    /// `virtual_networks` are the ranges Tor maps onion names into. Counters
+    /// are written to `stats_path` as they change.
+    pub async fn spawn(
+        addresses: &[IpAddr],
+        dns_port: u16,
+        virtual_networks: &[&str],
+        stats_path: PathBuf,
+    ) -> TorrerResult<Self> {
+        let resolver = Arc::new(
+            DnsResolver::new((Ipv4Addr::LOCALHOST, dns_port).into()).with_virtual_networks(virtual_networks),
+        );
+
+        let mut sockets = Vec::new();
+        for &address in addresses {
+            let socket = UdpSocket::bind((address, DNS_RESOLVER_PORT)).await.map_err(|e| {
+                TorrerError::Config(format!("Failed to listen for DNS on {}:{}: {}", address, DNS_RESOLVER_PORT, e))
+            })?;
+            sockets.push(socket);
+        }
+
+        let mut tasks: Vec<JoinHandle<()>> = sockets
+            .into_iter()
+            .map(|socket| tokio::spawn(resolver.clone().serve(socket)))
+            .collect();
+
+        let (writer, path) = (resolver.clone(), stats_path.clone());
+        tasks.push(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(STATS_INTERVAL);
+            let mut written = None;
+            loop {
+                ticker.tick().await;
+                let stats = writer.stats();
+                if written.as_ref() != Some(&stats) {
+                    if let Err(e) = stats.save(&path) {
+                        log::debug!("Failed to write DNS resolver statistics: {}", e);
+                    }
+                    written = Some(stats);
+                }
+            }
+        }));
+
+        log::info!("DNS resolver listening on port {} of {:?}", DNS_RESOLVER_PORT, addresses);
+        Ok(Self {
+            resolver,
+            tasks,
+            stats_path,
+        })
+    }
+
+    pub fn resolver(&self) -> &DnsResolver {
+        &self.resolver
+    }
+
+    /// Stop answering and write the final counters
+    pub fn stop(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Err(e) = self.resolver.stats().save(&self.stats_path) {
+            log::debug!("Failed to write DNS resolver statistics: {}", e);
+        }
+    }
+}
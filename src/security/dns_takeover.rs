@@ -0,0 +1,287 @@
+// Pointing the system resolver at Tor's DNSPort, and putting it back
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::security::DNS_RESOLVER_PORT;
+
+/// First line of every file Torrer writes, so they can be recognized later
+pub const TAKEOVER_MARKER: &str = "# Written by Torrer while routing through Tor; removed by `torrer stop`";
+
+/// Address systemd-resolved's stub listener answers on
+const RESOLVED_STUB_ADDRESS: &str = "127.0.0.53";
+
+/// Files touched by a DNS takeover
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsPaths {
+    pub resolv_conf: PathBuf,
+    /// Drop-in pointing systemd-resolved at Tor
+    pub resolved_drop_in: PathBuf,
+    /// Drop-in stopping NetworkManager from rewriting resolv.conf
+    pub network_manager_drop_in: PathBuf,
+    /// Where the takeover record and the resolv.conf backup are kept
+    pub state_dir: PathBuf,
+}
+
+impl Default for DnsPaths {
+    fn default() -> Self {
+        Self {
+            resolv_conf: PathBuf::from("/etc/resolv.conf"),
+            resolved_drop_in: PathBuf::from("/etc/systemd/resolved.conf.d/torrer.conf"),
+            network_manager_drop_in: PathBuf::from("/etc/NetworkManager/conf.d/torrer-dns.conf"),
+            state_dir: PathBuf::from("/var/lib/torrer/dns"),
+        }
+    }
+}
+
+impl DnsPaths {
+    /// Record of the takeover in progress
+    pub fn record(&self) -> PathBuf {
+        self.state_dir.join("takeover.json")
+    }
+
+    /// Copy of resolv.conf from before the takeover
+    pub fn resolv_conf_backup(&self) -> PathBuf {
+        self.state_dir.join("resolv.conf.backup")
+    }
+
+    /// Counters written by the DNS resolver
+    pub fn resolver_stats(&self) -> PathBuf {
+        self.state_dir.join("resolver-stats.json")
+    }
+}
+
+/// Who manages /etc/resolv.conf
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResolvConfMode {
+    /// systemd-resolved, through its stub (127.0.0.53) or its own file
+    SystemdResolved,
+    /// NetworkManager writes the file itself
+    NetworkManager,
+    /// A plain file nothing rewrites
+    Static,
+}
+
+impl ResolvConfMode {
+    /// Work out the mode from resolv.conf's symlink target and contents
+    pub fn detect(link_target: Option<&Path>, contents: &str) -> Self {
+        let target = link_target.map(|target| target.to_string_lossy().into_owned()).unwrap_or_default();
+        let nameservers: Vec<&str> = contents
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("nameserver"))
+            .map(str::trim)
+            .collect();
+
+        if target.contains("systemd/resolve")
+            || contents.contains("systemd-resolved")
+            || nameservers.contains(&RESOLVED_STUB_ADDRESS)
+        {
+            ResolvConfMode::SystemdResolved
+        } else if target.contains("NetworkManager") || contents.contains("Generated by NetworkManager") {
+            ResolvConfMode::NetworkManager
+        } else {
+            ResolvConfMode::Static
+        }
+    }
+
+    /// Whether a takeover in this mode sends every process to `dns_port`
+    ///
+    /// resolv.conf cannot name a port, so it only works with the resolver on
+    /// 127.0.0.1:53. Anything else relies on the DNS redirect, which
+    /// split-tunneled processes skip.
+    pub fn reaches(&self, dns_port: u16) -> bool {
+        *self == ResolvConfMode::SystemdResolved || dns_port == DNS_RESOLVER_PORT
+    }
+
+    /// The mode of resolv.conf at `path`
+    pub fn of(path: &Path) -> TorrerResult<Self> {
+        let target = fs::read_link(path).ok();
+        let contents = fs::read_to_string(path).unwrap_or_default();
+        Ok(Self::detect(target.as_deref(), &contents))
+    }
+}
+
+/// resolv.conf sending every query to Tor
+///
+/// Resolvers cannot be given a port, so queries go to 127.0.0.1:53, which
+/// the DNS redirect sends on to the DNSPort.
+pub fn tor_resolv_conf() -> String {
+    format!("{}\nnameserver 127.0.0.1\n", TAKEOVER_MARKER)
+}
+
+/// systemd-resolved drop-in making Tor's DNSPort the only upstream
+///
+/// LLMNR and multicast DNS are turned off, since they would announce and
+/// resolve names on the local network outside Tor.
+pub fn resolved_drop_in(dns_port: u16) -> String {
+    format!(
+        "{}\n[Resolve]\nDNS=127.0.0.1:{}\nDomains=~.\nDNSOverTLS=no\nDNSSEC=no\nLLMNR=no\nMulticastDNS=no\n",
+        TAKEOVER_MARKER, dns_port
+    )
+}
+
+/// NetworkManager drop-in leaving resolv.conf to Torrer
+pub fn network_manager_drop_in() -> String {
+    format!("{}\n[main]\ndns=none\nrc-manager=unmanaged\n", TAKEOVER_MARKER)
+}
+
+/// What a DNS takeover changed, saved so `torrer stop` can undo it
+///
+/// The record is written before anything is changed, and undoing tolerates
+/// steps that never happened, so an interrupted takeover is reverted too.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DnsTakeover {
+    pub mode: ResolvConfMode,
+    pub paths: DnsPaths,
+    /// Drop-ins written, removed on release
+    pub drop_ins: Vec<PathBuf>,
+    /// Whether resolv.conf was replaced (and backed up)
+    pub replaced_resolv_conf: bool,
+    /// Where resolv.conf pointed, if it was a symlink
+    pub resolv_conf_link: Option<PathBuf>,
+}
+
+impl DnsTakeover {
+    /// The changes needed for resolv.conf in `mode`
+    pub fn plan(mode: ResolvConfMode, paths: DnsPaths) -> Self {
+        let (drop_ins, replaced_resolv_conf) = match mode {
+            ResolvConfMode::SystemdResolved => (vec![paths.resolved_drop_in.clone()], false),
+            ResolvConfMode::NetworkManager => (vec![paths.network_manager_drop_in.clone()], true),
+            ResolvConfMode::Static => (Vec::new(), true),
+        };
+        let resolv_conf_link = fs::read_link(&paths.resolv_conf).ok();
+
+        Self {
+            mode,
+            paths,
+            drop_ins,
+            replaced_resolv_conf,
+            resolv_conf_link,
+        }
+    }
+
+    /// The takeover left by an earlier `torrer start`, if any
+    pub fn load(paths: &DnsPaths) -> TorrerResult<Option<Self>> {
+        match fs::read_to_string(paths.record()) {
+            Ok(record) => Ok(Some(serde_json::from_str(&record)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the record, the backup and Torrer's files
+    ///
+    /// Services are not touched; see `reload_services`.
+    pub fn write_files(&self, dns_port: u16) -> TorrerResult<()> {
+        fs::create_dir_all(&self.paths.state_dir)?;
+        fs::write(self.paths.record(), serde_json::to_string_pretty(self)?)?;
+
+        for drop_in in &self.drop_ins {
+            let contents = if *drop_in == self.paths.resolved_drop_in {
+                resolved_drop_in(dns_port)
+            } else {
+                network_manager_drop_in()
+            };
+            if let Some(dir) = drop_in.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(drop_in, contents)?;
+        }
+
+        if self.replaced_resolv_conf {
+            // A missing resolv.conf is backed up as empty
+            let current = fs::read(&self.paths.resolv_conf).unwrap_or_default();
+            fs::write(self.paths.resolv_conf_backup(), current)?;
+            replace_file(&self.paths.resolv_conf, &tor_resolv_conf())?;
+        }
+
+        Ok(())
+    }
+
+    /// Put back resolv.conf, remove the drop-ins and the record
+    ///
+    /// A resolv.conf changed by someone else since the takeover is left alone.
+    pub fn restore_files(&self) -> TorrerResult<()> {
+        let resolv_conf = &self.paths.resolv_conf;
+        let backup = self.paths.resolv_conf_backup();
+
+        if self.replaced_resolv_conf && backup.exists() {
+            let current = fs::read_to_string(resolv_conf).unwrap_or_default();
+            if !current.starts_with(TAKEOVER_MARKER) {
+                log::warn!("{:?} was changed since Torrer replaced it; leaving it alone", resolv_conf);
+            } else if let Some(ref target) = self.resolv_conf_link {
+                let _ = fs::remove_file(resolv_conf);
+                std::os::unix::fs::symlink(target, resolv_conf)?;
+            } else {
+                replace_file(resolv_conf, &fs::read_to_string(&backup)?)?;
+            }
+            fs::remove_file(&backup)?;
+        }
+
+        for drop_in in &self.drop_ins {
+            let ours = fs::read_to_string(drop_in)
+                .map(|contents| contents.starts_with(TAKEOVER_MARKER))
+                .unwrap_or(false);
+            if ours {
+                fs::remove_file(drop_in)?;
+            }
+        }
+
+        match fs::remove_file(self.paths.record()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Make systemd-resolved or NetworkManager pick up the changed files
+    pub fn reload_services(&self) -> TorrerResult<()> {
+        match self.mode {
+            ResolvConfMode::SystemdResolved => {
+                run_command("systemctl", &["restart", "systemd-resolved"])?;
+                // Answers cached before the switch would skip Tor
+                if let Err(e) = run_command("resolvectl", &["flush-caches"]) {
+                    log::debug!("{}", e);
+                }
+                Ok(())
+            }
+            ResolvConfMode::NetworkManager => run_command("systemctl", &["reload", "NetworkManager"]),
+            ResolvConfMode::Static => Ok(()),
+        }
+    }
+}
+
+/// Replace `path` through a temporary file, so readers never see it half-written
+///
+/// A symlink at `path` is replaced rather than followed.
+fn replace_file(path: &Path, contents: &str) -> TorrerResult<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".torrer");
+    let temporary = PathBuf::from(temporary);
+
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Run a service manager command, failing on a non-zero exit code
+fn run_command(program: &str, args: &[&str]) -> TorrerResult<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .map_err(|e| TorrerError::Config(format!("Failed to run {}: {}", program, e)))?;
+
+    if !output.status.success() {
+        return Err(TorrerError::Config(format!(
+            "{} {} failed: {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
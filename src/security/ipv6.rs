@@ -1,16 +1,23 @@
-use std::process::{Command, Stdio};
+use std::sync::Arc;
 
-use crate::error::{TorrerError, TorrerResult};
+use crate::error::TorrerResult;
+use crate::iptables::FirewallBackend;
 
 /// IPv6 management
 pub struct Ipv6Manager {
     enabled: bool,
+    routed: bool,
+    firewall: Arc<dyn FirewallBackend>,
 }
 
 impl Ipv6Manager {
-    /// Create a new Ipv6Manager
-    pub fn new(enabled: bool) -> Self {
-        Self { enabled }
+    /// Create a new Ipv6Manager that installs its rules through `firewall`
+    pub fn new(enabled: bool, firewall: Arc<dyn FirewallBackend>) -> Self {
+        Self {
+            enabled,
+            routed: false,
+            firewall,
+        }
     }
 
     /// Enable or disable IPv6
@@ -31,6 +38,26 @@ impl Ipv6Manager {
         self.enabled
     }
 
+    /// Route IPv6 TCP and DNS through Tor, rejecting other IPv6 traffic
+    ///
+    /// An alternative to disabling IPv6 on IPv6-only and dual-stack networks.
+    /// The block, if also set, still takes precedence.
+    pub fn set_routed(&mut self, routed: bool) -> TorrerResult<()> {
+        if routed {
+            log::info!("Routing IPv6 through Tor");
+            self.firewall.apply_ipv6_routing()?;
+        } else {
+            self.firewall.remove_ipv6_routing()?;
+        }
+        self.routed = routed;
+        Ok(())
+    }
+
+    /// Check if IPv6 is routed through Tor
+    pub fn is_routed(&self) -> bool {
+        self.routed
+    }
+
     /// Enable IPv6
     fn enable_ipv6(&self) -> TorrerResult<()> {
         log::info!("Enabling IPv6");
@@ -42,35 +69,12 @@ impl Ipv6Manager {
     fn disable_ipv6(&self) -> TorrerResult<()> {
         log::info!("Disabling IPv6 to prevent leaks");
         
-        // Block IPv6 traffic via iptables
-        self.run_iptables(&["-A", "OUTPUT", "-p", "ipv6", "-j", "DROP"])?;
-        
-        Ok(())
+        // Block IPv6 traffic via the firewall backend
+        self.firewall.block_ipv6()
     }
 
     /// Remove IPv6 block rules
     fn remove_ipv6_block(&self) -> TorrerResult<()> {
-        let _ = self.run_iptables(&["-D", "OUTPUT", "-p", "ipv6", "-j", "DROP"]);
-        Ok(())
-    }
-
-    /// Run an iptables command
-    fn run_iptables(&self, args: &[&str]) -> TorrerResult<()> {
-        let output = Command::new("iptables")
-            .args(args)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()
-            .map_err(|e| {
-                TorrerError::Iptables(format!("Failed to run iptables: {}", e))
-            })?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            log::debug!("iptables command failed: {} (args: {:?})", stderr, args);
-        }
-
-        Ok(())
+        self.firewall.unblock_ipv6()
     }
 }
-
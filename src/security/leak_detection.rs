@@ -1,5 +1,9 @@
+use crate::config::ConfigManager;
 use crate::error::TorrerResult;
-use std::net::UdpSocket;
+use crate::iptables::{FirewallPlan, RoutingRules};
+use crate::security::leak_probe::{rejected, redirected};
+use crate::security::{LeakProbe, LeakTarget, ProbeKind, ProbeVerdict, RulesetInspection};
+use std::net::{IpAddr, SocketAddr};
 use std::time::Duration;
 
 /// DNS leak detection
@@ -52,6 +56,40 @@ impl LeakDetector {
         Ok(ipv6_available)
     }
 
+    /// Test whether UDP other than DNS leaves the host outside Tor
+    ///
+    /// Sends the leak test's UDP probe, as a browser trying QUIC would, and
+    /// classifies it against Torrer's rules when they are loaded.
+    pub async fn test_udp_leak(&self) -> TorrerResult<ProbeVerdict> {
+        log::info!("Testing for UDP leaks...");
+
+        let config = ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .unwrap_or_default();
+        let plan = FirewallPlan::from_config(&config);
+        // Nothing is redirected or rejected unless Torrer's rules are loaded
+        let rules = if RulesetInspection::inspect(&plan).active() {
+            plan.rules.clone()
+        } else {
+            RoutingRules::default()
+        };
+
+        let address = SocketAddr::new(IpAddr::V4(LeakTarget::default().ipv4), ProbeKind::Udp.port());
+        let (outcome, detail) = LeakProbe::new(ProbeKind::Udp, Some(address)).send().await;
+        let verdict = ProbeVerdict::classify(
+            outcome,
+            None,
+            redirected(&rules, ProbeKind::Udp, address.ip()),
+            rejected(&rules, ProbeKind::Udp, address.ip()),
+        );
+        if verdict == ProbeVerdict::Leaked {
+            log::warn!("UDP traffic leaves the host outside Tor ({})", detail);
+        } else {
+            log::info!("UDP traffic is {} ({})", verdict.label(), detail);
+        }
+        Ok(verdict)
+    }
+
     async fn test_tor_dns(&self) -> bool {
         use tokio::time::timeout;
         use tokio::process::Command;
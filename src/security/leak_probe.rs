@@ -0,0 +1,678 @@
+// Leak probes: test sockets toward a target, classified as routed via Tor,
+// blocked or leaked
+
+use std::collections::HashSet;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::process::Command;
+use tokio::time::timeout;
+
+use crate::config::UdpPolicy;
+use crate::error::TorrerResult;
+use crate::iptables::{DiffLine, FirewallPlan, RoutingRules};
+use crate::security::dns_server::{build_query, Query, RCODE_NOERROR, TYPE_A};
+use crate::utils::{in_range, network_range};
+
+/// Port of the plain TCP and IPv6 probes
+pub const TCP_PROBE_PORT: u16 = 80;
+/// Port of the UDP probe (QUIC)
+pub const UDP_PROBE_PORT: u16 = 443;
+/// Port of the DNS probe
+pub const DNS_PROBE_PORT: u16 = 53;
+/// Port of the DNS over TLS probe
+pub const DOT_PROBE_PORT: u16 = 853;
+/// Port of the STUN probe, as WebRTC uses to learn the public address
+pub const STUN_PROBE_PORT: u16 = 3478;
+
+/// Prefix of the token each probe carries, so a stand-in can tell them apart
+pub const TOKEN_PREFIX: &str = "torrer-leak-";
+
+/// How long a probe waits to connect or for an answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const STUN_BINDING_REQUEST: u16 = 0x0001;
+const STUN_BINDING_SUCCESS: u16 = 0x0101;
+const STUN_MAGIC_COOKIE: u32 = 0x2112_A442;
+const STUN_MAPPED_ADDRESS: u16 = 0x0001;
+const STUN_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const STUN_SOFTWARE: u16 = 0x8022;
+
+/// Kind of traffic a probe sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeKind {
+    /// TCP over IPv4
+    Tcp,
+    /// UDP other than DNS
+    Udp,
+    /// ICMP echo
+    Icmp,
+    /// TCP over IPv6
+    Ipv6,
+    /// DNS over UDP
+    Dns,
+    /// DNS over TLS
+    DnsOverTls,
+    /// STUN binding request
+    Stun,
+}
+
+impl ProbeKind {
+    /// All probes, in the order they are reported
+    pub const ALL: [ProbeKind; 7] = [
+        ProbeKind::Tcp,
+        ProbeKind::Udp,
+        ProbeKind::Icmp,
+        ProbeKind::Ipv6,
+        ProbeKind::Dns,
+        ProbeKind::DnsOverTls,
+        ProbeKind::Stun,
+    ];
+
+    /// Name shown in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProbeKind::Tcp => "TCP",
+            ProbeKind::Udp => "UDP",
+            ProbeKind::Icmp => "ICMP",
+            ProbeKind::Ipv6 => "IPv6",
+            ProbeKind::Dns => "DNS",
+            ProbeKind::DnsOverTls => "DNS over TLS",
+            ProbeKind::Stun => "STUN",
+        }
+    }
+
+    /// Port the probe is sent to; 0 for ICMP
+    pub fn port(&self) -> u16 {
+        match self {
+            ProbeKind::Tcp | ProbeKind::Ipv6 => TCP_PROBE_PORT,
+            ProbeKind::Udp => UDP_PROBE_PORT,
+            ProbeKind::Icmp => 0,
+            ProbeKind::Dns => DNS_PROBE_PORT,
+            ProbeKind::DnsOverTls => DOT_PROBE_PORT,
+            ProbeKind::Stun => STUN_PROBE_PORT,
+        }
+    }
+
+    /// Whether Torrer's rules can send this traffic to Tor at all
+    ///
+    /// Tor only carries TCP, plus DNS through its DNSPort.
+    pub fn routable(&self) -> bool {
+        matches!(self, ProbeKind::Tcp | ProbeKind::Ipv6 | ProbeKind::Dns)
+    }
+}
+
+/// Where a probe's traffic ended up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProbeVerdict {
+    /// Redirected to Tor
+    ViaTor,
+    /// Refused or dropped before leaving the host
+    Blocked,
+    /// Left the host outside Tor
+    Leaked,
+}
+
+impl ProbeVerdict {
+    /// Classify a probe
+    ///
+    /// `observed` is whether the stand-in internet received the probe (`None`
+    /// without one), `redirected` whether the live ruleset sends it to Tor and
+    /// `rejected` whether it rejects it. Without a stand-in, traffic that got
+    /// out and is not redirected counts as leaked even if nothing answered.
+    pub fn classify(outcome: ProbeOutcome, observed: Option<bool>, redirected: bool, rejected: bool) -> Self {
+        match (outcome, observed) {
+            (_, Some(true)) => ProbeVerdict::Leaked,
+            (ProbeOutcome::Failed, _) => ProbeVerdict::Blocked,
+            (ProbeOutcome::Refused, _) if rejected => ProbeVerdict::Blocked,
+            _ if redirected => ProbeVerdict::ViaTor,
+            (_, Some(false)) => ProbeVerdict::Blocked,
+            _ => ProbeVerdict::Leaked,
+        }
+    }
+
+    /// Text shown in reports
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProbeVerdict::ViaTor => "routed via Tor",
+            ProbeVerdict::Blocked => "blocked",
+            ProbeVerdict::Leaked => "LEAKED",
+        }
+    }
+}
+
+/// What the probe's own socket saw
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeOutcome {
+    /// Connecting or sending failed (refused, unreachable, not permitted)
+    Failed,
+    /// A datagram was answered with port unreachable, by Torrer's REJECT
+    /// rule or by the far end
+    Refused,
+    /// Sent, but nothing came back in time
+    NoAnswer,
+    /// Connected, or an answer came back
+    Answered,
+}
+
+/// Addresses the probes are sent to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakTarget {
+    pub ipv4: Ipv4Addr,
+    /// IPv6 probe destination; the probe counts as blocked without one
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl Default for LeakTarget {
+    fn default() -> Self {
+        Self {
+            ipv4: Ipv4Addr::new(1, 1, 1, 1),
+            ipv6: Some(Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111)),
+        }
+    }
+}
+
+/// One test socket toward the target
+#[derive(Debug, Clone)]
+pub struct LeakProbe {
+    pub kind: ProbeKind,
+    /// Destination; `None` when the target has no address of this family
+    pub address: Option<SocketAddr>,
+    /// Carried in the payload so a stand-in can recognise the probe
+    pub token: String,
+}
+
+impl LeakProbe {
+    /// A probe of `kind` toward `address` with a fresh token
+    pub fn new(kind: ProbeKind, address: Option<SocketAddr>) -> Self {
+        let bytes: [u8; 12] = rand::thread_rng().gen();
+        Self {
+            kind,
+            address,
+            token: format!("{}{}", TOKEN_PREFIX, hex::encode(bytes)),
+        }
+    }
+
+    /// One probe of each kind toward `target`
+    pub fn plan(target: &LeakTarget) -> Vec<Self> {
+        ProbeKind::ALL
+            .iter()
+            .map(|&kind| {
+                let address = match kind {
+                    ProbeKind::Ipv6 => target.ipv6.map(IpAddr::V6),
+                    _ => Some(IpAddr::V4(target.ipv4)),
+                };
+                Self::new(kind, address.map(|address| SocketAddr::new(address, kind.port())))
+            })
+            .collect()
+    }
+
+    /// Destination as shown in reports
+    pub fn target(&self) -> String {
+        match (self.address, self.kind) {
+            (None, _) => "-".to_string(),
+            (Some(address), ProbeKind::Icmp) => address.ip().to_string(),
+            (Some(address), _) => address.to_string(),
+        }
+    }
+
+    /// Send the probe, returning the outcome and a short description
+    pub async fn send(&self) -> (ProbeOutcome, String) {
+        let address = match self.address {
+            Some(address) => address,
+            None => return (ProbeOutcome::Failed, "no address to probe".to_string()),
+        };
+
+        match self.kind {
+            ProbeKind::Tcp | ProbeKind::Ipv6 | ProbeKind::DnsOverTls => self.send_tcp(address).await,
+            ProbeKind::Udp => self.send_udp(address, self.token.as_bytes()).await,
+            ProbeKind::Dns => {
+                let name = format!("{}.leak-test.invalid", self.token);
+                self.send_udp(address, &build_query(rand::random(), &name, TYPE_A)).await
+            }
+            ProbeKind::Stun => {
+                let transaction: [u8; 12] = rand::thread_rng().gen();
+                self.send_udp(address, &stun_request(&transaction, &self.token)).await
+            }
+            ProbeKind::Icmp => ping(address.ip()).await,
+        }
+    }
+
+    async fn send_tcp(&self, address: SocketAddr) -> (ProbeOutcome, String) {
+        let mut stream = match timeout(PROBE_TIMEOUT, TcpStream::connect(address)).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => return (ProbeOutcome::Failed, e.to_string()),
+            Err(_) => return (ProbeOutcome::NoAnswer, "no answer to connect".to_string()),
+        };
+
+        let _ = stream.write_all(format!("{}\n", self.token).as_bytes()).await;
+        let _ = stream.flush().await;
+        (ProbeOutcome::Answered, "connected".to_string())
+    }
+
+    async fn send_udp(&self, address: SocketAddr, payload: &[u8]) -> (ProbeOutcome, String) {
+        let bind: SocketAddr = match address {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = match UdpSocket::bind(bind).await {
+            Ok(socket) => socket,
+            Err(e) => return (ProbeOutcome::Failed, e.to_string()),
+        };
+        let sent = match socket.connect(address).await {
+            Ok(()) => socket.send(payload).await,
+            Err(e) => Err(e),
+        };
+        match sent {
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                return (ProbeOutcome::Refused, "port unreachable".to_string())
+            }
+            Err(e) => return (ProbeOutcome::Failed, e.to_string()),
+            Ok(_) => {}
+        }
+
+        let mut buffer = [0u8; 512];
+        match timeout(PROBE_TIMEOUT, socket.recv(&mut buffer)).await {
+            Ok(Ok(length)) => {
+                let detail = match self.kind {
+                    ProbeKind::Stun => match stun_mapped_address(&buffer[..length]) {
+                        Some(mapped) => format!("answered, public address {}", mapped.ip()),
+                        None => "answered".to_string(),
+                    },
+                    _ => "answered".to_string(),
+                };
+                (ProbeOutcome::Answered, detail)
+            }
+            Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {
+                (ProbeOutcome::Refused, "port unreachable".to_string())
+            }
+            Ok(Err(e)) => (ProbeOutcome::Failed, e.to_string()),
+            Err(_) => (ProbeOutcome::NoAnswer, "sent, no answer".to_string()),
+        }
+    }
+}
+
+/// Ping `address` once
+async fn ping(address: IpAddr) -> (ProbeOutcome, String) {
+    let output = Command::new("ping")
+        .args(["-n", "-c", "1", "-W", &PROBE_TIMEOUT.as_secs().to_string()])
+        .arg(address.to_string())
+        .output()
+        .await;
+
+    match output {
+        Ok(output) if output.status.success() => (ProbeOutcome::Answered, "echo reply received".to_string()),
+        Ok(_) => (ProbeOutcome::Failed, "no echo reply".to_string()),
+        // Unverified, so not counted as blocked
+        Err(e) => (ProbeOutcome::NoAnswer, format!("could not run ping: {}", e)),
+    }
+}
+
+/// Whether the ruleset in `rules` sends a probe of `kind` to `address` to Tor
+pub fn redirected(rules: &RoutingRules, kind: ProbeKind, address: IpAddr) -> bool {
+    let covers = |range: &String| match network_range(range) {
+        Ok((network, prefix)) => in_range(address, network, prefix),
+        Err(_) => false,
+    };
+    if kind == ProbeKind::Dns {
+        return rules.dns_redirect;
+    }
+    if !kind.routable() {
+        return false;
+    }
+    if rules.virtual_networks.iter().any(covers) {
+        return true;
+    }
+
+    let routed = match address {
+        IpAddr::V4(_) => rules.tor_routing,
+        IpAddr::V6(_) => rules.routes_ipv6(),
+    };
+    routed && !rules.bypass.destinations.iter().any(covers)
+}
+
+/// Whether Torrer's rules answer a probe of `kind` to `address` with port unreachable
+///
+/// Only then is a refusal Torrer's doing rather than the far end's.
+pub fn rejected(rules: &RoutingRules, kind: ProbeKind, address: IpAddr) -> bool {
+    let allowed = |(range, port): &(Option<String>, u16)| {
+        *port == kind.port()
+            && match range {
+                Some(range) => match network_range(range) {
+                    Ok((network, prefix)) => in_range(address, network, prefix),
+                    Err(_) => false,
+                },
+                None => true,
+            }
+    };
+    match kind {
+        ProbeKind::Udp | ProbeKind::Stun => {
+            rules.filters_udp() && rules.udp.policy == UdpPolicy::Reject && !rules.udp.allow.iter().any(allowed)
+        }
+        _ => false,
+    }
+}
+
+/// Torrer's rules in the kernel, compared with what the configuration plans
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RulesetInspection {
+    pub backend: String,
+    /// Number of Torrer rules loaded
+    pub loaded: usize,
+    /// Planned rules that are not loaded
+    pub missing: Vec<String>,
+    /// Loaded rules that are not planned
+    pub unexpected: Vec<String>,
+    /// Why the ruleset could not be read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RulesetInspection {
+    /// Read the live ruleset and compare it with `plan`
+    pub fn inspect(plan: &FirewallPlan) -> Self {
+        match plan.live() {
+            Ok(live) => Self::compare(plan, &live),
+            Err(e) => Self {
+                backend: plan.kind.as_str().to_string(),
+                error: Some(e.to_string()),
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Compare `live` rules with `plan`
+    pub fn compare(plan: &FirewallPlan, live: &[String]) -> Self {
+        let mut inspection = Self {
+            backend: plan.kind.as_str().to_string(),
+            loaded: live.len(),
+            ..Self::default()
+        };
+        for line in plan.diff(live) {
+            match line {
+                DiffLine::Added(rule) => inspection.missing.push(rule),
+                DiffLine::Removed(rule) => inspection.unexpected.push(rule),
+                DiffLine::Same(_) => {}
+            }
+        }
+        inspection
+    }
+
+    /// Whether any Torrer rules are loaded
+    pub fn active(&self) -> bool {
+        self.loaded > 0
+    }
+
+    /// Whether the loaded rules are exactly the planned ones
+    pub fn matches_plan(&self) -> bool {
+        self.active() && self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Result of one probe
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub kind: ProbeKind,
+    pub target: String,
+    pub verdict: ProbeVerdict,
+    pub detail: String,
+}
+
+/// Everything `torrer leak-test` found
+#[derive(Debug, Clone, Serialize)]
+pub struct LeakReport {
+    /// Whether the probes went to the stand-in internet of the local harness
+    pub harness: bool,
+    pub ruleset: RulesetInspection,
+    pub probes: Vec<ProbeResult>,
+}
+
+impl LeakReport {
+    /// Number of probes with `verdict`
+    pub fn count(&self, verdict: ProbeVerdict) -> usize {
+        self.probes.iter().filter(|probe| probe.verdict == verdict).count()
+    }
+}
+
+/// Tokens of the probes the stand-in internet has received
+#[derive(Debug, Clone, Default)]
+pub struct StandInLog {
+    tokens: Arc<Mutex<HashSet<String>>>,
+}
+
+impl StandInLog {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record one line printed by the stand-in (`<protocol> <port> <token>`)
+    pub fn record(&self, line: &str) {
+        if let Some(token) = line.split_whitespace().nth(2).filter(|token| token.starts_with(TOKEN_PREFIX)) {
+            self.tokens.lock().unwrap().insert(token.to_string());
+        }
+    }
+
+    /// Whether the probe carrying `token` arrived
+    pub fn saw(&self, token: &str) -> bool {
+        self.tokens.lock().unwrap().contains(token)
+    }
+}
+
+/// Run every probe toward `target` and classify it
+///
+/// `rules` are the rules the kernel is expected to apply (empty when routing
+/// is off); with `stand_in`, probes are also checked against what arrived.
+pub async fn run_probes(target: &LeakTarget, rules: &RoutingRules, stand_in: Option<&StandInLog>) -> Vec<ProbeResult> {
+    let mut results = Vec::new();
+
+    for probe in LeakProbe::plan(target) {
+        let (outcome, detail) = probe.send().await;
+        results.push((probe, outcome, detail));
+    }
+
+    // Give the stand-in time to report what reached it
+    if stand_in.is_some() {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+
+    results
+        .into_iter()
+        .map(|(probe, outcome, detail)| {
+            let observed = match (stand_in, probe.kind) {
+                // ICMP echo has no payload the stand-in sees; the reply tells
+                (Some(_), ProbeKind::Icmp) | (None, _) => None,
+                (Some(log), _) => Some(log.saw(&probe.token)),
+            };
+            let (redirected, rejected) = match probe.address {
+                Some(address) => (
+                    redirected(rules, probe.kind, address.ip()),
+                    rejected(rules, probe.kind, address.ip()),
+                ),
+                None => (false, false),
+            };
+
+            ProbeResult {
+                kind: probe.kind,
+                target: probe.target(),
+                verdict: ProbeVerdict::classify(outcome, observed, redirected, rejected),
+                detail,
+            }
+        })
+        .collect()
+}
+
+/// A STUN binding request carrying `software` (the probe token)
+pub fn stun_request(transaction: &[u8; 12], software: &str) -> Vec<u8> {
+    let mut attribute = STUN_SOFTWARE.to_be_bytes().to_vec();
+    attribute.extend_from_slice(&(software.len() as u16).to_be_bytes());
+    attribute.extend_from_slice(software.as_bytes());
+    attribute.resize(attribute.len().div_ceil(4) * 4, 0);
+
+    let mut packet = STUN_BINDING_REQUEST.to_be_bytes().to_vec();
+    packet.extend_from_slice(&(attribute.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+    packet.extend_from_slice(transaction);
+    packet.extend_from_slice(&attribute);
+    packet
+}
+
+/// A binding success response to `request` telling the client it is `peer`
+pub fn stun_response(request: &[u8], peer: SocketAddr) -> Option<Vec<u8>> {
+    let header = request.get(..20)?;
+    if header[..2] != STUN_BINDING_REQUEST.to_be_bytes() || header[4..8] != STUN_MAGIC_COOKIE.to_be_bytes() {
+        return None;
+    }
+
+    let cookie = STUN_MAGIC_COOKIE.to_be_bytes();
+    let mut value = vec![0];
+    let xored_port = peer.port() ^ (STUN_MAGIC_COOKIE >> 16) as u16;
+    match peer.ip() {
+        IpAddr::V4(address) => {
+            value.push(0x01);
+            value.extend_from_slice(&xored_port.to_be_bytes());
+            value.extend(address.octets().iter().zip(cookie.iter()).map(|(a, b)| a ^ b));
+        }
+        IpAddr::V6(address) => {
+            value.push(0x02);
+            value.extend_from_slice(&xored_port.to_be_bytes());
+            let key = cookie.iter().chain(&header[8..20]);
+            value.extend(address.octets().iter().zip(key).map(|(a, b)| a ^ b));
+        }
+    }
+
+    let mut packet = STUN_BINDING_SUCCESS.to_be_bytes().to_vec();
+    packet.extend_from_slice(&((value.len() + 4) as u16).to_be_bytes());
+    packet.extend_from_slice(&header[4..20]);
+    packet.extend_from_slice(&STUN_XOR_MAPPED_ADDRESS.to_be_bytes());
+    packet.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&value);
+    Some(packet)
+}
+
+/// The address a STUN binding response says the client has
+pub fn stun_mapped_address(response: &[u8]) -> Option<SocketAddr> {
+    if response.get(..2)? != STUN_BINDING_SUCCESS.to_be_bytes() {
+        return None;
+    }
+    let cookie = STUN_MAGIC_COOKIE.to_be_bytes();
+    let transaction = response.get(8..20)?;
+    let length = u16::from_be_bytes([response[2], response[3]]) as usize;
+    let attributes = response.get(20..20 + length)?;
+
+    let mut offset = 0;
+    while offset + 4 <= attributes.len() {
+        let kind = u16::from_be_bytes([attributes[offset], attributes[offset + 1]]);
+        let size = u16::from_be_bytes([attributes[offset + 2], attributes[offset + 3]]) as usize;
+        let value = attributes.get(offset + 4..offset + 4 + size)?;
+        offset += 4 + size.div_ceil(4) * 4;
+
+        let xored = match kind {
+            STUN_XOR_MAPPED_ADDRESS => true,
+            STUN_MAPPED_ADDRESS => false,
+            _ => continue,
+        };
+        let mut port = u16::from_be_bytes([*value.get(2)?, *value.get(3)?]);
+        let mut key: Vec<u8> = cookie.iter().chain(transaction).copied().collect();
+        if xored {
+            port ^= (STUN_MAGIC_COOKIE >> 16) as u16;
+        } else {
+            key.fill(0);
+        }
+        let address = match value.get(1)? {
+            0x01 => {
+                let octets: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+                IpAddr::from(std::array::from_fn::<u8, 4, _>(|i| octets[i] ^ key[i]))
+            }
+            0x02 => {
+                let octets: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+                IpAddr::from(std::array::from_fn::<u8, 16, _>(|i| octets[i] ^ key[i]))
+            }
+            _ => return None,
+        };
+        return Some(SocketAddr::new(address, port));
+    }
+
+    None
+}
+
+/// The probe token in `payload`, if any
+pub fn find_token(payload: &[u8]) -> Option<String> {
+    let text = String::from_utf8_lossy(payload);
+    let start = text.find(TOKEN_PREFIX)?;
+    let token: String = text[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    Some(token)
+}
+
+/// What the stand-in answers to a datagram on `port`
+///
+/// DNS gets an empty answer, STUN the sender's address, and anything else
+/// an echo.
+pub fn stand_in_reply(port: u16, payload: &[u8], peer: SocketAddr) -> Option<Vec<u8>> {
+    match port {
+        DNS_PROBE_PORT => Some(Query::parse(payload).ok()?.respond(RCODE_NOERROR, &[])),
+        STUN_PROBE_PORT => stun_response(payload, peer),
+        _ => Some(payload.to_vec()),
+    }
+}
+
+/// Serve the stand-in internet on every probe port until killed
+///
+/// Prints `<protocol> <port> <token>` for each probe received, and `ready`
+/// once all ports are bound.
+pub async fn serve_stand_in() -> TorrerResult<()> {
+    for port in [TCP_PROBE_PORT, DOT_PROBE_PORT] {
+        let listener = match TcpListener::bind((Ipv6Addr::UNSPECIFIED, port)).await {
+            Ok(listener) => listener,
+            Err(_) => TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).await?,
+        };
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buffer = [0u8; 512];
+                    let length = timeout(PROBE_TIMEOUT, stream.read(&mut buffer)).await;
+                    let token = match length {
+                        Ok(Ok(length)) => find_token(&buffer[..length]),
+                        _ => None,
+                    };
+                    println!("tcp {} {}", port, token.as_deref().unwrap_or("-"));
+                });
+            }
+        });
+    }
+
+    for port in [UDP_PROBE_PORT, DNS_PROBE_PORT, STUN_PROBE_PORT] {
+        let socket = match UdpSocket::bind((Ipv6Addr::UNSPECIFIED, port)).await {
+            Ok(socket) => socket,
+            Err(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await?,
+        };
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((length, peer)) = socket.recv_from(&mut buffer).await {
+                // Sockets bound to [::] see IPv4 peers as mapped addresses
+                let peer = SocketAddr::new(peer.ip().to_canonical(), peer.port());
+                let payload = &buffer[..length];
+                let token = find_token(payload);
+                println!("udp {} {}", port, token.as_deref().unwrap_or("-"));
+                if let Some(reply) = stand_in_reply(port, payload, peer) {
+                    let _ = socket.send_to(&reply, peer).await;
+                }
+            }
+        });
+    }
+
+    println!("ready");
+    std::future::pending::<()>().await;
+    Ok(())
+}
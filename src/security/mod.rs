@@ -1,11 +1,20 @@
 pub mod dns;
+pub mod dns_takeover;
+pub mod dns_server;
 pub mod ipv6;
 pub mod mac;
 pub mod leak_detection;
+pub mod leak_probe;
 pub mod firewall;
 
 pub use dns::DnsManager;
+pub use dns_takeover::{DnsPaths, DnsTakeover, ResolvConfMode};
+pub use dns_server::{DnsCache, DnsResolver, DnsResolverHandle, DnsStats, DNS_RESOLVER_PORT};
 pub use ipv6::Ipv6Manager;
 pub use mac::MacManager;
 pub use leak_detection::{LeakDetector, LeakTestResult};
+pub use leak_probe::{
+    LeakProbe, LeakReport, LeakTarget, ProbeKind, ProbeOutcome, ProbeResult, ProbeVerdict, RulesetInspection,
+    StandInLog,
+};
 pub use firewall::{FirewallManager, FirewallType};
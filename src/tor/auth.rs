@@ -0,0 +1,200 @@
+// Tor control port authentication (PROTOCOLINFO negotiation and SAFECOOKIE)
+
+use std::path::PathBuf;
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::tor::protocol::{self, Response};
+use crate::utils::Crypto;
+
+/// Length of the Tor authentication cookie in bytes
+pub const COOKIE_LEN: usize = 32;
+
+/// Length of the SAFECOOKIE client nonce in bytes
+pub const NONCE_LEN: usize = 32;
+
+const SERVER_TO_CONTROLLER_KEY: &[u8] = b"Tor safe cookie authentication server-to-controller hash";
+const CONTROLLER_TO_SERVER_KEY: &[u8] = b"Tor safe cookie authentication controller-to-server hash";
+
+/// Authentication methods advertised by Tor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMethod {
+    Null,
+    HashedPassword,
+    Cookie,
+    SafeCookie,
+}
+
+impl AuthMethod {
+    fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "NULL" => Some(Self::Null),
+            "HASHEDPASSWORD" => Some(Self::HashedPassword),
+            "COOKIE" => Some(Self::Cookie),
+            "SAFECOOKIE" => Some(Self::SafeCookie),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed `PROTOCOLINFO` reply
+#[derive(Debug, Clone, Default)]
+pub struct ProtocolInfo {
+    pub methods: Vec<AuthMethod>,
+    pub cookie_file: Option<PathBuf>,
+    pub tor_version: Option<String>,
+}
+
+impl ProtocolInfo {
+    /// Parse the reply to `PROTOCOLINFO 1`
+    pub fn parse(response: &Response) -> TorrerResult<Self> {
+        let mut info = ProtocolInfo::default();
+        let mut saw_auth = false;
+
+        for line in &response.lines {
+            if let Some(rest) = line.text.strip_prefix("AUTH ") {
+                saw_auth = true;
+                for (key, value) in keyword_arguments(rest)? {
+                    match key.as_str() {
+                        "METHODS" => {
+                            info.methods = value
+                                .split(',')
+                                .filter_map(AuthMethod::from_token)
+                                .collect();
+                        }
+                        "COOKIEFILE" => info.cookie_file = Some(PathBuf::from(value)),
+                        _ => {}
+                    }
+                }
+            } else if let Some(rest) = line.text.strip_prefix("VERSION ") {
+                for (key, value) in keyword_arguments(rest)? {
+                    if key == "Tor" {
+                        info.tor_version = Some(value);
+                    }
+                }
+            }
+        }
+
+        if !saw_auth {
+            return Err(TorrerError::Tor(
+                "PROTOCOLINFO reply did not list authentication methods".to_string(),
+            ));
+        }
+
+        Ok(info)
+    }
+
+    /// Whether Tor accepts the given method
+    pub fn supports(&self, method: AuthMethod) -> bool {
+        self.methods.contains(&method)
+    }
+}
+
+/// Server side of a SAFECOOKIE challenge (`AUTHCHALLENGE` reply)
+#[derive(Debug, Clone)]
+pub struct AuthChallenge {
+    pub server_hash: Vec<u8>,
+    pub server_nonce: Vec<u8>,
+}
+
+impl AuthChallenge {
+    /// Parse the reply to `AUTHCHALLENGE SAFECOOKIE <nonce>`
+    pub fn parse(response: &Response) -> TorrerResult<Self> {
+        let rest = response
+            .message()
+            .strip_prefix("AUTHCHALLENGE ")
+            .ok_or_else(|| TorrerError::Tor("Unexpected AUTHCHALLENGE reply".to_string()))?;
+
+        let mut server_hash = None;
+        let mut server_nonce = None;
+        for (key, value) in keyword_arguments(rest)? {
+            let bytes = hex::decode(&value).map_err(|e| {
+                TorrerError::Tor(format!("Invalid {} in AUTHCHALLENGE reply: {}", key, e))
+            })?;
+            match key.as_str() {
+                "SERVERHASH" => server_hash = Some(bytes),
+                "SERVERNONCE" => server_nonce = Some(bytes),
+                _ => {}
+            }
+        }
+
+        match (server_hash, server_nonce) {
+            (Some(server_hash), Some(server_nonce)) => Ok(Self { server_hash, server_nonce }),
+            _ => Err(TorrerError::Tor(
+                "AUTHCHALLENGE reply is missing SERVERHASH or SERVERNONCE".to_string(),
+            )),
+        }
+    }
+}
+
+/// Read and check the authentication cookie
+pub fn read_cookie(path: &std::path::Path) -> TorrerResult<Vec<u8>> {
+    let cookie = std::fs::read(path).map_err(|e| {
+        TorrerError::Tor(format!("Failed to read auth cookie {}: {}", path.display(), e))
+    })?;
+
+    if cookie.len() != COOKIE_LEN {
+        return Err(TorrerError::Tor(format!(
+            "Auth cookie {} has {} bytes, expected {}",
+            path.display(),
+            cookie.len(),
+            COOKIE_LEN
+        )));
+    }
+
+    Ok(cookie)
+}
+
+/// Verify Tor's SAFECOOKIE server hash and compute the client hash to send
+pub fn safecookie_client_hash(
+    cookie: &[u8],
+    client_nonce: &[u8],
+    challenge: &AuthChallenge,
+) -> TorrerResult<Vec<u8>> {
+    let mut message = Vec::with_capacity(cookie.len() + client_nonce.len() + challenge.server_nonce.len());
+    message.extend_from_slice(cookie);
+    message.extend_from_slice(client_nonce);
+    message.extend_from_slice(&challenge.server_nonce);
+
+    let expected = Crypto::hmac_sha256(SERVER_TO_CONTROLLER_KEY, &message);
+    if !constant_time_eq(&expected, &challenge.server_hash) {
+        return Err(TorrerError::Tor(
+            "Tor's SAFECOOKIE server hash did not match; refusing to authenticate".to_string(),
+        ));
+    }
+
+    Ok(Crypto::hmac_sha256(CONTROLLER_TO_SERVER_KEY, &message))
+}
+
+/// Quote a password as a control protocol string
+pub fn quote_password(password: &str) -> String {
+    let mut quoted = String::with_capacity(password.len() + 2);
+    quoted.push('"');
+    for c in password.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\r' => quoted.push_str("\\r"),
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Keyword (`KEY=value`) arguments of a reply line
+fn keyword_arguments(input: &str) -> TorrerResult<Vec<(String, String)>> {
+    let arguments = protocol::parse_arguments(input)
+        .map_err(|e| TorrerError::Parse(format!("{}: {}", e, input)))?;
+
+    Ok(arguments
+        .into_iter()
+        .filter_map(|(key, value)| value.map(|v| (key, v)))
+        .collect())
+}
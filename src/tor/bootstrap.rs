@@ -0,0 +1,176 @@
+// Tor bootstrap progress (status/bootstrap-phase and BOOTSTRAP events)
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Serialize, Deserialize};
+
+use crate::error::{TorrerError, TorrerResult};
+use crate::tor::commands::build_getinfo;
+use crate::tor::events::{self, StatusEvent};
+use crate::tor::protocol;
+use crate::tor::{TorClient, TorEvent};
+
+/// Bootstrap warnings kept by `BootstrapTracker`
+const MAX_WARNINGS: usize = 10;
+
+/// One bootstrap phase as reported by Tor
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootstrapPhase {
+    /// NOTICE while progressing, WARN when a step failed
+    pub severity: String,
+    /// Percentage complete (0-100)
+    pub progress: u8,
+    /// Machine-readable phase name (e.g. "conn_done", "done")
+    pub tag: String,
+    /// Human-readable description
+    pub summary: String,
+    /// Why bootstrapping is stuck, for WARN phases
+    pub warning: Option<String>,
+    /// Reason keyword for WARN phases (e.g. "TIMEOUT", "NOROUTE")
+    pub reason: Option<String>,
+    /// Relay or bridge Tor failed to reach
+    pub host: Option<String>,
+}
+
+impl BootstrapPhase {
+    /// Parse `NOTICE BOOTSTRAP PROGRESS=50 TAG=... SUMMARY="..."`
+    pub fn parse(status: &str) -> Option<Self> {
+        let arguments = protocol::parse_arguments(status).ok()?;
+        let (positional, keywords) = events::split_arguments(arguments);
+
+        match positional.as_slice() {
+            [severity, action, ..] if action == "BOOTSTRAP" => Self::from_keywords(severity, &keywords),
+            _ => None,
+        }
+    }
+
+    /// Build from a `STATUS_CLIENT ... BOOTSTRAP` event
+    pub fn from_status_event(event: &StatusEvent) -> Option<Self> {
+        if event.action != "BOOTSTRAP" {
+            return None;
+        }
+        Self::from_keywords(&event.severity, &event.arguments)
+    }
+
+    fn from_keywords(severity: &str, keywords: &HashMap<String, String>) -> Option<Self> {
+        let progress = keywords.get("PROGRESS")?.parse::<u8>().ok()?.min(100);
+
+        Some(Self {
+            severity: severity.to_string(),
+            progress,
+            tag: keywords.get("TAG").cloned().unwrap_or_default(),
+            summary: keywords.get("SUMMARY").cloned().unwrap_or_default(),
+            warning: keywords.get("WARNING").cloned(),
+            reason: keywords.get("REASON").cloned(),
+            host: keywords.get("HOSTADDR").cloned(),
+        })
+    }
+
+    /// Whether Tor has finished bootstrapping
+    pub fn is_done(&self) -> bool {
+        self.progress >= 100
+    }
+
+    /// Whether Tor reported a problem at this phase
+    pub fn is_warning(&self) -> bool {
+        self.severity == "WARN" || self.severity == "ERR"
+    }
+
+    /// Query the current phase with `GETINFO status/bootstrap-phase`
+    pub async fn fetch(client: &mut TorClient) -> TorrerResult<Self> {
+        let response = client.send_command(&build_getinfo("status/bootstrap-phase")).await?;
+        let status = response.get("status/bootstrap-phase").ok_or_else(|| {
+            TorrerError::Tor("Tor reply did not include status/bootstrap-phase".to_string())
+        })?;
+
+        Self::parse(&status).ok_or_else(|| {
+            TorrerError::Parse(format!("Invalid bootstrap phase: {}", status))
+        })
+    }
+}
+
+/// Latest bootstrap phase and the warnings seen on the way
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BootstrapStatus {
+    pub phase: BootstrapPhase,
+    /// Most recent warnings first
+    pub warnings: Vec<BootstrapPhase>,
+}
+
+impl BootstrapStatus {
+    /// The warning explaining where bootstrap stalls, if it has not finished
+    pub fn stalled_at(&self) -> Option<&BootstrapPhase> {
+        if self.phase.is_done() {
+            None
+        } else {
+            self.warnings.first()
+        }
+    }
+}
+
+/// Follows bootstrap progress from events and `GETINFO` queries
+///
+/// Clones share state, so one clone can be fed from an event task while
+/// another is read for status reports.
+#[derive(Debug, Clone, Default)]
+pub struct BootstrapTracker {
+    status: Arc<Mutex<Option<BootstrapStatus>>>,
+}
+
+impl BootstrapTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a phase reported by Tor
+    pub fn update(&self, phase: BootstrapPhase) {
+        let mut status = self.status.lock().unwrap();
+        let status = status.get_or_insert_with(BootstrapStatus::default);
+
+        if phase.is_warning() {
+            // Polling status/bootstrap-phase repeats the same warning
+            if status.warnings.first() != Some(&phase) {
+                status.warnings.insert(0, phase.clone());
+                status.warnings.truncate(MAX_WARNINGS);
+            }
+            // WARN events repeat the phase Tor is stuck at; keep its progress
+            if phase.progress < status.phase.progress {
+                return;
+            }
+        }
+        status.phase = phase;
+    }
+
+    /// Feed a Tor event; returns whether it was a bootstrap update
+    pub fn handle_event(&self, event: &TorEvent) -> bool {
+        match event {
+            TorEvent::StatusClient(status) => match BootstrapPhase::from_status_event(status) {
+                Some(phase) => {
+                    self.update(phase);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Refresh from `GETINFO status/bootstrap-phase`
+    pub async fn refresh(&self, client: &mut TorClient) -> TorrerResult<BootstrapPhase> {
+        let phase = BootstrapPhase::fetch(client).await?;
+        self.update(phase.clone());
+        Ok(phase)
+    }
+
+    /// Current bootstrap status, if any phase has been seen
+    pub fn status(&self) -> Option<BootstrapStatus> {
+        self.status.lock().unwrap().clone()
+    }
+
+    /// Forget everything (e.g. when routing stops)
+    pub fn reset(&self) {
+        *self.status.lock().unwrap() = None;
+    }
+}
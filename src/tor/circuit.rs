@@ -1,5 +1,17 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
 use crate::error::{TorrerError, TorrerResult};
 use crate::tor::TorClient;
+use crate::tor::commands::{
+    build_attachstream, build_closecircuit, build_closestream, build_extendcircuit,
+    build_getinfo, build_signal_newym,
+};
+use crate::tor::events::{self, CircEvent};
+use crate::tor::protocol;
+
+/// `CLOSESTREAM` reason sent by Torrer (REASON_MISC)
+const CLOSE_STREAM_REASON: u8 = 1;
 
 /// Circuit information and management
 pub struct CircuitManager;
@@ -8,116 +20,321 @@ impl CircuitManager {
     /// Get circuit information
     pub async fn get_circuits(client: &mut TorClient) -> TorrerResult<Vec<CircuitInfo>> {
         // Query circuit status
-        let command = "GETINFO circuit-status\r\n";
-        let response = client.send_command(command).await?;
+        let command = build_getinfo("circuit-status");
+        let response = client.send_command(&command).await?;
 
         // Parse circuit information
-        let circuits = Self::parse_circuit_status(&response)?;
+        let status = response.get("circuit-status").ok_or_else(|| {
+            TorrerError::Tor("Tor reply did not include circuit-status".to_string())
+        })?;
 
-        Ok(circuits)
+        Ok(Self::parse_circuit_status(&status))
     }
 
     /// Request new circuit (NEWNYM)
     pub async fn new_circuit(client: &mut TorClient) -> TorrerResult<()> {
         log::info!("Requesting new Tor circuit...");
-        
-        let command = "SIGNAL NEWNYM\r\n";
-        client.send_command(command).await?;
+
+        client.send_command(&build_signal_newym()).await?;
 
         log::info!("New circuit requested");
         Ok(())
     }
 
-    /// Parse circuit status from Tor response
-    fn parse_circuit_status(response: &str) -> TorrerResult<Vec<CircuitInfo>> {
-        let mut circuits = Vec::new();
-
-        // Parse circuit status response
-        // Format: "250+circuit-status=\n<circuit lines>\n250 OK"
-        // Each circuit line: "ID STATUS PURPOSE FLAGS BUILD_FLAGS TIME_CREATED"
-        
-        let mut in_circuit_section = false;
-        
-        for line in response.lines() {
-            // Check if we're entering the circuit-status section
-            if line.contains("circuit-status=") {
-                in_circuit_section = true;
-                continue;
-            }
-            
-            // Check if we've left the circuit-status section
-            if in_circuit_section && line.starts_with("250") && !line.contains("circuit-status") {
-                break;
-            }
-            
-            // Parse circuit lines
-            if in_circuit_section && !line.trim().is_empty() && !line.starts_with("250") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 2 {
-                    let id = parts[0].to_string();
-                    let status = parts[1].to_string();
-                    
-                    // Extract purpose (usually 3rd field)
-                    let purpose = if parts.len() > 2 {
-                        Some(parts[2].to_string())
-                    } else {
-                        None
-                    };
-                    
-                    // Extract flags (4th field onwards, space-separated)
-                    let flags = if parts.len() > 3 {
-                        Some(parts[3..].join(" "))
-                    } else {
-                        None
-                    };
-                    
-                    circuits.push(CircuitInfo {
-                        id,
-                        status,
-                        purpose,
-                        flags,
-                    });
-                }
-            }
+    /// Close a single circuit, leaving other circuits and the identity alone
+    pub async fn close_circuit(client: &mut TorClient, circuit_id: &str) -> TorrerResult<()> {
+        check_id("circuit", circuit_id)?;
+        log::info!("Closing Tor circuit {}", circuit_id);
+
+        client.send_command(&build_closecircuit(circuit_id)).await?;
+        Ok(())
+    }
+
+    /// Build a new circuit through the given relays (fingerprints or nicknames)
+    ///
+    /// An empty path lets Tor choose the relays. Returns the new circuit ID.
+    pub async fn extend_circuit(client: &mut TorClient, path: &[String]) -> TorrerResult<String> {
+        for relay in path {
+            check_relay(relay)?;
+        }
+
+        let response = client.send_command(&build_extendcircuit("0", path)).await?;
+
+        // Reply: "250 EXTENDED <CircuitID>"
+        let circuit_id = response
+            .message()
+            .strip_prefix("EXTENDED ")
+            .map(|id| id.trim().to_string())
+            .ok_or_else(|| {
+                TorrerError::Tor(format!("Unexpected EXTENDCIRCUIT reply: {}", response.message()))
+            })?;
+
+        log::info!("Extending new Tor circuit {}", circuit_id);
+        Ok(circuit_id)
+    }
+
+    /// List application streams
+    pub async fn list_streams(client: &mut TorClient) -> TorrerResult<Vec<StreamInfo>> {
+        let response = client.send_command(&build_getinfo("stream-status")).await?;
+
+        let status = response.get("stream-status").ok_or_else(|| {
+            TorrerError::Tor("Tor reply did not include stream-status".to_string())
+        })?;
+
+        Ok(status.lines().filter_map(StreamInfo::parse).collect())
+    }
+
+    /// Close a single stream
+    pub async fn close_stream(client: &mut TorClient, stream_id: &str) -> TorrerResult<()> {
+        check_id("stream", stream_id)?;
+        log::info!("Closing Tor stream {}", stream_id);
+
+        client
+            .send_command(&build_closestream(stream_id, CLOSE_STREAM_REASON))
+            .await?;
+        Ok(())
+    }
+
+    /// Attach a stream to a circuit
+    ///
+    /// Tor only accepts this for streams it has not attached yet, i.e. with
+    /// `__LeaveStreamsUnattached 1` set, or for detached streams.
+    pub async fn attach_stream(
+        client: &mut TorClient,
+        stream_id: &str,
+        circuit_id: &str,
+    ) -> TorrerResult<()> {
+        check_id("stream", stream_id)?;
+        check_id("circuit", circuit_id)?;
+        log::info!("Attaching Tor stream {} to circuit {}", stream_id, circuit_id);
+
+        client
+            .send_command(&build_attachstream(stream_id, circuit_id))
+            .await?;
+        Ok(())
+    }
+
+    /// Parse the value of `GETINFO circuit-status`
+    fn parse_circuit_status(status: &str) -> Vec<CircuitInfo> {
+        status.lines().filter_map(CircuitInfo::parse).collect()
+    }
+}
+
+/// Circuit and stream IDs are 1-16 alphanumeric characters
+fn check_id(kind: &str, id: &str) -> TorrerResult<()> {
+    if id.is_empty() || id.len() > 16 || !id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(TorrerError::Tor(format!("Invalid {} ID: {:?}", kind, id)));
+    }
+    Ok(())
+}
+
+/// Relays are given as `$FINGERPRINT`, a bare fingerprint or a nickname
+fn check_relay(relay: &str) -> TorrerResult<()> {
+    let name = relay.strip_prefix('$').unwrap_or(relay);
+    let valid = !name.is_empty()
+        && name.len() <= 40
+        && name.chars().all(|c| c.is_ascii_alphanumeric());
+
+    if !valid {
+        return Err(TorrerError::Tor(format!("Invalid relay: {:?}", relay)));
+    }
+    Ok(())
+}
+
+/// Position of a relay within a circuit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HopRole {
+    Guard,
+    Middle,
+    Exit,
+}
+
+impl HopRole {
+    /// Human-readable role name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HopRole::Guard => "Guard",
+            HopRole::Middle => "Middle",
+            HopRole::Exit => "Exit",
         }
+    }
+}
 
-        // If no circuits found with detailed parsing, try simple parsing
-        if circuits.is_empty() {
-            for line in response.lines() {
-                if line.contains("circuit-status=") || line.contains("circuit ") {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 2 {
-                        let id = parts[0].split('=').last()
-                            .or_else(|| parts[0].split(' ').last())
-                            .unwrap_or("0")
-                            .to_string();
-                        let status = parts.get(1).copied().unwrap_or("UNKNOWN").to_string();
-                        
-                        circuits.push(CircuitInfo {
-                            id,
-                            status,
-                            purpose: parts.get(2).map(|s| s.to_string()),
-                            flags: if parts.len() > 3 {
-                                Some(parts[3..].join(" "))
-                            } else {
-                                None
-                            },
-                        });
-                    }
-                }
-            }
+/// One relay in a circuit path (`$FINGERPRINT~nickname`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitHop {
+    pub fingerprint: String,
+    pub nickname: Option<String>,
+}
+
+impl CircuitHop {
+    /// Parse a long name such as `$FP~nick`, `$FP=nick` or `$FP`
+    pub fn parse(long_name: &str) -> Option<Self> {
+        let name = long_name.strip_prefix('$')?;
+        let (fingerprint, nickname) = match name.find(['~', '=']) {
+            Some(pos) => (&name[..pos], Some(name[pos + 1..].to_string())),
+            None => (name, None),
+        };
+
+        if fingerprint.len() != 40 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
         }
 
-        Ok(circuits)
+        Some(Self {
+            fingerprint: fingerprint.to_uppercase(),
+            nickname: nickname.filter(|n| !n.is_empty()),
+        })
+    }
+}
+
+impl fmt::Display for CircuitHop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.nickname {
+            Some(ref nickname) => write!(f, "{} (${})", nickname, self.fingerprint),
+            None => write!(f, "${}", self.fingerprint),
+        }
     }
 }
 
 /// Circuit information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CircuitInfo {
     pub id: String,
     pub status: String,
+    /// Relays in order, guard first
+    pub path: Vec<CircuitHop>,
+    pub build_flags: Vec<String>,
     pub purpose: Option<String>,
-    pub flags: Option<String>,
+    pub hs_state: Option<String>,
+    pub rend_query: Option<String>,
+    pub time_created: Option<String>,
+    pub reason: Option<String>,
+    pub remote_reason: Option<String>,
+    /// Stream isolation: SOCKS credentials the circuit is bound to
+    pub socks_username: Option<String>,
+    pub socks_password: Option<String>,
+}
+
+impl CircuitInfo {
+    /// Parse one `circuit-status` line or `CIRC` event body
+    ///
+    /// Format: `ID STATUS [PATH] [BUILD_FLAGS=..] [PURPOSE=..] [HS_STATE=..]
+    /// [REND_QUERY=..] [TIME_CREATED=..] [REASON=..] [REMOTE_REASON=..]
+    /// [SOCKS_USERNAME=..] [SOCKS_PASSWORD=..]`
+    pub fn parse(line: &str) -> Option<Self> {
+        let arguments = protocol::parse_arguments(line).ok()?;
+        let (positional, keywords) = events::split_arguments(arguments);
+        Self::from_parts(&positional, &keywords)
+    }
+
+    /// Build from a `CIRC` event
+    pub fn from_event(event: &CircEvent) -> Self {
+        let mut positional = vec![event.id.clone(), event.status.clone()];
+        if !event.path.is_empty() {
+            positional.push(event.path.join(","));
+        }
+        Self::from_parts(&positional, &event.keywords).unwrap_or_default()
+    }
+
+    fn from_parts(positional: &[String], keywords: &HashMap<String, String>) -> Option<Self> {
+        let id = positional.first()?.clone();
+        let status = positional.get(1)?.clone();
+        let path = positional
+            .get(2)
+            .map(|p| p.split(',').filter_map(CircuitHop::parse).collect())
+            .unwrap_or_default();
+
+        let keyword = |key: &str| keywords.get(key).cloned();
+
+        Some(Self {
+            id,
+            status,
+            path,
+            build_flags: keywords
+                .get("BUILD_FLAGS")
+                .map(|f| f.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            purpose: keyword("PURPOSE"),
+            hs_state: keyword("HS_STATE"),
+            rend_query: keyword("REND_QUERY"),
+            time_created: keyword("TIME_CREATED"),
+            reason: keyword("REASON"),
+            remote_reason: keyword("REMOTE_REASON"),
+            socks_username: keyword("SOCKS_USERNAME"),
+            socks_password: keyword("SOCKS_PASSWORD"),
+        })
+    }
+
+    /// Whether the circuit is fully built
+    pub fn is_built(&self) -> bool {
+        self.status == "BUILT"
+    }
+
+    /// Whether this is a general-purpose (exit) circuit
+    pub fn is_general(&self) -> bool {
+        self.purpose.as_deref().is_none_or(|p| p == "GENERAL")
+    }
+
+    /// Whether the circuit was built with the given BUILD_FLAGS entry
+    pub fn has_build_flag(&self, flag: &str) -> bool {
+        self.build_flags.iter().any(|f| f == flag)
+    }
+
+    /// First hop (entry guard)
+    pub fn guard(&self) -> Option<&CircuitHop> {
+        self.path.first()
+    }
+
+    /// Last hop; only an exit for general-purpose circuits
+    pub fn exit(&self) -> Option<&CircuitHop> {
+        if self.is_general() && !self.has_build_flag("IS_INTERNAL") && self.path.len() > 1 {
+            self.path.last()
+        } else {
+            None
+        }
+    }
+
+    /// Hops paired with their role in the circuit
+    pub fn hops(&self) -> impl Iterator<Item = (HopRole, &CircuitHop)> {
+        let last = self.path.len().saturating_sub(1);
+        let has_exit = self.exit().is_some();
+        self.path.iter().enumerate().map(move |(i, hop)| {
+            let role = if i == 0 {
+                HopRole::Guard
+            } else if i == last && has_exit {
+                HopRole::Exit
+            } else {
+                HopRole::Middle
+            };
+            (role, hop)
+        })
+    }
+}
+
+/// Application stream (`GETINFO stream-status` entry)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StreamInfo {
+    pub id: String,
+    pub status: String,
+    /// Circuit the stream is attached to ("0" when unattached)
+    pub circuit_id: String,
+    pub target: String,
 }
 
+impl StreamInfo {
+    /// Parse one `stream-status` line: `ID STATUS CIRCUIT_ID TARGET`
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        Some(Self {
+            id: parts.next()?.to_string(),
+            status: parts.next()?.to_string(),
+            circuit_id: parts.next()?.to_string(),
+            target: parts.next()?.to_string(),
+        })
+    }
+
+    /// Whether the stream is attached to a circuit
+    pub fn is_attached(&self) -> bool {
+        self.circuit_id != "0"
+    }
+}
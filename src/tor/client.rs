@@ -1,57 +1,123 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::fmt;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UnixStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
 
+use crate::config::{ConfigManager, Configuration};
 use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::RoutingPorts;
+use crate::tor::auth::{self, AuthChallenge, AuthMethod, ProtocolInfo};
+use crate::tor::commands;
+use crate::tor::endpoint::ControlEndpoint;
+use crate::tor::events::{EventKind, EventStream};
+use crate::tor::protocol::{self, Response};
+use crate::utils::Crypto;
 
 const DEFAULT_CONTROL_PORT: u16 = 9051;
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 const AUTH_COOKIE_PATH: &str = "/var/run/tor/control.authcookie";
+const EVENT_BUFFER_SIZE: usize = 256;
 
 /// Tor control port client
+///
+/// Replies are read by a background task so that asynchronous `650`
+/// events never get mixed into command replies; events are delivered on
+/// a separate channel (see [`TorClient::take_events`]).
 pub struct TorClient {
-    stream: Option<TcpStream>,
-    control_port: u16,
+    writer: Option<Box<dyn AsyncWrite + Send + Unpin>>,
+    replies: Option<mpsc::UnboundedReceiver<Response>>,
+    events: Option<mpsc::Receiver<Response>>,
+    reader_task: Option<JoinHandle<()>>,
+    endpoint: ControlEndpoint,
+    password: Option<String>,
 }
 
 impl TorClient {
     /// Create a new TorClient instance
     pub fn new() -> Self {
-        Self {
-            stream: None,
-            control_port: DEFAULT_CONTROL_PORT,
-        }
+        Self::with_port(DEFAULT_CONTROL_PORT)
     }
 
     /// Create a TorClient with custom control port
     pub fn with_port(port: u16) -> Self {
+        Self::with_endpoint(ControlEndpoint::localhost(port))
+    }
+
+    /// Create a TorClient for a TCP or Unix socket control endpoint
+    pub fn with_endpoint(endpoint: ControlEndpoint) -> Self {
         Self {
-            stream: None,
-            control_port: port,
+            writer: None,
+            replies: None,
+            events: None,
+            reader_task: None,
+            endpoint,
+            password: None,
         }
     }
 
+    /// Create a TorClient using the control settings from the configuration
+    pub fn from_config(config: &Configuration) -> Self {
+        let mut client = Self::with_endpoint(config.control_endpoint());
+        client.password = config.tor_control_password.clone();
+        client
+    }
+
+    /// Create a TorClient from the system configuration file
+    ///
+    /// Falls back to defaults when the configuration cannot be loaded.
+    pub fn from_system_config() -> Self {
+        let config = ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .unwrap_or_else(|e| {
+                log::debug!("Using default Tor control settings: {}", e);
+                Configuration::default()
+            });
+        Self::from_config(&config)
+    }
+
+    /// Set the password used for HashedControlPassword authentication
+    pub fn with_password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Control endpoint this client connects to
+    pub fn endpoint(&self) -> &ControlEndpoint {
+        &self.endpoint
+    }
+
     /// Connect to Tor control port
     pub async fn connect(&mut self) -> TorrerResult<()> {
-        let addr = format!("127.0.0.1:{}", self.control_port);
-        
-        log::info!("Connecting to Tor control port at {}", addr);
-        
-        let stream_result = timeout(DEFAULT_TIMEOUT, TcpStream::connect(&addr)).await;
-        
-        match stream_result {
-            Ok(Ok(stream)) => {
-                self.stream = Some(stream);
+        log::info!("Connecting to Tor control port at {}", self.endpoint);
+
+        let connected = match self.endpoint.clone() {
+            ControlEndpoint::Tcp { host, port } => {
+                timeout(DEFAULT_TIMEOUT, TcpStream::connect((host.as_str(), port)))
+                    .await
+                    .map(|result| result.map(|stream| self.attach(stream.into_split())))
+            }
+            ControlEndpoint::Unix(path) => {
+                timeout(DEFAULT_TIMEOUT, UnixStream::connect(&path))
+                    .await
+                    .map(|result| result.map(|stream| self.attach(stream.into_split())))
+            }
+        };
+
+        match connected {
+            Ok(Ok(())) => {
                 log::info!("Connected to Tor control port");
                 Ok(())
             }
             Ok(Err(e)) => {
                 log::error!("Failed to connect to Tor: {}", e);
                 Err(TorrerError::Tor(format!(
-                    "Failed to connect to Tor control port: {}. Is Tor running?",
-                    e
+                    "Failed to connect to Tor control port {}: {}. Is Tor running?",
+                    self.endpoint, e
                 )))
             }
             Err(_) => {
@@ -63,43 +129,179 @@ impl TorClient {
         }
     }
 
-    /// Authenticate with Tor control port
-    pub async fn authenticate(&mut self) -> TorrerResult<()> {
-        if self.stream.is_none() {
-            return Err(TorrerError::Tor("Not connected to Tor".to_string()));
-        }
+    /// Take over a freshly connected stream and start the reply reader
+    fn attach<R, W>(&mut self, (read_half, write_half): (R, W))
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        self.disconnect();
 
-        log::info!("Authenticating with Tor control port");
+        let (reply_tx, reply_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::channel(EVENT_BUFFER_SIZE);
 
-        // Try cookie authentication first
-        if let Ok(cookie) = std::fs::read(AUTH_COOKIE_PATH) {
-            log::debug!("Using cookie authentication");
-            let hex_cookie = hex::encode(cookie);
-            let auth_cmd = format!("AUTHENTICATE {}\r\n", hex_cookie);
-            
-            match self.send_raw_command(&auth_cmd).await {
-                Ok(response) => {
-                    if response.contains("250") {
-                        log::info!("Authenticated with cookie");
-                        return Ok(());
-                    } else {
-                        log::warn!("Cookie authentication failed: {}", response);
+        self.reader_task = Some(tokio::spawn(Self::read_loop(read_half, reply_tx, event_tx)));
+        self.writer = Some(Box::new(write_half));
+        self.replies = Some(reply_rx);
+        self.events = Some(event_rx);
+    }
+
+    /// Read replies until the connection closes, routing async events aside
+    async fn read_loop<R>(
+        read_half: R,
+        reply_tx: mpsc::UnboundedSender<Response>,
+        event_tx: mpsc::Sender<Response>,
+    ) where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        let mut reader = BufReader::new(read_half);
+
+        loop {
+            match protocol::read_reply(&mut reader).await {
+                Ok(Some(reply)) if reply.is_async_event() => {
+                    if event_tx.try_send(reply).is_err() {
+                        log::debug!("Dropping Tor event: no listener or buffer full");
+                    }
+                }
+                Ok(Some(reply)) => {
+                    if reply_tx.send(reply).is_err() {
+                        break;
                     }
                 }
+                Ok(None) => {
+                    log::debug!("Tor control connection closed");
+                    break;
+                }
                 Err(e) => {
-                    log::warn!("Cookie authentication failed: {}", e);
+                    log::warn!("Failed to read Tor control reply: {}", e);
+                    break;
                 }
             }
         }
+    }
+
+    /// Whether the client currently holds a control connection
+    pub fn is_connected(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// Drop the control connection and stop the reader task
+    pub fn disconnect(&mut self) {
+        if let Some(task) = self.reader_task.take() {
+            task.abort();
+        }
+        self.writer = None;
+        self.replies = None;
+        self.events = None;
+    }
+
+    /// Take the receiver for asynchronous (`650`) event notifications
+    ///
+    /// Can only be taken once per connection; events are dropped while
+    /// nobody is listening and the buffer is full.
+    pub fn take_events(&mut self) -> Option<mpsc::Receiver<Response>> {
+        self.events.take()
+    }
+
+    /// Subscribe to asynchronous events with `SETEVENTS`
+    ///
+    /// Replaces any previous subscription on this connection. The returned
+    /// stream stays valid while the client is used for other commands.
+    pub async fn subscribe(&mut self, kinds: &[EventKind]) -> TorrerResult<EventStream> {
+        let names: Vec<&str> = kinds.iter().map(EventKind::as_str).collect();
+        self.send_raw_command(&commands::build_setevents(&names)).await?;
 
-        // Fallback: try null authentication (if configured)
-        log::debug!("Trying null authentication");
-        let auth_cmd = "AUTHENTICATE\r\n";
-        
-        if let Err(e) = self.send_raw_command(auth_cmd).await {
+        let receiver = self.take_events().ok_or_else(|| {
+            TorrerError::Tor("Tor events are already being consumed on this connection".to_string())
+        })?;
+
+        log::debug!("Subscribed to Tor events: {}", names.join(" "));
+        Ok(EventStream::new(receiver))
+    }
+
+    /// Make `TransPort` and `DNSPort` listen on `addresses`
+    ///
+    /// Replaces the current listeners; Tor opens and closes ports to match.
+    /// With `ipv6`, the listeners carry IPv6 traffic too.
+    pub async fn set_listeners(
+        &mut self,
+        addresses: &[IpAddr],
+        ports: RoutingPorts,
+        ipv6: bool,
+    ) -> TorrerResult<()> {
+        let values = commands::listener_values(addresses, ports.trans_port, ports.dns_port, ipv6);
+        self.send_raw_command(&commands::build_setconf_values(&values)).await?;
+        Ok(())
+    }
+
+    /// Make Tor answer `.onion` lookups with addresses from the virtual networks
+    pub async fn set_automap(&mut self, ipv4: &str, ipv6: &str) -> TorrerResult<()> {
+        let values = commands::automap_values(ipv4, ipv6);
+        self.send_raw_command(&commands::build_setconf_values(&values)).await?;
+        Ok(())
+    }
+
+    /// Put the automap options back to Tor's own configuration
+    pub async fn reset_automap(&mut self) -> TorrerResult<()> {
+        self.send_raw_command(&commands::build_resetconf(&commands::AUTOMAP_OPTIONS)).await?;
+        Ok(())
+    }
+
+    /// Authenticate with Tor control port
+    ///
+    /// Asks Tor which methods it accepts via `PROTOCOLINFO` and uses the
+    /// first usable one. Tor closes the connection after a failed
+    /// `AUTHENTICATE`, so only a single method is attempted.
+    pub async fn authenticate(&mut self) -> TorrerResult<()> {
+        if !self.is_connected() {
+            return Err(TorrerError::Tor("Not connected to Tor".to_string()));
+        }
+
+        log::info!("Authenticating with Tor control port");
+
+        let response = self.send_raw_command(&commands::build_protocolinfo()).await?;
+        let info = ProtocolInfo::parse(&response)?;
+        log::debug!("Tor accepts authentication methods: {:?}", info.methods);
+
+        let cookie_path = info
+            .cookie_file
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(AUTH_COOKIE_PATH));
+
+        let result = if info.supports(AuthMethod::Null) {
+            log::debug!("Using null authentication");
+            self.send_raw_command(&commands::build_authenticate(None)).await.map(|_| ())
+        } else if info.supports(AuthMethod::HashedPassword) && self.password.is_some() {
+            log::debug!("Using password authentication");
+            let password = auth::quote_password(self.password.as_deref().unwrap_or_default());
+            self.send_raw_command(&commands::build_authenticate(Some(&password)))
+                .await
+                .map(|_| ())
+        } else if info.supports(AuthMethod::SafeCookie) {
+            log::debug!("Using SAFECOOKIE authentication with {}", cookie_path.display());
+            self.authenticate_safecookie(&cookie_path).await
+        } else if info.supports(AuthMethod::Cookie) {
+            log::debug!("Using cookie authentication with {}", cookie_path.display());
+            let cookie = auth::read_cookie(&cookie_path)?;
+            self.send_raw_command(&commands::build_authenticate(Some(&hex::encode(cookie))))
+                .await
+                .map(|_| ())
+        } else if info.supports(AuthMethod::HashedPassword) {
+            Err(TorrerError::Tor(
+                "Tor requires a control password; set tor_control_password in the configuration"
+                    .to_string(),
+            ))
+        } else {
+            Err(TorrerError::Tor(
+                "Tor offered no supported authentication method".to_string(),
+            ))
+        };
+
+        if let Err(e) = result {
             log::error!("Authentication failed: {}", e);
             return Err(TorrerError::Tor(format!(
-                "Authentication failed. Please check Tor configuration."
+                "Authentication failed: {}. Please check Tor configuration.",
+                e
             )));
         }
 
@@ -107,67 +309,115 @@ impl TorClient {
         Ok(())
     }
 
+    /// Run the SAFECOOKIE challenge-response exchange
+    async fn authenticate_safecookie(&mut self, cookie_path: &Path) -> TorrerResult<()> {
+        let cookie = auth::read_cookie(cookie_path)?;
+        let client_nonce = Crypto::random_bytes(auth::NONCE_LEN);
+
+        let response = self
+            .send_raw_command(&commands::build_authchallenge(&hex::encode(&client_nonce)))
+            .await?;
+        let challenge = AuthChallenge::parse(&response)?;
+
+        let client_hash = auth::safecookie_client_hash(&cookie, &client_nonce, &challenge)?;
+        self.send_raw_command(&commands::build_authenticate(Some(&hex::encode(client_hash))))
+            .await?;
+
+        Ok(())
+    }
+
     /// Get Tor connection status
     pub async fn get_status(&mut self) -> TorrerResult<TorStatus> {
-        if self.stream.is_none() {
+        if !self.is_connected() {
             return Err(TorrerError::Tor("Not connected to Tor".to_string()));
         }
 
         // Check if circuit is established
-        let circuit_cmd = "GETINFO status/circuit-established\r\n";
-        let response = self.send_raw_command(circuit_cmd).await?;
-        
-        let circuit_established = response.contains("status/circuit-established=1");
+        let response = self
+            .send_raw_command(&commands::build_getinfo("status/circuit-established"))
+            .await?;
+
+        let circuit_established =
+            response.get("status/circuit-established").as_deref() == Some("1");
 
         // Get circuit status
-        let status_cmd = "GETINFO circuit-status\r\n";
-        let circuit_response = self.send_raw_command(status_cmd).await?;
+        let circuit_response = self
+            .send_raw_command(&commands::build_getinfo("circuit-status"))
+            .await?;
 
         Ok(TorStatus {
             is_connected: true,
             circuit_established,
             circuit_info: Some(CircuitInfo {
-                status: circuit_response,
+                status: circuit_response.get("circuit-status").unwrap_or_default(),
             }),
         })
     }
 
-    /// Send a raw command to Tor
-    async fn send_raw_command(&mut self, command: &str) -> TorrerResult<String> {
-        let stream = self.stream.as_mut().ok_or_else(|| {
+    /// Send a raw command to Tor and wait for its complete reply
+    async fn send_raw_command(&mut self, command: &str) -> TorrerResult<Response> {
+        let writer = self.writer.as_mut().ok_or_else(|| {
             TorrerError::Tor("Not connected to Tor".to_string())
         })?;
 
         log::debug!("Sending command: {}", command.trim());
 
         // Write command
-        stream.write_all(command.as_bytes()).await.map_err(|e| {
-            TorrerError::Tor(format!("Failed to write command: {}", e))
-        })?;
+        let mut line = command.trim_end_matches(['\r', '\n']).to_string();
+        line.push_str("\r\n");
+        if let Err(e) = writer.write_all(line.as_bytes()).await {
+            self.disconnect();
+            return Err(TorrerError::Tor(format!("Failed to write command: {}", e)));
+        }
 
         // Read response
-        let mut buffer = vec![0u8; 4096];
-        let n = timeout(DEFAULT_TIMEOUT, stream.read(&mut buffer)).await
-            .map_err(|_| TorrerError::Tor("Read timeout".to_string()))?
-            .map_err(|e| TorrerError::Tor(format!("Failed to read response: {}", e)))?;
+        let replies = self.replies.as_mut().ok_or_else(|| {
+            TorrerError::Tor("Not connected to Tor".to_string())
+        })?;
+
+        let response = match timeout(DEFAULT_TIMEOUT, replies.recv()).await {
+            Ok(Some(response)) => response,
+            Ok(None) => {
+                self.disconnect();
+                return Err(TorrerError::Tor("Tor closed the control connection".to_string()));
+            }
+            Err(_) => {
+                // A late reply would be paired with the next command
+                self.disconnect();
+                return Err(TorrerError::Tor("Read timeout".to_string()));
+            }
+        };
 
-        let response = String::from_utf8_lossy(&buffer[..n]).to_string();
-        log::debug!("Received response: {}", response.trim());
+        log::debug!("Received response: {} {}", response.status_code, response.message());
 
         // Check for error responses
-        if response.starts_with("515") {
+        if response.status_code == 515 {
             return Err(TorrerError::Tor("Authentication required".to_string()));
         }
 
+        if !response.is_success() {
+            return Err(TorrerError::Tor(format!(
+                "Tor rejected command ({}): {}",
+                response.status_code,
+                response.message()
+            )));
+        }
+
         Ok(response)
     }
 
     /// Send a command and parse response
-    pub async fn send_command(&mut self, command: &str) -> TorrerResult<String> {
+    pub async fn send_command(&mut self, command: &str) -> TorrerResult<Response> {
         self.send_raw_command(command).await
     }
 }
 
+impl Drop for TorClient {
+    fn drop(&mut self) {
+        self.disconnect();
+    }
+}
+
 impl Default for TorClient {
     fn default() -> Self {
         Self::new()
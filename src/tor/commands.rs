@@ -1,5 +1,7 @@
 // Tor control protocol command builders
 
+use std::net::{IpAddr, SocketAddr};
+
 /// Build AUTHENTICATE command
 pub fn build_authenticate(cookie: Option<&str>) -> String {
     match cookie {
@@ -8,6 +10,16 @@ pub fn build_authenticate(cookie: Option<&str>) -> String {
     }
 }
 
+/// Build PROTOCOLINFO command
+pub fn build_protocolinfo() -> String {
+    "PROTOCOLINFO 1\r\n".to_string()
+}
+
+/// Build AUTHCHALLENGE command for SAFECOOKIE authentication
+pub fn build_authchallenge(client_nonce_hex: &str) -> String {
+    format!("AUTHCHALLENGE SAFECOOKIE {}\r\n", client_nonce_hex)
+}
+
 /// Build GETINFO command
 pub fn build_getinfo(key: &str) -> String {
     format!("GETINFO {}\r\n", key)
@@ -18,8 +30,116 @@ pub fn build_signal_newym() -> String {
     "SIGNAL NEWNYM\r\n".to_string()
 }
 
+/// Build SIGNAL command (e.g. SHUTDOWN, RELOAD)
+pub fn build_signal(signal: &str) -> String {
+    format!("SIGNAL {}\r\n", signal)
+}
+
+/// Build TAKEOWNERSHIP command
+pub fn build_takeownership() -> String {
+    "TAKEOWNERSHIP\r\n".to_string()
+}
+
+/// Build SETEVENTS command
+pub fn build_setevents(events: &[&str]) -> String {
+    if events.is_empty() {
+        "SETEVENTS\r\n".to_string()
+    } else {
+        format!("SETEVENTS {}\r\n", events.join(" "))
+    }
+}
+
 /// Build SETCONF command
 pub fn build_setconf(key: &str, value: &str) -> String {
     format!("SETCONF {}={}\r\n", key, value)
 }
 
+/// Build SETCONF command setting several options at once
+///
+/// A key given more than once gets all of its values, e.g. one `TransPort`
+/// line per listen address. Values with spaces are quoted.
+pub fn build_setconf_values(values: &[(&str, String)]) -> String {
+    let settings: Vec<String> = values
+        .iter()
+        .map(|(key, value)| {
+            if value.contains(char::is_whitespace) {
+                format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect();
+    format!("SETCONF {}\r\n", settings.join(" "))
+}
+
+/// `TransPort` and `DNSPort` values for listeners on `addresses`
+///
+/// With `ipv6`, Tor may connect to and resolve IPv6 addresses for them.
+pub fn listener_values(
+    addresses: &[IpAddr],
+    trans_port: u16,
+    dns_port: u16,
+    ipv6: bool,
+) -> Vec<(&'static str, String)> {
+    let flags = if ipv6 { " IPv6Traffic PreferIPv6" } else { "" };
+    let mut values = Vec::new();
+    for (key, port) in [("TransPort", trans_port), ("DNSPort", dns_port)] {
+        for address in addresses {
+            values.push((key, format!("{}{}", SocketAddr::new(*address, port), flags)));
+        }
+    }
+    values
+}
+
+/// Options making Tor map `.onion` and `.exit` names to virtual addresses
+pub const AUTOMAP_OPTIONS: [&str; 4] = [
+    "AutomapHostsOnResolve",
+    "AutomapHostsSuffixes",
+    "VirtualAddrNetworkIPv4",
+    "VirtualAddrNetworkIPv6",
+];
+
+/// Values for `AUTOMAP_OPTIONS` handing out addresses from `ipv4` and `ipv6`
+///
+/// Tor wants the IPv6 network in brackets, e.g. `[fc00::]/7`.
+pub fn automap_values(ipv4: &str, ipv6: &str) -> Vec<(&'static str, String)> {
+    let ipv6 = match ipv6.split_once('/') {
+        Some((address, prefix)) => format!("[{}]/{}", address.trim_matches(|c| c == '[' || c == ']'), prefix),
+        None => ipv6.to_string(),
+    };
+    vec![
+        (AUTOMAP_OPTIONS[0], "1".to_string()),
+        (AUTOMAP_OPTIONS[1], ".onion,.exit".to_string()),
+        (AUTOMAP_OPTIONS[2], ipv4.to_string()),
+        (AUTOMAP_OPTIONS[3], ipv6),
+    ]
+}
+
+/// Build RESETCONF command, returning options to their torrc values
+pub fn build_resetconf(keys: &[&str]) -> String {
+    format!("RESETCONF {}\r\n", keys.join(" "))
+}
+
+/// Build CLOSECIRCUIT command
+pub fn build_closecircuit(circuit_id: &str) -> String {
+    format!("CLOSECIRCUIT {}\r\n", circuit_id)
+}
+
+/// Build EXTENDCIRCUIT command (circuit ID 0 builds a new circuit)
+pub fn build_extendcircuit(circuit_id: &str, path: &[String]) -> String {
+    if path.is_empty() {
+        format!("EXTENDCIRCUIT {}\r\n", circuit_id)
+    } else {
+        format!("EXTENDCIRCUIT {} {}\r\n", circuit_id, path.join(","))
+    }
+}
+
+/// Build CLOSESTREAM command
+pub fn build_closestream(stream_id: &str, reason: u8) -> String {
+    format!("CLOSESTREAM {} {}\r\n", stream_id, reason)
+}
+
+/// Build ATTACHSTREAM command
+pub fn build_attachstream(stream_id: &str, circuit_id: &str) -> String {
+    format!("ATTACHSTREAM {} {}\r\n", stream_id, circuit_id)
+}
@@ -51,37 +51,27 @@ impl CountrySelector {
         Ok(validated)
     }
 
+    /// Format country code(s) as an `ExitNodes` value, e.g. `{US},{CA}`
+    pub fn exit_nodes(country_codes: &str) -> TorrerResult<String> {
+        let codes = Self::validate_country_codes(country_codes)?;
+        Ok(codes
+            .iter()
+            .map(|code| format!("{{{}}}", code))
+            .collect::<Vec<_>>()
+            .join(","))
+    }
+
     /// Set exit node country (single or multiple)
     pub async fn set_exit_country(&self, client: &mut TorClient, country_code: &str) -> TorrerResult<()> {
         log::info!("Setting exit node country to: {}", country_code);
 
-        // Validate country code(s)
-        let validated_codes = if country_code.contains(',') {
-            // Multiple countries
-            Self::validate_country_codes(country_code)?
-        } else {
-            // Single country
-            Self::validate_country_code(country_code)?;
-            vec![country_code.to_uppercase()]
-        };
-
-        // Format country codes for Tor (comma-separated, wrapped in braces)
-        let exit_nodes = if validated_codes.len() == 1 {
-            format!("{{{}}}", validated_codes[0])
-        } else {
-            format!("{{{}}}", validated_codes.join(","))
-        };
-        
+        let exit_nodes = Self::exit_nodes(country_code)?;
+
         // Set exit node country via Tor control port
         let command = format!("SETCONF ExitNodes={}\r\n", exit_nodes);
-        let response = client.send_command(&command).await?;
-
-        // Check for errors in response
-        if response.contains("552") || response.contains("error") {
-            return Err(TorrerError::Tor(
-                format!("Failed to set exit country: {}", response)
-            ));
-        }
+        client.send_command(&command).await.map_err(|e| {
+            TorrerError::Tor(format!("Failed to set exit country: {}", e))
+        })?;
 
         log::info!("Exit node country set to: {}", exit_nodes);
         Ok(())
@@ -103,29 +93,22 @@ impl CountrySelector {
         let command = "GETCONF ExitNodes\r\n";
         let response = client.send_command(command).await?;
 
-        // Parse ExitNodes from response
-        // Format: "250 ExitNodes={US}\r\n" or "250 ExitNodes=\r\n"
-        for line in response.lines() {
-            if line.starts_with("250 ExitNodes=") {
-                let nodes = line.strip_prefix("250 ExitNodes=")
-                    .and_then(|s| s.strip_suffix("\r\n"))
-                    .unwrap_or("");
-                
-                if nodes.is_empty() || nodes == "{}" {
-                    return Ok(None);
-                }
-
-                // Extract country codes from {US} or {US,CA} format
-                let codes = nodes.trim_matches('{').trim_matches('}');
-                if codes.is_empty() {
-                    return Ok(None);
-                }
-
-                return Ok(Some(codes.to_string()));
-            }
+        // Format: "250 ExitNodes={US}" or "250 ExitNodes"
+        let nodes = response.get("ExitNodes").unwrap_or_default();
+        if nodes.is_empty() || nodes == "{}" {
+            return Ok(None);
         }
 
-        Ok(None)
+        // Extract country codes from {US} or {US},{CA} format
+        let codes: Vec<&str> = nodes
+            .split(',')
+            .map(|code| code.trim_matches(|c| c == '{' || c == '}'))
+            .filter(|code| !code.is_empty())
+            .collect();
+        if codes.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(codes.join(",")))
     }
 }
-
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TorrerError;
+
+/// Prefix selecting a Unix domain socket in the string form of an endpoint
+const UNIX_PREFIX: &str = "unix:";
+
+/// Where to reach Tor's control port
+///
+/// Written in configuration as `"127.0.0.1:9051"` for TCP or
+/// `"unix:/run/tor/control"` for a `ControlSocket`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ControlEndpoint {
+    Tcp { host: String, port: u16 },
+    Unix(PathBuf),
+}
+
+impl ControlEndpoint {
+    /// TCP endpoint on localhost
+    pub fn localhost(port: u16) -> Self {
+        Self::Tcp {
+            host: "127.0.0.1".to_string(),
+            port,
+        }
+    }
+
+    /// Unix domain socket endpoint
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self::Unix(path.into())
+    }
+}
+
+impl fmt::Display for ControlEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { host, port } if host.contains(':') => write!(f, "[{}]:{}", host, port),
+            Self::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            Self::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+impl FromStr for ControlEndpoint {
+    type Err = TorrerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(TorrerError::Config("Empty control socket path".to_string()));
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+
+        if s.starts_with('/') {
+            return Ok(Self::Unix(PathBuf::from(s)));
+        }
+
+        let (host, port) = s.rsplit_once(':').ok_or_else(|| {
+            TorrerError::Config(format!(
+                "Invalid control endpoint '{}' (expected host:port or unix:/path)",
+                s
+            ))
+        })?;
+
+        let port = port
+            .parse::<u16>()
+            .ok()
+            .filter(|p| *p != 0)
+            .ok_or_else(|| TorrerError::Config(format!("Invalid control port in '{}'", s)))?;
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(TorrerError::Config(format!("Missing control host in '{}'", s)));
+        }
+
+        Ok(Self::Tcp {
+            host: host.to_string(),
+            port,
+        })
+    }
+}
+
+impl TryFrom<String> for ControlEndpoint {
+    type Error = TorrerError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<ControlEndpoint> for String {
+    fn from(endpoint: ControlEndpoint) -> Self {
+        endpoint.to_string()
+    }
+}
@@ -0,0 +1,264 @@
+// Typed Tor asynchronous events (SETEVENTS)
+
+use std::collections::HashMap;
+use std::fmt;
+
+use tokio::sync::mpsc;
+
+use crate::tor::protocol::{self, Response};
+
+/// Event types that can be requested with `SETEVENTS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Circ,
+    Stream,
+    Bw,
+    StreamBw,
+    StatusClient,
+    Guard,
+    Notice,
+    Warn,
+}
+
+impl EventKind {
+    /// Events Torrer subscribes to by default
+    pub const DEFAULT: [EventKind; 7] = [
+        EventKind::Circ,
+        EventKind::Stream,
+        EventKind::Bw,
+        EventKind::StatusClient,
+        EventKind::Guard,
+        EventKind::Notice,
+        EventKind::Warn,
+    ];
+
+    /// Keyword used by the control protocol
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Circ => "CIRC",
+            EventKind::Stream => "STREAM",
+            EventKind::Bw => "BW",
+            EventKind::StreamBw => "STREAM_BW",
+            EventKind::StatusClient => "STATUS_CLIENT",
+            EventKind::Guard => "GUARD",
+            EventKind::Notice => "NOTICE",
+            EventKind::Warn => "WARN",
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `CIRC` event: a circuit changed state
+#[derive(Debug, Clone)]
+pub struct CircEvent {
+    pub id: String,
+    pub status: String,
+    pub path: Vec<String>,
+    pub keywords: HashMap<String, String>,
+}
+
+impl CircEvent {
+    /// Circuit purpose (e.g. GENERAL, HS_CLIENT_REND)
+    pub fn purpose(&self) -> Option<&str> {
+        self.keywords.get("PURPOSE").map(String::as_str)
+    }
+
+    /// Reason for FAILED/CLOSED circuits
+    pub fn reason(&self) -> Option<&str> {
+        self.keywords.get("REASON").map(String::as_str)
+    }
+}
+
+/// `STREAM` event: an application stream changed state
+#[derive(Debug, Clone)]
+pub struct StreamEvent {
+    pub id: String,
+    pub status: String,
+    pub circuit_id: String,
+    pub target: String,
+    pub keywords: HashMap<String, String>,
+}
+
+impl StreamEvent {
+    /// Reason for FAILED/CLOSED/DETACHED streams
+    pub fn reason(&self) -> Option<&str> {
+        self.keywords.get("REASON").map(String::as_str)
+    }
+}
+
+/// `BW` event: bytes read and written in the last second
+#[derive(Debug, Clone, Copy)]
+pub struct BwEvent {
+    pub read: u64,
+    pub written: u64,
+}
+
+/// `STREAM_BW` event: bytes moved on one stream since the last event
+#[derive(Debug, Clone)]
+pub struct StreamBwEvent {
+    pub stream_id: String,
+    pub read: u64,
+    pub written: u64,
+}
+
+/// `STATUS_CLIENT` event (bootstrap, circuit established, ...)
+#[derive(Debug, Clone)]
+pub struct StatusEvent {
+    pub severity: String,
+    pub action: String,
+    pub arguments: HashMap<String, String>,
+}
+
+/// `GUARD` event: an entry guard changed status
+#[derive(Debug, Clone)]
+pub struct GuardEvent {
+    pub guard_type: String,
+    pub name: String,
+    pub status: String,
+}
+
+/// A typed asynchronous event from Tor
+#[derive(Debug, Clone)]
+pub enum TorEvent {
+    Circ(CircEvent),
+    Stream(StreamEvent),
+    Bw(BwEvent),
+    StreamBw(StreamBwEvent),
+    StatusClient(StatusEvent),
+    Guard(GuardEvent),
+    Log { severity: String, message: String },
+}
+
+impl TorEvent {
+    /// Parse a `650` reply into a typed event
+    ///
+    /// Returns `None` for events Torrer does not model or malformed lines.
+    pub fn parse(response: &Response) -> Option<TorEvent> {
+        if !response.is_async_event() {
+            return None;
+        }
+
+        let text = &response.lines.first()?.text;
+        let (kind, body) = text.split_once(' ').unwrap_or((text.as_str(), ""));
+
+        match kind {
+            "NOTICE" | "WARN" | "ERR" | "INFO" | "DEBUG" => Some(TorEvent::Log {
+                severity: kind.to_string(),
+                message: body.to_string(),
+            }),
+            _ => {
+                let arguments = protocol::parse_arguments(body).ok()?;
+                let (positional, keywords) = split_arguments(arguments);
+                Self::from_arguments(kind, positional, keywords)
+            }
+        }
+    }
+
+    fn from_arguments(
+        kind: &str,
+        positional: Vec<String>,
+        keywords: HashMap<String, String>,
+    ) -> Option<TorEvent> {
+        let mut positional = positional.into_iter();
+
+        match kind {
+            "CIRC" => {
+                let id = positional.next()?;
+                let status = positional.next()?;
+                let path = positional
+                    .next()
+                    .map(|p| p.split(',').map(str::to_string).collect())
+                    .unwrap_or_default();
+                Some(TorEvent::Circ(CircEvent { id, status, path, keywords }))
+            }
+            "STREAM" => Some(TorEvent::Stream(StreamEvent {
+                id: positional.next()?,
+                status: positional.next()?,
+                circuit_id: positional.next()?,
+                target: positional.next()?,
+                keywords,
+            })),
+            "BW" => Some(TorEvent::Bw(BwEvent {
+                read: positional.next()?.parse().ok()?,
+                written: positional.next()?.parse().ok()?,
+            })),
+            "STREAM_BW" => {
+                let stream_id = positional.next()?;
+                let written = positional.next()?.parse().ok()?;
+                let read = positional.next()?.parse().ok()?;
+                Some(TorEvent::StreamBw(StreamBwEvent { stream_id, read, written }))
+            }
+            "STATUS_CLIENT" => Some(TorEvent::StatusClient(StatusEvent {
+                severity: positional.next()?,
+                action: positional.next()?,
+                arguments: keywords,
+            })),
+            "GUARD" => Some(TorEvent::Guard(GuardEvent {
+                guard_type: positional.next()?,
+                name: positional.next()?,
+                status: positional.next()?,
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// Separate positional tokens from `KEY=value` keywords
+pub(crate) fn split_arguments(
+    arguments: Vec<(String, Option<String>)>,
+) -> (Vec<String>, HashMap<String, String>) {
+    let mut positional = Vec::new();
+    let mut keywords = HashMap::new();
+
+    for (key, value) in arguments {
+        match value {
+            // Relay paths may use the "$FP=nickname" long-name form
+            Some(value) if key.starts_with('$') => positional.push(format!("{}={}", key, value)),
+            Some(value) => {
+                keywords.insert(key, value);
+            }
+            None => positional.push(key),
+        }
+    }
+
+    (positional, keywords)
+}
+
+/// Stream of typed events from a subscribed control connection
+pub struct EventStream {
+    receiver: mpsc::Receiver<Response>,
+}
+
+impl EventStream {
+    pub(crate) fn new(receiver: mpsc::Receiver<Response>) -> Self {
+        Self { receiver }
+    }
+
+    /// Wait for the next event Torrer understands
+    ///
+    /// Returns `None` once the control connection is closed.
+    pub async fn next(&mut self) -> Option<TorEvent> {
+        loop {
+            let response = self.receiver.recv().await?;
+            match TorEvent::parse(&response) {
+                Some(event) => return Some(event),
+                None => log::debug!("Ignoring Tor event: {}", response.message()),
+            }
+        }
+    }
+
+    /// Take an already received event without waiting
+    pub fn try_next(&mut self) -> Option<TorEvent> {
+        while let Ok(response) = self.receiver.try_recv() {
+            if let Some(event) = TorEvent::parse(&response) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
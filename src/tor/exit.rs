@@ -0,0 +1,218 @@
+// Exit verification from Tor's own view: the stream, its circuit and the consensus
+
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ConfigManager;
+use crate::error::TorrerResult;
+use crate::tor::commands::build_getinfo;
+use crate::tor::{
+    CircuitHop, CircuitInfo, CircuitManager, CountrySelector, EventKind, RelayInfo, RelayManager, StreamInfo,
+    TorClient, TorEvent,
+};
+
+/// Which exit a stream left Tor through, checked against the consensus
+///
+/// Nothing is fetched from outside Tor: the stream and circuit come from the
+/// control port, the exit's address and flags from the consensus, and its
+/// country from Tor's GeoIP database.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitVerification {
+    pub stream_id: String,
+    /// Destination of the stream, as `host:port`
+    pub target: String,
+    pub circuit_id: String,
+    /// Last hop of the stream's circuit
+    pub exit: CircuitHop,
+    /// Consensus entry of the exit; `None` when it is not listed
+    pub relay: Option<RelayInfo>,
+    /// Country of the exit's consensus address
+    pub country: Option<String>,
+    /// Countries exits are restricted to (upper case); empty for any
+    pub expected_countries: Vec<String>,
+}
+
+impl ExitVerification {
+    /// Why the exit does not check out; empty when it does
+    pub fn problems(&self) -> Vec<String> {
+        let relay = match self.relay {
+            Some(ref relay) => relay,
+            None => return vec![format!("exit ${} is not in the consensus", self.exit.fingerprint)],
+        };
+
+        let mut problems = Vec::new();
+        if let (Some(ref circuit), Some(ref consensus)) = (&self.exit.nickname, &relay.nickname) {
+            if circuit != consensus {
+                problems.push(format!("the circuit names the exit {}, the consensus {}", circuit, consensus));
+            }
+        }
+        if relay.address.is_none() {
+            problems.push("the consensus lists no address for the exit".to_string());
+        }
+        if !relay.is_exit {
+            problems.push("the exit has no Exit flag in the consensus".to_string());
+        }
+        if relay.is_bad_exit {
+            problems.push("the exit is flagged BadExit".to_string());
+        }
+        if !self.expected_countries.is_empty() {
+            match self.country {
+                Some(ref country) if self.expected_countries.contains(country) => {}
+                Some(ref country) => problems.push(format!(
+                    "the exit is in {}, not in {}",
+                    country,
+                    self.expected_countries.join(",")
+                )),
+                None => problems.push("the exit's country is unknown".to_string()),
+            }
+        }
+        problems
+    }
+
+    /// Whether the exit checks out
+    pub fn is_verified(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// Address of the exit from the consensus
+    pub fn address(&self) -> Option<&str> {
+        self.relay.as_ref().and_then(|relay| relay.address.as_deref())
+    }
+}
+
+impl fmt::Display for ExitVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.exit)?;
+        if let Some(address) = self.address() {
+            write!(f, " at {}", address)?;
+        }
+        if let Some(ref country) = self.country {
+            write!(f, " ({})", country)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exit verification
+pub struct ExitVerifier;
+
+impl ExitVerifier {
+    /// Verify the exit of the latest stream that went out through one
+    ///
+    /// Uses streams Tor already has open; with none, waits up to `wait` for
+    /// a `STREAM` event. `Ok(None)` means no stream left through an exit.
+    pub async fn verify(
+        client: &mut TorClient,
+        expected_countries: &[String],
+        wait: Duration,
+    ) -> TorrerResult<Option<ExitVerification>> {
+        let streams = CircuitManager::list_streams(client).await?;
+        let circuits = CircuitManager::get_circuits(client).await?;
+
+        let found = match Self::latest_exit_stream(&streams, &circuits) {
+            Some(found) => Some(found),
+            None if wait.is_zero() => None,
+            None => Self::wait_for_stream(client, wait).await?,
+        };
+
+        let (stream, exit) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        let relay = RelayManager::get_relay_info(client, &exit.fingerprint).await.ok();
+        let country = match relay.as_ref().and_then(|relay| relay.address.clone()) {
+            // IPv6-only entries come from the `a` line, as `[address]:port`
+            Some(address) => match address.strip_prefix('[').and_then(|rest| rest.split(']').next()) {
+                Some(ipv6) => Self::country_of(client, ipv6).await,
+                None => Self::country_of(client, &address).await,
+            },
+            None => None,
+        };
+
+        let verification = ExitVerification {
+            stream_id: stream.id,
+            target: stream.target,
+            circuit_id: stream.circuit_id,
+            exit,
+            relay,
+            country,
+            expected_countries: expected_countries.iter().map(|c| c.to_uppercase()).collect(),
+        };
+        if verification.is_verified() {
+            log::info!("Stream {} left Tor through {}", verification.stream_id, verification);
+        } else {
+            log::warn!("Exit of stream {} did not verify: {}", verification.stream_id, verification.problems().join("; "));
+        }
+        Ok(Some(verification))
+    }
+
+    /// Countries `country_code` in the configuration restricts exits to
+    pub fn expected_countries() -> Vec<String> {
+        ConfigManager::new()
+            .and_then(|manager| manager.load())
+            .ok()
+            .and_then(|config| config.country_code)
+            .and_then(|codes| CountrySelector::validate_country_codes(&codes).ok())
+            .unwrap_or_default()
+    }
+
+    /// The newest succeeded stream whose circuit ends in an exit, with that exit
+    ///
+    /// Streams to onion services use circuits without one and are skipped.
+    pub fn latest_exit_stream(streams: &[StreamInfo], circuits: &[CircuitInfo]) -> Option<(StreamInfo, CircuitHop)> {
+        let mut candidates: Vec<&StreamInfo> = streams
+            .iter()
+            .filter(|stream| stream.status == "SUCCEEDED" && stream.is_attached())
+            .collect();
+        candidates.sort_by_key(|stream| std::cmp::Reverse(stream.id.parse::<u64>().unwrap_or(0)));
+
+        candidates.into_iter().find_map(|stream| {
+            circuits
+                .iter()
+                .find(|circuit| circuit.id == stream.circuit_id)
+                .and_then(CircuitInfo::exit)
+                .map(|exit| (stream.clone(), exit.clone()))
+        })
+    }
+
+    /// Wait for a stream to succeed through an exit
+    async fn wait_for_stream(client: &mut TorClient, wait: Duration) -> TorrerResult<Option<(StreamInfo, CircuitHop)>> {
+        let mut events = client.subscribe(&[EventKind::Stream]).await?;
+        let deadline = tokio::time::Instant::now() + wait;
+
+        loop {
+            let event = match tokio::time::timeout_at(deadline, events.next()).await {
+                Ok(Some(event)) => event,
+                Ok(None) | Err(_) => return Ok(None),
+            };
+            let stream = match event {
+                TorEvent::Stream(stream) if stream.status == "SUCCEEDED" && stream.circuit_id != "0" => stream,
+                _ => continue,
+            };
+
+            let stream = StreamInfo {
+                id: stream.id,
+                status: stream.status,
+                circuit_id: stream.circuit_id,
+                target: stream.target,
+            };
+            let circuits = CircuitManager::get_circuits(client).await?;
+            if let Some(found) = Self::latest_exit_stream(std::slice::from_ref(&stream), &circuits) {
+                return Ok(Some(found));
+            }
+        }
+    }
+
+    /// Country of `address` in Tor's GeoIP database
+    async fn country_of(client: &mut TorClient, address: &str) -> Option<String> {
+        let key = format!("ip-to-country/{}", address);
+        let response = client.send_command(&build_getinfo(&key)).await.ok()?;
+        response
+            .get(&key)
+            .map(|country| country.trim().to_uppercase())
+            .filter(|country| country.len() == 2 && country != "??")
+    }
+}
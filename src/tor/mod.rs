@@ -1,11 +1,22 @@
+pub mod auth;
+pub mod bootstrap;
 pub mod client;
 pub mod protocol;
 pub mod commands;
+pub mod endpoint;
+pub mod events;
 pub mod country;
 pub mod circuit;
 pub mod relay;
+pub mod exit;
+pub mod process;
 
 pub use client::TorClient;
+pub use bootstrap::{BootstrapPhase, BootstrapStatus, BootstrapTracker};
+pub use endpoint::ControlEndpoint;
+pub use events::{EventKind, EventStream, TorEvent};
 pub use country::CountrySelector;
-pub use circuit::{CircuitManager, CircuitInfo};
+pub use circuit::{CircuitManager, CircuitInfo, CircuitHop, HopRole, StreamInfo};
 pub use relay::{RelayManager, RelayInfo};
+pub use exit::{ExitVerification, ExitVerifier};
+pub use process::{ManagedTorConfig, TorProcess};
@@ -0,0 +1,378 @@
+// Managed tor process: Torrer launches and owns a private tor instance
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::{Child, Command};
+
+use crate::bridge::{Bridge, BridgeManager};
+use crate::config::Configuration;
+use crate::error::{TorrerError, TorrerResult};
+use crate::iptables::backend::TOR_USER;
+use crate::tor::bootstrap::BootstrapPhase;
+use crate::tor::commands::{automap_values, build_signal, build_takeownership, listener_values};
+use crate::tor::{ControlEndpoint, CountrySelector, TorClient};
+use crate::utils::{is_root, lookup_gid};
+
+/// tor executable used when none is configured
+pub const DEFAULT_TOR_BINARY: &str = "tor";
+
+/// Pluggable transport clients used when `transport_plugins` names none
+pub const DEFAULT_TRANSPORT_PLUGINS: &[(&str, &str)] = &[
+    ("obfs4", "/usr/bin/obfs4proxy"),
+    ("meek_lite", "/usr/bin/obfs4proxy"),
+    ("snowflake", "/usr/bin/snowflake-client"),
+];
+
+/// Default DataDirectory of the managed tor process
+pub const DEFAULT_DATA_DIRECTORY: &str = "/var/lib/torrer/tor";
+
+/// ControlSocket file name inside the data directory
+pub const CONTROL_SOCKET_NAME: &str = "control";
+
+const TORRC_NAME: &str = "torrc";
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Settings for a managed tor process
+#[derive(Debug, Clone)]
+pub struct ManagedTorConfig {
+    pub tor_binary: PathBuf,
+    pub data_directory: PathBuf,
+    pub trans_port: u16,
+    pub dns_port: u16,
+    /// `Bridge ...` lines; bridges are used when non-empty
+    pub bridges: Vec<String>,
+    /// Client executable of each transport the bridges use
+    pub transport_plugins: Vec<(String, PathBuf)>,
+    /// `ExitNodes` value, e.g. `{US},{CA}`
+    pub exit_nodes: Option<String>,
+    /// How long to wait for tor to open its control socket and bootstrap
+    pub bootstrap_timeout: Duration,
+    /// Group tor runs with, so firewall rules can tell its traffic apart
+    pub tor_gid: Option<u32>,
+    /// Also listen on IPv6 loopback and let exits use IPv6
+    pub ipv6: bool,
+    /// Virtual networks (IPv4, IPv6) `.onion` names are mapped into, if any
+    pub virtual_networks: Option<(String, String)>,
+}
+
+impl ManagedTorConfig {
+    /// Defaults for a private tor in `data_directory`
+    pub fn new(data_directory: impl Into<PathBuf>) -> Self {
+        Self {
+            tor_binary: PathBuf::from(DEFAULT_TOR_BINARY),
+            data_directory: data_directory.into(),
+            trans_port: 9040,
+            dns_port: 5353,
+            bridges: Vec::new(),
+            transport_plugins: Vec::new(),
+            exit_nodes: None,
+            bootstrap_timeout: Duration::from_secs(120),
+            tor_gid: None,
+            ipv6: false,
+            virtual_networks: None,
+        }
+    }
+
+    /// Build from Torrer's configuration, bridges and exit country
+    pub fn from_config(config: &Configuration) -> TorrerResult<Self> {
+        let bridges = match BridgeManager::new().and_then(|manager| manager.list_bridges()) {
+            Ok(bridges) => bridges,
+            Err(e) => {
+                log::warn!("Starting managed tor without bridges: {}", e);
+                Vec::new()
+            }
+        };
+
+        let transports: BTreeSet<&str> = bridges.iter().filter_map(|bridge| bridge.transport.as_deref()).collect();
+        let mut transport_plugins = Vec::new();
+        for transport in transports {
+            match config.transport_plugin(transport) {
+                Some(path) => transport_plugins.push((transport.to_string(), path)),
+                None => log::warn!(
+                    "No client for the {} transport; set it under [transport_plugins] in the config file",
+                    transport
+                ),
+            }
+        }
+
+        let exit_nodes = match config.country_code {
+            Some(ref codes) if !codes.trim().is_empty() => Some(CountrySelector::exit_nodes(codes)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            tor_binary: config.tor_binary(),
+            data_directory: config.tor_data_directory(),
+            trans_port: config.tor_transport_port,
+            dns_port: config.tor_dns_port,
+            bridges: bridges.iter().map(Bridge::to_tor_config).collect(),
+            transport_plugins,
+            exit_nodes,
+            // Only root may switch groups
+            tor_gid: if is_root() { lookup_gid(TOR_USER) } else { None },
+            ipv6: config.ipv6_enabled,
+            virtual_networks: if config.onion.automap {
+                Some((config.onion.virtual_network_ipv4.clone(), config.onion.virtual_network_ipv6.clone()))
+            } else {
+                None
+            },
+            ..Self::new(DEFAULT_DATA_DIRECTORY)
+        })
+    }
+
+    /// ControlSocket path
+    pub fn control_socket(&self) -> PathBuf {
+        self.data_directory.join(CONTROL_SOCKET_NAME)
+    }
+
+    /// Path of the generated torrc
+    pub fn torrc_path(&self) -> PathBuf {
+        self.data_directory.join(TORRC_NAME)
+    }
+
+    /// Generate the private torrc
+    pub fn torrc(&self) -> String {
+        let mut torrc = String::from("# Generated by Torrer; overwritten on every start\n");
+        let dir = &self.data_directory;
+
+        let _ = writeln!(torrc, "DataDirectory {}", torrc_path_value(dir));
+        let _ = writeln!(torrc, "ControlSocket {}", torrc_path_value(&self.control_socket()));
+        let _ = writeln!(torrc, "CookieAuthentication 1");
+        let _ = writeln!(
+            torrc,
+            "CookieAuthFile {}",
+            torrc_path_value(&dir.join("control_auth_cookie"))
+        );
+        let _ = writeln!(torrc, "Log notice file {}", torrc_path_value(&dir.join("notices.log")));
+        let _ = writeln!(torrc, "RunAsDaemon 0");
+        let _ = writeln!(torrc, "ClientOnly 1");
+        // Avoid clashing with a system tor on 9050; routing only needs TransPort
+        let _ = writeln!(torrc, "SocksPort 0");
+        let mut addresses = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
+        if self.ipv6 {
+            // IPv6 connections are redirected to ::1
+            addresses.push(IpAddr::V6(Ipv6Addr::LOCALHOST));
+        }
+        for (key, value) in listener_values(&addresses, self.trans_port, self.dns_port, self.ipv6) {
+            let _ = writeln!(torrc, "{} {}", key, value);
+        }
+        if let Some((ref ipv4, ref ipv6)) = self.virtual_networks {
+            for (key, value) in automap_values(ipv4, ipv6) {
+                let _ = writeln!(torrc, "{} {}", key, value);
+            }
+        }
+
+        if !self.bridges.is_empty() {
+            let _ = writeln!(torrc, "UseBridges 1");
+            for bridge in &self.bridges {
+                let _ = writeln!(torrc, "{}", bridge);
+            }
+            for (transport, path) in &self.transport_plugins {
+                let _ = writeln!(torrc, "ClientTransportPlugin {} exec {}", transport, path.display());
+            }
+        }
+
+        if let Some(ref exit_nodes) = self.exit_nodes {
+            let _ = writeln!(torrc, "ExitNodes {}", exit_nodes);
+            let _ = writeln!(torrc, "StrictNodes 1");
+        }
+
+        torrc
+    }
+}
+
+/// A tor child process owned by Torrer
+///
+/// tor is started with `__OwningControllerProcess` and told to
+/// `TAKEOWNERSHIP` of the control connection, so it exits by itself if
+/// Torrer dies without calling `stop`.
+pub struct TorProcess {
+    child: Child,
+    config: ManagedTorConfig,
+    owner: Option<TorClient>,
+}
+
+impl TorProcess {
+    /// Write the torrc, spawn tor and wait until it has bootstrapped
+    pub async fn launch(config: ManagedTorConfig) -> TorrerResult<Self> {
+        prepare_data_directory(&config.data_directory)?;
+
+        let torrc_path = config.torrc_path();
+        std::fs::write(&torrc_path, config.torrc()).map_err(|e| {
+            TorrerError::Tor(format!("Failed to write {}: {}", torrc_path.display(), e))
+        })?;
+
+        log::info!("Launching managed tor ({})", config.tor_binary.display());
+        let mut command = Command::new(&config.tor_binary);
+        command
+            .arg("-f")
+            .arg(&torrc_path)
+            .arg("__OwningControllerProcess")
+            .arg(std::process::id().to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(gid) = config.tor_gid {
+            command.gid(gid);
+        }
+
+        let child = command
+            .spawn()
+            .map_err(|e| {
+                TorrerError::Tor(format!("Failed to start {}: {}", config.tor_binary.display(), e))
+            })?;
+
+        let mut process = Self {
+            child,
+            config,
+            owner: None,
+        };
+
+        if let Err(e) = process.wait_until_ready().await {
+            process.kill().await;
+            return Err(e);
+        }
+
+        Ok(process)
+    }
+
+    /// Control endpoint of the managed tor
+    pub fn endpoint(&self) -> ControlEndpoint {
+        ControlEndpoint::Unix(self.config.control_socket())
+    }
+
+    /// Settings the process was launched with
+    pub fn config(&self) -> &ManagedTorConfig {
+        &self.config
+    }
+
+    /// Process ID of tor, if it is still running
+    pub fn id(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Ask tor to shut down and wait for it, killing it if it does not exit
+    pub async fn stop(&mut self) -> TorrerResult<()> {
+        log::info!("Stopping managed tor");
+
+        if let Some(mut owner) = self.owner.take() {
+            if let Err(e) = owner.send_command(&build_signal("SHUTDOWN")).await {
+                log::debug!("SIGNAL SHUTDOWN failed: {}", e);
+            }
+        }
+
+        match tokio::time::timeout(SHUTDOWN_TIMEOUT, self.child.wait()).await {
+            Ok(Ok(status)) => {
+                log::info!("Managed tor exited ({})", status);
+                Ok(())
+            }
+            Ok(Err(e)) => Err(TorrerError::Tor(format!("Failed to wait for tor: {}", e))),
+            Err(_) => {
+                log::warn!("Managed tor did not exit in time, killing it");
+                self.kill().await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn kill(&mut self) {
+        self.owner = None;
+        if let Err(e) = self.child.kill().await {
+            log::debug!("Failed to kill tor: {}", e);
+        }
+    }
+
+    async fn wait_until_ready(&mut self) -> TorrerResult<()> {
+        let deadline = tokio::time::Instant::now() + self.config.bootstrap_timeout;
+
+        // Wait for the control socket, then take ownership of tor
+        let mut client = loop {
+            self.check_running()?;
+
+            let mut client = TorClient::with_endpoint(self.endpoint());
+            if client.connect().await.is_ok() {
+                break client;
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(TorrerError::Tor(
+                    "Managed tor did not open its control socket in time".to_string(),
+                ));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+
+        client.authenticate().await?;
+        client.send_command(&build_takeownership()).await?;
+
+        // Wait for bootstrap
+        let mut last_progress = None;
+        loop {
+            self.check_running()?;
+
+            let phase = BootstrapPhase::fetch(&mut client).await?;
+            if last_progress != Some(phase.progress) {
+                log::info!("Managed tor bootstrapped {}%: {}", phase.progress, phase.summary);
+                last_progress = Some(phase.progress);
+            }
+            if phase.is_done() {
+                break;
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(TorrerError::Tor(format!(
+                    "Managed tor did not finish bootstrapping in time (stuck at {}%: {})",
+                    phase.progress,
+                    phase.warning.as_deref().unwrap_or(&phase.summary)
+                )));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        self.owner = Some(client);
+        Ok(())
+    }
+
+    fn check_running(&mut self) -> TorrerResult<()> {
+        match self.child.try_wait() {
+            Ok(None) => Ok(()),
+            Ok(Some(status)) => Err(TorrerError::Tor(format!(
+                "Managed tor exited during startup ({}); see {}",
+                status,
+                self.config.data_directory.join("notices.log").display()
+            ))),
+            Err(e) => Err(TorrerError::Tor(format!("Failed to check tor process: {}", e))),
+        }
+    }
+}
+
+/// tor refuses data directories other users can read
+fn prepare_data_directory(dir: &Path) -> TorrerResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::create_dir_all(dir).map_err(|e| {
+        TorrerError::Tor(format!("Failed to create {}: {}", dir.display(), e))
+    })?;
+    std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700)).map_err(|e| {
+        TorrerError::Tor(format!("Failed to secure {}: {}", dir.display(), e))
+    })?;
+
+    Ok(())
+}
+
+/// Quote a path for torrc if it contains whitespace
+fn torrc_path_value(path: &Path) -> String {
+    let path = path.display().to_string();
+    if path.contains(char::is_whitespace) {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        path
+    }
+}
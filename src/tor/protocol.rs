@@ -1,38 +1,215 @@
 // Tor control protocol parsing utilities
 
-/// Parse Tor control protocol response
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Status code used by Tor for asynchronous event notifications
+pub const ASYNC_EVENT_STATUS: u16 = 650;
+
+/// Parse a complete Tor control protocol reply
 pub fn parse_response(response: &str) -> Result<Response, ParseError> {
-    let lines: Vec<&str> = response.lines().collect();
-    
-    if lines.is_empty() {
-        return Err(ParseError::EmptyResponse);
-    }
-
-    // Parse status code from first line
-    let first_line = lines[0];
-    let status_code = parse_status_code(first_line)?;
-    
-    // Parse data lines (if any)
-    let data = if lines.len() > 1 {
-        lines[1..].join("\n")
+    let mut parser = ReplyParser::new();
+
+    for line in response.lines() {
+        if let Some(reply) = parser.push_line(line)? {
+            return Ok(reply);
+        }
+    }
+
+    if parser.is_empty() {
+        Err(ParseError::EmptyResponse)
     } else {
-        String::new()
-    };
+        Err(ParseError::Incomplete)
+    }
+}
+
+/// Read one complete reply (or async event) from a control connection
+///
+/// Returns `Ok(None)` when the connection is closed between replies.
+pub async fn read_reply<R>(reader: &mut R) -> Result<Option<Response>, ParseError>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut parser = ReplyParser::new();
+    let mut buffer = String::new();
 
-    Ok(Response {
-        status_code,
-        data,
-    })
+    loop {
+        buffer.clear();
+        let n = reader
+            .read_line(&mut buffer)
+            .await
+            .map_err(|e| ParseError::Io(e.to_string()))?;
+
+        if n == 0 {
+            return if parser.is_empty() {
+                Ok(None)
+            } else {
+                Err(ParseError::Incomplete)
+            };
+        }
+
+        if let Some(reply) = parser.push_line(&buffer)? {
+            return Ok(Some(reply));
+        }
+    }
 }
 
-/// Parse status code from response line
-fn parse_status_code(line: &str) -> Result<u16, ParseError> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.is_empty() {
+/// Incremental, line-oriented parser for control protocol replies
+///
+/// Handles mid-reply lines (`250-`), data blocks (`250+` terminated by a
+/// single `.`) and the final end-of-reply line (`250 `).
+#[derive(Debug, Default)]
+pub struct ReplyParser {
+    lines: Vec<ReplyLine>,
+    data_block: Option<ReplyLine>,
+}
+
+impl ReplyParser {
+    /// Create an empty parser
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether no line of the current reply has been consumed yet
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.data_block.is_none()
+    }
+
+    /// Feed one line; returns the reply once its final line is seen
+    pub fn push_line(&mut self, line: &str) -> Result<Option<Response>, ParseError> {
+        let line = line.trim_end_matches(['\r', '\n']);
+
+        if let Some(block) = self.data_block.as_mut() {
+            if line == "." {
+                let block = self.data_block.take().expect("data block in progress");
+                self.lines.push(block);
+            } else {
+                // Leading dots are escaped by doubling them
+                let unescaped = line.strip_prefix('.').filter(|l| l.starts_with('.')).unwrap_or(line);
+                block.data.push(unescaped.to_string());
+            }
+            return Ok(None);
+        }
+
+        if line.is_empty() && self.lines.is_empty() {
+            return Ok(None);
+        }
+
+        if line.len() < 4 || !line.is_char_boundary(3) {
+            return Err(ParseError::InvalidFormat);
+        }
+
+        let status_code = parse_status_code(&line[..3])?;
+        let separator = line.as_bytes()[3];
+        let text = line[4..].to_string();
+
+        if let Some(first) = self.lines.first() {
+            if first.status_code != status_code {
+                return Err(ParseError::InvalidFormat);
+            }
+        }
+
+        let reply_line = ReplyLine {
+            status_code,
+            text,
+            data: Vec::new(),
+        };
+
+        match separator {
+            b'-' => {
+                self.lines.push(reply_line);
+                Ok(None)
+            }
+            b'+' => {
+                self.data_block = Some(reply_line);
+                Ok(None)
+            }
+            b' ' => {
+                self.lines.push(reply_line);
+                let lines = std::mem::take(&mut self.lines);
+                Ok(Some(Response { status_code, lines }))
+            }
+            _ => Err(ParseError::InvalidFormat),
+        }
+    }
+}
+
+/// Split reply text into arguments
+///
+/// Positional tokens are returned with `None`; `KEY=value` and
+/// `KEY="quoted value"` tokens carry their (unescaped) value.
+pub fn parse_arguments(input: &str) -> Result<Vec<(String, Option<String>)>, ParseError> {
+    let mut arguments = Vec::new();
+    let mut chars = input.chars().peekable();
+
+    loop {
+        while chars.peek() == Some(&' ') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(&c) = chars.peek() {
+            if c == '=' || c == ' ' {
+                break;
+            }
+            key.push(c);
+            chars.next();
+        }
+
+        if chars.peek() != Some(&'=') {
+            arguments.push((key, None));
+            continue;
+        }
+        chars.next();
+
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        closed = true;
+                        break;
+                    }
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('r') => value.push('\r'),
+                        Some('t') => value.push('\t'),
+                        Some(other) => value.push(other),
+                        None => break,
+                    },
+                    _ => value.push(c),
+                }
+            }
+            if !closed {
+                return Err(ParseError::InvalidFormat);
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ' ' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+        }
+
+        arguments.push((key, Some(value)));
+    }
+
+    Ok(arguments)
+}
+
+/// Parse status code from the first three characters of a reply line
+fn parse_status_code(code: &str) -> Result<u16, ParseError> {
+    if code.len() != 3 || !code.chars().all(|c| c.is_ascii_digit()) {
         return Err(ParseError::InvalidFormat);
     }
 
-    parts[0].parse::<u16>()
+    code.parse::<u16>()
         .map_err(|_| ParseError::InvalidFormat)
 }
 
@@ -40,7 +217,74 @@ fn parse_status_code(line: &str) -> Result<u16, ParseError> {
 #[derive(Debug, Clone)]
 pub struct Response {
     pub status_code: u16,
-    pub data: String,
+    pub lines: Vec<ReplyLine>,
+}
+
+/// A single reply line, with its data block if it was sent as `NNN+`
+#[derive(Debug, Clone)]
+pub struct ReplyLine {
+    pub status_code: u16,
+    pub text: String,
+    pub data: Vec<String>,
+}
+
+impl ReplyLine {
+    /// Split the line into a `key=value` pair, if it has one
+    pub fn key_value(&self) -> Option<(&str, &str)> {
+        self.text.split_once('=')
+    }
+}
+
+impl Response {
+    /// Whether the reply indicates success (2xx)
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+
+    /// Whether the reply is an asynchronous event notification
+    pub fn is_async_event(&self) -> bool {
+        self.status_code == ASYNC_EVENT_STATUS
+    }
+
+    /// Text of the final reply line (e.g. "OK" or an error message)
+    pub fn message(&self) -> &str {
+        self.lines.last().map(|l| l.text.as_str()).unwrap_or("")
+    }
+
+    /// Look up a value by key
+    ///
+    /// Data blocks are joined with newlines; single-line values are
+    /// returned as sent.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.lines.iter().find_map(|line| match line.key_value() {
+            Some((k, v)) if k == key => {
+                if line.data.is_empty() {
+                    Some(v.to_string())
+                } else {
+                    Some(line.data.join("\n"))
+                }
+            }
+            _ => None,
+        })
+    }
+
+    /// Data block lines sent for `key`, if any
+    pub fn data_lines(&self, key: &str) -> Option<&[String]> {
+        self.lines.iter().find_map(|line| match line.key_value() {
+            Some((k, _)) if k == key => Some(line.data.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// All `key=value` pairs carried by single-line reply lines
+    pub fn key_values(&self) -> Vec<(String, String)> {
+        self.lines
+            .iter()
+            .filter(|line| line.data.is_empty())
+            .filter_map(|line| line.key_value())
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
 }
 
 /// Protocol parsing error
@@ -50,5 +294,8 @@ pub enum ParseError {
     EmptyResponse,
     #[error("Invalid response format")]
     InvalidFormat,
+    #[error("Incomplete response")]
+    Incomplete,
+    #[error("IO error: {0}")]
+    Io(String),
 }
-
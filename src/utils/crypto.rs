@@ -26,6 +26,29 @@ impl Crypto {
         format!("{:x}", hasher.finalize())
     }
 
+    /// Compute HMAC-SHA256 (RFC 2104) of `data` under `key`
+    pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+        use sha2::{Sha256, Digest};
+        const BLOCK_SIZE: usize = 64;
+
+        let mut block_key = [0u8; BLOCK_SIZE];
+        if key.len() > BLOCK_SIZE {
+            block_key[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(block_key.map(|b| b ^ 0x36));
+        inner.update(data);
+        let inner_hash = inner.finalize();
+
+        let mut outer = Sha256::new();
+        outer.update(block_key.map(|b| b ^ 0x5c));
+        outer.update(inner_hash);
+        outer.finalize().to_vec()
+    }
+
     /// Verify file checksum
     pub fn verify_checksum(data: &[u8], expected: &str) -> bool {
         let actual = Self::sha256(data);
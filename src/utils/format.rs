@@ -35,6 +35,12 @@ pub fn format_bytes(bytes: u64) -> String {
     format!("{:.2} {}", value, UNITS[exp])
 }
 
+/// Format a percentage as a text progress bar, e.g. `[#####-----]`
+pub fn format_progress_bar(percent: u8, width: usize) -> String {
+    let filled = (percent.min(100) as usize * width + 50) / 100;
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
 /// Format percentage
 pub fn format_percentage(value: f64) -> String {
     format!("{:.2}%", value)
@@ -7,6 +7,114 @@ pub fn validate_ip(ip: &str) -> TorrerResult<IpAddr> {
         .map_err(|_| TorrerError::Config(format!("Invalid IP address: {}", ip)))
 }
 
+/// Parse an address range like `192.168.0.0/16` (a bare address is one host)
+pub fn parse_cidr(range: &str) -> TorrerResult<(IpAddr, u8)> {
+    let (address, prefix) = match range.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (range, None),
+    };
+
+    let address = validate_ip(address)?;
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|prefix| *prefix <= max)
+            .ok_or_else(|| TorrerError::Config(format!("Invalid address range: {}", range)))?,
+        None => max,
+    };
+
+    Ok((address, prefix))
+}
+
+/// Normalize an address range to `network/prefix`, clearing host bits
+///
+/// This is the form iptables and nft print ranges in.
+pub fn network_range(range: &str) -> TorrerResult<(IpAddr, u8)> {
+    let (address, prefix) = parse_cidr(range)?;
+    let network = match address {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & mask).into())
+        }
+    };
+    Ok((network, prefix))
+}
+
+/// Whether `address` lies in `network/prefix`
+pub fn in_range(address: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(address) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(address) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// Split a "port" or "host:port" entry; IPv6 hosts are written in brackets
+///
+/// The host is returned as written and may be an address, a range or a name.
+pub fn split_host_port(entry: &str) -> TorrerResult<(Option<&str>, u16)> {
+    let invalid = || TorrerError::Config(format!("Invalid host:port entry: {}", entry));
+
+    let (host, port) = if let Some(rest) = entry.strip_prefix('[') {
+        let (host, port) = rest.split_once("]:").ok_or_else(invalid)?;
+        (Some(host), port)
+    } else {
+        match entry.rsplit_once(':') {
+            Some((host, _)) if host.contains(':') => return Err(invalid()),
+            Some((host, port)) => (Some(host), port),
+            None => (None, entry),
+        }
+    };
+
+    let port = port.parse::<u16>().ok().filter(|port| *port != 0).ok_or_else(invalid)?;
+    match host {
+        Some("") => Err(invalid()),
+        _ => Ok((host, port)),
+    }
+}
+
+/// Parse an onion service address: "host.onion", "host.onion:port" or a URL
+///
+/// Only v3 addresses (56 base32 characters) exist any more. The port
+/// defaults to 443 for https URLs and 80 otherwise.
+pub fn parse_onion_address(entry: &str) -> TorrerResult<(String, u16)> {
+    let invalid = |reason: &str| TorrerError::Config(format!("Invalid onion address {}: {}", entry, reason));
+
+    let (rest, default_port) = match entry.split_once("://") {
+        Some(("https", rest)) => (rest, 443),
+        Some((_, rest)) => (rest, 80),
+        None => (entry, 80),
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, split_host_port(port)?.1),
+        None => (authority, default_port),
+    };
+
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let service = host
+        .strip_suffix(".onion")
+        .and_then(|name| name.rsplit('.').next())
+        .ok_or_else(|| invalid("not a .onion name"))?;
+    match service.len() {
+        56 if service.chars().all(|c| matches!(c, 'a'..='z' | '2'..='7')) => Ok((host, port)),
+        16 => Err(invalid("v2 onion services no longer exist")),
+        _ => Err(invalid("expected 56 base32 characters before .onion")),
+    }
+}
+
 /// Validate port number
 pub fn validate_port(port: u16) -> TorrerResult<()> {
     if port == 0 || port > 65535 {
@@ -25,6 +133,27 @@ pub async fn is_port_available(port: u16) -> bool {
         .is_ok()
 }
 
+/// First IPv4 address of a network interface
+pub fn interface_ipv4(interface: &str) -> Option<Ipv4Addr> {
+    let output = std::process::Command::new("ip")
+        .args(["-4", "-o", "addr", "show", "dev", interface])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_interface_ipv4(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Address from `ip -4 -o addr show` output, e.g. `2: eth1    inet 10.0.0.1/24 ...`
+pub fn parse_interface_ipv4(output: &str) -> Option<Ipv4Addr> {
+    output.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        fields.find(|field| *field == "inet")?;
+        fields.next()?.split('/').next()?.parse().ok()
+    })
+}
+
 /// Get local IP address
 pub fn get_local_ip() -> Option<Ipv4Addr> {
     use std::net::UdpSocket;
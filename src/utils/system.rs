@@ -21,6 +21,40 @@ pub fn command_exists(cmd: &str) -> bool {
         .unwrap_or(false)
 }
 
+/// Look up a user's UID in /etc/passwd
+pub fn lookup_uid(user: &str) -> Option<u32> {
+    lookup_id("/etc/passwd", user)
+}
+
+/// Look up a group's GID in /etc/group
+pub fn lookup_gid(group: &str) -> Option<u32> {
+    lookup_id("/etc/group", group)
+}
+
+/// Look up a user's UID and primary GID in /etc/passwd, by name or UID
+pub fn lookup_user(user: &str) -> Option<(u32, u32)> {
+    let content = std::fs::read_to_string("/etc/passwd").ok()?;
+    content.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() < 4 || (fields[0] != user && fields[2] != user) {
+            return None;
+        }
+        Some((fields[2].parse().ok()?, fields[3].parse().ok()?))
+    })
+}
+
+/// Third field of the `name:x:id:...` line for `name`
+fn lookup_id(path: &str, name: &str) -> Option<u32> {
+    let content = std::fs::read_to_string(path).ok()?;
+    content.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != name {
+            return None;
+        }
+        fields.nth(1)?.parse().ok()
+    })
+}
+
 /// Get system information
 pub fn get_system_info() -> SystemInfo {
     SystemInfo {
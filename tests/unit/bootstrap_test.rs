@@ -0,0 +1,98 @@
+// Unit tests for Tor bootstrap progress tracking
+
+#[cfg(test)]
+mod tests {
+    use torrer::tor::protocol::parse_response;
+    use torrer::tor::{BootstrapPhase, BootstrapTracker, TorEvent};
+    use torrer::utils::format_progress_bar;
+
+    fn event(line: &str) -> TorEvent {
+        TorEvent::parse(&parse_response(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_bootstrap_phase() {
+        let phase = BootstrapPhase::parse(
+            "NOTICE BOOTSTRAP PROGRESS=50 TAG=loading_descriptors SUMMARY=\"Loading relay descriptors\"",
+        )
+        .unwrap();
+
+        assert_eq!(phase.progress, 50);
+        assert_eq!(phase.tag, "loading_descriptors");
+        assert_eq!(phase.summary, "Loading relay descriptors");
+        assert!(!phase.is_done());
+        assert!(!phase.is_warning());
+
+        assert!(BootstrapPhase::parse("NOTICE CIRCUIT_ESTABLISHED").is_none());
+    }
+
+    #[test]
+    fn test_parse_bootstrap_warning() {
+        let phase = BootstrapPhase::parse(
+            "WARN BOOTSTRAP PROGRESS=10 TAG=conn_done SUMMARY=\"Connected to a relay\" WARNING=\"Connection timed out\" REASON=TIMEOUT COUNT=3 RECOMMENDATION=warn HOSTID=\"0123456789ABCDEF0123456789ABCDEF01234567\" HOSTADDR=\"192.0.2.1:443\"",
+        )
+        .unwrap();
+
+        assert!(phase.is_warning());
+        assert_eq!(phase.warning.as_deref(), Some("Connection timed out"));
+        assert_eq!(phase.reason.as_deref(), Some("TIMEOUT"));
+        assert_eq!(phase.host.as_deref(), Some("192.0.2.1:443"));
+    }
+
+    #[test]
+    fn test_tracker_follows_status_client_events() {
+        let tracker = BootstrapTracker::new();
+        assert!(tracker.status().is_none());
+
+        assert!(tracker.handle_event(&event(
+            "650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=14 TAG=handshake SUMMARY=\"Handshaking with a relay\"\r\n",
+        )));
+        assert!(!tracker.handle_event(&event("650 STATUS_CLIENT NOTICE CIRCUIT_ESTABLISHED\r\n")));
+
+        let status = tracker.status().unwrap();
+        assert_eq!(status.phase.progress, 14);
+        assert_eq!(status.phase.tag, "handshake");
+
+        tracker.handle_event(&event(
+            "650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"\r\n",
+        ));
+        assert!(tracker.status().unwrap().phase.is_done());
+
+        tracker.reset();
+        assert!(tracker.status().is_none());
+    }
+
+    #[test]
+    fn test_tracker_reports_where_bootstrap_stalls() {
+        let tracker = BootstrapTracker::new();
+        let warning = "650 STATUS_CLIENT WARN BOOTSTRAP PROGRESS=5 TAG=conn SUMMARY=\"Connecting to a relay\" WARNING=\"No route to host\" REASON=NOROUTE HOSTADDR=\"192.0.2.7:9001\"\r\n";
+
+        tracker.handle_event(&event(
+            "650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=10 TAG=conn_done SUMMARY=\"Connected to a relay\"\r\n",
+        ));
+        tracker.handle_event(&event(warning));
+        tracker.handle_event(&event(warning));
+
+        let status = tracker.status().unwrap();
+        // A warning about an earlier step does not move progress backwards
+        assert_eq!(status.phase.progress, 10);
+        assert_eq!(status.warnings.len(), 1);
+
+        let stall = status.stalled_at().unwrap();
+        assert_eq!(stall.reason.as_deref(), Some("NOROUTE"));
+        assert_eq!(stall.host.as_deref(), Some("192.0.2.7:9001"));
+
+        tracker.handle_event(&event(
+            "650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"\r\n",
+        ));
+        assert!(tracker.status().unwrap().stalled_at().is_none());
+    }
+
+    #[test]
+    fn test_format_progress_bar() {
+        assert_eq!(format_progress_bar(0, 10), "[----------]");
+        assert_eq!(format_progress_bar(50, 10), "[#####-----]");
+        assert_eq!(format_progress_bar(100, 10), "[##########]");
+        assert_eq!(format_progress_bar(250, 4), "[####]");
+    }
+}
@@ -37,5 +37,20 @@ mod tests {
         let config = bridge.to_tor_config();
         assert_eq!(config, "Bridge 192.168.1.1:443");
     }
-}
 
+    #[test]
+    fn test_bridge_with_transport() {
+        let line = "Bridge obfs4 192.0.2.7:443 0123456789ABCDEF0123456789ABCDEF01234567 cert=AbCd iat-mode=0";
+        let bridge = Bridge::from_str(line).unwrap();
+        assert_eq!(bridge.transport.as_deref(), Some("obfs4"));
+        assert_eq!(bridge.address, "192.0.2.7");
+        assert_eq!(bridge.fingerprint.as_deref(), Some("0123456789ABCDEF0123456789ABCDEF01234567"));
+        assert_eq!(bridge.args, vec!["cert=AbCd", "iat-mode=0"]);
+        assert_eq!(bridge.to_tor_config(), line);
+
+        // The older "IP:PORT FINGERPRINT TRANSPORT" order still parses
+        let old = Bridge::from_str("192.0.2.7:443 ABC123DEF456 obfs4").unwrap();
+        assert_eq!(old.transport.as_deref(), Some("obfs4"));
+        assert_eq!(old.to_tor_config(), "Bridge obfs4 192.0.2.7:443 ABC123DEF456");
+    }
+}
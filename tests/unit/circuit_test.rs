@@ -0,0 +1,112 @@
+// Unit tests for the structured circuit model
+
+#[cfg(test)]
+mod tests {
+    use torrer::tor::protocol::parse_response;
+    use torrer::tor::commands::{build_closecircuit, build_closestream, build_extendcircuit};
+    use torrer::tor::{CircuitHop, CircuitInfo, HopRole, StreamInfo, TorEvent};
+
+    const GUARD: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const MIDDLE: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+    const EXIT: &str = "CCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC";
+
+    #[test]
+    fn test_parse_built_general_circuit() {
+        let line = format!(
+            "7 BUILT ${}~guard,${}~middle,${}~exit BUILD_FLAGS=NEED_CAPACITY,NEED_UPTIME PURPOSE=GENERAL TIME_CREATED=2024-01-01T00:00:00.000000 SOCKS_USERNAME=\"alice\"",
+            GUARD, MIDDLE, EXIT
+        );
+        let circuit = CircuitInfo::parse(&line).unwrap();
+
+        assert_eq!(circuit.id, "7");
+        assert!(circuit.is_built());
+        assert_eq!(circuit.path.len(), 3);
+        assert_eq!(circuit.guard().unwrap().nickname.as_deref(), Some("guard"));
+        assert_eq!(circuit.exit().unwrap().fingerprint, EXIT);
+        assert!(circuit.has_build_flag("NEED_UPTIME"));
+        assert_eq!(circuit.purpose.as_deref(), Some("GENERAL"));
+        assert_eq!(circuit.time_created.as_deref(), Some("2024-01-01T00:00:00.000000"));
+        assert_eq!(circuit.socks_username.as_deref(), Some("alice"));
+
+        let roles: Vec<HopRole> = circuit.hops().map(|(role, _)| role).collect();
+        assert_eq!(roles, vec![HopRole::Guard, HopRole::Middle, HopRole::Exit]);
+    }
+
+    #[test]
+    fn test_internal_circuit_has_no_exit() {
+        let line = format!(
+            "9 BUILT ${}~guard,${}~middle BUILD_FLAGS=IS_INTERNAL,NEED_CAPACITY PURPOSE=HS_CLIENT_REND HS_STATE=HSCR_JOINED REND_QUERY=abcdefghijklmnop",
+            GUARD, MIDDLE
+        );
+        let circuit = CircuitInfo::parse(&line).unwrap();
+
+        assert!(circuit.exit().is_none());
+        assert_eq!(circuit.hs_state.as_deref(), Some("HSCR_JOINED"));
+        assert_eq!(circuit.rend_query.as_deref(), Some("abcdefghijklmnop"));
+        assert!(circuit.hops().all(|(role, _)| role != HopRole::Exit));
+    }
+
+    #[test]
+    fn test_parse_launched_circuit_without_path() {
+        let circuit = CircuitInfo::parse("3 LAUNCHED PURPOSE=GENERAL").unwrap();
+        assert!(circuit.path.is_empty());
+        assert!(circuit.guard().is_none());
+        assert!(!circuit.is_built());
+
+        assert!(CircuitInfo::parse("").is_none());
+    }
+
+    #[test]
+    fn test_parse_hop_forms() {
+        let hop = CircuitHop::parse(&format!("${}=relay", GUARD.to_lowercase())).unwrap();
+        assert_eq!(hop.fingerprint, GUARD);
+        assert_eq!(hop.nickname.as_deref(), Some("relay"));
+
+        assert!(CircuitHop::parse(&format!("${}", EXIT)).unwrap().nickname.is_none());
+        assert!(CircuitHop::parse("nickname-only").is_none());
+    }
+
+    #[test]
+    fn test_from_circ_event() {
+        let reply = format!(
+            "650 CIRC 12 FAILED ${}~guard,${}~middle PURPOSE=GENERAL REASON=TIMEOUT REMOTE_REASON=DESTROYED\r\n",
+            GUARD, MIDDLE
+        );
+        let event = TorEvent::parse(&parse_response(&reply).unwrap()).unwrap();
+
+        match event {
+            TorEvent::Circ(circ) => {
+                let circuit = CircuitInfo::from_event(&circ);
+                assert_eq!(circuit.id, "12");
+                assert_eq!(circuit.path.len(), 2);
+                assert_eq!(circuit.reason.as_deref(), Some("TIMEOUT"));
+                assert_eq!(circuit.remote_reason.as_deref(), Some("DESTROYED"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_status() {
+        let stream = StreamInfo::parse("42 SUCCEEDED 7 example.com:443").unwrap();
+        assert_eq!(stream.id, "42");
+        assert_eq!(stream.status, "SUCCEEDED");
+        assert_eq!(stream.circuit_id, "7");
+        assert_eq!(stream.target, "example.com:443");
+        assert!(stream.is_attached());
+
+        assert!(!StreamInfo::parse("43 NEW 0 10.0.0.1:80").unwrap().is_attached());
+        assert!(StreamInfo::parse("44 NEW").is_none());
+    }
+
+    #[test]
+    fn test_circuit_control_commands() {
+        assert_eq!(build_closecircuit("7"), "CLOSECIRCUIT 7\r\n");
+        assert_eq!(build_closestream("42", 1), "CLOSESTREAM 42 1\r\n");
+        assert_eq!(build_extendcircuit("0", &[]), "EXTENDCIRCUIT 0\r\n");
+        assert_eq!(
+            build_extendcircuit("0", &[format!("${}", GUARD), "exitrelay".to_string()]),
+            format!("EXTENDCIRCUIT 0 ${},exitrelay\r\n", GUARD)
+        );
+    }
+}
@@ -0,0 +1,68 @@
+// Unit tests for Tor control endpoints
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    use torrer::config::Configuration;
+    use torrer::tor::{ControlEndpoint, TorClient};
+
+    #[test]
+    fn test_parse_endpoints() {
+        assert_eq!(
+            "127.0.0.1:9051".parse::<ControlEndpoint>().unwrap(),
+            ControlEndpoint::localhost(9051)
+        );
+        assert_eq!(
+            "unix:/run/tor/control".parse::<ControlEndpoint>().unwrap(),
+            ControlEndpoint::Unix(PathBuf::from("/run/tor/control"))
+        );
+        assert_eq!(
+            "[::1]:9051".parse::<ControlEndpoint>().unwrap().to_string(),
+            "[::1]:9051"
+        );
+        assert!("localhost".parse::<ControlEndpoint>().is_err());
+        assert!("localhost:0".parse::<ControlEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_configuration_endpoint() {
+        let mut config = Configuration::default();
+        assert_eq!(config.control_endpoint(), ControlEndpoint::localhost(9051));
+
+        config = toml::from_str(
+            "tor_control_port = 9051\n\
+             tor_control_endpoint = \"unix:/run/tor/control\"\n\
+             tor_transport_port = 9040\n\
+             tor_dns_port = 5353\n\
+             ipv6_enabled = false\n\
+             auto_fallback = true\n",
+        )
+        .unwrap();
+        assert_eq!(config.control_endpoint(), ControlEndpoint::unix("/run/tor/control"));
+    }
+
+    #[tokio::test]
+    async fn test_connect_over_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line.starts_with("GETINFO version") {
+                    write_half.write_all(b"250-version=0.4.8.9\r\n250 OK\r\n").await.unwrap();
+                }
+            }
+        });
+
+        let mut client = TorClient::with_endpoint(ControlEndpoint::unix(&path));
+        client.connect().await.unwrap();
+        let response = client.send_command("GETINFO version\r\n").await.unwrap();
+        assert_eq!(response.get("version").as_deref(), Some("0.4.8.9"));
+    }
+}
@@ -0,0 +1,164 @@
+// Unit tests for Torrer's DNS resolver
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    use tokio::net::UdpSocket;
+    use torrer::config::Configuration;
+    use torrer::iptables::{chain_rules, render_ruleset, ChainFamily, RoutingRules};
+    use torrer::security::dns_server::{
+        reverse_address, Query, RCODE_FORMERR, RCODE_NOERROR, RCODE_NOTIMP, RCODE_NXDOMAIN, TYPE_A, TYPE_PTR,
+    };
+    use torrer::security::{DnsCache, DnsResolver};
+
+    const TYPE_MX: u16 = 15;
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = id.to_be_bytes().to_vec();
+        packet.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&1u16.to_be_bytes());
+        packet
+    }
+
+    /// Tor's answer to an A query: one record with `ttl`
+    fn answer(query: &[u8], address: Ipv4Addr, ttl: u32) -> Vec<u8> {
+        let mut packet = query.to_vec();
+        packet[2..4].copy_from_slice(&[0x81, 0x80]);
+        packet[6..8].copy_from_slice(&1u16.to_be_bytes());
+        packet.extend_from_slice(&[0xC0, 0x0C, 0, 1, 0, 1]);
+        packet.extend_from_slice(&ttl.to_be_bytes());
+        packet.extend_from_slice(&[0, 4]);
+        packet.extend_from_slice(&address.octets());
+        packet
+    }
+
+    fn rcode(response: &[u8]) -> u8 {
+        response[3] & 0x0F
+    }
+
+    /// A DNSPort answering each A query once with `address`
+    async fn fake_dns_port(address: Ipv4Addr) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let local = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((length, peer)) = socket.recv_from(&mut buffer).await {
+                let _ = socket.send_to(&answer(&buffer[..length], address, 300), peer).await;
+            }
+        });
+        local
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let parsed = Query::parse(&query(7, "Example.COM", TYPE_A)).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(parsed.question.name, "example.com");
+        assert_eq!(parsed.question.qtype, TYPE_A);
+
+        let response = parsed.respond(RCODE_NOTIMP, &[]);
+        assert_eq!(&response[..2], &[0, 7]);
+        assert_eq!(response[2] & 0x80, 0x80);
+        assert_eq!(rcode(&response), RCODE_NOTIMP);
+
+        assert!(Query::parse(&[0, 1, 2]).is_err());
+        assert!(Query::parse(&answer(&query(7, "example.com", TYPE_A), Ipv4Addr::LOCALHOST, 60)).is_err());
+    }
+
+    #[test]
+    fn test_reverse_address() {
+        assert_eq!(
+            reverse_address("5.0.192.127.in-addr.arpa"),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 192, 0, 5)))
+        );
+        let ipv6 = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.e.f.ip6.arpa";
+        assert_eq!(reverse_address(ipv6), Some(IpAddr::V6("fe80::1".parse::<Ipv6Addr>().unwrap())));
+        assert_eq!(reverse_address("1.2.3.in-addr.arpa"), None);
+        assert_eq!(reverse_address("example.com"), None);
+    }
+
+    #[test]
+    fn test_cache_counts_ttls_down() {
+        let packet = query(1, "example.com", TYPE_A);
+        let question = Query::parse(&packet).unwrap().question;
+        let mut cache = DnsCache::new();
+        let now = Instant::now();
+
+        assert!(cache.insert(&question, &answer(&packet, Ipv4Addr::new(93, 184, 216, 34), 300), now));
+        let cached = cache.get(&question, 42, now + Duration::from_secs(100)).unwrap();
+        assert_eq!(&cached[..2], &[0, 42]);
+        let ttl = u32::from_be_bytes(cached[cached.len() - 10..cached.len() - 6].try_into().unwrap());
+        assert_eq!(ttl, 200);
+
+        assert!(cache.get(&question, 42, now + Duration::from_secs(300)).is_none());
+        assert!(cache.is_empty());
+        // Zero TTLs are not kept
+        assert!(!cache.insert(&question, &answer(&packet, Ipv4Addr::LOCALHOST, 0), now));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_refuses_unsupported_types() {
+        let resolver = DnsResolver::new("127.0.0.1:9".parse().unwrap());
+        let response = resolver.resolve(&query(3, "example.com", TYPE_MX)).await.unwrap();
+        assert_eq!(rcode(&response), RCODE_NOTIMP);
+        assert_eq!(resolver.stats().unsupported, 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolver_ignores_responses() {
+        let resolver = DnsResolver::new("127.0.0.1:9".parse().unwrap());
+
+        // A query without its question is malformed
+        let broken = &query(5, "example.com", TYPE_A)[..12];
+        assert_eq!(rcode(&resolver.resolve(broken).await.unwrap()), RCODE_FORMERR);
+
+        let mut response = query(6, "example.com", TYPE_A);
+        response[2] |= 0x80;
+        assert!(resolver.resolve(&response).await.is_none());
+        assert!(resolver.resolve(&response[..12]).await.is_none());
+        assert!(resolver.resolve(&[0, 7, 0x01]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_resolver_caches_and_answers_virtual_ptr() {
+        let upstream = fake_dns_port(Ipv4Addr::new(127, 192, 0, 5)).await;
+        let resolver = DnsResolver::new(upstream);
+        let onion = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+
+        let first = resolver.resolve(&query(1, onion, TYPE_A)).await.unwrap();
+        assert_eq!(rcode(&first), RCODE_NOERROR);
+        let second = resolver.resolve(&query(2, onion, TYPE_A)).await.unwrap();
+        assert_eq!(&second[..2], &[0, 2]);
+
+        let ptr = resolver.resolve(&query(3, "5.0.192.127.in-addr.arpa", TYPE_PTR)).await.unwrap();
+        assert_eq!(rcode(&ptr), RCODE_NOERROR);
+        assert!(ptr.ends_with(b"swzczad\x05onion\x00"));
+        let unmapped = resolver.resolve(&query(4, "9.0.192.127.in-addr.arpa", TYPE_PTR)).await.unwrap();
+        assert_eq!(rcode(&unmapped), RCODE_NXDOMAIN);
+
+        let stats = resolver.stats();
+        assert_eq!((stats.queries, stats.forwarded, stats.cache_hits, stats.mapped_ptr), (4, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_dns_redirected_to_resolver() {
+        let config = Configuration {
+            dns_resolver: true,
+            ..Configuration::default()
+        };
+        let rules = RoutingRules::planned(&config);
+        assert_eq!(rules.local_dns_port(), 53);
+        assert!(chain_rules(&rules, ChainFamily::Ipv4)
+            .iter()
+            .any(|rule| rule.rule.join(" ") == "-p udp -m udp --dport 53 -j REDIRECT --to-ports 53"));
+        assert!(render_ruleset(&rules).contains("meta nfproto ipv4 udp dport 53 redirect to :53"));
+    }
+}
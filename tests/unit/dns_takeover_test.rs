@@ -0,0 +1,115 @@
+// Unit tests for the DNS takeover
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use torrer::config::Configuration;
+    use torrer::iptables::{render_ruleset, RoutingRules};
+    use torrer::security::dns_takeover::{resolved_drop_in, tor_resolv_conf, TAKEOVER_MARKER};
+    use torrer::security::{DnsPaths, DnsTakeover, ResolvConfMode};
+
+    fn temp_paths(dir: &Path) -> DnsPaths {
+        DnsPaths {
+            resolv_conf: dir.join("resolv.conf"),
+            resolved_drop_in: dir.join("resolved.conf.d/torrer.conf"),
+            network_manager_drop_in: dir.join("NetworkManager/conf.d/torrer-dns.conf"),
+            state_dir: dir.join("state"),
+        }
+    }
+
+    #[test]
+    fn test_detect_resolv_conf_mode() {
+        let stub = Path::new("../run/systemd/resolve/stub-resolv.conf");
+        assert_eq!(ResolvConfMode::detect(Some(stub), ""), ResolvConfMode::SystemdResolved);
+        assert_eq!(
+            ResolvConfMode::detect(None, "nameserver 127.0.0.53\noptions edns0 trust-ad\n"),
+            ResolvConfMode::SystemdResolved
+        );
+        assert_eq!(
+            ResolvConfMode::detect(None, "# Generated by NetworkManager\nnameserver 192.168.1.1\n"),
+            ResolvConfMode::NetworkManager
+        );
+        assert_eq!(
+            ResolvConfMode::detect(Some(Path::new("/run/NetworkManager/resolv.conf")), ""),
+            ResolvConfMode::NetworkManager
+        );
+        assert_eq!(ResolvConfMode::detect(None, "nameserver 9.9.9.9\n"), ResolvConfMode::Static);
+    }
+
+    #[test]
+    fn test_takeover_needs_resolver_on_port_53() {
+        assert!(ResolvConfMode::SystemdResolved.reaches(5353));
+        assert!(ResolvConfMode::Static.reaches(53));
+        assert!(ResolvConfMode::NetworkManager.reaches(53));
+        // Bypassed processes would query 127.0.0.1:53 with nothing there
+        assert!(!ResolvConfMode::Static.reaches(5353));
+        assert!(!ResolvConfMode::NetworkManager.reaches(5353));
+    }
+
+    #[test]
+    fn test_takeover_files() {
+        let drop_in = resolved_drop_in(5353);
+        assert!(drop_in.starts_with(TAKEOVER_MARKER));
+        assert!(drop_in.contains("[Resolve]\nDNS=127.0.0.1:5353\nDomains=~.\n"));
+        assert!(drop_in.contains("LLMNR=no\n"));
+        assert!(tor_resolv_conf().ends_with("nameserver 127.0.0.1\n"));
+    }
+
+    #[test]
+    fn test_static_takeover_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = temp_paths(dir.path());
+        fs::write(&paths.resolv_conf, "nameserver 9.9.9.9\n").unwrap();
+
+        let takeover = DnsTakeover::plan(ResolvConfMode::Static, paths.clone());
+        takeover.write_files(5353).unwrap();
+        assert_eq!(fs::read_to_string(&paths.resolv_conf).unwrap(), tor_resolv_conf());
+        assert_eq!(DnsTakeover::load(&paths).unwrap(), Some(takeover.clone()));
+
+        takeover.restore_files().unwrap();
+        assert_eq!(fs::read_to_string(&paths.resolv_conf).unwrap(), "nameserver 9.9.9.9\n");
+        assert!(DnsTakeover::load(&paths).unwrap().is_none());
+        assert!(!paths.resolv_conf_backup().exists());
+    }
+
+    #[test]
+    fn test_takeover_restores_symlink_and_drop_ins() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = temp_paths(dir.path());
+        let managed = dir.path().join("nm-resolv.conf");
+        fs::write(&managed, "# Generated by NetworkManager\nnameserver 192.168.1.1\n").unwrap();
+        std::os::unix::fs::symlink(&managed, &paths.resolv_conf).unwrap();
+
+        let takeover = DnsTakeover::plan(ResolvConfMode::NetworkManager, paths.clone());
+        takeover.write_files(5353).unwrap();
+        assert!(fs::read_to_string(&paths.network_manager_drop_in).unwrap().contains("dns=none"));
+        assert!(fs::read_link(&paths.resolv_conf).is_err());
+
+        takeover.restore_files().unwrap();
+        assert_eq!(fs::read_link(&paths.resolv_conf).unwrap(), managed);
+        assert!(!paths.network_manager_drop_in.exists());
+    }
+
+    #[test]
+    fn test_release_leaves_foreign_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = temp_paths(dir.path());
+        fs::write(&paths.resolv_conf, "nameserver 9.9.9.9\n").unwrap();
+
+        let takeover = DnsTakeover::plan(ResolvConfMode::Static, paths.clone());
+        takeover.write_files(5353).unwrap();
+        fs::write(&paths.resolv_conf, "nameserver 10.0.0.1\n").unwrap();
+
+        takeover.restore_files().unwrap();
+        assert_eq!(fs::read_to_string(&paths.resolv_conf).unwrap(), "nameserver 10.0.0.1\n");
+    }
+
+    #[test]
+    fn test_dns_over_tcp_nft_rules() {
+        let script = render_ruleset(&RoutingRules::planned(&Configuration::default()));
+        assert!(script.contains("\t\ttcp dport { 53, 853 } return\n\t\toifname \"lo\" return"));
+        assert!(script.contains("oifname != \"lo\" tcp dport { 53, 853 } reject with tcp reset"));
+    }
+}
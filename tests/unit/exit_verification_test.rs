@@ -0,0 +1,103 @@
+// Unit tests for verifying the exit of a stream against the consensus
+
+#[cfg(test)]
+mod tests {
+    use torrer::tor::{CircuitHop, CircuitInfo, ExitVerification, ExitVerifier, RelayInfo, StreamInfo};
+
+    const GUARD: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+    const MIDDLE: &str = "BBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBBB";
+    const EXIT: &str = "CCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC";
+
+    fn relay() -> RelayInfo {
+        RelayInfo {
+            fingerprint: EXIT.to_string(),
+            nickname: Some("exit".to_string()),
+            address: Some("198.51.100.7".to_string()),
+            country: None,
+            is_exit: true,
+            is_guard: false,
+            is_bad_exit: false,
+        }
+    }
+
+    fn verification(relay: Option<RelayInfo>, country: Option<&str>, expected: &[&str]) -> ExitVerification {
+        ExitVerification {
+            stream_id: "12".to_string(),
+            target: "example.com:443".to_string(),
+            circuit_id: "7".to_string(),
+            exit: CircuitHop::parse(&format!("${}~exit", EXIT)).unwrap(),
+            relay,
+            country: country.map(str::to_string),
+            expected_countries: expected.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_latest_exit_stream() {
+        let circuits: Vec<CircuitInfo> = [
+            format!("7 BUILT ${}~guard,${}~middle,${}~exit PURPOSE=GENERAL", GUARD, MIDDLE, EXIT),
+            format!("9 BUILT ${}~guard,${}~middle PURPOSE=HS_CLIENT_REND BUILD_FLAGS=IS_INTERNAL", GUARD, MIDDLE),
+        ]
+        .iter()
+        .filter_map(|line| CircuitInfo::parse(line))
+        .collect();
+        let streams: Vec<StreamInfo> = [
+            "3 SUCCEEDED 7 example.com:443",
+            "4 SENTCONNECT 7 example.org:443",
+            "5 SUCCEEDED 9 duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion:443",
+            "6 SUCCEEDED 0 example.net:80",
+        ]
+        .iter()
+        .filter_map(|line| StreamInfo::parse(line))
+        .collect();
+
+        // The onion stream is newer but has no exit
+        let (stream, exit) = ExitVerifier::latest_exit_stream(&streams, &circuits).unwrap();
+        assert_eq!(stream.id, "3");
+        assert_eq!(exit.fingerprint, EXIT);
+
+        assert!(ExitVerifier::latest_exit_stream(&streams[1..], &circuits).is_none());
+        assert!(ExitVerifier::latest_exit_stream(&streams, &[]).is_none());
+    }
+
+    #[test]
+    fn test_verified_exit() {
+        let verified = verification(Some(relay()), Some("CA"), &["CA", "US"]);
+        assert!(verified.is_verified());
+        assert_eq!(verified.address(), Some("198.51.100.7"));
+        assert_eq!(verified.to_string(), format!("exit (${}) at 198.51.100.7 (CA)", EXIT));
+
+        // Any country will do without country_code
+        assert!(verification(Some(relay()), None, &[]).is_verified());
+    }
+
+    #[test]
+    fn test_exit_problems() {
+        assert_eq!(
+            verification(None, None, &[]).problems(),
+            vec![format!("exit ${} is not in the consensus", EXIT)]
+        );
+
+        let bad = RelayInfo {
+            is_exit: false,
+            is_bad_exit: true,
+            ..relay()
+        };
+        assert_eq!(verification(Some(bad), None, &[]).problems().len(), 2);
+
+        let renamed = RelayInfo {
+            nickname: Some("other".to_string()),
+            ..relay()
+        };
+        assert_eq!(
+            verification(Some(renamed), None, &[]).problems(),
+            vec!["the circuit names the exit exit, the consensus other".to_string()]
+        );
+
+        assert_eq!(
+            verification(Some(relay()), Some("DE"), &["CA"]).problems(),
+            vec!["the exit is in DE, not in CA".to_string()]
+        );
+        assert!(!verification(Some(relay()), None, &["CA"]).is_verified());
+    }
+}
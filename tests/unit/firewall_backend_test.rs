@@ -0,0 +1,126 @@
+// Unit tests for firewall backend selection and nftables rule generation
+
+#[cfg(test)]
+mod tests {
+    use torrer::config::{validate_config, Configuration};
+    use torrer::utils::parse_cidr;
+    use torrer::iptables::{render_ruleset, FirewallBackendKind, RoutingPorts, RoutingRules};
+
+    #[test]
+    fn test_detect_backend() {
+        assert_eq!(
+            FirewallBackendKind::detect_from(true, Some("iptables v1.8.7 (legacy)")),
+            Some(FirewallBackendKind::Nftables)
+        );
+        assert_eq!(
+            FirewallBackendKind::detect_from(false, Some("iptables v1.8.7 (nf_tables)\n")),
+            Some(FirewallBackendKind::IptablesNft)
+        );
+        assert_eq!(
+            FirewallBackendKind::detect_from(false, Some("iptables v1.8.7 (legacy)\n")),
+            Some(FirewallBackendKind::IptablesLegacy)
+        );
+        assert_eq!(FirewallBackendKind::detect_from(false, None), None);
+        assert_eq!(
+            FirewallBackendKind::IptablesNft.resolve(),
+            FirewallBackendKind::IptablesNft
+        );
+    }
+
+    #[test]
+    fn test_backend_in_configuration() {
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9040\ntor_dns_port = 5353\nipv6_enabled = false\nauto_fallback = true\n",
+        )
+        .unwrap();
+        assert_eq!(config.firewall_backend, FirewallBackendKind::Auto);
+
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9041\ntor_dns_port = 5354\nipv6_enabled = false\nauto_fallback = true\nfirewall_backend = \"iptables-nft\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.firewall_backend, FirewallBackendKind::IptablesNft);
+        assert_eq!(
+            config.routing_ports(),
+            RoutingPorts { trans_port: 9041, dns_port: 5354 }
+        );
+    }
+
+    #[test]
+    fn test_render_full_ruleset() {
+        let rules = RoutingRules {
+            ports: RoutingPorts::default(),
+            tor_routing: true,
+            dns_redirect: true,
+            block_ipv6: true,
+            tor_uid: Some(105),
+            ..RoutingRules::default()
+        };
+        let script = render_ruleset(&rules);
+
+        // Replaces the table atomically, even when it does not exist yet
+        assert!(script.starts_with("table inet torrer\ndelete table inet torrer\ntable inet torrer {\n"));
+        assert!(script.contains("type nat hook output priority dstnat; policy accept;"));
+        assert!(script.contains("meta skuid 105 return"));
+        assert!(script.contains("meta nfproto ipv4 udp dport 53 redirect to :5353"));
+        assert!(script.contains("tcp flags & (fin|syn|rst|ack) == syn redirect to :9040"));
+        assert!(script.contains("oifname != \"lo\" udp dport 53 drop"));
+        assert!(script.contains("meta nfproto ipv6 oifname != \"lo\" drop"));
+
+        // The tor daemon must bypass the redirect before it applies
+        let bypass = script.find("meta skuid").unwrap();
+        assert!(bypass < script.find("redirect to :9040").unwrap());
+    }
+
+    #[test]
+    fn test_render_kill_switch() {
+        let rules = RoutingRules {
+            kill_switch: true,
+            lan_ranges: vec!["10.0.0.0/8".to_string(), "fd00::/8".to_string()],
+            tor_gid: Some(110),
+            ..RoutingRules::default()
+        };
+        let script = render_ruleset(&rules);
+
+        assert!(script.contains("meta skgid 110 return"));
+        assert!(script.contains("ct status dnat return"));
+        assert!(script.contains("ip daddr 10.0.0.0/8 return"));
+        assert!(script.contains("ip6 daddr fd00::/8 return"));
+
+        let drop = script.find("drop comment \"torrer-kill-switch\"").unwrap();
+        assert!(script.find("oifname \"lo\" return").unwrap() < drop);
+        assert!(script.find("ip daddr 10.0.0.0/8").unwrap() < drop);
+    }
+
+    #[test]
+    fn test_kill_switch_configuration() {
+        let mut config = Configuration::default();
+        assert!(!config.kill_switch);
+
+        config.kill_switch = true;
+        config.lan_ranges = vec!["192.168.1.0/24".to_string(), "fe80::/10".to_string()];
+        assert!(validate_config(&config).is_ok());
+
+        config.lan_ranges.push("192.168.1.0/33".to_string());
+        assert!(validate_config(&config).is_err());
+
+        assert!(parse_cidr("10.1.2.3").unwrap().1 == 32);
+        assert!(parse_cidr("not-a-range/8").is_err());
+    }
+
+    #[test]
+    fn test_render_partial_and_empty_ruleset() {
+        let rules = RoutingRules {
+            tor_routing: true,
+            ..RoutingRules::default()
+        };
+        let script = render_ruleset(&rules);
+        assert!(script.contains("redirect to :9040"));
+        assert!(!script.contains("dport 53"));
+        assert!(!script.contains("ipv6"));
+
+        let empty = RoutingRules::default();
+        assert!(empty.is_empty());
+        assert_eq!(render_ruleset(&empty), "table inet torrer\ndelete table inet torrer\n");
+    }
+}
@@ -0,0 +1,146 @@
+// Unit tests for firewall plans and ruleset diffs
+
+#[cfg(test)]
+mod tests {
+    use torrer::config::Configuration;
+    use torrer::utils::network_range;
+    use torrer::iptables::plan::{iptables_lines, nft_lines, parse_iptables_save};
+    use torrer::iptables::{
+        diff_lines, render_ruleset, ChainFamily, DiffLine, FirewallBackendKind, FirewallPlan,
+        RoutingRules,
+    };
+
+    fn planned_rules() -> RoutingRules {
+        let config = Configuration {
+            kill_switch: true,
+            lan_ranges: vec!["192.168.1.7/24".to_string()],
+            ..Configuration::default()
+        };
+        RoutingRules {
+            tor_uid: Some(105),
+            tor_gid: None,
+            ..RoutingRules::planned(&config)
+        }
+    }
+
+    #[test]
+    fn test_planned_rules_follow_configuration() {
+        let rules = planned_rules();
+        assert!(rules.tor_routing && rules.dns_redirect && rules.block_ipv6);
+        assert!(rules.kill_switch);
+
+        let config = Configuration {
+            lan_ranges: vec!["10.0.0.0/8".to_string()],
+            ..Configuration::default()
+        };
+        let rules = RoutingRules::planned(&config);
+        assert!(!rules.kill_switch);
+        assert!(rules.lan_ranges.is_empty());
+    }
+
+    #[test]
+    fn test_iptables_plan_matches_iptables_save() {
+        let plan = FirewallPlan::new(FirewallBackendKind::IptablesLegacy, planned_rules());
+        assert!(plan.script.starts_with("# iptables-restore --noflush\n*nat\n"));
+        assert!(plan.script.contains("# ip6tables-restore --noflush\n*filter\n"));
+
+        // LAN ranges are normalized the way iptables-save prints them
+        assert!(plan
+            .planned
+            .contains(&"filter: -A TORRER_FILTER -d 192.168.1.0/24 -j RETURN".to_string()));
+
+        let dump = "\
+*filter
+:INPUT ACCEPT [0:0]
+:OUTPUT ACCEPT [0:0]
+:TORRER_FILTER - [0:0]
+-A INPUT -i docker0 -j ACCEPT
+-A OUTPUT -j TORRER_FILTER
+-A OUTPUT -o docker0 -j ACCEPT
+-A TORRER_FILTER -m owner --uid-owner 105 -j RETURN
+COMMIT
+*nat
+:OUTPUT ACCEPT [0:0]
+:TORRER_NAT - [0:0]
+-A OUTPUT -j TORRER_NAT
+-A TORRER_NAT -m owner --uid-owner 105 -j RETURN
+COMMIT
+";
+        let live = parse_iptables_save(dump, ChainFamily::Ipv4);
+        assert_eq!(
+            live,
+            vec![
+                "nat: -A OUTPUT -j TORRER_NAT",
+                "nat: -A TORRER_NAT -m owner --uid-owner 105 -j RETURN",
+                "filter: -A OUTPUT -j TORRER_FILTER",
+                "filter: -A TORRER_FILTER -m owner --uid-owner 105 -j RETURN",
+            ]
+        );
+
+        // The parsed live rules line up with the planned ones
+        let planned = iptables_lines(&planned_rules());
+        assert!(live.iter().all(|line| planned.contains(line)));
+        let diff = plan.diff(&live);
+        assert!(diff.iter().all(|line| !matches!(line, DiffLine::Removed(_))));
+        assert!(diff.contains(&DiffLine::Added(
+            "filter: -A TORRER_FILTER -m comment --comment torrer-kill-switch -j DROP".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_nft_lines_normalize_listing() {
+        let rules = planned_rules();
+        let planned = nft_lines(&render_ruleset(&rules));
+        assert_eq!(planned[0], "chain output_nat {");
+        assert!(planned.contains(&"meta skuid 105 return".to_string()));
+        assert!(!planned.iter().any(|line| line.contains("delete")));
+
+        let listed = "table inet torrer {\n\tchain output_nat {\n\t\ttype nat hook output priority dstnat; policy accept;\n\t\tmeta nfproto ipv4 tcp flags & (fin | syn | rst | ack) == syn redirect to :9040\n\t}\n}\n";
+        assert_eq!(
+            nft_lines(listed),
+            vec![
+                "chain output_nat {",
+                "type nat hook output priority dstnat; policy accept;",
+                "meta nfproto ipv4 tcp flags & (fin|syn|rst|ack) == syn redirect to :9040",
+                "}",
+            ]
+        );
+
+        // Newer nft lists SYN matches and ICMP rejects differently
+        let listed = "table inet torrer {\n\t\tmeta nfproto ipv4 tcp flags syn / fin,syn,rst,ack redirect to :9040\n\t\tmeta nfproto ipv6 oifname != \"lo\" reject with icmpv6 type admin-prohibited\n}\n";
+        assert_eq!(
+            nft_lines(listed),
+            vec![
+                "meta nfproto ipv4 tcp flags & (fin|syn|rst|ack) == syn redirect to :9040",
+                "meta nfproto ipv6 oifname != \"lo\" reject with icmpv6 admin-prohibited",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = |items: &[&str]| items.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let diff = diff_lines(&lines(&["a", "b", "c"]), &lines(&["a", "c", "d"]));
+        assert_eq!(
+            diff,
+            vec![
+                DiffLine::Same("a".to_string()),
+                DiffLine::Removed("b".to_string()),
+                DiffLine::Same("c".to_string()),
+                DiffLine::Added("d".to_string()),
+            ]
+        );
+        assert_eq!(diff[1].to_string(), "- b");
+        assert!(diff_lines(&lines(&["a"]), &lines(&["a"])).iter().all(|l| !l.is_change()));
+
+        let empty = FirewallPlan::new(FirewallBackendKind::Nftables, RoutingRules::default());
+        assert!(empty.planned.is_empty());
+    }
+
+    #[test]
+    fn test_network_range_clears_host_bits() {
+        assert_eq!(network_range("192.168.1.7/24").unwrap(), ("192.168.1.0".parse().unwrap(), 24));
+        assert_eq!(network_range("fd00::1/8").unwrap(), ("fd00::".parse().unwrap(), 8));
+        assert_eq!(network_range("10.1.2.3/0").unwrap().0.to_string(), "0.0.0.0");
+    }
+}
@@ -0,0 +1,132 @@
+// Unit tests for gateway mode
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use torrer::config::{validate_config, Configuration, GatewayConfig};
+    use torrer::iptables::plan::{iptables_lines, parse_iptables_save};
+    use torrer::iptables::rules::{GATEWAY_FORWARD_CHAIN, GATEWAY_INPUT_CHAIN, GATEWAY_NAT_CHAIN};
+    use torrer::iptables::{chain_rules, render_ruleset, restore_script, ChainFamily, RoutingRules};
+    use torrer::tor::commands::build_setconf_values;
+    use torrer::utils::parse_interface_ipv4;
+
+    fn gateway_config(clients: &[&str]) -> Configuration {
+        Configuration {
+            gateway: Some(GatewayConfig {
+                interface: "eth1".to_string(),
+                clients: clients.iter().map(|c| c.to_string()).collect(),
+            }),
+            ..Configuration::default()
+        }
+    }
+
+    fn lines(rules: &RoutingRules, chain: &str) -> Vec<String> {
+        chain_rules(rules, ChainFamily::Gateway)
+            .iter()
+            .filter(|rule| rule.chain == chain)
+            .map(|rule| rule.rule.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_gateway_configuration() {
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9040\ntor_dns_port = 5353\nipv6_enabled = false\nauto_fallback = true\n\n[gateway]\ninterface = \"eth1\"\nclients = [\"192.168.50.0/24\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.gateway.unwrap().clients, vec!["192.168.50.0/24"]);
+        assert!(Configuration::default().gateway.is_none());
+
+        assert!(validate_config(&gateway_config(&["192.168.50.0/24"])).is_ok());
+        assert!(validate_config(&gateway_config(&["fd00::/8"])).is_err());
+        let mut config = gateway_config(&[]);
+        config.gateway.as_mut().unwrap().interface = "eth1; reboot".to_string();
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_gateway_iptables_chains() {
+        let rules = RoutingRules::planned(&gateway_config(&["192.168.50.7/24"]));
+        assert!(rules.serves_gateway());
+
+        assert_eq!(
+            lines(&rules, GATEWAY_NAT_CHAIN),
+            vec![
+                "-s 192.168.50.0/24 -i eth1 -p udp -m udp --dport 53 -j REDIRECT --to-ports 5353",
+                "-s 192.168.50.0/24 -i eth1 -p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j REDIRECT --to-ports 9040",
+            ]
+        );
+        assert_eq!(lines(&rules, GATEWAY_FORWARD_CHAIN), vec!["-i eth1 -j DROP", "-o eth1 -j DROP"]);
+        assert_eq!(
+            lines(&rules, GATEWAY_INPUT_CHAIN).last().unwrap(),
+            "-i eth1 -p udp -m udp --dport 5353 -j DROP"
+        );
+
+        let script = restore_script(
+            &chain_rules(&rules, ChainFamily::Gateway),
+            ChainFamily::Gateway,
+            &[GATEWAY_NAT_CHAIN, GATEWAY_FORWARD_CHAIN],
+        );
+        assert!(script.contains("-I PREROUTING 1 -j TORRER_GW_NAT\n"));
+        assert!(script.contains("-I FORWARD 1 -j TORRER_GW_FORWARD\n"));
+        assert!(!script.contains("-I INPUT 1"));
+
+        // Without client ranges the whole interface is served, unguarded
+        let open = RoutingRules::planned(&gateway_config(&[]));
+        assert!(lines(&open, GATEWAY_NAT_CHAIN)[0].starts_with("-i eth1 -p udp"));
+        assert!(lines(&open, GATEWAY_INPUT_CHAIN).is_empty());
+    }
+
+    #[test]
+    fn test_gateway_plan_matches_iptables_save() {
+        let rules = RoutingRules::planned(&gateway_config(&["192.168.50.0/24"]));
+        let dump = "\
+*filter
+:FORWARD DROP [0:0]
+:TORRER_GW_FORWARD - [0:0]
+:TORRER_GW_INPUT - [0:0]
+-A INPUT -j TORRER_GW_INPUT
+-A FORWARD -j TORRER_GW_FORWARD
+-A TORRER_GW_FORWARD -i eth1 -j DROP
+COMMIT
+";
+        let live = parse_iptables_save(dump, ChainFamily::Gateway);
+        assert_eq!(
+            live,
+            vec![
+                "filter: -A FORWARD -j TORRER_GW_FORWARD",
+                "filter: -A TORRER_GW_FORWARD -i eth1 -j DROP",
+                "filter: -A INPUT -j TORRER_GW_INPUT",
+            ]
+        );
+        let planned = iptables_lines(&rules);
+        assert!(live.iter().all(|line| planned.contains(line)));
+        assert!(planned.contains(&"nat: -A PREROUTING -j TORRER_GW_NAT".to_string()));
+    }
+
+    #[test]
+    fn test_gateway_nft_chains() {
+        let script = render_ruleset(&RoutingRules::planned(&gateway_config(&["192.168.50.0/24"])));
+        assert!(script.contains("type nat hook prerouting priority dstnat;"));
+        assert!(script.contains("iifname \"eth1\" ip saddr 192.168.50.0/24 udp dport 53 redirect to :5353"));
+        assert!(script.contains("type filter hook forward priority filter;"));
+        assert!(script.contains("\t\tiifname \"eth1\" drop\n\t\toifname \"eth1\" drop\n"));
+        assert!(script.contains("iifname \"eth1\" tcp dport 9040 drop"));
+
+        assert!(!render_ruleset(&RoutingRules::planned(&Configuration::default())).contains("prerouting"));
+    }
+
+    #[test]
+    fn test_lan_listeners() {
+        let output = "3: eth1    inet 192.168.50.1/24 brd 192.168.50.255 scope global eth1\\       valid_lft forever preferred_lft forever\n";
+        assert_eq!(parse_interface_ipv4(output), Some(Ipv4Addr::new(192, 168, 50, 1)));
+        assert_eq!(parse_interface_ipv4(""), None);
+
+        let command = build_setconf_values(&[
+            ("TransPort", "127.0.0.1:9040".to_string()),
+            ("TransPort", "192.168.50.1:9040".to_string()),
+        ]);
+        assert_eq!(command, "SETCONF TransPort=127.0.0.1:9040 TransPort=192.168.50.1:9040\r\n");
+    }
+}
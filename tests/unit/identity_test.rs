@@ -0,0 +1,114 @@
+// Unit tests for identity rotation
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    use torrer::core::identity::{parse_newnym_delay, IdentityRotator, NEWNYM_INTERVAL};
+    use torrer::core::RateLimiter;
+    use torrer::tor::{ControlEndpoint, TorClient};
+
+    #[test]
+    fn test_parse_newnym_delay() {
+        assert_eq!(
+            parse_newnym_delay("Rate limiting NEWNYM request: delaying by 7 second(s)"),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(parse_newnym_delay("Bootstrapped 100% (done): Done"), None);
+    }
+
+    #[test]
+    fn test_rate_limiter_time_until_allowed() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(10));
+        assert_eq!(limiter.time_until_allowed("newnym"), Duration::ZERO);
+
+        assert!(limiter.check("newnym"));
+        let wait = limiter.time_until_allowed("newnym");
+        assert!(wait > Duration::from_secs(9) && wait <= Duration::from_secs(10));
+
+        limiter.reset("newnym");
+        assert_eq!(limiter.time_until_allowed("newnym"), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_reports_tor_delay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // Fake Tor that postpones the signal by one second
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = if line == "SIGNAL NEWNYM" {
+                    b"250 OK\r\n650 NOTICE Rate limiting NEWNYM request: delaying by 1 second(s)\r\n"
+                } else {
+                    b"250 OK\r\n"
+                };
+                write_half.write_all(reply).await.unwrap();
+            }
+        });
+
+        let mut client = TorClient::with_endpoint(ControlEndpoint::unix(&path));
+        client.connect().await.unwrap();
+        let mut rotator = IdentityRotator::new(client).await;
+
+        let rotation = rotator.rotate().await.unwrap();
+        assert_eq!(rotation.delayed_by, Duration::from_secs(1));
+        assert!(rotation.effective_at >= rotation.requested_at + 1);
+
+        // Torrer itself will not send another NEWNYM inside Tor's interval
+        let wait = rotator.time_until_allowed();
+        assert!(wait > Duration::ZERO && wait <= NEWNYM_INTERVAL);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_reconnects_after_tor_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        // Fake Tor that restarts after the first subscription
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            lines.next_line().await.unwrap();
+            write_half.write_all(b"250 OK\r\n").await.unwrap();
+            drop((lines, write_half));
+
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read_half, mut write_half) = stream.into_split();
+            let mut lines = BufReader::new(read_half).lines();
+            let mut received = Vec::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply: &[u8] = if line == "PROTOCOLINFO 1" {
+                    b"250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250-VERSION Tor=\"0.4.8.9\"\r\n250 OK\r\n"
+                } else {
+                    b"250 OK\r\n"
+                };
+                write_half.write_all(reply).await.unwrap();
+                let done = line == "SIGNAL NEWNYM";
+                received.push(line);
+                if done {
+                    break;
+                }
+            }
+            received
+        });
+
+        let mut client = TorClient::with_endpoint(ControlEndpoint::unix(&path));
+        client.connect().await.unwrap();
+        let mut rotator = IdentityRotator::new(client).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        rotator.rotate().await.unwrap();
+        let received = server.await.unwrap();
+        assert_eq!(received.first().map(String::as_str), Some("PROTOCOLINFO 1"));
+        assert!(received.iter().any(|line| line == "SETEVENTS NOTICE"));
+        assert_eq!(received.last().map(String::as_str), Some("SIGNAL NEWNYM"));
+    }
+}
@@ -2,7 +2,12 @@
 
 #[cfg(test)]
 mod tests {
-    use torrer::iptables::IptablesManager;
+    use torrer::iptables::rules::{FILTER_CHAIN, NAT_CHAIN};
+    use torrer::iptables::rules::Rule;
+    use torrer::iptables::{
+        chain_rules, merge_scripts, remove_script, restore_script, ChainFamily, IptablesManager, RoutingPorts,
+        RoutingRules,
+    };
 
     #[test]
     fn test_iptables_manager_creation() {
@@ -12,6 +17,146 @@ mod tests {
         let _ = manager;
     }
 
+    fn all_rules() -> RoutingRules {
+        RoutingRules {
+            ports: RoutingPorts::default(),
+            tor_routing: true,
+            dns_redirect: true,
+            block_ipv6: true,
+            tor_uid: Some(105),
+            ..RoutingRules::default()
+        }
+    }
+
+    #[test]
+    fn test_chain_rules_put_exclusions_first() {
+        let rules = chain_rules(&all_rules(), ChainFamily::Ipv4);
+        let nat: Vec<String> = rules
+            .iter()
+            .filter(|rule| rule.chain == NAT_CHAIN)
+            .map(|rule| rule.rule.join(" "))
+            .collect();
+
+        assert_eq!(
+            nat,
+            vec![
+                "-m owner --uid-owner 105 -j RETURN",
+                "-p udp -m udp --dport 53 -j REDIRECT --to-ports 5353",
+                "-p tcp -m multiport --dports 53,853 -j RETURN",
+                "-o lo -j RETURN",
+                "-p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j REDIRECT --to-ports 9040",
+            ]
+        );
+        assert!(rules.iter().all(|rule| rule.chain == NAT_CHAIN || rule.chain == FILTER_CHAIN));
+    }
+
+    #[test]
+    fn test_restore_script_only_touches_torrer_chains() {
+        let rules = all_rules();
+        let script = restore_script(&chain_rules(&rules, ChainFamily::Ipv4), ChainFamily::Ipv4, &[]);
+
+        assert!(script.starts_with("*nat\n:TORRER_NAT - [0:0]\n-A TORRER_NAT -m owner"));
+        assert!(script.contains("*filter\n:TORRER_FILTER - [0:0]\n"));
+        assert!(script.contains("-A TORRER_FILTER ! -o lo -p udp -m udp --dport 53 -j DROP\n"));
+        assert_eq!(script.matches("COMMIT\n").count(), 2);
+        assert!(!script.contains("-F"));
+        assert!(!script.contains("OUTPUT"));
+
+        let ipv6 = restore_script(&chain_rules(&rules, ChainFamily::Ipv6), ChainFamily::Ipv6, &[]);
+        assert_eq!(
+            ipv6,
+            "*filter\n:TORRER_FILTER - [0:0]\n-A TORRER_FILTER -m owner --uid-owner 105 -j RETURN\n-A TORRER_FILTER ! -o lo -j DROP\nCOMMIT\n"
+        );
+
+        // Missing hooks go in with the chain they jump to
+        let hooked = restore_script(&chain_rules(&rules, ChainFamily::Ipv4), ChainFamily::Ipv4, &["TORRER_NAT"]);
+        assert!(hooked.contains("-I OUTPUT 1 -j TORRER_NAT\nCOMMIT\n*filter\n"));
+        assert_eq!(hooked.matches("OUTPUT").count(), 1);
+    }
+
+    fn chain(rules: &[Rule], chain: &str) -> Vec<String> {
+        rules
+            .iter()
+            .filter(|rule| rule.chain == chain)
+            .map(|rule| rule.rule.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_remove_script_unhooks_existing_chains() {
+        let script = remove_script(ChainFamily::Ipv4, &[("TORRER_NAT", 2)]);
+        assert_eq!(
+            script,
+            "*nat\n:TORRER_NAT - [0:0]\n-D OUTPUT -j TORRER_NAT\n-D OUTPUT -j TORRER_NAT\n-X TORRER_NAT\nCOMMIT\n"
+        );
+        assert!(remove_script(ChainFamily::Gateway, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_merged_script_commits_each_table_once() {
+        let rules = all_rules();
+        let script = merge_scripts(&[
+            restore_script(&chain_rules(&rules, ChainFamily::Ipv4), ChainFamily::Ipv4, &["TORRER_NAT"]),
+            remove_script(ChainFamily::Gateway, &[("TORRER_GW_NAT", 1), ("TORRER_GW_INPUT", 0)]),
+        ]);
+
+        assert_eq!(script.matches("COMMIT\n").count(), 2);
+        assert_eq!(script.matches("*filter\n").count(), 1);
+        // Declarations come before any rule of their table
+        assert!(script.starts_with("*nat\n:TORRER_NAT - [0:0]\n:TORRER_GW_NAT - [0:0]\n-A TORRER_NAT"));
+        assert!(script.contains("-I OUTPUT 1 -j TORRER_NAT\n-D PREROUTING -j TORRER_GW_NAT\n-X TORRER_GW_NAT\nCOMMIT\n"));
+        assert!(script.ends_with("-X TORRER_GW_INPUT\nCOMMIT\n"));
+        assert!(merge_scripts(&[String::new()]).is_empty());
+    }
+
+    #[test]
+    fn test_kill_switch_drops_everything_else() {
+        let rules = RoutingRules {
+            tor_routing: true,
+            kill_switch: true,
+            lan_ranges: vec!["192.168.0.0/16".to_string(), "fe80::/10".to_string()],
+            tor_uid: Some(105),
+            tor_gid: Some(110),
+            ..RoutingRules::default()
+        };
+
+        assert_eq!(
+            chain(&chain_rules(&rules, ChainFamily::Ipv4), FILTER_CHAIN),
+            vec![
+                "-m owner --uid-owner 105 -j RETURN",
+                "-m owner --gid-owner 110 -j RETURN",
+                "-m conntrack --ctstate DNAT -j RETURN",
+                "-o lo -j RETURN",
+                "-d 192.168.0.0/16 -j RETURN",
+                "-m comment --comment torrer-kill-switch -j DROP",
+            ]
+        );
+
+        // The kill switch also closes IPv6, with IPv6 LAN ranges allowed
+        assert_eq!(
+            chain(&chain_rules(&rules, ChainFamily::Ipv6), FILTER_CHAIN),
+            vec![
+                "-m owner --uid-owner 105 -j RETURN",
+                "-m owner --gid-owner 110 -j RETURN",
+                "-o lo -j RETURN",
+                "-d fe80::/10 -j RETURN",
+                "-m comment --comment torrer-kill-switch -j DROP",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_clear_keeps_kill_switch() {
+        let mut rules = all_rules();
+        rules.kill_switch = true;
+        rules.clear();
+
+        assert!(!rules.tor_routing && !rules.dns_redirect && !rules.block_ipv6);
+        assert!(rules.kill_switch);
+        assert!(!rules.is_empty());
+        assert!(rules.blocks_ipv6());
+    }
+
     // Note: Actual iptables operations require root access
     // and should be tested in integration tests
 }
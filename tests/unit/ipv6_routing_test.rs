@@ -0,0 +1,103 @@
+// Unit tests for routing IPv6 through Tor
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use torrer::config::Configuration;
+    use torrer::iptables::plan::iptables_lines;
+    use torrer::iptables::{chain_rules, render_ruleset, ChainFamily, RoutingRules};
+    use torrer::tor::commands::{build_setconf_values, listener_values};
+    use torrer::tor::ManagedTorConfig;
+
+    fn routed_rules() -> RoutingRules {
+        let config = Configuration {
+            ipv6_enabled: true,
+            ..Configuration::default()
+        };
+        RoutingRules {
+            tor_uid: Some(105),
+            tor_gid: None,
+            ..RoutingRules::planned(&config)
+        }
+    }
+
+    fn lines(rules: &RoutingRules, family: ChainFamily) -> Vec<String> {
+        chain_rules(rules, family).iter().map(|rule| rule.rule.join(" ")).collect()
+    }
+
+    #[test]
+    fn test_planned_rules_route_ipv6() {
+        let rules = routed_rules();
+        assert!(rules.routes_ipv6() && !rules.block_ipv6);
+        assert!(!RoutingRules::planned(&Configuration::default()).routes_ipv6());
+
+        // Blocking wins when both are set
+        let blocked = RoutingRules { block_ipv6: true, ..routed_rules() };
+        assert!(!blocked.routes_ipv6());
+        assert!(lines(&blocked, ChainFamily::Ipv6Routing).is_empty());
+    }
+
+    #[test]
+    fn test_ipv6_iptables_chains() {
+        let rules = routed_rules();
+        assert_eq!(
+            lines(&rules, ChainFamily::Ipv6Routing),
+            vec![
+                "-d fc00::/7 -p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j REDIRECT --to-ports 9040",
+                "-m owner --uid-owner 105 -j RETURN",
+                "-p udp -m udp --dport 53 -j REDIRECT --to-ports 5353",
+                "-p tcp -m multiport --dports 53,853 -j RETURN",
+                "-o lo -j RETURN",
+                "-p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j REDIRECT --to-ports 9040",
+            ]
+        );
+        assert_eq!(
+            lines(&rules, ChainFamily::Ipv6),
+            vec![
+                "-m owner --uid-owner 105 -j RETURN",
+                "-m conntrack --ctstate DNAT -j RETURN",
+                "! -o lo -j REJECT --reject-with icmp6-adm-prohibited",
+            ]
+        );
+
+        let planned = iptables_lines(&rules);
+        assert!(planned.contains(&"ip6 nat: -A OUTPUT -j TORRER_NAT".to_string()));
+        assert!(planned.contains(&"ip6 filter: -A OUTPUT -j TORRER_FILTER".to_string()));
+
+        // The kill switch drops instead, after letting redirected traffic through
+        let kill_switch = RoutingRules { kill_switch: true, ..routed_rules() };
+        let filter = lines(&kill_switch, ChainFamily::Ipv6);
+        assert_eq!(filter[1], "-m conntrack --ctstate DNAT -j RETURN");
+        assert!(!filter.iter().any(|rule| rule.contains("REJECT")));
+    }
+
+    #[test]
+    fn test_ipv6_nft_ruleset() {
+        let script = render_ruleset(&routed_rules());
+        assert!(script.contains("meta nfproto ipv6 udp dport 53 redirect to :5353"));
+        assert!(script.contains("meta nfproto ipv6 tcp flags & (fin|syn|rst|ack) == syn redirect to :9040"));
+        assert!(script.contains("meta nfproto ipv6 oifname != \"lo\" reject with icmpv6 admin-prohibited"));
+        assert!(!script.contains("meta nfproto ipv6 oifname != \"lo\" drop"));
+        assert_eq!(script.matches("oifname \"lo\" return").count(), 1);
+    }
+
+    #[test]
+    fn test_ipv6_listeners() {
+        let addresses = [IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V6(Ipv6Addr::LOCALHOST)];
+        let values = listener_values(&addresses, 9040, 5353, true);
+        assert_eq!(values[1], ("TransPort", "[::1]:9040 IPv6Traffic PreferIPv6".to_string()));
+        assert_eq!(values[3].0, "DNSPort");
+        assert_eq!(
+            build_setconf_values(&values[..1]),
+            "SETCONF TransPort=\"127.0.0.1:9040 IPv6Traffic PreferIPv6\"\r\n"
+        );
+
+        let mut managed = ManagedTorConfig::new("/var/lib/torrer/tor");
+        assert!(managed.torrc().contains("TransPort 127.0.0.1:9040\n"));
+        managed.ipv6 = true;
+        let torrc = managed.torrc();
+        assert!(torrc.contains("TransPort [::1]:9040 IPv6Traffic PreferIPv6\n"));
+        assert!(torrc.contains("DNSPort [::1]:5353 IPv6Traffic PreferIPv6\n"));
+    }
+}
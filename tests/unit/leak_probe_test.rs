@@ -0,0 +1,171 @@
+// Unit tests for leak-test probes and their classification
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use torrer::config::{BypassConfig, Configuration, UdpConfig, UdpPolicy};
+    use torrer::iptables::{FirewallBackendKind, FirewallPlan, RoutingRules};
+    use torrer::security::leak_probe::{
+        find_token, redirected, rejected, stand_in_reply, stun_mapped_address, stun_request, DNS_PROBE_PORT, STUN_PROBE_PORT,
+    };
+    use torrer::security::dns_server::{build_query, Query, TYPE_A};
+    use torrer::security::{
+        LeakProbe, LeakTarget, ProbeKind, ProbeOutcome, ProbeVerdict, RulesetInspection, StandInLog,
+    };
+
+    fn address(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_classify() {
+        use ProbeOutcome::*;
+
+        // Whatever the socket saw, reaching the stand-in is a leak
+        assert_eq!(ProbeVerdict::classify(Answered, Some(true), true, false), ProbeVerdict::Leaked);
+        assert_eq!(ProbeVerdict::classify(Failed, None, true, false), ProbeVerdict::Blocked);
+        assert_eq!(ProbeVerdict::classify(Answered, Some(false), true, false), ProbeVerdict::ViaTor);
+        assert_eq!(ProbeVerdict::classify(Answered, None, true, false), ProbeVerdict::ViaTor);
+        assert_eq!(ProbeVerdict::classify(NoAnswer, Some(false), false, false), ProbeVerdict::Blocked);
+        // Without a stand-in, unanswered datagrams may still have left
+        assert_eq!(ProbeVerdict::classify(NoAnswer, None, false, false), ProbeVerdict::Leaked);
+        assert_eq!(ProbeVerdict::classify(Answered, None, false, false), ProbeVerdict::Leaked);
+        // Port unreachable is only Torrer's when its REJECT rule applies
+        assert_eq!(ProbeVerdict::classify(Refused, None, false, true), ProbeVerdict::Blocked);
+        assert_eq!(ProbeVerdict::classify(Refused, None, false, false), ProbeVerdict::Leaked);
+    }
+
+    #[test]
+    fn test_rejected_follows_udp_policy() {
+        let reject = |allow: &[&str]| {
+            let config = Configuration {
+                udp: UdpConfig {
+                    policy: UdpPolicy::Reject,
+                    allow: allow.iter().map(|entry| entry.to_string()).collect(),
+                },
+                ..Configuration::default()
+            };
+            RoutingRules::planned(&config)
+        };
+
+        let target = address("1.1.1.1");
+        assert!(rejected(&reject(&[]), ProbeKind::Udp, target));
+        assert!(rejected(&reject(&[]), ProbeKind::Stun, target));
+        assert!(!rejected(&reject(&["3478"]), ProbeKind::Stun, target));
+        assert!(!rejected(&reject(&["1.1.1.0/24:3478"]), ProbeKind::Stun, target));
+        // An exception for another host still leaves the probe rejected
+        assert!(rejected(&reject(&["192.0.2.1:3478"]), ProbeKind::Stun, target));
+        assert!(!rejected(&reject(&[]), ProbeKind::Tcp, target));
+        assert!(!rejected(&RoutingRules::planned(&Configuration::default()), ProbeKind::Udp, target));
+        assert!(!rejected(&RoutingRules::default(), ProbeKind::Udp, target));
+    }
+
+    #[test]
+    fn test_redirected_follows_rules() {
+        let config = Configuration {
+            bypass: BypassConfig {
+                local_networks: true,
+                ..BypassConfig::default()
+            },
+            ..Configuration::default()
+        };
+        let rules = RoutingRules::planned(&config);
+
+        assert!(redirected(&rules, ProbeKind::Tcp, address("1.1.1.1")));
+        assert!(redirected(&rules, ProbeKind::Dns, address("1.1.1.1")));
+        assert!(!redirected(&rules, ProbeKind::Udp, address("1.1.1.1")));
+        assert!(!redirected(&rules, ProbeKind::Stun, address("1.1.1.1")));
+        assert!(!redirected(&rules, ProbeKind::DnsOverTls, address("1.1.1.1")));
+        // Bypassed, except for the onion virtual network
+        assert!(!redirected(&rules, ProbeKind::Tcp, address("10.0.0.1")));
+        assert!(redirected(&rules, ProbeKind::Tcp, address("10.192.0.1")));
+        // IPv6 is blocked by default
+        assert!(!redirected(&rules, ProbeKind::Ipv6, address("2606:4700:4700::1111")));
+
+        assert!(!redirected(&RoutingRules::default(), ProbeKind::Tcp, address("1.1.1.1")));
+    }
+
+    #[test]
+    fn test_probe_plan() {
+        let probes = LeakProbe::plan(&LeakTarget::default());
+        assert_eq!(probes.len(), ProbeKind::ALL.len());
+        assert_eq!(probes[0].target(), "1.1.1.1:80");
+        assert_eq!(probes[2].target(), "1.1.1.1");
+        assert_eq!(probes[3].target(), "[2606:4700:4700::1111]:80");
+        assert_ne!(probes[0].token, probes[1].token);
+
+        let no_ipv6 = LeakTarget { ipv6: None, ..LeakTarget::default() };
+        assert!(LeakProbe::plan(&no_ipv6)[3].address.is_none());
+    }
+
+    #[test]
+    fn test_stun_round_trip() {
+        let probe = LeakProbe::new(ProbeKind::Stun, None);
+        let request = stun_request(&[7; 12], &probe.token);
+        assert_eq!(request.len() % 4, 0);
+        assert_eq!(find_token(&request).as_deref(), Some(probe.token.as_str()));
+
+        for peer in ["198.18.0.1:40000", "[2001:db8:7e57::1]:40000"] {
+            let peer: SocketAddr = peer.parse().unwrap();
+            let response = stand_in_reply(STUN_PROBE_PORT, &request, peer).unwrap();
+            assert_eq!(&response[8..20], &[7; 12]);
+            assert_eq!(stun_mapped_address(&response), Some(peer));
+        }
+        assert!(stun_mapped_address(&request).is_none());
+    }
+
+    #[test]
+    fn test_stand_in_dns_and_log() {
+        let probe = LeakProbe::new(ProbeKind::Dns, None);
+        let query = build_query(9, &format!("{}.leak-test.invalid", probe.token), TYPE_A);
+        let token = find_token(&query).unwrap();
+        assert_eq!(token, probe.token);
+
+        let peer: SocketAddr = "198.18.0.1:5000".parse().unwrap();
+        let response = stand_in_reply(DNS_PROBE_PORT, &query, peer).unwrap();
+        assert_eq!(&response[..2], &[0, 9]);
+        assert!(Query::parse(&response).is_err());
+
+        let log = StandInLog::new();
+        log.record(&format!("udp 53 {}", token));
+        log.record("tcp 80 -");
+        assert!(log.saw(&probe.token));
+        assert!(!log.saw("-"));
+    }
+
+    #[test]
+    fn test_ruleset_inspection() {
+        let rules = RoutingRules::planned(&Configuration::default());
+        let plan = FirewallPlan::new(FirewallBackendKind::Nftables, rules);
+
+        let inspection = RulesetInspection::compare(&plan, &plan.planned);
+        assert!(inspection.matches_plan());
+        assert_eq!(inspection.loaded, plan.planned.len());
+
+        let partial = RulesetInspection::compare(&plan, &plan.planned[1..]);
+        assert!(partial.active() && !partial.matches_plan());
+        assert_eq!(partial.missing, vec![plan.planned[0].clone()]);
+
+        assert!(!RulesetInspection::compare(&plan, &[]).active());
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_carries_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let probe = LeakProbe::new(ProbeKind::Tcp, Some(listener.local_addr().unwrap()));
+
+        let (outcome, _) = probe.send().await;
+        assert_eq!(outcome, ProbeOutcome::Answered);
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = [0u8; 128];
+        let length = stream.read(&mut buffer).await.unwrap();
+        assert_eq!(find_token(&buffer[..length]), Some(probe.token.clone()));
+
+        // A target without IPv6 has nowhere to send the IPv6 probe
+        let unreachable = LeakProbe::new(ProbeKind::Ipv6, None);
+        assert_eq!(unreachable.send().await.0, ProbeOutcome::Failed);
+    }
+}
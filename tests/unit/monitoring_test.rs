@@ -0,0 +1,62 @@
+// Unit tests for bandwidth monitoring
+
+#[cfg(test)]
+mod tests {
+    use torrer::core::monitoring::BandwidthHistory;
+    use torrer::core::Monitoring;
+    use torrer::tor::protocol::parse_response;
+    use torrer::tor::TorEvent;
+
+    fn event(line: &str) -> TorEvent {
+        TorEvent::parse(&parse_response(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_history_aggregates_minutes() {
+        let mut history = BandwidthHistory::default();
+        for second in 0..120 {
+            history.record(6000 + second, 10, 5);
+        }
+
+        assert_eq!(history.per_second().count(), 120);
+        let minutes: Vec<_> = history.per_minute().collect();
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].timestamp, 6000);
+        assert_eq!(minutes[0].read, 600);
+        assert_eq!(minutes[1].written, 300);
+    }
+
+    #[test]
+    fn test_history_keeps_last_hour() {
+        let mut history = BandwidthHistory::default();
+        for second in 0..4000 {
+            history.record(second, 1, 1);
+        }
+
+        assert_eq!(history.per_second().count(), 3600);
+        assert_eq!(history.per_second().next().unwrap().timestamp, 400);
+    }
+
+    #[test]
+    fn test_monitoring_counts_bw_events() {
+        let mut monitoring = Monitoring::new();
+        monitoring.handle_tor_event(&event("650 BW 1000 250\r\n"));
+        monitoring.handle_tor_event(&event("650 BW 500 50\r\n"));
+
+        let stats = monitoring.get_stats();
+        assert_eq!(stats.bytes_received, 1500);
+        assert_eq!(stats.bytes_sent, 300);
+        assert_eq!((stats.read_rate, stats.write_rate), (500, 50));
+    }
+
+    #[test]
+    fn test_monitoring_tracks_open_streams() {
+        let mut monitoring = Monitoring::new();
+        monitoring.handle_tor_event(&event("650 STREAM_BW 7 100 900 2024-01-01T00:00:00.000000\r\n"));
+        assert_eq!(monitoring.stream_bandwidth()["7"].read, 900);
+        assert_eq!(monitoring.stream_bandwidth()["7"].written, 100);
+
+        monitoring.handle_tor_event(&event("650 STREAM 7 CLOSED 3 example.com:443\r\n"));
+        assert!(monitoring.stream_bandwidth().is_empty());
+    }
+}
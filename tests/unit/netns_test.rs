@@ -0,0 +1,58 @@
+// Unit tests for torrer exec namespaces
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use torrer::core::NamespaceLayout;
+    use torrer::iptables::{insert_script, namespace_rules, render_namespace_table, RoutingPorts};
+
+    #[test]
+    fn test_namespace_layout() {
+        let layout = NamespaceLayout::new(4194304);
+        assert_eq!(layout.name, "torrer-4194304");
+        assert!(layout.host_veth.len() <= 15 && layout.ns_veth.len() <= 15);
+        assert_ne!(layout.host_veth, layout.ns_veth);
+        assert_eq!(layout.resolv_conf(), format!("nameserver {}\n", layout.host_addr));
+
+        let layout = NamespaceLayout::new(3);
+        assert_eq!(layout.host_addr, Ipv4Addr::new(10, 200, 0, 13));
+        assert_eq!(layout.ns_addr, Ipv4Addr::new(10, 200, 0, 14));
+
+        // IDs wrap around inside 10.200.0.0/16
+        let last = NamespaceLayout::new(16383);
+        assert_eq!(last.ns_addr, Ipv4Addr::new(10, 200, 255, 254));
+        assert_eq!(NamespaceLayout::new(16384).host_addr, Ipv4Addr::new(10, 200, 0, 1));
+    }
+
+    #[test]
+    fn test_namespace_rules_script() {
+        let rules = namespace_rules("tx42h", &RoutingPorts::default());
+        let script = insert_script(&rules, false);
+
+        assert!(script.starts_with("*nat\n-I PREROUTING 1 -i tx42h -p udp -m udp --dport 53 -j DNAT --to-destination 127.0.0.1:5353\n"));
+        assert!(script.contains("-I PREROUTING 2 -i tx42h -p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j DNAT --to-destination 127.0.0.1:9040\n"));
+        assert!(script.contains("*filter\n-I INPUT 1 -i tx42h -m conntrack --ctstate DNAT -j ACCEPT\n-I INPUT 2 -i tx42h -j DROP\n-I FORWARD 1 -i tx42h -j DROP\n"));
+        assert_eq!(script.matches("COMMIT\n").count(), 2);
+
+        let delete = insert_script(&rules, true);
+        assert!(delete.contains("-D INPUT -i tx42h -j DROP\n"));
+        assert!(!delete.contains("-I "));
+    }
+
+    #[test]
+    fn test_namespace_nft_table() {
+        let ports = RoutingPorts { trans_port: 9041, dns_port: 5354 };
+        let script = render_namespace_table("tx42h", &ports, false);
+
+        assert!(script.starts_with("table ip torrer_tx42h\ndelete table ip torrer_tx42h\ntable ip torrer_tx42h {\n"));
+        assert!(script.contains("iifname \"tx42h\" udp dport 53 dnat to 127.0.0.1:5354"));
+        assert!(script.contains("dnat to 127.0.0.1:9041"));
+        assert!(script.contains("iifname \"tx42h\" ct status dnat accept\n\t\tiifname \"tx42h\" drop"));
+
+        assert_eq!(
+            render_namespace_table("tx42h", &ports, true),
+            "table ip torrer_tx42h\ndelete table ip torrer_tx42h\n"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+// Unit tests for reaching onion services through transparent routing
+
+#[cfg(test)]
+mod tests {
+    use torrer::config::{validate_config, BypassConfig, Configuration, OnionConfig};
+    use torrer::iptables::rules::NAT_CHAIN;
+    use torrer::iptables::{chain_rules, render_ruleset, ChainFamily, RoutingRules};
+    use torrer::tor::commands::{automap_values, build_resetconf, build_setconf_values, AUTOMAP_OPTIONS};
+    use torrer::tor::ManagedTorConfig;
+    use torrer::utils::parse_onion_address;
+
+    const ONION: &str = "duckduckgogg42xjoc72x3sjasowoarfbgcmvfimaftt6twagswzczad.onion";
+
+    fn nat(rules: &RoutingRules) -> Vec<String> {
+        chain_rules(rules, ChainFamily::Ipv4)
+            .iter()
+            .filter(|rule| rule.chain == NAT_CHAIN)
+            .map(|rule| rule.rule.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_onion_configuration() {
+        let config = Configuration::default();
+        assert!(config.onion.automap);
+        assert_eq!(config.onion.virtual_networks(), ["10.192.0.0/10", "fc00::/7"]);
+
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9040\ntor_dns_port = 5353\nipv6_enabled = false\nauto_fallback = true\n\n[onion]\nvirtual_network_ipv4 = \"10.64.0.0/10\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.onion.virtual_network_ipv4, "10.64.0.0/10");
+        assert_eq!(config.onion.virtual_network_ipv6, "fc00::/7");
+
+        let mut config = Configuration::default();
+        config.onion.virtual_network_ipv4 = "fc00::/7".to_string();
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_virtual_network_redirected_first() {
+        // Local networks bypass Tor, but mapped onion addresses must not
+        let config = Configuration {
+            bypass: BypassConfig {
+                local_networks: true,
+                ..BypassConfig::default()
+            },
+            ..Configuration::default()
+        };
+        let rules = RoutingRules::planned(&config);
+        assert_eq!(
+            nat(&rules)[0],
+            "-d 10.192.0.0/10 -p tcp -m tcp --tcp-flags FIN,SYN,RST,ACK SYN -j REDIRECT --to-ports 9040"
+        );
+        assert!(!nat(&rules).iter().any(|rule| rule.contains("fc00::/7")));
+
+        let script = render_ruleset(&rules);
+        let redirect = script
+            .find("ip daddr 10.192.0.0/10 tcp flags & (fin|syn|rst|ack) == syn redirect to :9040")
+            .unwrap();
+        assert!(redirect < script.find("ip daddr 10.0.0.0/8 return").unwrap());
+        assert!(!script.contains("ip6 daddr fc00::/7 tcp"));
+
+        let off = Configuration {
+            onion: OnionConfig { automap: false, ..OnionConfig::default() },
+            ..Configuration::default()
+        };
+        assert!(RoutingRules::planned(&off).virtual_networks.is_empty());
+    }
+
+    #[test]
+    fn test_automap_options() {
+        let values = automap_values("10.192.0.0/10", "fc00::/7");
+        assert_eq!(
+            build_setconf_values(&values),
+            "SETCONF AutomapHostsOnResolve=1 AutomapHostsSuffixes=.onion,.exit VirtualAddrNetworkIPv4=10.192.0.0/10 VirtualAddrNetworkIPv6=[fc00::]/7\r\n"
+        );
+        assert_eq!(
+            build_resetconf(&AUTOMAP_OPTIONS),
+            "RESETCONF AutomapHostsOnResolve AutomapHostsSuffixes VirtualAddrNetworkIPv4 VirtualAddrNetworkIPv6\r\n"
+        );
+
+        let managed = ManagedTorConfig::from_config(&Configuration::default()).unwrap();
+        let torrc = managed.torrc();
+        assert!(torrc.contains("AutomapHostsOnResolve 1\n"));
+        assert!(torrc.contains("VirtualAddrNetworkIPv6 [fc00::]/7\n"));
+    }
+
+    #[test]
+    fn test_parse_onion_address() {
+        assert_eq!(parse_onion_address(ONION).unwrap(), (ONION.to_string(), 80));
+        assert_eq!(
+            parse_onion_address(&format!("https://www.{}/search?q=tor", ONION.to_uppercase())).unwrap(),
+            (format!("www.{}", ONION), 443)
+        );
+        assert_eq!(parse_onion_address(&format!("{}:8080", ONION)).unwrap().1, 8080);
+        assert!(parse_onion_address("expyuzz4wqqyqhjn.onion").is_err());
+        assert!(parse_onion_address("example.com").is_err());
+        assert!(parse_onion_address(&format!("{}:0", ONION)).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+// Unit tests for Tor control protocol parsing
+
+#[cfg(test)]
+mod tests {
+    use torrer::tor::protocol::{parse_response, read_reply, ParseError};
+
+    #[test]
+    fn test_single_line_reply() {
+        let response = parse_response("250 OK\r\n").unwrap();
+        assert_eq!(response.status_code, 250);
+        assert!(response.is_success());
+        assert_eq!(response.message(), "OK");
+    }
+
+    #[test]
+    fn test_mid_reply_key_values() {
+        let reply = "250-version=0.4.8.9\r\n250-status/circuit-established=1\r\n250 OK\r\n";
+        let response = parse_response(reply).unwrap();
+
+        assert_eq!(response.get("version").as_deref(), Some("0.4.8.9"));
+        assert_eq!(response.get("status/circuit-established").as_deref(), Some("1"));
+        assert_eq!(response.key_values().len(), 2);
+    }
+
+    #[test]
+    fn test_data_block_with_dot_escaping() {
+        let reply = "250+circuit-status=\r\n\
+                     1 BUILT $AAAA~a,$BBBB~b PURPOSE=GENERAL\r\n\
+                     ..hidden\r\n\
+                     .\r\n\
+                     250 OK\r\n";
+        let response = parse_response(reply).unwrap();
+
+        let lines = response.data_lines("circuit-status").unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1], ".hidden");
+        assert!(response.get("circuit-status").unwrap().starts_with("1 BUILT"));
+    }
+
+    #[test]
+    fn test_error_and_incomplete_replies() {
+        let response = parse_response("552 Unrecognized key \"foo\"\r\n").unwrap();
+        assert!(!response.is_success());
+
+        assert!(matches!(parse_response("250-a=1\r\n"), Err(ParseError::Incomplete)));
+        assert!(matches!(parse_response(""), Err(ParseError::EmptyResponse)));
+        assert!(matches!(parse_response("25x OK\r\n"), Err(ParseError::InvalidFormat)));
+    }
+
+    #[tokio::test]
+    async fn test_read_reply_separates_async_events() {
+        let input: &[u8] = b"650 BW 10 20\r\n250 OK\r\n";
+        let mut reader = tokio::io::BufReader::new(input);
+
+        let event = read_reply(&mut reader).await.unwrap().unwrap();
+        assert!(event.is_async_event());
+        assert_eq!(event.message(), "BW 10 20");
+
+        let reply = read_reply(&mut reader).await.unwrap().unwrap();
+        assert!(reply.is_success());
+
+        assert!(read_reply(&mut reader).await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,104 @@
+// Unit tests for split tunneling (bypass) rules
+
+#[cfg(test)]
+mod tests {
+    use torrer::config::{validate_config, BypassConfig, Configuration};
+    use torrer::iptables::rules::{FILTER_CHAIN, NAT_CHAIN};
+    use torrer::iptables::{chain_rules, render_ruleset, BypassRules, ChainFamily, RoutingRules};
+
+    fn bypass_rules() -> RoutingRules {
+        RoutingRules {
+            tor_routing: true,
+            dns_redirect: true,
+            block_ipv6: true,
+            tor_uid: Some(105),
+            bypass: BypassRules::resolve(&BypassConfig {
+                networks: vec!["203.0.113.9/24".to_string(), "2001:db8::/32".to_string()],
+                users: vec!["1001".to_string()],
+                groups: vec!["2002".to_string()],
+                cgroups: vec!["/system.slice/backup.service".to_string()],
+                ..BypassConfig::default()
+            }),
+            ..RoutingRules::default()
+        }
+    }
+
+    #[test]
+    fn test_bypass_configuration() {
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9040\ntor_dns_port = 5353\nipv6_enabled = false\nauto_fallback = true\n\n[bypass]\nlocal_networks = true\nusers = [\"backup\"]\n",
+        )
+        .unwrap();
+        assert!(config.bypass.local_networks);
+        assert_eq!(config.bypass.users, vec!["backup"]);
+        assert!(Configuration::default().bypass.is_empty());
+
+        let mut config = Configuration::default();
+        config.bypass.networks = vec!["10.0.0.0/33".to_string()];
+        assert!(validate_config(&config).is_err());
+        config.bypass.networks.clear();
+        config.bypass.cgroups = vec!["bad path".to_string()];
+        assert!(validate_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_resolve_bypass() {
+        let bypass = BypassRules::resolve(&BypassConfig {
+            local_networks: true,
+            networks: vec!["192.168.1.0/24".to_string(), "not-a-range".to_string()],
+            users: vec!["1001".to_string(), "no-such-user-torrer".to_string()],
+            cgroups: vec!["/system.slice/".to_string()],
+            ..BypassConfig::default()
+        });
+
+        assert_eq!(bypass.destinations(false).count(), 4);
+        assert_eq!(bypass.destinations(true).collect::<Vec<_>>(), vec!["fc00::/7", "fe80::/10"]);
+        assert_eq!(bypass.uids, vec![1001]);
+        assert_eq!(bypass.cgroups, vec!["system.slice"]);
+    }
+
+    #[test]
+    fn test_bypass_in_iptables_chains() {
+        let rules = chain_rules(&bypass_rules(), ChainFamily::Ipv4);
+        let nat: Vec<String> = rules
+            .iter()
+            .filter(|rule| rule.chain == NAT_CHAIN)
+            .map(|rule| rule.rule.join(" "))
+            .collect();
+
+        assert_eq!(
+            &nat[..5],
+            &[
+                "-m owner --uid-owner 105 -j RETURN",
+                "-m owner --uid-owner 1001 -j RETURN",
+                "-m owner --gid-owner 2002 -j RETURN",
+                "-m cgroup --path system.slice/backup.service -j RETURN",
+                "-d 203.0.113.0/24 -j RETURN",
+            ]
+        );
+        // Bypassed traffic is also exempt from the filter drops
+        assert!(rules
+            .iter()
+            .any(|rule| rule.chain == FILTER_CHAIN && rule.rule.join(" ") == "-d 203.0.113.0/24 -j RETURN"));
+
+        let ipv6: Vec<String> = chain_rules(&bypass_rules(), ChainFamily::Ipv6)
+            .iter()
+            .map(|rule| rule.rule.join(" "))
+            .collect();
+        assert!(ipv6.contains(&"-d 2001:db8::/32 -j RETURN".to_string()));
+        assert!(!ipv6.iter().any(|rule| rule.contains("203.0.113.0")));
+        assert_eq!(ipv6.last().unwrap(), "! -o lo -j DROP");
+    }
+
+    #[test]
+    fn test_bypass_in_nft_ruleset() {
+        let script = render_ruleset(&bypass_rules());
+
+        assert!(script.contains("meta skuid 1001 return"));
+        assert!(script.contains("meta skgid 2002 return"));
+        assert!(script.contains("socket cgroupv2 level 2 \"system.slice/backup.service\" return"));
+        assert!(script.contains("ip daddr 203.0.113.0/24 return"));
+        assert!(script.contains("ip6 daddr 2001:db8::/32 return"));
+        assert!(script.find("ip daddr 203.0.113.0/24").unwrap() < script.find("redirect to :9040").unwrap());
+    }
+}
@@ -11,6 +11,8 @@ mod tests {
             is_running: true,
             tor_connected: true,
             circuit_established: true,
+            bootstrap: None,
+            kill_switch: false,
         };
         
         let display = format!("{}", status);
@@ -25,6 +27,8 @@ mod tests {
             is_running: false,
             tor_connected: false,
             circuit_established: false,
+            bootstrap: None,
+            kill_switch: false,
         };
         
         let display = format!("{}", status);
@@ -39,6 +43,8 @@ mod tests {
             is_running: true,
             tor_connected: true,
             circuit_established: true,
+            bootstrap: None,
+            kill_switch: false,
         };
         
         let cloned = status.clone();
@@ -53,6 +59,8 @@ mod tests {
             is_running: true,
             tor_connected: false,
             circuit_established: false,
+            bootstrap: None,
+            kill_switch: false,
         };
         
         let debug = format!("{:?}", status);
@@ -107,6 +115,8 @@ mod tests {
             is_running: true,
             tor_connected: true,
             circuit_established: true,
+            bootstrap: None,
+            kill_switch: false,
         };
         
         // Verify the struct can be used (serialization would require serde Serialize)
@@ -134,6 +144,8 @@ mod tests {
                 is_running: running,
                 tor_connected: connected,
                 circuit_established: circuit,
+                bootstrap: None,
+                kill_switch: false,
             };
             
             assert_eq!(status.is_running, running);
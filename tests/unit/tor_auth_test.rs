@@ -0,0 +1,80 @@
+// Unit tests for Tor control port authentication
+
+#[cfg(test)]
+mod tests {
+    use torrer::tor::auth::{
+        quote_password, safecookie_client_hash, AuthChallenge, AuthMethod, ProtocolInfo,
+    };
+    use torrer::tor::protocol::parse_response;
+    use torrer::utils::Crypto;
+
+    #[test]
+    fn test_hmac_sha256_known_vector() {
+        // RFC 4231 test case 2
+        let mac = Crypto::hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex::encode(mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_parse_protocolinfo() {
+        let reply = "250-PROTOCOLINFO 1\r\n\
+                     250-AUTH METHODS=COOKIE,SAFECOOKIE,HASHEDPASSWORD COOKIEFILE=\"/run/tor/control.authcookie\"\r\n\
+                     250-VERSION Tor=\"0.4.8.9\"\r\n\
+                     250 OK\r\n";
+        let info = ProtocolInfo::parse(&parse_response(reply).unwrap()).unwrap();
+
+        assert!(info.supports(AuthMethod::SafeCookie));
+        assert!(info.supports(AuthMethod::HashedPassword));
+        assert!(!info.supports(AuthMethod::Null));
+        assert_eq!(
+            info.cookie_file.unwrap().to_str(),
+            Some("/run/tor/control.authcookie")
+        );
+        assert_eq!(info.tor_version.as_deref(), Some("0.4.8.9"));
+    }
+
+    #[test]
+    fn test_safecookie_exchange() {
+        let cookie = [7u8; 32];
+        let client_nonce = [1u8; 32];
+        let server_nonce = [2u8; 32];
+
+        let mut message = cookie.to_vec();
+        message.extend_from_slice(&client_nonce);
+        message.extend_from_slice(&server_nonce);
+        let server_hash = Crypto::hmac_sha256(
+            b"Tor safe cookie authentication server-to-controller hash",
+            &message,
+        );
+
+        let reply = format!(
+            "250 AUTHCHALLENGE SERVERHASH={} SERVERNONCE={}\r\n",
+            hex::encode(&server_hash),
+            hex::encode(server_nonce)
+        );
+        let challenge = AuthChallenge::parse(&parse_response(&reply).unwrap()).unwrap();
+
+        let client_hash = safecookie_client_hash(&cookie, &client_nonce, &challenge).unwrap();
+        let expected = Crypto::hmac_sha256(
+            b"Tor safe cookie authentication controller-to-server hash",
+            &message,
+        );
+        assert_eq!(client_hash, expected);
+
+        // A forged server hash must be rejected
+        let forged = AuthChallenge {
+            server_hash: vec![0u8; 32],
+            server_nonce: server_nonce.to_vec(),
+        };
+        assert!(safecookie_client_hash(&cookie, &client_nonce, &forged).is_err());
+    }
+
+    #[test]
+    fn test_quote_password() {
+        assert_eq!(quote_password("secret"), "\"secret\"");
+        assert_eq!(quote_password("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+}
@@ -0,0 +1,107 @@
+// Unit tests for Tor asynchronous event parsing
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use torrer::core::events::EVENT_QUEUE_CAPACITY;
+    use torrer::core::{Event, EventManager};
+    use torrer::tor::protocol::parse_response;
+    use torrer::tor::TorEvent;
+
+    fn parse(line: &str) -> TorEvent {
+        TorEvent::parse(&parse_response(line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_circ_event() {
+        let event = parse(
+            "650 CIRC 12 BUILT $AAAA~guard,$BBBB~middle,$CCCC~exit BUILD_FLAGS=NEED_CAPACITY PURPOSE=GENERAL TIME_CREATED=2024-01-01T00:00:00.000000\r\n",
+        );
+
+        match event {
+            TorEvent::Circ(circ) => {
+                assert_eq!(circ.id, "12");
+                assert_eq!(circ.status, "BUILT");
+                assert_eq!(circ.path.len(), 3);
+                assert_eq!(circ.purpose(), Some("GENERAL"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_bw_and_status_events() {
+        match parse("650 STREAM 7 FAILED 12 example.com:443 REASON=TIMEOUT\r\n") {
+            TorEvent::Stream(stream) => {
+                assert_eq!(stream.circuit_id, "12");
+                assert_eq!(stream.reason(), Some("TIMEOUT"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        match parse("650 BW 1024 2048\r\n") {
+            TorEvent::Bw(bw) => assert_eq!((bw.read, bw.written), (1024, 2048)),
+            other => panic!("unexpected event: {:?}", other),
+        }
+
+        match parse("650 STATUS_CLIENT NOTICE BOOTSTRAP PROGRESS=100 TAG=done SUMMARY=\"Done\"\r\n") {
+            TorEvent::StatusClient(status) => {
+                assert_eq!(status.action, "BOOTSTRAP");
+                assert_eq!(status.arguments.get("SUMMARY").map(String::as_str), Some("Done"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_non_event_replies_are_ignored() {
+        assert!(TorEvent::parse(&parse_response("250 OK\r\n").unwrap()).is_none());
+        assert!(TorEvent::parse(&parse_response("650 ADDRMAP a b NEVER\r\n").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_translate_to_engine_events() {
+        let failed = parse("650 CIRC 3 FAILED $AAAA~guard REASON=TIMEOUT\r\n");
+        assert!(matches!(
+            Event::from_tor_event(&failed),
+            Some(Event::CircuitFailed(ref msg)) if msg == "3 (TIMEOUT)"
+        ));
+
+        let established = parse("650 STATUS_CLIENT NOTICE CIRCUIT_ESTABLISHED\r\n");
+        assert!(matches!(Event::from_tor_event(&established), Some(Event::CircuitEstablished)));
+
+        let extended = parse("650 CIRC 3 EXTENDED $AAAA~guard\r\n");
+        assert!(Event::from_tor_event(&extended).is_none());
+    }
+
+    #[test]
+    fn test_event_queue_is_bounded() {
+        let events = EventManager::new();
+        for _ in 0..EVENT_QUEUE_CAPACITY + 10 {
+            events.emit(Event::Bandwidth { read: 1, written: 1 }).unwrap();
+        }
+
+        let mut received = 0;
+        while events.try_receive().is_some() {
+            received += 1;
+        }
+        assert_eq!(received, EVENT_QUEUE_CAPACITY);
+    }
+
+    #[test]
+    fn test_listener_takes_receiver() {
+        let events = EventManager::new();
+        let (seen, rx) = mpsc::channel();
+        events.start_listener(move |event| {
+            let _ = seen.send(event.name());
+        });
+
+        // The listener waiting for events must not block the caller
+        assert!(events.try_receive().is_none());
+        events.emit(Event::RoutingStarted).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok("routing_started"));
+        assert!(events.receive().is_err());
+    }
+}
@@ -0,0 +1,132 @@
+// Unit tests for the managed tor process, using a fake tor binary
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixListener;
+    use torrer::bridge::Bridge;
+    use torrer::config::Configuration;
+    use torrer::tor::{CountrySelector, ManagedTorConfig, TorProcess};
+
+    /// Stub that records its arguments and runs until the control socket is removed
+    const FAKE_TOR: &str = r#"#!/bin/sh
+echo "$@" > "$(dirname "$2")/args"
+sock=$(sed -n 's/^ControlSocket //p' "$2")
+while [ -S "$sock" ]; do sleep 0.1; done
+"#;
+
+    fn write_stub(path: &Path, script: &str) {
+        std::fs::write(path, script).unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Answer the control commands a managed launch sends
+    async fn serve_control(listener: UnixListener) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (read_half, mut write_half) = stream.into_split();
+        let mut lines = BufReader::new(read_half).lines();
+        let mut progress = 50;
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply = match line.as_str() {
+                "PROTOCOLINFO 1" => {
+                    "250-PROTOCOLINFO 1\r\n250-AUTH METHODS=NULL\r\n250-VERSION Tor=\"0.4.8.9\"\r\n250 OK\r\n".to_string()
+                }
+                "GETINFO status/bootstrap-phase" => {
+                    let reply = format!(
+                        "250-status/bootstrap-phase=NOTICE BOOTSTRAP PROGRESS={} TAG=x SUMMARY=\"Working\"\r\n250 OK\r\n",
+                        progress
+                    );
+                    progress = 100;
+                    reply
+                }
+                "SIGNAL SHUTDOWN" => {
+                    write_half.write_all(b"250 OK\r\n").await.unwrap();
+                    break;
+                }
+                _ => "250 OK\r\n".to_string(),
+            };
+            write_half.write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_launch_and_stop_managed_tor() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("tor");
+        std::fs::create_dir_all(&data).unwrap();
+
+        let mut config = ManagedTorConfig::new(&data);
+        config.tor_binary = dir.path().join("fake-tor");
+        config.bootstrap_timeout = Duration::from_secs(10);
+        config.bridges = vec!["Bridge 192.0.2.1:443".to_string()];
+        config.exit_nodes = Some(CountrySelector::exit_nodes("ca, us").unwrap());
+        write_stub(&config.tor_binary, FAKE_TOR);
+
+        let socket = config.control_socket();
+        let listener = UnixListener::bind(&socket).unwrap();
+        let server = tokio::spawn(async move {
+            serve_control(listener).await;
+            let _ = std::fs::remove_file(socket);
+        });
+
+        let mut process = TorProcess::launch(config).await.unwrap();
+        assert!(process.id().is_some());
+
+        let torrc = std::fs::read_to_string(data.join("torrc")).unwrap();
+        assert!(torrc.contains("TransPort 127.0.0.1:9040"));
+        assert!(torrc.contains("DNSPort 127.0.0.1:5353"));
+        assert!(torrc.contains(&format!("ControlSocket {}", data.join("control").display())));
+        assert!(torrc.contains("UseBridges 1\nBridge 192.0.2.1:443"));
+        assert!(torrc.contains("ExitNodes {CA},{US}"));
+
+        let args = std::fs::read_to_string(data.join("args")).unwrap();
+        assert!(args.contains(&format!("__OwningControllerProcess {}", std::process::id())));
+
+        tokio::time::timeout(Duration::from_secs(5), process.stop())
+            .await
+            .unwrap()
+            .unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_launch_fails_when_tor_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = ManagedTorConfig::new(dir.path().join("tor"));
+        config.tor_binary = dir.path().join("fake-tor");
+        config.bootstrap_timeout = Duration::from_secs(5);
+        write_stub(&config.tor_binary, "#!/bin/sh\nexit 1\n");
+
+        let error = TorProcess::launch(config).await.err().unwrap();
+        assert!(error.to_string().contains("exited during startup"));
+    }
+
+    #[test]
+    fn test_torrc_with_transport_bridge() {
+        let line = "obfs4 192.0.2.7:443 0123456789ABCDEF0123456789ABCDEF01234567 cert=AbCd iat-mode=0";
+        let bridge = Bridge::from_str(line).unwrap();
+
+        let mut settings = Configuration::default();
+        settings
+            .transport_plugins
+            .insert("obfs4".to_string(), PathBuf::from("/usr/bin/lyrebird"));
+
+        let mut config = ManagedTorConfig::new("/var/lib/torrer/tor");
+        config.bridges = vec![bridge.to_tor_config()];
+        config.transport_plugins = vec![("obfs4".to_string(), settings.transport_plugin("obfs4").unwrap())];
+
+        let torrc = config.torrc();
+        assert!(torrc.contains(&format!("UseBridges 1\nBridge {}\n", line)));
+        assert!(torrc.contains("ClientTransportPlugin obfs4 exec /usr/bin/lyrebird\n"));
+
+        assert_eq!(
+            Configuration::default().transport_plugin("snowflake"),
+            Some(PathBuf::from("/usr/bin/snowflake-client"))
+        );
+        assert!(settings.transport_plugin("unknown").is_none());
+    }
+}
@@ -0,0 +1,125 @@
+// Unit tests for the UDP policy
+
+#[cfg(test)]
+mod tests {
+    use torrer::config::{validate_config, Configuration, UdpConfig, UdpPolicy};
+    use torrer::iptables::rules::FILTER_CHAIN;
+    use torrer::iptables::{chain_rules, render_ruleset, ChainFamily, RoutingRules, UdpRules};
+    use torrer::utils::split_host_port;
+
+    fn udp_rules(policy: UdpPolicy, allow: &[&str]) -> RoutingRules {
+        let config = Configuration {
+            udp: UdpConfig {
+                policy,
+                allow: allow.iter().map(|entry| entry.to_string()).collect(),
+            },
+            ..Configuration::default()
+        };
+        RoutingRules {
+            tor_uid: None,
+            tor_gid: None,
+            ..RoutingRules::planned(&config)
+        }
+    }
+
+    fn filter(rules: &RoutingRules, family: ChainFamily) -> Vec<String> {
+        chain_rules(rules, family)
+            .iter()
+            .filter(|rule| rule.chain == FILTER_CHAIN)
+            .map(|rule| rule.rule.join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_udp_configuration() {
+        let config: Configuration = toml::from_str(
+            "tor_control_port = 9051\ntor_transport_port = 9040\ntor_dns_port = 5353\nipv6_enabled = false\nauto_fallback = true\n\n[udp]\npolicy = \"reject\"\nallow = [\"pool.ntp.org:123\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.udp.policy, UdpPolicy::Reject);
+        assert!(Configuration::default().udp.is_empty());
+
+        let mut config = Configuration::default();
+        config.udp.allow = vec!["10.0.0.0/33:123".to_string()];
+        assert!(validate_config(&config).is_err());
+        config.udp.allow = vec!["ntp:0".to_string()];
+        assert!(validate_config(&config).is_err());
+        config.udp.allow = vec!["123".to_string(), "[2001:db8::1]:123".to_string()];
+        assert!(validate_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_split_host_port() {
+        assert_eq!(split_host_port("123").unwrap(), (None, 123));
+        assert_eq!(split_host_port("pool.ntp.org:123").unwrap(), (Some("pool.ntp.org"), 123));
+        assert_eq!(split_host_port("[fd00::/8]:443").unwrap(), (Some("fd00::/8"), 443));
+        assert!(split_host_port("2001:db8::1:123").is_err());
+        assert!(split_host_port(":123").is_err());
+        assert!(split_host_port("host:http").is_err());
+    }
+
+    #[test]
+    fn test_resolve_udp_exceptions() {
+        let udp = UdpRules::resolve(&UdpConfig {
+            policy: UdpPolicy::Drop,
+            allow: vec![
+                "123".to_string(),
+                "192.168.1.7/24:123".to_string(),
+                "[2001:db8::1]:5060".to_string(),
+                "bad entry".to_string(),
+            ],
+        });
+        assert_eq!(
+            udp.allowed(false).collect::<Vec<_>>(),
+            vec![(None, 123), (Some("192.168.1.0/24"), 123)]
+        );
+        assert_eq!(
+            udp.allowed(true).collect::<Vec<_>>(),
+            vec![(None, 123), (Some("2001:db8::1/128"), 5060)]
+        );
+    }
+
+    #[test]
+    fn test_udp_policy_iptables_rules() {
+        // The default policy leaves UDP alone
+        let rules = udp_rules(UdpPolicy::Allow, &[]);
+        assert!(!rules.filters_udp());
+        assert!(!filter(&rules, ChainFamily::Ipv4).iter().any(|rule| rule.contains("-p udp -j")));
+
+        let rules = udp_rules(UdpPolicy::Reject, &["192.168.1.1:123"]);
+        assert_eq!(
+            filter(&rules, ChainFamily::Ipv4),
+            vec![
+                "! -o lo -p udp -m udp --dport 53 -j DROP",
+                "! -o lo -p tcp -m multiport --dports 53,853 -j REJECT --reject-with tcp-reset",
+                "-d 192.168.1.1/32 -p udp -m udp --dport 123 -j RETURN",
+                "-p udp -m udp --sport 68 --dport 67 -j RETURN",
+                "! -o lo -p udp -j REJECT --reject-with icmp-port-unreachable",
+            ]
+        );
+
+        // Routed IPv6 gets the same policy before the final reject
+        let rules = RoutingRules {
+            block_ipv6: false,
+            route_ipv6: true,
+            ..udp_rules(UdpPolicy::Drop, &["123"])
+        };
+        let ipv6 = filter(&rules, ChainFamily::Ipv6);
+        assert!(ipv6.contains(&"-p udp -m udp --dport 123 -j RETURN".to_string()));
+        assert!(ipv6.contains(&"-p udp -m udp --sport 546 --dport 547 -j RETURN".to_string()));
+        assert!(ipv6.contains(&"! -o lo -p udp -j DROP".to_string()));
+    }
+
+    #[test]
+    fn test_udp_policy_nft_ruleset() {
+        let script = render_ruleset(&udp_rules(UdpPolicy::Drop, &["123", "[2001:db8::1]:123"]));
+        assert!(script.contains("meta nfproto ipv4 udp dport 123 return"));
+        assert!(script.contains("meta nfproto ipv4 udp sport 68 udp dport 67 return"));
+        assert!(script.contains("oifname != \"lo\" meta l4proto udp drop"));
+        // IPv6 is blocked, so its exceptions are left out
+        assert!(!script.contains("2001:db8::1"));
+
+        let script = render_ruleset(&udp_rules(UdpPolicy::Reject, &[]));
+        assert!(script.contains("oifname != \"lo\" meta l4proto udp reject"));
+    }
+}